@@ -5,7 +5,7 @@ use hermes_rs::debug_info::{
 use hermes_rs::string_kind::StringKindEntryNew;
 use hermes_rs::{define_instructions, HermesFile};
 
-use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::io::Cursor;
 
 use hermes_rs::bytecode_options::BytecodeOptions;
 use hermes_rs::function_header::FunctionHeader;
@@ -190,19 +190,10 @@ fn main() {
     hermes_file.debug_info = debug_info;
     hermes_file.set_debug_strings(vec!["yes.js".to_string()]);
 
-    let mut writer: Cursor<Vec<u8>> = Cursor::new(Vec::new());
-
-    hermes_file.serialize(&mut writer);
-
     // write the new bytecode to the file
     let mut file = File::create("out.hbc").expect("unable to create file");
 
-    writer
-        .seek(SeekFrom::Start(0))
-        .expect("unable to seek to start");
-
-    file.write_all(&writer.bytes().map(|b| b.unwrap()).collect::<Vec<u8>>())
-        .expect("unable to write to file");
+    hermes_file.serialize(&mut file);
 
     println!("File 'out.hbc' was created.");
 }
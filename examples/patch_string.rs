@@ -1,8 +1,5 @@
 use hermes_rs::{HermesFile, StringKind};
 
-use std::io::{Cursor, Read, Write};
-use std::io::{Seek, SeekFrom};
-
 use std::{fs::File, io};
 
 fn main() {
@@ -47,23 +44,11 @@ fn main() {
     println!("File length is: {:?}", hermes_file.header.file_length);
     println!("Header: {:?}", hermes_file.header);
 
-    let mut writer: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+    let mut file: File = File::create("patched_strings.hbc").expect("unable to create file");
 
     println!("Serializing...");
-    hermes_file.serialize(&mut writer);
+    hermes_file.serialize(&mut file);
     println!("Done serializing...");
 
-    let mut file: File = File::create("patched_strings.hbc").expect("unable to create file");
-    writer
-        .seek(SeekFrom::Start(0))
-        .expect("unable to seek to start");
-
-    println!("Writing to file...");
-
-    file.write_all(&writer.bytes().map(|b| b.unwrap()).collect::<Vec<u8>>())
-        .expect("unable to write to file");
-
-    println!("Done writing to file...");
-
     println!("File 'patched_strings.hbc' was created.");
 }
@@ -1,5 +1,6 @@
 use hermes_rs::hermes_file::HermesFile;
-use std::{env, fs::File, io};
+use hermes_rs::signatures::SignatureDatabase;
+use std::{env, fs, fs::File, io};
 
 fn main() {
     // Get first parameter passed to the program
@@ -23,5 +24,27 @@ fn main() {
 
     let mut hermes_file = HermesFile::deserialize(&mut reader);
 
+    // Leave out functions that match a known-library signature database
+    if args.get(2).map(|a| a.as_str()) == Some("--hide-libraries") {
+        let signature_file = match args.get(3) {
+            Some(path) => path,
+            None => {
+                println!("Usage: bytecode <hbc_file> --hide-libraries <signature_file>");
+                std::process::exit(1);
+            }
+        };
+        let text = fs::read_to_string(signature_file).expect("unable to read signature file");
+        let db = SignatureDatabase::from_text(&text).unwrap_or_else(|e| {
+            println!("{}", e);
+            std::process::exit(1);
+        });
+        let labels = hermes_file.identify_libraries(&db);
+        println!(
+            "{}",
+            hermes_file.get_disassembled_bytecode_without_libraries(&labels)
+        );
+        return;
+    }
+
     hermes_file.print_bytecode_new();
 }
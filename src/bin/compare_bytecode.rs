@@ -1,31 +1,167 @@
-use hermes_rs::{define_instructions, InstructionParser};
+/*
+
+Semantic diff between two builds of a bundle.
+
+Functions are matched by fingerprint, name and position rather than by ID, so
+renumbering between builds doesn't show up as a change. Prints removed, added
+and modified functions - with an instruction-level diff of each modified
+function, strings resolved - followed by strings added to and removed from the
+string table.
+
+With --hide-libraries, functions matching a signature database (see the
+signatures binary) are left out, so only first-party changes are listed.
+
+Usage:
+
+    cargo run --bin compare_bytecode <old_hbc_file> <new_hbc_file> [--summary] [--hide-libraries <signature_file>]
+
+*/
+
+use hermes_rs::diff::{BundleDiff, DiffLine};
+use hermes_rs::hermes_file::HermesFile;
+use hermes_rs::signatures::SignatureDatabase;
+use std::{env, fs, fs::File, io};
+
+// Unchanged instructions shown around each change
+const CONTEXT: usize = 2;
 
 fn main() {
-    #[cfg(feature = "v96")]
-    // Use the define_instructions macro. The first parameter is the hermes version
-    let instructions = define_instructions!(
-        hermes_rs::v96,
-        LoadConstString {
-            r0: 0.into(),
-            p0: 1.into()
-        },
-        DirectEval {
-            r0: 0.into(),
-            r1: 0.into(),
-            p0: 0.into()
-        },
-        Ret { r0: 0.into() },
-    )
-    .unwrap();
-
-    let mut writer = Vec::new();
-
-    for instr in instructions {
-        instr.serialize(&mut writer);
-    }
-
-    assert!(
-        writer == vec![115, 0, 1, 0, 94, 0, 0, 0, 92, 0],
-        "Bytecode is incorrect!"
-    );
+    let args: Vec<String> = env::args().collect();
+
+    if args.len() < 3 {
+        println!("Usage: compare_bytecode <old_hbc_file> <new_hbc_file> [--summary] [--hide-libraries <signature_file>]");
+        std::process::exit(1);
+    }
+
+    for hbc_file in &args[1..3] {
+        // check if file exists
+        if !std::path::Path::new(hbc_file).exists() {
+            println!("File not found: {}", hbc_file);
+            std::process::exit(1);
+        }
+    }
+
+    let summary_only = args.iter().any(|a| a == "--summary");
+
+    let old_f = File::open(&args[1]).expect("no file found");
+    let mut old_reader = io::BufReader::new(old_f);
+    let mut old_file = HermesFile::deserialize(&mut old_reader);
+
+    let new_f = File::open(&args[2]).expect("no file found");
+    let mut new_reader = io::BufReader::new(new_f);
+    let mut new_file = HermesFile::deserialize(&mut new_reader);
+
+    let mut diff = old_file.diff(&mut new_file);
+
+    if let Some(pos) = args.iter().position(|a| a == "--hide-libraries") {
+        let signature_file = match args.get(pos + 1) {
+            Some(path) => path,
+            None => {
+                println!("Missing signature file after --hide-libraries");
+                std::process::exit(1);
+            }
+        };
+        let text = fs::read_to_string(signature_file).expect("unable to read signature file");
+        let db = SignatureDatabase::from_text(&text).unwrap_or_else(|e| {
+            println!("{}", e);
+            std::process::exit(1);
+        });
+        let old_labels = old_file.identify_libraries(&db);
+        let new_labels = new_file.identify_libraries(&db);
+        diff.hide_libraries(&old_labels, &new_labels);
+    }
+
+    print_summary(&diff);
+    if summary_only {
+        return;
+    }
+
+    if !diff.removed.is_empty() {
+        println!("Removed functions:");
+        for id in diff.removed.iter() {
+            println!("  - #{} {}", id, old_file.get_function_name(*id as usize));
+        }
+        println!();
+    }
+
+    if !diff.added.is_empty() {
+        println!("Added functions:");
+        for id in diff.added.iter() {
+            println!("  + #{} {}", id, new_file.get_function_name(*id as usize));
+        }
+        println!();
+    }
+
+    for function in diff.modified.iter() {
+        println!(
+            "Modified #{} {} -> #{} {} ({:?}, confidence {:.2})",
+            function.left,
+            function.left_name,
+            function.right,
+            function.right_name,
+            function.kind,
+            function.confidence
+        );
+        print_hunks(&function.lines);
+        println!();
+    }
+
+    if !diff.strings_removed.is_empty() {
+        println!("Removed strings:");
+        for s in diff.strings_removed.iter() {
+            println!("  - {:?}", s);
+        }
+        println!();
+    }
+
+    if !diff.strings_added.is_empty() {
+        println!("Added strings:");
+        for s in diff.strings_added.iter() {
+            println!("  + {:?}", s);
+        }
+        println!();
+    }
+}
+
+fn print_summary(diff: &BundleDiff) {
+    println!("Matched functions:  {}", diff.matches.len());
+    println!("  unchanged:        {}", diff.unchanged_count());
+    println!("  modified:         {}", diff.modified.len());
+    println!("Removed functions:  {}", diff.removed.len());
+    println!("Added functions:    {}", diff.added.len());
+    println!("Removed strings:    {}", diff.strings_removed.len());
+    println!("Added strings:      {}", diff.strings_added.len());
+    println!();
+}
+
+fn print_hunks(lines: &[DiffLine]) {
+    // Show changed lines plus CONTEXT lines either side, "..." between hunks
+    let mut visible = vec![false; lines.len()];
+    for (i, line) in lines.iter().enumerate() {
+        if line.is_change() {
+            let start = i.saturating_sub(CONTEXT);
+            let end = (i + CONTEXT + 1).min(lines.len());
+            visible[start..end].iter_mut().for_each(|v| *v = true);
+        }
+    }
+
+    let mut skipped = false;
+    for (line, visible) in lines.iter().zip(visible.iter()) {
+        if !visible {
+            skipped = true;
+            continue;
+        }
+        if skipped {
+            println!("    ...");
+            skipped = false;
+        }
+        match line {
+            DiffLine::Same(text) => println!("    {}", text),
+            DiffLine::Removed(text) => println!("  - {}", text),
+            DiffLine::Added(text) => println!("  + {}", text),
+        }
+    }
+    if skipped {
+        println!("    ...");
+    }
 }
@@ -0,0 +1,81 @@
+/*
+
+Lists functions that are never referenced - no CreateClosure-style or CallDirect
+instruction reachable from the global function or the CJS module table refers
+to them - along with their size and name. These are likely dead code.
+
+Functions only referenced from other dead functions are listed with the
+functions that reference them.
+
+Usage:
+
+    cargo run --bin dead_code <hbc_file>
+
+*/
+
+use hermes_rs::hermes_file::HermesFile;
+use std::{env, fs::File, io};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    if args.len() < 2 {
+        println!("Usage: dead_code <hbc_file>");
+        std::process::exit(1);
+    }
+
+    let hbc_file = &args[1];
+
+    // check if file exists
+    if !std::path::Path::new(hbc_file).exists() {
+        println!("File not found: {}", hbc_file);
+        std::process::exit(1);
+    }
+
+    let f = File::open(hbc_file).expect("no file found");
+
+    let mut reader = io::BufReader::new(f);
+
+    let mut hermes_file = HermesFile::deserialize(&mut reader);
+
+    let report = hermes_file.get_reachability_report();
+
+    println!(
+        "Entry points: {}",
+        report
+            .entry_points
+            .iter()
+            .map(|f| format!("#{}", f))
+            .collect::<Vec<String>>()
+            .join(", ")
+    );
+    println!(
+        "{} of {} functions reachable, {} unreferenced ({} bytes)",
+        report.reachable_count,
+        hermes_file.function_headers.len(),
+        report.unreferenced.len(),
+        report.unreferenced_size()
+    );
+    println!();
+
+    if report.unreferenced.is_empty() {
+        return;
+    }
+
+    println!("{:>8} {:>8}  name", "funcID", "bytes");
+    for function in report.unreferenced.iter() {
+        let mut line = format!(
+            "{:>8} {:>8}  {}",
+            function.function_id, function.byte_size, function.name
+        );
+        if !function.referenced_by.is_empty() {
+            let ids: Vec<String> = function
+                .referenced_by
+                .iter()
+                .map(|f| format!("#{}", f))
+                .collect();
+            line.push_str(&format!("  (referenced by {})", ids.join(", ")));
+        }
+        println!("{}", line);
+    }
+}
@@ -0,0 +1,69 @@
+/*
+
+Decompiles Hermes bytecode into JavaScript-like pseudocode.
+
+Without a function ID the global function is decompiled - every closure it
+creates is printed inline, so this is the whole program. Functions that are
+only ever called directly (CallDirect) can be decompiled by ID.
+
+Usage:
+
+    cargo run --bin decompile <hbc_file>                    # the whole program
+    cargo run --bin decompile <hbc_file> <function_id>      # a single function
+    cargo run --bin decompile <hbc_file> [function_id] --ir # the SSA IR instead
+
+*/
+
+use hermes_rs::hermes_file::HermesFile;
+use std::{env, fs::File, io};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    if args.len() < 2 {
+        println!("Usage: decompile <hbc_file> [function_id] [--ir]");
+        std::process::exit(1);
+    }
+
+    let hbc_file = &args[1];
+
+    // check if file exists
+    if !std::path::Path::new(hbc_file).exists() {
+        println!("File not found: {}", hbc_file);
+        std::process::exit(1);
+    }
+
+    let print_ir = args.iter().any(|a| a == "--ir");
+    let function_id = args.iter().skip(2).find(|a| *a != "--ir");
+
+    let f = File::open(hbc_file).expect("no file found");
+
+    let mut reader = io::BufReader::new(f);
+
+    let mut hermes_file = HermesFile::deserialize(&mut reader);
+
+    let function_ids: Vec<usize> = match function_id {
+        Some(id) => {
+            let id: usize = id.parse().expect("function_id must be a number");
+            if id >= hermes_file.function_headers.len() {
+                println!(
+                    "Function {} out of range ({} functions)",
+                    id,
+                    hermes_file.function_headers.len()
+                );
+                std::process::exit(1);
+            }
+            vec![id]
+        }
+        None if print_ir => (0..hermes_file.function_headers.len()).collect(),
+        None => vec![hermes_file.header.global_code_index as usize],
+    };
+
+    for id in function_ids {
+        if print_ir {
+            println!("{}", hermes_file.lift_function(id));
+        } else {
+            println!("{}", hermes_file.decompile_function(id));
+        }
+    }
+}
@@ -0,0 +1,70 @@
+/*
+
+Lists every global the bundle declares (DeclareGlobalVar,
+ThrowIfHasRestrictedGlobalProperty) and every global read or written through
+the global object, with the functions that touch each one.
+
+Globals are grouped into declared ones, ones assigned without a declaration
+(polyfills, runtime hooks) and read-only ones (builtins, host objects). Metro
+runtime hooks like `__d` and `__r` are marked with [metro].
+
+Usage:
+
+    cargo run --bin globals <hbc_file>
+
+*/
+
+use hermes_rs::globals::GlobalVariable;
+use hermes_rs::hermes_file::HermesFile;
+use std::{env, fs::File, io};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    if args.len() < 2 {
+        println!("Usage: globals <hbc_file>");
+        std::process::exit(1);
+    }
+
+    let hbc_file = &args[1];
+
+    // check if file exists
+    if !std::path::Path::new(hbc_file).exists() {
+        println!("File not found: {}", hbc_file);
+        std::process::exit(1);
+    }
+
+    let f = File::open(hbc_file).expect("no file found");
+
+    let mut reader = io::BufReader::new(f);
+
+    let mut hermes_file = HermesFile::deserialize(&mut reader);
+
+    let inventory = hermes_file.get_global_inventory();
+
+    print_globals("Declared", &inventory.declared());
+    print_globals("Assigned", &inventory.assigned());
+    print_globals("Read only", &inventory.read_only());
+}
+
+fn print_globals(title: &str, globals: &[&GlobalVariable]) {
+    println!("{} ({}):", title, globals.len());
+    for global in globals {
+        let mut line = format!("  {}", global.name);
+        if global.is_metro_runtime() {
+            line.push_str(" [metro]");
+        }
+        for (label, functions) in [
+            ("declared", &global.declared_in),
+            ("read", &global.read_in),
+            ("written", &global.written_in),
+        ] {
+            if !functions.is_empty() {
+                let ids: Vec<String> = functions.iter().map(|f| format!("#{}", f)).collect();
+                line.push_str(&format!("  {}: {}", label, ids.join(", ")));
+            }
+        }
+        println!("{}", line);
+    }
+    println!();
+}
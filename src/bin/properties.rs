@@ -0,0 +1,118 @@
+/*
+
+Dumps the property names read/written/deleted through the *ById instructions,
+and the object literal shapes (key sets) built in the bundle - useful for
+recognising request/response objects across an app.
+
+Usage:
+
+    cargo run --bin properties <hbc_file>                 # the whole bundle
+    cargo run --bin properties <hbc_file> <function_id>   # a single function
+    cargo run --bin properties <hbc_file> --keys url,method # shapes containing these keys
+
+*/
+
+use hermes_rs::hermes_file::HermesFile;
+use hermes_rs::properties::{ObjectShape, PropertyInventory};
+use std::{env, fs::File, io};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    if args.len() < 2 {
+        println!("Usage: properties <hbc_file> [function_id | --keys <key,key,...>]");
+        std::process::exit(1);
+    }
+
+    let hbc_file = &args[1];
+
+    // check if file exists
+    if !std::path::Path::new(hbc_file).exists() {
+        println!("File not found: {}", hbc_file);
+        std::process::exit(1);
+    }
+
+    let f = File::open(hbc_file).expect("no file found");
+
+    let mut reader = io::BufReader::new(f);
+
+    let mut hermes_file = HermesFile::deserialize(&mut reader);
+
+    match args.get(2).map(|a| a.as_str()) {
+        Some("--keys") => {
+            let keys: Vec<&str> = match args.get(3) {
+                Some(keys) => keys.split(',').map(|k| k.trim()).collect(),
+                None => {
+                    println!("Usage: properties <hbc_file> --keys <key,key,...>");
+                    std::process::exit(1);
+                }
+            };
+            let inventory = hermes_file.get_property_inventory();
+            for shape in inventory.shapes_with_keys(&keys) {
+                print_shape(shape);
+            }
+        }
+        Some(id) => {
+            let id: usize = id.parse().expect("function_id must be a number");
+            if id >= hermes_file.function_headers.len() {
+                println!(
+                    "Function {} out of range ({} functions)",
+                    id,
+                    hermes_file.function_headers.len()
+                );
+                std::process::exit(1);
+            }
+
+            println!("Function #{} {}", id, hermes_file.get_function_name(id));
+            for access in hermes_file.get_function_property_accesses(id) {
+                println!(
+                    "  {:>6}  {:<9} {}",
+                    access.offset,
+                    format!("{:?}", access.kind),
+                    access.name
+                );
+            }
+            println!();
+            print_inventory(&hermes_file.get_function_property_inventory(id));
+        }
+        None => print_inventory(&hermes_file.get_property_inventory()),
+    }
+}
+
+fn print_inventory(inventory: &PropertyInventory) {
+    println!("Object shapes ({}):", inventory.shapes.len());
+    for shape in inventory.shapes.iter() {
+        print_shape(shape);
+    }
+
+    println!();
+    println!("Properties ({}):", inventory.properties.len());
+    println!(
+        "  {:>6} {:>6} {:>6} {:>9}  name",
+        "reads", "writes", "dels", "functions"
+    );
+    for (name, usage) in inventory.properties.iter() {
+        println!(
+            "  {:>6} {:>6} {:>6} {:>9}  {}",
+            usage.reads,
+            usage.writes,
+            usage.deletes,
+            usage.functions.len(),
+            name
+        );
+    }
+}
+
+fn print_shape(shape: &ObjectShape) {
+    let functions: Vec<String> = shape
+        .functions()
+        .iter()
+        .map(|f| format!("#{}", f))
+        .collect();
+    println!(
+        "  {{{}}} x{} in {}",
+        shape.keys.join(", "),
+        shape.literals.len(),
+        functions.join(", ")
+    );
+}
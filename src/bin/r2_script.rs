@@ -29,6 +29,17 @@ fn main() {
     println!("f hbc.header=0x0");
     println!("f hbc.header.size=0x{:x}\n", hermes_file.header.file_length);
 
+    println!("# Section flags");
+    for section in hermes_file.section_layout().sections.iter() {
+        println!(
+            "'f section.{} {} 0x{:x}",
+            section.kind.to_string().replace(' ', "_"),
+            section.size,
+            section.offset
+        );
+    }
+    println!();
+
     println!("# Function flags");
     for (i, function_header) in hermes_file.function_headers.iter().enumerate() {
         let function_name =
@@ -0,0 +1,83 @@
+/*
+
+Searches a bundle for instruction sequence patterns and lists the matching
+functions and offsets.
+
+Rules are read from a file, or a single pattern is given inline with its steps
+separated by `;`. See src/hermes/pattern for the syntax. Example rule file:
+
+    rule fetch_https
+        GetById* $fetch _ _ "fetch"
+        within 5 LoadConstString* $url /^https/
+        within 5 Call* _ $fetch ... $url ...
+    end
+
+Usage:
+
+    cargo run --bin search <hbc_file> <rules_file>
+    cargo run --bin search <hbc_file> --pattern '<pattern>'
+
+*/
+
+use hermes_rs::hermes_file::HermesFile;
+use hermes_rs::pattern::{parse_pattern, parse_rules};
+use std::{env, fs, fs::File, io};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    if args.len() < 3 {
+        println!("Usage: search <hbc_file> <rules_file>");
+        println!("       search <hbc_file> --pattern '<pattern>'");
+        std::process::exit(1);
+    }
+
+    let hbc_file = &args[1];
+
+    // check if file exists
+    if !std::path::Path::new(hbc_file).exists() {
+        println!("File not found: {}", hbc_file);
+        std::process::exit(1);
+    }
+
+    let rules = if args[2] == "--pattern" {
+        let pattern = match args.get(3) {
+            Some(pattern) => pattern,
+            None => {
+                println!("Usage: search <hbc_file> --pattern '<pattern>'");
+                std::process::exit(1);
+            }
+        };
+        parse_pattern("pattern", pattern).map(|rule| vec![rule])
+    } else {
+        let text = fs::read_to_string(&args[2]).expect("unable to read rules file");
+        parse_rules(&text)
+    };
+    let rules = rules.unwrap_or_else(|e| {
+        println!("Invalid pattern: {}", e);
+        std::process::exit(1);
+    });
+
+    let f = File::open(hbc_file).expect("no file found");
+
+    let mut reader = io::BufReader::new(f);
+
+    let mut hermes_file = HermesFile::deserialize(&mut reader);
+
+    let matches = hermes_file.search(&rules);
+
+    for m in matches.iter() {
+        println!(
+            "{}: #{} {} @ {}",
+            m.rule,
+            m.function_id,
+            m.function_name,
+            m.offset()
+        );
+        for (offset, ins) in m.offsets.iter().zip(m.instructions.iter()) {
+            println!("    {:>6}  {}", offset, ins);
+        }
+    }
+
+    println!("{} match(es)", matches.len());
+}
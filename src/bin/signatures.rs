@@ -0,0 +1,128 @@
+/*
+
+Known-library signatures.
+
+`generate` fingerprints every Metro module with a known path in a reference
+bundle and writes a signature database. Paths come from a module names file
+with one `<module id> <path>` per line, or from the bundle itself for dev
+bundles - in which case only modules under node_modules are recorded.
+
+`identify` labels the functions and modules of a target bundle that match a
+signature database, grouped by library. Pass the same database to
+`bytecode --hide-libraries` or `compare_bytecode --hide-libraries` to leave
+library code out of disassembly and diffs.
+
+Usage:
+
+    cargo run --bin signatures generate <reference_hbc_file> <signature_file> [module_names_file]
+    cargo run --bin signatures identify <hbc_file> <signature_file>
+
+*/
+
+use hermes_rs::hermes_file::HermesFile;
+use hermes_rs::signatures::{LabelKind, SignatureDatabase};
+use std::collections::HashMap;
+use std::{env, fs, fs::File, io};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    if args.len() < 4 || (args[1] != "generate" && args[1] != "identify") {
+        println!(
+            "Usage: signatures generate <reference_hbc_file> <signature_file> [module_names_file]"
+        );
+        println!("       signatures identify <hbc_file> <signature_file>");
+        std::process::exit(1);
+    }
+
+    let hbc_file = &args[2];
+
+    // check if file exists
+    if !std::path::Path::new(hbc_file).exists() {
+        println!("File not found: {}", hbc_file);
+        std::process::exit(1);
+    }
+
+    let f = File::open(hbc_file).expect("no file found");
+
+    let mut reader = io::BufReader::new(f);
+
+    let mut hermes_file = HermesFile::deserialize(&mut reader);
+
+    if args[1] == "generate" {
+        let module_names = match args.get(4) {
+            Some(path) => read_module_names(path),
+            None => HashMap::new(),
+        };
+
+        let db = hermes_file.generate_signatures(&module_names);
+        fs::write(&args[3], db.to_text()).expect("unable to write signature file");
+        println!(
+            "Wrote {} function and {} module signatures to {}",
+            db.functions.len(),
+            db.modules.len(),
+            args[3]
+        );
+        return;
+    }
+
+    let text = fs::read_to_string(&args[3]).expect("unable to read signature file");
+    let db = SignatureDatabase::from_text(&text).unwrap_or_else(|e| {
+        println!("{}", e);
+        std::process::exit(1);
+    });
+
+    let labels = hermes_file.identify_libraries(&db);
+
+    println!(
+        "{} of {} functions labelled as library code\n",
+        labels.functions.len(),
+        hermes_file.function_headers.len()
+    );
+
+    println!("Libraries:");
+    for (library, count) in labels.libraries() {
+        println!("  {:>6}  {}", count, library);
+    }
+    println!();
+
+    println!("Modules ({}):", labels.modules.len());
+    for (id, label) in labels.modules.iter() {
+        println!("  {:>6}  {}  ({})", id, label.library, label.module);
+    }
+    println!();
+
+    println!("Functions:");
+    for (fid, label) in labels.functions.iter() {
+        if label.kind == LabelKind::Function {
+            println!(
+                "  #{} {}  {}  ({})",
+                fid,
+                hermes_file.get_function_name(*fid as usize),
+                label.library,
+                label.module
+            );
+        }
+    }
+}
+
+fn read_module_names(path: &str) -> HashMap<u32, String> {
+    let text = fs::read_to_string(path).expect("unable to read module names file");
+    let mut names = HashMap::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.split_once(char::is_whitespace) {
+            Some((id, name)) if id.parse::<u32>().is_ok() => {
+                names.insert(id.parse::<u32>().unwrap(), name.trim().to_string());
+            }
+            _ => {
+                println!("Invalid module name line: {}", line);
+                std::process::exit(1);
+            }
+        }
+    }
+    names
+}
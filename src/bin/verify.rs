@@ -0,0 +1,81 @@
+/*
+
+Validates the file structure - magic, header counts, section layout, file
+length, footer hash, identifier hashes and string tables - then verifies every
+function's bytecode - register, string, function, BigInt and RegExp operands,
+jump and switch targets, exception handlers, environment slots and property
+cache indices - and prints what the Hermes VM would trip over.
+
+Exits with status 1 if any errors are found, so it can be used to check a
+patched bundle before shipping it.
+
+Usage:
+
+    cargo run --bin verify <hbc_file>
+
+*/
+
+use hermes_rs::hermes_file::HermesFile;
+use hermes_rs::verifier::Severity;
+use std::{env, fs::File, io};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    if args.len() < 2 {
+        println!("Usage: verify <hbc_file>");
+        std::process::exit(1);
+    }
+
+    let hbc_file = &args[1];
+
+    // check if file exists
+    if !std::path::Path::new(hbc_file).exists() {
+        println!("File not found: {}", hbc_file);
+        std::process::exit(1);
+    }
+
+    let f = File::open(hbc_file).expect("no file found");
+
+    let mut reader = io::BufReader::new(f);
+
+    let mut hermes_file = HermesFile::deserialize(&mut reader);
+
+    let findings = hermes_file.validate();
+
+    for finding in findings.iter() {
+        println!("{}", finding);
+    }
+
+    let file_errors = findings
+        .iter()
+        .filter(|f| f.severity == Severity::Error)
+        .count();
+    println!(
+        "File structure: {} error(s), {} warning(s)",
+        file_errors,
+        findings.len() - file_errors
+    );
+
+    let diagnostics = hermes_file.verify();
+
+    for diagnostic in diagnostics.iter() {
+        println!("{}", diagnostic);
+    }
+
+    let errors = diagnostics
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .count();
+    println!(
+        "{} functions verified: {} error(s), {} warning(s)",
+        hermes_file.function_headers.len(),
+        errors,
+        diagnostics.len() - errors
+    );
+    let errors = errors + file_errors;
+
+    if errors > 0 {
+        std::process::exit(1);
+    }
+}
@@ -1,6 +1,8 @@
+use std::collections::HashMap;
+
 #[cfg_attr(feature = "specta", derive(specta::Type))]
 #[cfg_attr(feature = "serde", derive(serde::Serialize))]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ArrayTypes {
     EmptyValueSized { value: u32 },
     NullValue {},
@@ -12,3 +14,181 @@ pub enum ArrayTypes {
     ByteStringValue { value: u8 },
     IntegerValue { value: u32 },
 }
+
+const NULL_TAG: u8 = 0 << 4;
+const TRUE_TAG: u8 = 1 << 4;
+const FALSE_TAG: u8 = 2 << 4;
+const NUMBER_TAG: u8 = 3 << 4;
+const LONG_STRING_TAG: u8 = 4 << 4;
+const SHORT_STRING_TAG: u8 = 5 << 4;
+const BYTE_STRING_TAG: u8 = 6 << 4;
+const INTEGER_TAG: u8 = 7 << 4;
+
+/*
+ * Parses `count` literal values from a serialized literal buffer (array buffer,
+ * object key buffer or object value buffer) starting at `offset`.
+ *
+ * Values are stored as a series of tagged sequences - each tag byte holds the
+ * type of the values that follow it and how many there are (with the 0x80 bit
+ * set, the length is 12 bits and spills into the next byte). An array literal
+ * can span several sequences, so we keep reading tags until `count` values
+ * have been parsed.
+ *
+ * Returns the offset just past the last parsed value along with the values.
+ */
+pub fn parse_literal_buffer(buf: &[u8], offset: usize, count: usize) -> (usize, Vec<ArrayTypes>) {
+    let mut values: Vec<ArrayTypes> = vec![];
+    let mut idx = offset;
+
+    while values.len() < count && idx < buf.len() {
+        let tag = buf[idx];
+        let seq_len = if (tag & 0x80) != 0 {
+            if idx + 1 >= buf.len() {
+                break;
+            }
+            let len = (((tag & 0x0f) as usize) << 8) | (buf[idx + 1] as usize);
+            idx += 2;
+            len
+        } else {
+            idx += 1;
+            (tag & 0x0f) as usize
+        };
+
+        for _ in 0..seq_len {
+            if values.len() >= count {
+                break;
+            }
+
+            let width = match tag & 0x70 {
+                NUMBER_TAG => 8,
+                LONG_STRING_TAG | INTEGER_TAG => 4,
+                SHORT_STRING_TAG => 2,
+                BYTE_STRING_TAG => 1,
+                _ => 0,
+            };
+
+            if idx + width > buf.len() {
+                return (idx, values);
+            }
+
+            let bytes = &buf[idx..idx + width];
+            let value = match tag & 0x70 {
+                NULL_TAG => ArrayTypes::NullValue {},
+                TRUE_TAG => ArrayTypes::TrueValue { value: true },
+                FALSE_TAG => ArrayTypes::FalseValue { value: false },
+                NUMBER_TAG => ArrayTypes::NumberValue {
+                    value: u64::from_le_bytes(bytes.try_into().unwrap()),
+                },
+                LONG_STRING_TAG => ArrayTypes::LongStringValue {
+                    value: u32::from_le_bytes(bytes.try_into().unwrap()),
+                },
+                SHORT_STRING_TAG => ArrayTypes::ShortStringValue {
+                    value: u16::from_le_bytes(bytes.try_into().unwrap()),
+                },
+                BYTE_STRING_TAG => ArrayTypes::ByteStringValue { value: bytes[0] },
+                _ => ArrayTypes::IntegerValue {
+                    value: u32::from_le_bytes(bytes.try_into().unwrap()),
+                },
+            };
+
+            values.push(value);
+            idx += width;
+        }
+    }
+
+    (idx, values)
+}
+
+/*
+ * Rewrites every string ID in a serialized literal buffer, passing each one
+ * through `remap`. A sequence keeps its tag as long as its new IDs fit, and is
+ * re-tagged with the narrowest string tag that fits all of them otherwise - so
+ * the buffer can grow and the sequences after it move.
+ *
+ * Returns the new buffer, along with where each sequence (and the end of the
+ * buffer) starts now, by where it started before. A buffer that ends in the
+ * middle of a sequence is an error.
+ */
+pub fn remap_literal_strings<F>(
+    buf: &[u8],
+    mut remap: F,
+) -> Result<(Vec<u8>, HashMap<u32, u32>), String>
+where
+    F: FnMut(u32) -> Result<u32, String>,
+{
+    let mut out: Vec<u8> = Vec::with_capacity(buf.len());
+    let mut offsets: HashMap<u32, u32> = HashMap::new();
+    let mut idx = 0;
+
+    while idx < buf.len() {
+        offsets.insert(idx as u32, out.len() as u32);
+
+        let tag = buf[idx];
+        let header_len = if (tag & 0x80) != 0 { 2 } else { 1 };
+        if idx + header_len > buf.len() {
+            return Err(format!("buffer ends in the tag at offset {}", idx));
+        }
+        let seq_len = if header_len == 2 {
+            (((tag & 0x0f) as usize) << 8) | (buf[idx + 1] as usize)
+        } else {
+            (tag & 0x0f) as usize
+        };
+        let header = &buf[idx..idx + header_len];
+        idx += header_len;
+
+        let width = match tag & 0x70 {
+            NUMBER_TAG => 8,
+            LONG_STRING_TAG | INTEGER_TAG => 4,
+            SHORT_STRING_TAG => 2,
+            BYTE_STRING_TAG => 1,
+            _ => 0,
+        };
+        if idx + seq_len * width > buf.len() {
+            return Err(format!(
+                "buffer ends in the sequence of {} values at offset {}",
+                seq_len,
+                idx - header_len
+            ));
+        }
+        let values = &buf[idx..idx + seq_len * width];
+        idx += values.len();
+
+        if !matches!(
+            tag & 0x70,
+            LONG_STRING_TAG | SHORT_STRING_TAG | BYTE_STRING_TAG
+        ) {
+            out.extend_from_slice(header);
+            out.extend_from_slice(values);
+            continue;
+        }
+
+        let mut ids = vec![];
+        for value in values.chunks_exact(width) {
+            let mut bytes = [0u8; 4];
+            bytes[..width].copy_from_slice(value);
+            ids.push(remap(u32::from_le_bytes(bytes))?);
+        }
+
+        // Never narrower than it was, so a buffer whose IDs all still fit comes
+        // out byte for byte the same
+        let new_width = ids.iter().fold(width, |new_width, id| match id {
+            0..=0xff => new_width,
+            0x100..=0xffff => new_width.max(2),
+            _ => 4,
+        });
+        let new_tag = match new_width {
+            1 => BYTE_STRING_TAG,
+            2 => SHORT_STRING_TAG,
+            _ => LONG_STRING_TAG,
+        };
+
+        out.push((header[0] & !0x70) | new_tag);
+        out.extend_from_slice(&header[1..]);
+        for id in ids {
+            out.extend_from_slice(&id.to_le_bytes()[..new_width]);
+        }
+    }
+    offsets.insert(buf.len() as u32, out.len() as u32);
+
+    Ok((out, offsets))
+}
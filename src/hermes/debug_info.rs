@@ -1,7 +1,7 @@
 use std::io;
 
 use crate::hermes::decode::decode_u32;
-use crate::hermes::encode::encode_u32;
+use crate::hermes::encode::{encode_sleb128, encode_u32};
 use crate::hermes::Serializable;
 
 use super::decode::align_reader;
@@ -165,6 +165,21 @@ impl DebugInfoOffsets {
         }
     }
 
+    /// Where the function's locations start in the sources data.
+    pub fn src(&self) -> u32 {
+        match self {
+            DebugInfoOffsets::Old(x) => x.src,
+            DebugInfoOffsets::New(x) => x.src,
+        }
+    }
+
+    pub fn set_src(&mut self, src: u32) {
+        match self {
+            DebugInfoOffsets::Old(x) => x.src = src,
+            DebugInfoOffsets::New(x) => x.src = src,
+        }
+    }
+
     pub fn serialize<W>(&self, w: &mut W)
     where
         W: io::Write + io::Seek,
@@ -247,7 +262,8 @@ impl Serializable for DebugInfoOffsetsOld {
     where
         W: io::Write,
     {
-        // encode_u32(w, self.string_data_off);
+        encode_u32(_w, self.src);
+        encode_u32(_w, self.scope_desc);
     }
 }
 
@@ -388,3 +404,153 @@ pub struct FunctionDebugInfoDeserializer {
     current_line: u32,
     current_column: u32,
 }
+
+/*
+ * The source locations of a function, from the sources data of the debug info.
+ *
+ * Every number is a signed LEB128. A function starts with its index, line and
+ * column, followed by an entry for each instruction that starts a new location
+ * and an address delta of -1. Each entry holds deltas from the previous one: the
+ * address, the line (shifted left, with the low bit set if a statement delta
+ * follows), the column, the statement and - depending on the version - the
+ * scope address and environment register.
+ */
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionLocations {
+    pub function_index: i64,
+    pub line: i64,
+    pub column: i64,
+    /// The bytecode address of each entry, and the rest of the entry as stored.
+    pub entries: Vec<(u32, Vec<i64>)>,
+}
+
+fn read_sleb128(data: &[u8], pos: &mut usize) -> Option<i64> {
+    let mut result: i64 = 0;
+    let mut shift = 0;
+    loop {
+        let byte = *data.get(*pos)?;
+        *pos += 1;
+        if shift < 64 {
+            result |= ((byte & 0x7f) as i64) << shift;
+        }
+        shift += 7;
+        if byte & 0x80 == 0 {
+            if shift < 64 && byte & 0x40 != 0 {
+                result |= -1 << shift;
+            }
+            return Some(result);
+        }
+    }
+}
+
+impl FunctionLocations {
+    /// Parses the locations at `offset`, with `extra_fields` numbers after the
+    /// statement delta. Returns them along with where they end.
+    pub fn parse(data: &[u8], offset: usize, extra_fields: usize) -> Option<(Self, usize)> {
+        let mut pos = offset;
+        let function_index = read_sleb128(data, &mut pos)?;
+        let line = read_sleb128(data, &mut pos)?;
+        let column = read_sleb128(data, &mut pos)?;
+
+        let mut entries = vec![];
+        let mut address: i64 = 0;
+        loop {
+            let delta = read_sleb128(data, &mut pos)?;
+            if delta == -1 {
+                break;
+            }
+            address += delta;
+
+            let line_delta = read_sleb128(data, &mut pos)?;
+            let mut rest = vec![line_delta, read_sleb128(data, &mut pos)?];
+            if line_delta & 1 != 0 {
+                rest.push(read_sleb128(data, &mut pos)?);
+            }
+            for _ in 0..extra_fields {
+                rest.push(read_sleb128(data, &mut pos)?);
+            }
+            entries.push((u32::try_from(address).ok()?, rest));
+        }
+
+        Some((
+            FunctionLocations {
+                function_index,
+                line,
+                column,
+                entries,
+            },
+            pos,
+        ))
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = vec![];
+        encode_sleb128(&mut out, self.function_index);
+        encode_sleb128(&mut out, self.line);
+        encode_sleb128(&mut out, self.column);
+
+        let mut address: i64 = 0;
+        for (entry_address, rest) in self.entries.iter() {
+            encode_sleb128(&mut out, *entry_address as i64 - address);
+            address = *entry_address as i64;
+            for value in rest.iter() {
+                encode_sleb128(&mut out, *value);
+            }
+        }
+        encode_sleb128(&mut out, -1);
+        out
+    }
+}
+
+impl DebugInfo {
+    /*
+     * Reads the locations of the function at `offset` in the sources data. `next`
+     * is where the locations after it start (or the end of the sources data).
+     * How many numbers an entry holds depends on the version, so each layout is
+     * tried until one ends exactly at `next`.
+     */
+    pub fn function_locations(&self, offset: u32, next: u32) -> Option<FunctionLocations> {
+        (0..=2).find_map(|extra_fields| {
+            match FunctionLocations::parse(
+                &self.sources_data_storage,
+                offset as usize,
+                extra_fields,
+            ) {
+                Some((locations, end)) if end == next as usize => Some(locations),
+                _ => None,
+            }
+        })
+    }
+
+    /*
+     * Replaces the sources data from `offset` to `end` with `locations`. Returns
+     * how far everything after it moved; the file regions and header offsets after
+     * it are moved here, function headers are up to the caller.
+     */
+    pub fn replace_function_locations(
+        &mut self,
+        offset: u32,
+        end: u32,
+        locations: &FunctionLocations,
+    ) -> i64 {
+        let encoded = locations.encode();
+        let shift = encoded.len() as i64 - (end - offset) as i64;
+        self.sources_data_storage
+            .splice(offset as usize..end as usize, encoded);
+
+        let moved = |value: u32| (value as i64 + shift) as u32;
+        for region in self.file_regions.iter_mut() {
+            if region.from_address > offset {
+                region.from_address = moved(region.from_address);
+            }
+        }
+
+        let header = &mut self.header;
+        header.scope_desc_data_offset = moved(header.scope_desc_data_offset);
+        header.textified_callee_offset = header.textified_callee_offset.map(moved);
+        header.string_table_offset = header.string_table_offset.map(moved);
+        header.debug_data_size = moved(header.debug_data_size);
+        shift
+    }
+}
@@ -0,0 +1,562 @@
+use std::fmt;
+
+use crate::hermes::ir::ClosureKind;
+
+/*
+ * A small JavaScript AST - just enough to print the structured output of
+ * the decompiler with correct precedence and indentation.
+ */
+
+const INDENT: &str = "    ";
+
+#[derive(Debug, Clone)]
+pub enum Expr {
+    /// Identifiers and literals, already rendered.
+    Atom(String),
+    /// Prefix operator - the operator includes any trailing space (`typeof `).
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+    Assign(Box<Expr>, Box<Expr>),
+    Member(Box<Expr>, String),
+    Index(Box<Expr>, Box<Expr>),
+    Call(Box<Expr>, Vec<Expr>),
+    New(Box<Expr>, Vec<Expr>),
+    Array(Vec<Expr>),
+    /// Object literal - keys are already rendered.
+    Object(Vec<(String, Expr)>),
+    Function(Box<JsFunction>),
+}
+
+impl Expr {
+    pub fn atom<S: Into<String>>(s: S) -> Expr {
+        Expr::Atom(s.into())
+    }
+
+    pub fn binary(op: &'static str, lhs: Expr, rhs: Expr) -> Expr {
+        Expr::Binary(op, Box::new(lhs), Box::new(rhs))
+    }
+
+    pub fn assign(target: Expr, value: Expr) -> Expr {
+        Expr::Assign(Box::new(target), Box::new(value))
+    }
+
+    pub fn is_atom(&self, s: &str) -> bool {
+        matches!(self, Expr::Atom(a) if a == s)
+    }
+
+    /*
+     * Returns the logical negation of an expression, flipping comparisons
+     * where that's always valid instead of wrapping them in `!(...)`.
+     */
+    pub fn negate(self) -> Expr {
+        match self {
+            Expr::Unary("!", inner) => *inner,
+            Expr::Binary(op, lhs, rhs) if negated_op(op).is_some() => {
+                Expr::Binary(negated_op(op).unwrap(), lhs, rhs)
+            }
+            Expr::Atom(a) if a == "true" => Expr::atom("false"),
+            Expr::Atom(a) if a == "false" => Expr::atom("true"),
+            other => Expr::Unary("!", Box::new(other)),
+        }
+    }
+
+    /// Whether the first token is an object literal or function expression -
+    /// `function f() {}.call(x)` starts with one as much as `function f() {}` does.
+    fn starts_with_literal(&self) -> bool {
+        // The leftmost operand, and the precedence below which it's parenthesized
+        let (first, min) = match self {
+            Expr::Object(_) | Expr::Function(_) => return true,
+            Expr::Binary(op, lhs, _) => (lhs, binary_precedence(op)),
+            Expr::Conditional(cond, ..) => (cond, 4),
+            Expr::Assign(first, _)
+            | Expr::Member(first, _)
+            | Expr::Index(first, _)
+            | Expr::Call(first, _) => (first, 18),
+            _ => return false,
+        };
+        first.precedence() >= min && first.starts_with_literal()
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            Expr::Atom(_) | Expr::Array(_) | Expr::Object(_) | Expr::Function(_) => 20,
+            Expr::Member(..) | Expr::Index(..) => 19,
+            Expr::Call(..) | Expr::New(..) => 18,
+            Expr::Unary(..) => 15,
+            Expr::Binary(op, ..) => binary_precedence(op),
+            Expr::Conditional(..) => 3,
+            Expr::Assign(..) => 2,
+        }
+    }
+
+    fn write(&self, out: &mut String, indent: usize, min: u8) {
+        let parens = self.precedence() < min;
+        if parens {
+            out.push('(');
+        }
+
+        match self {
+            Expr::Atom(a) => out.push_str(a),
+            Expr::Unary(op, inner) => {
+                out.push_str(op);
+                inner.write(out, indent, 15);
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                let prec = binary_precedence(op);
+                lhs.write(out, indent, prec);
+                out.push(' ');
+                out.push_str(op);
+                out.push(' ');
+                rhs.write(out, indent, prec + 1);
+            }
+            Expr::Conditional(cond, a, b) => {
+                cond.write(out, indent, 4);
+                out.push_str(" ? ");
+                a.write(out, indent, 2);
+                out.push_str(" : ");
+                b.write(out, indent, 2);
+            }
+            Expr::Assign(target, value) => {
+                target.write(out, indent, 18);
+                out.push_str(" = ");
+                value.write(out, indent, 2);
+            }
+            Expr::Member(object, name) => {
+                object.write(out, indent, 18);
+                out.push('.');
+                out.push_str(name);
+            }
+            Expr::Index(object, key) => {
+                object.write(out, indent, 18);
+                out.push('[');
+                key.write(out, indent, 0);
+                out.push(']');
+            }
+            Expr::Call(callee, args) => {
+                callee.write(out, indent, 18);
+                write_list(out, indent, "(", args, ")");
+            }
+            Expr::New(callee, args) => {
+                out.push_str("new ");
+                callee.write(out, indent, 19);
+                write_list(out, indent, "(", args, ")");
+            }
+            Expr::Array(elements) => write_list(out, indent, "[", elements, "]"),
+            Expr::Object(properties) => {
+                if properties.is_empty() {
+                    out.push_str("{}");
+                } else {
+                    out.push_str("{ ");
+                    for (i, (key, value)) in properties.iter().enumerate() {
+                        if i > 0 {
+                            out.push_str(", ");
+                        }
+                        out.push_str(key);
+                        out.push_str(": ");
+                        value.write(out, indent, 2);
+                    }
+                    out.push_str(" }");
+                }
+            }
+            Expr::Function(function) => function.write(out, indent),
+        }
+
+        if parens {
+            out.push(')');
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut out = String::new();
+        self.write(&mut out, 0, 0);
+        write!(f, "{}", out)
+    }
+}
+
+fn write_list(out: &mut String, indent: usize, open: &str, items: &[Expr], close: &str) {
+    out.push_str(open);
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        item.write(out, indent, 2);
+    }
+    out.push_str(close);
+}
+
+fn binary_precedence(op: &str) -> u8 {
+    match op {
+        "*" | "/" | "%" => 13,
+        "+" | "-" => 12,
+        "<<" | ">>" | ">>>" => 11,
+        "<" | "<=" | ">" | ">=" | "instanceof" | "in" => 10,
+        "==" | "!=" | "===" | "!==" => 9,
+        "&" => 8,
+        "^" => 7,
+        "|" => 6,
+        "&&" => 5,
+        "||" => 4,
+        _ => 4,
+    }
+}
+
+// Relational ops aren't negated - `!(a < b)` differs from `a >= b` for NaN.
+fn negated_op(op: &str) -> Option<&'static str> {
+    match op {
+        "==" => Some("!="),
+        "!=" => Some("=="),
+        "===" => Some("!=="),
+        "!==" => Some("==="),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SwitchCase {
+    pub values: Vec<i64>,
+    pub body: Vec<Stmt>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Stmt {
+    Expr(Expr),
+    Var(String, Option<Expr>),
+    If {
+        condition: Expr,
+        then: Vec<Stmt>,
+        otherwise: Vec<Stmt>,
+    },
+    While {
+        label: Option<String>,
+        condition: Expr,
+        body: Vec<Stmt>,
+    },
+    For {
+        label: Option<String>,
+        init: Vec<Expr>,
+        condition: Expr,
+        update: Vec<Expr>,
+        body: Vec<Stmt>,
+    },
+    Switch {
+        value: Expr,
+        cases: Vec<SwitchCase>,
+        default: Option<Vec<Stmt>>,
+    },
+    Try {
+        body: Vec<Stmt>,
+        catch_var: Option<String>,
+        handler: Vec<Stmt>,
+    },
+    Return(Expr),
+    Throw(Expr),
+    Break(Option<String>),
+    Continue(Option<String>),
+    /// Target of a `Goto`, for control flow that couldn't be structured.
+    Label(String),
+    Goto(String),
+    Comment(String),
+}
+
+impl Stmt {
+    /// Whether control can't fall through to the statement after this one.
+    pub fn is_terminal(&self) -> bool {
+        match self {
+            Stmt::Return(_)
+            | Stmt::Throw(_)
+            | Stmt::Break(_)
+            | Stmt::Continue(_)
+            | Stmt::Goto(_) => true,
+            Stmt::If {
+                then, otherwise, ..
+            } => ends_terminal(then) && ends_terminal(otherwise),
+            _ => false,
+        }
+    }
+
+    fn write(&self, out: &mut String, indent: usize) {
+        let pad = INDENT.repeat(indent);
+        match self {
+            Stmt::Expr(expr) => {
+                out.push_str(&pad);
+                // `{` and `function` at the start of a statement would be parsed as a block/declaration
+                let wrap = expr.starts_with_literal();
+                expr.write(out, indent, if wrap { 21 } else { 0 });
+                out.push_str(";\n");
+            }
+            Stmt::Var(name, value) => {
+                out.push_str(&pad);
+                out.push_str("var ");
+                out.push_str(name);
+                if let Some(value) = value {
+                    out.push_str(" = ");
+                    value.write(out, indent, 2);
+                }
+                out.push_str(";\n");
+            }
+            Stmt::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                out.push_str(&pad);
+                write_if(out, indent, condition, then, otherwise);
+            }
+            Stmt::While {
+                label,
+                condition,
+                body,
+            } => {
+                write_label(out, &pad, label);
+                out.push_str(&pad);
+                out.push_str("while (");
+                condition.write(out, indent, 0);
+                out.push_str(") ");
+                write_block(out, indent, body);
+                out.push('\n');
+            }
+            Stmt::For {
+                label,
+                init,
+                condition,
+                update,
+                body,
+            } => {
+                write_label(out, &pad, label);
+                out.push_str(&pad);
+                out.push_str("for (");
+                write_sequence(out, indent, init);
+                out.push_str("; ");
+                condition.write(out, indent, 0);
+                out.push_str("; ");
+                write_sequence(out, indent, update);
+                out.push_str(") ");
+                write_block(out, indent, body);
+                out.push('\n');
+            }
+            Stmt::Switch {
+                value,
+                cases,
+                default,
+            } => {
+                out.push_str(&pad);
+                out.push_str("switch (");
+                value.write(out, indent, 0);
+                out.push_str(") {\n");
+                let case_pad = INDENT.repeat(indent + 1);
+                for case in cases.iter() {
+                    for value in case.values.iter() {
+                        out.push_str(&format!("{}case {}:\n", case_pad, value));
+                    }
+                    write_stmts(out, indent + 2, &case.body);
+                }
+                if let Some(default) = default {
+                    out.push_str(&format!("{}default:\n", case_pad));
+                    write_stmts(out, indent + 2, default);
+                }
+                out.push_str(&pad);
+                out.push_str("}\n");
+            }
+            Stmt::Try {
+                body,
+                catch_var,
+                handler,
+            } => {
+                out.push_str(&pad);
+                out.push_str("try ");
+                write_block(out, indent, body);
+                match catch_var {
+                    Some(var) => out.push_str(&format!(" catch ({}) ", var)),
+                    None => out.push_str(" catch "),
+                }
+                write_block(out, indent, handler);
+                out.push('\n');
+            }
+            Stmt::Return(value) => {
+                out.push_str(&pad);
+                if value.is_atom("undefined") {
+                    out.push_str("return;\n");
+                } else {
+                    out.push_str("return ");
+                    value.write(out, indent, 0);
+                    out.push_str(";\n");
+                }
+            }
+            Stmt::Throw(value) => {
+                out.push_str(&pad);
+                out.push_str("throw ");
+                value.write(out, indent, 0);
+                out.push_str(";\n");
+            }
+            Stmt::Break(label) => match label {
+                Some(label) => out.push_str(&format!("{}break {};\n", pad, label)),
+                None => out.push_str(&format!("{}break;\n", pad)),
+            },
+            Stmt::Continue(label) => match label {
+                Some(label) => out.push_str(&format!("{}continue {};\n", pad, label)),
+                None => out.push_str(&format!("{}continue;\n", pad)),
+            },
+            Stmt::Label(label) => out.push_str(&format!("{}:\n", label)),
+            Stmt::Goto(label) => out.push_str(&format!("{}goto {};\n", pad, label)),
+            Stmt::Comment(comment) => out.push_str(&format!("{}// {}\n", pad, comment)),
+        }
+    }
+}
+
+// Prints `if (...) { ... } else if (...) { ... } else { ... }` without the leading indent.
+fn write_if(out: &mut String, indent: usize, condition: &Expr, then: &[Stmt], otherwise: &[Stmt]) {
+    out.push_str("if (");
+    condition.write(out, indent, 0);
+    out.push_str(") ");
+    write_block(out, indent, then);
+    if let [Stmt::If {
+        condition,
+        then,
+        otherwise,
+    }] = otherwise
+    {
+        out.push_str(" else ");
+        write_if(out, indent, condition, then, otherwise);
+        return;
+    }
+    if !otherwise.is_empty() {
+        out.push_str(" else ");
+        write_block(out, indent, otherwise);
+    }
+    out.push('\n');
+}
+
+pub fn ends_terminal(stmts: &[Stmt]) -> bool {
+    stmts.last().map(|s| s.is_terminal()).unwrap_or(false)
+}
+
+fn write_label(out: &mut String, pad: &str, label: &Option<String>) {
+    if let Some(label) = label {
+        out.push_str(&format!("{}{}:\n", pad, label));
+    }
+}
+
+fn write_sequence(out: &mut String, indent: usize, exprs: &[Expr]) {
+    for (i, expr) in exprs.iter().enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        expr.write(out, indent, 2);
+    }
+}
+
+fn write_stmts(out: &mut String, indent: usize, stmts: &[Stmt]) {
+    for stmt in stmts.iter() {
+        stmt.write(out, indent);
+    }
+}
+
+// Prints `{ ... }` - the opening brace on the current line, the closing one indented.
+fn write_block(out: &mut String, indent: usize, stmts: &[Stmt]) {
+    out.push_str("{\n");
+    write_stmts(out, indent + 1, stmts);
+    out.push_str(&INDENT.repeat(indent));
+    out.push('}');
+}
+
+#[derive(Debug, Clone)]
+pub struct JsFunction {
+    pub function_id: u32,
+    pub name: String,
+    pub kind: ClosureKind,
+    pub params: Vec<String>,
+    pub body: Vec<Stmt>,
+}
+
+impl JsFunction {
+    fn write(&self, out: &mut String, indent: usize) {
+        match self.kind {
+            ClosureKind::Async => out.push_str("async function"),
+            ClosureKind::Generator | ClosureKind::GeneratorObject => out.push_str("function*"),
+            ClosureKind::Function => out.push_str("function"),
+        }
+        if is_identifier(&self.name) {
+            out.push(' ');
+            out.push_str(&self.name);
+        }
+        out.push('(');
+        out.push_str(&self.params.join(", "));
+        out.push_str(") ");
+        out.push_str("{\n");
+        let comment = if is_identifier(&self.name) {
+            format!("// #{}", self.function_id)
+        } else {
+            format!("// #{} {:?}", self.function_id, self.name)
+        };
+        out.push_str(&format!("{}{}\n", INDENT.repeat(indent + 1), comment));
+        write_stmts(out, indent + 1, &self.body);
+        out.push_str(&INDENT.repeat(indent));
+        out.push('}');
+    }
+}
+
+impl fmt::Display for JsFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut out = String::new();
+        self.write(&mut out, 0);
+        writeln!(f, "{}", out)
+    }
+}
+
+pub fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c.is_alphabetic() || c == '_' || c == '$' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_alphanumeric() || c == '_' || c == '$') && !is_reserved(s)
+}
+
+fn is_reserved(s: &str) -> bool {
+    matches!(
+        s,
+        "break"
+            | "case"
+            | "catch"
+            | "class"
+            | "const"
+            | "continue"
+            | "debugger"
+            | "default"
+            | "delete"
+            | "do"
+            | "else"
+            | "export"
+            | "extends"
+            | "finally"
+            | "for"
+            | "function"
+            | "if"
+            | "import"
+            | "in"
+            | "instanceof"
+            | "new"
+            | "return"
+            | "super"
+            | "switch"
+            | "this"
+            | "throw"
+            | "try"
+            | "typeof"
+            | "var"
+            | "void"
+            | "while"
+            | "with"
+            | "yield"
+            | "let"
+            | "static"
+            | "enum"
+            | "await"
+            | "null"
+            | "true"
+            | "false"
+    )
+}
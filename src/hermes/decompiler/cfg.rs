@@ -0,0 +1,193 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::hermes::ir::IrFunction;
+
+/*
+ * Dominators, post-dominators and natural loops of an IR function, indexed by
+ * block ID. Exception edges (try block -> handler) are treated as regular edges
+ * so handlers are dominated by their try blocks and post-dominance accounts for
+ * the catch path.
+ */
+pub struct ControlFlow {
+    pub succs: Vec<Vec<usize>>,
+    pub preds: Vec<Vec<usize>>,
+    pub idom: Vec<Option<usize>>,
+    /// Immediate post-dominator - `None` when it's the function exit, or when
+    /// the block never reaches an exit.
+    pub ipdom: Vec<Option<usize>>,
+    /// Loop header -> blocks in the loop (including the header).
+    pub loops: HashMap<usize, BTreeSet<usize>>,
+}
+
+impl ControlFlow {
+    pub fn new(ir: &IrFunction) -> Self {
+        let n = ir.blocks.len();
+
+        // Successors along terminator edges, and along terminator + exception edges
+        let succs: Vec<Vec<usize>> = ir
+            .blocks
+            .iter()
+            .map(|b| {
+                b.terminator
+                    .successors()
+                    .iter()
+                    .map(|s| s.0 as usize)
+                    .collect()
+            })
+            .collect();
+        let mut all_succs = succs.clone();
+        for (i, b) in ir.blocks.iter().enumerate() {
+            if let Some(h) = b.handler {
+                if !all_succs[i].contains(&(h.0 as usize)) {
+                    all_succs[i].push(h.0 as usize);
+                }
+            }
+        }
+
+        let mut preds = vec![vec![]; n];
+        for (i, s) in all_succs.iter().enumerate() {
+            for t in s.iter() {
+                if !preds[*t].contains(&i) {
+                    preds[*t].push(i);
+                }
+            }
+        }
+
+        let idom = if n > 0 {
+            dominators(n, 0, &all_succs, &preds)
+        } else {
+            vec![]
+        };
+
+        // Post-dominators are the dominators of the reversed graph, rooted at a
+        // virtual exit node that every exiting block flows into.
+        let exit = n;
+        let mut rev_succs = preds.clone();
+        rev_succs.push((0..n).filter(|b| all_succs[*b].is_empty()).collect());
+        let mut rev_preds = all_succs.clone();
+        for (i, s) in rev_preds.iter_mut().enumerate() {
+            if all_succs[i].is_empty() {
+                s.push(exit);
+            }
+        }
+        rev_preds.push(vec![]);
+        let ipdom = dominators(n + 1, exit, &rev_succs, &rev_preds)
+            .into_iter()
+            .take(n)
+            .map(|d| d.filter(|d| *d != exit))
+            .collect();
+
+        let mut cfg = ControlFlow {
+            succs,
+            preds,
+            idom,
+            ipdom,
+            loops: HashMap::new(),
+        };
+        cfg.find_loops();
+        cfg
+    }
+
+    pub fn dominates(&self, a: usize, b: usize) -> bool {
+        let mut current = b;
+        loop {
+            if current == a {
+                return true;
+            }
+            match self.idom[current] {
+                Some(d) if d != current => current = d,
+                _ => return false,
+            }
+        }
+    }
+
+    // A back edge u -> h is one where h dominates u. The loop is h plus every
+    // block that reaches u without going through h.
+    fn find_loops(&mut self) {
+        for u in 0..self.succs.len() {
+            for h in self.succs[u].clone() {
+                if self.idom[u].is_none() || !self.dominates(h, u) {
+                    continue;
+                }
+                let mut body: BTreeSet<usize> = BTreeSet::new();
+                body.insert(h);
+                let mut stack = vec![u];
+                while let Some(b) = stack.pop() {
+                    if body.insert(b) {
+                        stack.extend(self.preds[b].iter().copied());
+                    }
+                }
+                self.loops.entry(h).or_default().extend(body);
+            }
+        }
+    }
+}
+
+// "A Simple, Fast Dominance Algorithm" (Cooper, Harvey, Kennedy)
+fn dominators(
+    n: usize,
+    entry: usize,
+    succs: &[Vec<usize>],
+    preds: &[Vec<usize>],
+) -> Vec<Option<usize>> {
+    // Reverse post-order
+    let mut order = vec![];
+    let mut visited = vec![false; n];
+    let mut stack: Vec<(usize, usize)> = vec![(entry, 0)];
+    visited[entry] = true;
+    while let Some((node, child)) = stack.pop() {
+        if child < succs[node].len() {
+            stack.push((node, child + 1));
+            let next = succs[node][child];
+            if !visited[next] {
+                visited[next] = true;
+                stack.push((next, 0));
+            }
+        } else {
+            order.push(node);
+        }
+    }
+    order.reverse();
+
+    let mut index = vec![usize::MAX; n];
+    for (i, b) in order.iter().enumerate() {
+        index[*b] = i;
+    }
+
+    let mut idom: Vec<Option<usize>> = vec![None; n];
+    idom[entry] = Some(entry);
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for b in order.iter().skip(1) {
+            let mut new_idom: Option<usize> = None;
+            for p in preds[*b].iter() {
+                if idom[*p].is_none() {
+                    continue;
+                }
+                new_idom = Some(match new_idom {
+                    None => *p,
+                    Some(current) => {
+                        let (mut f1, mut f2) = (*p, current);
+                        while f1 != f2 {
+                            while index[f1] > index[f2] {
+                                f1 = idom[f1].unwrap();
+                            }
+                            while index[f2] > index[f1] {
+                                f2 = idom[f2].unwrap();
+                            }
+                        }
+                        f1
+                    }
+                });
+            }
+            if new_idom.is_some() && idom[*b] != new_idom {
+                idom[*b] = new_idom;
+                changed = true;
+            }
+        }
+    }
+
+    idom
+}
@@ -0,0 +1,85 @@
+pub mod ast;
+pub mod cfg;
+pub mod structurer;
+pub mod values;
+
+use std::collections::HashMap;
+use std::io;
+
+use crate::hermes::decompiler::ast::JsFunction;
+use crate::hermes::decompiler::structurer::Structurer;
+use crate::hermes::decompiler::values::Values;
+use crate::hermes::hermes_file::HermesFile;
+use crate::hermes::ir::{ClosureKind, IrOp};
+
+/*
+ * Decompiles functions into JavaScript-like pseudocode.
+ *
+ * Each function is lifted to the IR (see hermes::ir), its control flow is
+ * structured into if/else, loops, switch and try/catch, and SSA values are
+ * turned back into expressions and variables. Closures created by a function
+ * are decompiled along with it and printed inline as function expressions.
+ *
+ * The output is meant for reading - it's close to the original source but
+ * isn't guaranteed to be valid JavaScript (environments show up as `$envN`,
+ * unstructured flow as `goto`, VM-only instructions as `__Name(...)` calls).
+ */
+
+impl<R> HermesFile<R>
+where
+    R: io::Read + io::BufRead + io::Seek,
+{
+    /*
+     * Decompiles a function, including every closure it creates.
+     */
+    pub fn decompile_function(&mut self, idx: usize) -> JsFunction {
+        let mut stack = vec![];
+        decompile(self, idx as u32, ClosureKind::Function, &mut stack)
+    }
+}
+
+fn decompile<R>(
+    file: &mut HermesFile<R>,
+    id: u32,
+    kind: ClosureKind,
+    stack: &mut Vec<u32>,
+) -> JsFunction
+where
+    R: io::Read + io::BufRead + io::Seek,
+{
+    let ir = file.lift_function(id as usize);
+
+    // Decompile the closures first so they can be inlined where they're created
+    stack.push(id);
+    let mut closures: HashMap<u32, JsFunction> = HashMap::new();
+    let mut function_names: HashMap<u32, String> = HashMap::new();
+    for ins in ir.blocks.iter().flat_map(|b| b.instructions.iter()) {
+        let (function, closure_kind) = match ins.op {
+            IrOp::CreateClosure { function, kind, .. } => (function, Some(kind)),
+            IrOp::CallDirect { function, .. } => (function, None),
+            _ => continue,
+        };
+        if function as usize >= file.function_headers.len() {
+            continue;
+        }
+        function_names.insert(function, file.get_function_name(function as usize));
+        if let Some(closure_kind) = closure_kind {
+            if !stack.contains(&function) && !closures.contains_key(&function) {
+                let closure = decompile(file, function, closure_kind, stack);
+                closures.insert(function, closure);
+            }
+        }
+    }
+    stack.pop();
+
+    let values = Values::new(&ir, closures, function_names);
+    let body = Structurer::new(&ir, values).structure();
+
+    JsFunction {
+        function_id: id,
+        name: ir.name.clone(),
+        kind,
+        params: (1..ir.param_count).map(|i| format!("a{}", i - 1)).collect(),
+        body,
+    }
+}
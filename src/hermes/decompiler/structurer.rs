@@ -0,0 +1,991 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::rc::Rc;
+
+use crate::hermes::decompiler::ast::{ends_terminal, Expr, Stmt, SwitchCase};
+use crate::hermes::decompiler::cfg::ControlFlow;
+use crate::hermes::decompiler::values::Values;
+use crate::hermes::ir::{IrFunction, IrOp, IrTerminator, ValueId};
+
+/*
+ * Recovers structured control flow from the CFG of an IR function.
+ *
+ * - loops are natural loops (a back edge to a dominating header). The loop
+ *   exits at the first post-dominator of the header outside the loop; edges
+ *   back to the header become `continue` and edges to the exit `break`.
+ * - two-way branches become `if`/`else` that join at the branch's immediate
+ *   post-dominator, `SwitchImm` a `switch` that does the same.
+ * - a try range becomes `try { ... } catch (e) { ... }`, joining at the first
+ *   post-dominator outside of both.
+ * - `a && b`/`a || b` conditions and values are rebuilt from chains of
+ *   branches through otherwise empty blocks.
+ *
+ * Anything left over (irreducible flow, blocks that are only reached by a
+ * generator resume) is emitted with a `goto`/label so no code is lost.
+ */
+
+#[derive(Clone)]
+struct LoopContext {
+    header: usize,
+    follow: Option<usize>,
+}
+
+#[derive(Clone, Default)]
+struct Context {
+    // Block the current sequence stops at (the join of the enclosing construct)
+    stop: Option<usize>,
+    // Blocks of the innermost loop/try/catch
+    region: Option<Rc<BTreeSet<usize>>>,
+    loops: Vec<LoopContext>,
+    // Inside a `switch` within the innermost loop - `break` needs a label there
+    in_switch: bool,
+    // Handlers of the try statements being emitted
+    tries: Vec<usize>,
+}
+
+struct TryRegion {
+    entry: usize,
+    handler: usize,
+    blocks: Rc<BTreeSet<usize>>,
+}
+
+// `phi = cond && value` / `phi = cond ? a : b` - see `find_logical_values`
+struct LogicalValue {
+    arm: usize,
+    join: usize,
+    phi: ValueId,
+    arm_is_true: bool,
+}
+
+pub struct Structurer<'a> {
+    ir: &'a IrFunction,
+    cfg: ControlFlow,
+    values: Values<'a>,
+    emitted: Vec<bool>,
+    used_labels: HashSet<usize>,
+    goto_targets: HashSet<usize>,
+    tries: Vec<TryRegion>,
+    logical: HashMap<usize, LogicalValue>,
+}
+
+impl<'a> Structurer<'a> {
+    pub fn new(ir: &'a IrFunction, values: Values<'a>) -> Self {
+        let cfg = ControlFlow::new(ir);
+
+        let mut tries = vec![];
+        for handler in ir.exception_handlers.iter() {
+            let start = handler
+                .try_blocks
+                .iter()
+                .map(|b| ir.blocks[b.0 as usize].start_offset)
+                .min()
+                .unwrap_or(0);
+            let end = handler
+                .try_blocks
+                .iter()
+                .map(|b| ir.blocks[b.0 as usize].end_offset)
+                .max()
+                .unwrap_or(0);
+            let blocks: BTreeSet<usize> = ir
+                .blocks
+                .iter()
+                .enumerate()
+                .filter(|(_, b)| b.start_offset >= start && b.start_offset < end)
+                .map(|(i, _)| i)
+                .collect();
+            let entry = match blocks.iter().next() {
+                Some(entry) => *entry,
+                None => continue,
+            };
+            tries.push(TryRegion {
+                entry,
+                handler: handler.handler.0 as usize,
+                blocks: Rc::new(blocks),
+            });
+        }
+
+        let mut structurer = Structurer {
+            ir,
+            emitted: vec![false; ir.blocks.len()],
+            cfg,
+            values,
+            used_labels: HashSet::new(),
+            goto_targets: HashSet::new(),
+            tries,
+            logical: HashMap::new(),
+        };
+        structurer.find_logical_values();
+        structurer
+    }
+
+    pub fn structure(mut self) -> Vec<Stmt> {
+        let mut body = vec![];
+
+        // Phis are assigned on the edges into their block, so declare them up front
+        let phis: Vec<String> = self
+            .ir
+            .blocks
+            .iter()
+            .flat_map(|b| b.phis.iter())
+            .map(|p| self.values.name(p.result))
+            .collect();
+        if !phis.is_empty() {
+            body.push(Stmt::Var(phis.join(", "), None));
+        }
+
+        if self.ir.blocks.is_empty() {
+            return body;
+        }
+
+        let ctx = Context::default();
+        self.seq(0, &ctx, &mut body);
+
+        // Blocks the structured walk never reached
+        for b in 0..self.ir.blocks.len() {
+            if !self.emitted[b] {
+                self.goto_targets.insert(b);
+                self.seq(b, &ctx, &mut body);
+            }
+        }
+
+        self.remove_unused_labels(body)
+    }
+
+    fn label(&self, block: usize) -> String {
+        format!("bb{}", block)
+    }
+
+    fn loop_label(&mut self, header: usize) -> String {
+        self.used_labels.insert(header);
+        format!("loop{}", header)
+    }
+
+    fn remove_unused_labels(&self, stmts: Vec<Stmt>) -> Vec<Stmt> {
+        let targets: HashSet<String> = self.goto_targets.iter().map(|b| self.label(*b)).collect();
+        filter_labels(stmts, &targets)
+    }
+
+    // ---------------------------------------------------------------------- //
+    // Sequences and edges
+    // ---------------------------------------------------------------------- //
+
+    fn seq(&mut self, start: usize, ctx: &Context, out: &mut Vec<Stmt>) {
+        let mut current = Some(start);
+        while let Some(b) = current {
+            current = self.step(b, ctx, out);
+        }
+    }
+
+    // Emits the construct starting at a block and returns where control continues.
+    fn step(&mut self, b: usize, ctx: &Context, out: &mut Vec<Stmt>) -> Option<usize> {
+        if self.emitted[b] && !self.can_duplicate(b) {
+            self.goto_targets.insert(b);
+            out.push(Stmt::Goto(self.label(b)));
+            return None;
+        }
+
+        let loop_here = self.cfg.loops.contains_key(&b) && !ctx.loops.iter().any(|l| l.header == b);
+        if let Some(t) = self.try_at(b, ctx) {
+            let loop_inside = match self.cfg.loops.get(&b) {
+                Some(body) => body.is_subset(&self.tries[t].blocks),
+                None => true,
+            };
+            if !loop_here || loop_inside {
+                return self.emit_try(t, ctx, out);
+            }
+        }
+        if loop_here {
+            return self.emit_loop(b, ctx, out);
+        }
+        self.emit_block(b, ctx, out)
+    }
+
+    // Continues at `target` - unless that leaves the current construct.
+    fn jump(&mut self, target: usize, ctx: &Context, out: &mut Vec<Stmt>) -> Option<usize> {
+        if Some(target) == ctx.stop {
+            return None;
+        }
+        let innermost = ctx.loops.len().saturating_sub(1);
+        for (i, l) in ctx.loops.iter().enumerate().rev() {
+            if l.header == target {
+                let label = if i == innermost {
+                    None
+                } else {
+                    Some(self.loop_label(l.header))
+                };
+                out.push(Stmt::Continue(label));
+                return None;
+            }
+            if l.follow == Some(target) {
+                let label = if i == innermost && !ctx.in_switch {
+                    None
+                } else {
+                    Some(self.loop_label(l.header))
+                };
+                out.push(Stmt::Break(label));
+                return None;
+            }
+        }
+        Some(target)
+    }
+
+    fn edge(
+        &mut self,
+        from: usize,
+        to: usize,
+        ctx: &Context,
+        out: &mut Vec<Stmt>,
+    ) -> Option<usize> {
+        out.extend(self.copies(from, to));
+        self.jump(to, ctx, out)
+    }
+
+    // The statements for one side of a branch, stopping at `join`.
+    fn arm(&mut self, from: usize, to: usize, join: Option<usize>, ctx: &Context) -> Vec<Stmt> {
+        let mut inner = ctx.clone();
+        if join.is_some() {
+            inner.stop = join;
+        }
+        let mut out = vec![];
+        if let Some(next) = self.edge(from, to, &inner, &mut out) {
+            self.seq(next, &inner, &mut out);
+        }
+        out
+    }
+
+    // Where the two sides of a branch in `b` come back together, if that's within the current construct.
+    fn join_point(&self, b: usize, ctx: &Context) -> Option<usize> {
+        let m = self.cfg.ipdom[b]?;
+        if Some(m) == ctx.stop
+            || ctx
+                .loops
+                .iter()
+                .any(|l| l.header == m || l.follow == Some(m))
+        {
+            return Some(m);
+        }
+        match &ctx.region {
+            Some(region) if !region.contains(&m) => None,
+            _ => Some(m),
+        }
+    }
+
+    /*
+     * Assignments for the phis of `to` on the edge from `from`. The copies are
+     * parallel - every source is read before any phi is written - so they're
+     * ordered to respect that, with a temporary to break cycles.
+     */
+    fn copies(&self, from: usize, to: usize) -> Vec<Stmt> {
+        let mut pending: Vec<(String, Expr, HashSet<String>)> = vec![];
+        for phi in self.ir.blocks[to].phis.iter() {
+            for (pred, v) in phi.incoming.iter() {
+                if pred.0 as usize != from || *v == phi.result {
+                    continue;
+                }
+                let reads = self
+                    .values
+                    .reads(*v)
+                    .into_iter()
+                    .map(|r| self.values.name(r))
+                    .collect();
+                pending.push((self.values.name(phi.result), self.values.value(*v), reads));
+            }
+        }
+
+        let mut out = vec![];
+        while !pending.is_empty() {
+            let ready = (0..pending.len()).find(|i| {
+                pending
+                    .iter()
+                    .enumerate()
+                    .all(|(j, p)| j == *i || !p.2.contains(&pending[*i].0))
+            });
+            match ready {
+                Some(i) => {
+                    let (dest, src, _) = pending.remove(i);
+                    out.push(Stmt::Expr(Expr::assign(Expr::atom(dest), src)));
+                }
+                None => {
+                    let tmp = format!("t{}", pending[0].0);
+                    let src = std::mem::replace(&mut pending[0].1, Expr::atom(tmp.clone()));
+                    pending[0].2.clear();
+                    out.push(Stmt::Var(tmp, Some(src)));
+                }
+            }
+        }
+        out
+    }
+
+    // Small blocks that return or throw are repeated rather than jumped to.
+    fn can_duplicate(&self, b: usize) -> bool {
+        let block = &self.ir.blocks[b];
+        block.phis.is_empty()
+            && block.instructions.len() <= 3
+            && matches!(
+                block.terminator,
+                IrTerminator::Return(_) | IrTerminator::Throw(_)
+            )
+            && !self.cfg.loops.contains_key(&b)
+            && !self.tries.iter().any(|t| t.entry == b || t.handler == b)
+    }
+
+    // ---------------------------------------------------------------------- //
+    // Blocks
+    // ---------------------------------------------------------------------- //
+
+    fn emit_block(&mut self, b: usize, ctx: &Context, out: &mut Vec<Stmt>) -> Option<usize> {
+        self.emitted[b] = true;
+        out.push(Stmt::Label(self.label(b)));
+        out.extend(self.values.statements(b));
+
+        // Handler phis take the values at the end of each try block
+        if let Some(handler) = self.ir.blocks[b].handler {
+            out.extend(self.copies(b, handler.0 as usize));
+        }
+
+        match self.ir.blocks[b].terminator.clone() {
+            IrTerminator::Jump(target) => self.edge(b, target.0 as usize, ctx, out),
+            IrTerminator::Branch { .. } => self.emit_if(b, ctx, out),
+            IrTerminator::Switch { .. } => self.emit_switch(b, ctx, out),
+            IrTerminator::Return(v) => {
+                out.push(Stmt::Return(self.values.value(v)));
+                None
+            }
+            IrTerminator::Throw(v) => {
+                out.push(Stmt::Throw(self.values.value(v)));
+                None
+            }
+            IrTerminator::Unreachable => None,
+        }
+    }
+
+    /*
+     * Finds blocks that compute `cond && value`, `cond || value` or
+     * `cond ? a : b`: a branch in `b` where one side is a block that only
+     * computes a value and jumps to the other side, which merges the two
+     * with a single phi.
+     */
+    fn find_logical_values(&mut self) {
+        for b in 0..self.ir.blocks.len() {
+            let (condition, t, f) = match self.ir.blocks[b].terminator {
+                IrTerminator::Branch {
+                    condition,
+                    true_target,
+                    false_target,
+                } => (condition, true_target.0 as usize, false_target.0 as usize),
+                _ => continue,
+            };
+
+            for (arm, join, arm_is_true) in [(t, f, true), (f, t, false)] {
+                let arm_block = &self.ir.blocks[arm];
+                let join_block = &self.ir.blocks[join];
+                if arm == join
+                    || arm == b
+                    || self.cfg.preds[arm] != vec![b]
+                    || !arm_block.phis.is_empty()
+                    || !matches!(arm_block.terminator, IrTerminator::Jump(j) if j.0 as usize == join)
+                    || self.cfg.loops.contains_key(&arm)
+                    || self.cfg.loops.contains_key(&join)
+                    || self
+                        .tries
+                        .iter()
+                        .any(|t| t.entry == arm || t.handler == arm)
+                    || join_block.phis.len() != 1
+                    || self.cfg.preds[join].len() != 2
+                {
+                    continue;
+                }
+                let phi = &join_block.phis[0];
+                let incoming = |from: usize| {
+                    phi.incoming
+                        .iter()
+                        .find(|(p, _)| p.0 as usize == from)
+                        .map(|(_, v)| *v)
+                };
+                let (from_b, from_arm) = match (incoming(b), incoming(arm)) {
+                    (Some(x), Some(y)) => (x, y),
+                    _ => continue,
+                };
+                if !self.values.block_is_expression(arm, from_arm) {
+                    continue;
+                }
+                if from_b == condition
+                    && self.values.defined_in(condition) == Some(b)
+                    && self.values.use_count(condition) == 2
+                    && !self.values.force_inline(condition)
+                {
+                    continue;
+                }
+                self.logical.insert(
+                    b,
+                    LogicalValue {
+                        arm,
+                        join,
+                        phi: phi.result,
+                        arm_is_true,
+                    },
+                );
+                break;
+            }
+        }
+    }
+
+    fn emit_logical_value(
+        &mut self,
+        b: usize,
+        ctx: &Context,
+        out: &mut Vec<Stmt>,
+    ) -> Option<usize> {
+        let logical = self.logical.remove(&b).unwrap();
+        let condition = match self.ir.blocks[b].terminator {
+            IrTerminator::Branch { condition, .. } => condition,
+            _ => unreachable!(),
+        };
+        let phi = self.ir.blocks[logical.join]
+            .phis
+            .iter()
+            .find(|p| p.result == logical.phi)
+            .unwrap();
+        let incoming = |from: usize| {
+            phi.incoming
+                .iter()
+                .find(|(p, _)| p.0 as usize == from)
+                .map(|(_, v)| *v)
+                .unwrap()
+        };
+        let (from_b, from_arm) = (incoming(b), incoming(logical.arm));
+
+        let arm_value = if self.values.defined_in(from_arm) == Some(logical.arm) {
+            self.values.render_definition(from_arm)
+        } else {
+            self.values.value(from_arm)
+        };
+        let cond = self.values.value(condition);
+        let value = if from_b == condition {
+            Expr::binary(
+                if logical.arm_is_true { "&&" } else { "||" },
+                cond,
+                arm_value,
+            )
+        } else {
+            let other = self.values.value(from_b);
+            let (a, b) = if logical.arm_is_true {
+                (arm_value, other)
+            } else {
+                (other, arm_value)
+            };
+            Expr::Conditional(Box::new(cond), Box::new(a), Box::new(b))
+        };
+
+        self.emitted[logical.arm] = true;
+        out.push(Stmt::Expr(Expr::assign(
+            Expr::atom(self.values.name(logical.phi)),
+            value,
+        )));
+        self.jump(logical.join, ctx, out)
+    }
+
+    // A block with nothing but a branch on a value that can be folded into a condition.
+    fn condition_block(&self, b: usize, from: usize) -> Option<(ValueId, usize, usize)> {
+        let block = &self.ir.blocks[b];
+        if self.emitted[b]
+            || self.cfg.preds[b] != vec![from]
+            || !block.phis.is_empty()
+            || self.cfg.loops.contains_key(&b)
+            || self.logical.contains_key(&b)
+            || self.tries.iter().any(|t| t.entry == b || t.handler == b)
+            || !self.values.statements(b).is_empty()
+        {
+            return None;
+        }
+        match block.terminator {
+            IrTerminator::Branch {
+                condition,
+                true_target,
+                false_target,
+            } => Some((condition, true_target.0 as usize, false_target.0 as usize)),
+            _ => None,
+        }
+    }
+
+    fn emit_if(&mut self, b: usize, ctx: &Context, out: &mut Vec<Stmt>) -> Option<usize> {
+        if self.logical.contains_key(&b) {
+            return self.emit_logical_value(b, ctx, out);
+        }
+
+        let (condition, t, f) = match self.ir.blocks[b].terminator {
+            IrTerminator::Branch {
+                condition,
+                true_target,
+                false_target,
+            } => (condition, true_target.0 as usize, false_target.0 as usize),
+            _ => unreachable!(),
+        };
+        let mut cond = self.values.value(condition);
+        let (mut t, mut f) = (t, f);
+        let (mut t_from, mut f_from) = (b, b);
+
+        // Fold `if (a) { if (b) X else Y } else Y` into `if (a && b) X else Y` (and the `||` forms)
+        loop {
+            if let Some((c2, t2, f2)) = self.condition_block(t, t_from) {
+                if self.ir.blocks[f].phis.is_empty() && (f2 == f || t2 == f) {
+                    let c2 = self.values.value(c2);
+                    self.emitted[t] = true;
+                    t_from = t;
+                    if f2 == f {
+                        cond = Expr::binary("&&", cond, c2);
+                        t = t2;
+                    } else {
+                        cond = Expr::binary("&&", cond, c2.negate());
+                        t = f2;
+                    }
+                    continue;
+                }
+            }
+            if let Some((c2, t2, f2)) = self.condition_block(f, f_from) {
+                if self.ir.blocks[t].phis.is_empty() && (t2 == t || f2 == t) {
+                    let c2 = self.values.value(c2);
+                    self.emitted[f] = true;
+                    f_from = f;
+                    if t2 == t {
+                        cond = Expr::binary("||", cond, c2);
+                        f = f2;
+                    } else {
+                        cond = Expr::binary("||", cond, c2.negate());
+                        f = t2;
+                    }
+                    continue;
+                }
+            }
+            break;
+        }
+
+        let join = self.join_point(b, ctx);
+        let then = self.arm(t_from, t, join, ctx);
+        let otherwise = self.arm(f_from, f, join, ctx);
+
+        match (then.is_empty(), otherwise.is_empty()) {
+            (true, true) => {
+                if !matches!(cond, Expr::Atom(_)) {
+                    out.push(Stmt::Expr(cond));
+                }
+            }
+            (true, false) => out.push(Stmt::If {
+                condition: cond.negate(),
+                then: otherwise,
+                otherwise: vec![],
+            }),
+            _ => out.push(Stmt::If {
+                condition: cond,
+                then,
+                otherwise,
+            }),
+        }
+
+        join.and_then(|j| self.jump(j, ctx, out))
+    }
+
+    fn emit_switch(&mut self, b: usize, ctx: &Context, out: &mut Vec<Stmt>) -> Option<usize> {
+        let (value, cases, default) = match &self.ir.blocks[b].terminator {
+            IrTerminator::Switch {
+                value,
+                cases,
+                default,
+            } => (*value, cases.clone(), default.0 as usize),
+            _ => unreachable!(),
+        };
+
+        let join = self.join_point(b, ctx);
+        let mut inner = ctx.clone();
+        inner.in_switch = !ctx.loops.is_empty();
+
+        // Group the case values by target, keeping the order of first appearance
+        let mut groups: Vec<(usize, Vec<i64>)> = vec![];
+        for (v, target) in cases.iter() {
+            let target = target.0 as usize;
+            if target == default {
+                continue;
+            }
+            match groups.iter_mut().find(|(t, _)| *t == target) {
+                Some((_, values)) => values.push(*v),
+                None => groups.push((target, vec![*v])),
+            }
+        }
+
+        let mut switch_cases = vec![];
+        for (target, values) in groups {
+            let mut body = self.arm(b, target, join, &inner);
+            if !ends_terminal(&body) {
+                body.push(Stmt::Break(None));
+            }
+            switch_cases.push(SwitchCase { values, body });
+        }
+        let default_body = if Some(default) == join {
+            None
+        } else {
+            Some(self.arm(b, default, join, &inner))
+        };
+
+        out.push(Stmt::Switch {
+            value: self.values.value(value),
+            cases: switch_cases,
+            default: default_body,
+        });
+
+        join.and_then(|j| self.jump(j, ctx, out))
+    }
+
+    // ---------------------------------------------------------------------- //
+    // Loops
+    // ---------------------------------------------------------------------- //
+
+    fn loop_follow(&self, header: usize) -> Option<usize> {
+        let body = &self.cfg.loops[&header];
+
+        let mut exits: BTreeSet<usize> = BTreeSet::new();
+        for b in body.iter() {
+            for s in self.cfg.succs[*b].iter() {
+                if !body.contains(s) {
+                    exits.insert(*s);
+                }
+            }
+        }
+        if exits.len() == 1 {
+            return exits.into_iter().next();
+        }
+
+        let mut m = self.cfg.ipdom[header];
+        while let Some(b) = m {
+            if !body.contains(&b) {
+                return Some(b);
+            }
+            m = self.cfg.ipdom[b];
+        }
+        exits.into_iter().next()
+    }
+
+    fn emit_loop(&mut self, header: usize, ctx: &Context, out: &mut Vec<Stmt>) -> Option<usize> {
+        let follow = self.loop_follow(header);
+        let mut inner = ctx.clone();
+        inner.loops.push(LoopContext { header, follow });
+        inner.stop = None;
+        inner.region = Some(Rc::new(self.cfg.loops[&header].clone()));
+        inner.in_switch = false;
+
+        let mut body = vec![];
+        self.seq(header, &inner, &mut body);
+
+        let label = if self.used_labels.contains(&header) {
+            Some(format!("loop{}", header))
+        } else {
+            None
+        };
+        let header_phis: HashSet<String> = self.ir.blocks[header]
+            .phis
+            .iter()
+            .map(|p| self.values.name(p.result))
+            .collect();
+        let stmt = build_loop(body, label, &header_phis, out);
+        out.push(stmt);
+
+        follow.and_then(|f| self.jump(f, ctx, out))
+    }
+
+    // ---------------------------------------------------------------------- //
+    // Exception handlers
+    // ---------------------------------------------------------------------- //
+
+    fn try_at(&self, b: usize, ctx: &Context) -> Option<usize> {
+        self.tries
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| t.entry == b && !ctx.tries.contains(&t.handler))
+            .max_by_key(|(_, t)| t.blocks.len())
+            .map(|(i, _)| i)
+    }
+
+    fn emit_try(&mut self, t: usize, ctx: &Context, out: &mut Vec<Stmt>) -> Option<usize> {
+        let (entry, handler, blocks) = {
+            let region = &self.tries[t];
+            (region.entry, region.handler, region.blocks.clone())
+        };
+
+        let catch_body: BTreeSet<usize> = (0..self.ir.blocks.len())
+            .filter(|b| self.cfg.idom[*b].is_some() && self.cfg.dominates(handler, *b))
+            .collect();
+
+        // Continue at the first post-dominator outside both the try and the catch
+        let mut exit = self.cfg.ipdom[entry];
+        while let Some(b) = exit {
+            if !blocks.contains(&b) && !catch_body.contains(&b) {
+                break;
+            }
+            exit = self.cfg.ipdom[b];
+        }
+        if exit.is_none() {
+            exit = ctx.stop;
+        }
+
+        let mut try_ctx = ctx.clone();
+        try_ctx.stop = exit;
+        try_ctx.region = Some(blocks);
+        try_ctx.tries.push(handler);
+        let mut body = vec![];
+        self.seq(entry, &try_ctx, &mut body);
+
+        let catch_var = self.ir.blocks[handler]
+            .instructions
+            .iter()
+            .find(|ins| matches!(ins.op, IrOp::Catch))
+            .and_then(|ins| ins.result)
+            .map(|v| self.values.name(v));
+
+        let mut catch_ctx = ctx.clone();
+        catch_ctx.stop = exit;
+        catch_ctx.region = Some(Rc::new(catch_body));
+        let mut handler_body = vec![];
+        if !self.emitted[handler] {
+            self.seq(handler, &catch_ctx, &mut handler_body);
+        }
+
+        out.push(Stmt::Try {
+            body,
+            catch_var,
+            handler: handler_body,
+        });
+
+        exit.and_then(|e| self.jump(e, ctx, out))
+    }
+}
+
+/*
+ * Builds the loop statement from its body. The body always starts with the
+ * header, so `if (!cond) break;` or `if (cond) { ...; continue; } break;` at
+ * the top turns into `while (cond)`, and assignments to the header's phis at
+ * the end of the body (and right before the loop) into a `for`.
+ */
+fn build_loop(
+    mut body: Vec<Stmt>,
+    label: Option<String>,
+    header_phis: &HashSet<String>,
+    before: &mut Vec<Stmt>,
+) -> Stmt {
+    strip_trailing_continue(&mut body);
+
+    let first = body.iter().position(|s| !matches!(s, Stmt::Label(_)));
+    let mut condition = None;
+    if let Some(k) = first {
+        match &body[k..] {
+            [Stmt::If {
+                condition: c,
+                then,
+                otherwise,
+            }, ..]
+                if is_plain_break(otherwise) =>
+            {
+                condition = Some(c.clone());
+                let mut rest = then.clone();
+                rest.extend(body[k + 1..].iter().cloned());
+                body = rest;
+            }
+            [Stmt::If {
+                condition: c,
+                then,
+                otherwise,
+            }, ..]
+                if is_plain_break(then) && otherwise.is_empty() =>
+            {
+                condition = Some(c.clone().negate());
+                body = body[k + 1..].to_vec();
+            }
+            [Stmt::If {
+                condition: c,
+                then,
+                otherwise,
+            }, Stmt::Break(None)]
+                if otherwise.is_empty() && matches!(then.last(), Some(Stmt::Continue(None))) =>
+            {
+                condition = Some(c.clone());
+                body = then.clone();
+            }
+            _ => {}
+        }
+    }
+
+    let condition = match condition {
+        Some(condition) => condition,
+        None => {
+            return Stmt::While {
+                label,
+                condition: Expr::atom("true"),
+                body,
+            }
+        }
+    };
+    strip_trailing_continue(&mut body);
+
+    // Trailing phi assignments become the update, unless a `continue` would skip them
+    let mut update = vec![];
+    if !contains_continue(&body, &label) {
+        while let Some(Stmt::Expr(Expr::Assign(target, _))) = body.last() {
+            if !matches!(&**target, Expr::Atom(name) if header_phis.contains(name)) {
+                break;
+            }
+            if let Some(Stmt::Expr(assign)) = body.pop() {
+                update.insert(0, assign);
+            }
+        }
+    }
+    if update.is_empty() {
+        return Stmt::While {
+            label,
+            condition,
+            body,
+        };
+    }
+
+    let mut init = vec![];
+    while let Some(Stmt::Expr(Expr::Assign(target, _))) = before.last() {
+        if !matches!(&**target, Expr::Atom(name) if header_phis.contains(name)) {
+            break;
+        }
+        if let Some(Stmt::Expr(assign)) = before.pop() {
+            init.insert(0, assign);
+        }
+    }
+
+    Stmt::For {
+        label,
+        init,
+        condition,
+        update,
+        body,
+    }
+}
+
+fn is_plain_break(stmts: &[Stmt]) -> bool {
+    matches!(stmts, [Stmt::Break(None)])
+}
+
+fn strip_trailing_continue(body: &mut Vec<Stmt>) {
+    while matches!(body.last(), Some(Stmt::Continue(None))) {
+        body.pop();
+    }
+}
+
+// Whether a `continue` in these statements targets the loop they're the body of.
+fn contains_continue(stmts: &[Stmt], label: &Option<String>) -> bool {
+    stmts.iter().any(|s| match s {
+        Stmt::Continue(None) => true,
+        Stmt::Continue(l) => l == label,
+        Stmt::If {
+            then, otherwise, ..
+        } => contains_continue(then, label) || contains_continue(otherwise, label),
+        Stmt::Switch { cases, default, .. } => {
+            cases.iter().any(|c| contains_continue(&c.body, label))
+                || default
+                    .as_ref()
+                    .map(|d| contains_continue(d, label))
+                    .unwrap_or(false)
+        }
+        Stmt::Try { body, handler, .. } => {
+            contains_continue(body, label) || contains_continue(handler, label)
+        }
+        // Unlabelled continues in nested loops belong to them
+        Stmt::While { body, .. } | Stmt::For { body, .. } => {
+            label.is_some() && contains_labelled_continue(body, label)
+        }
+        _ => false,
+    })
+}
+
+fn contains_labelled_continue(stmts: &[Stmt], label: &Option<String>) -> bool {
+    stmts.iter().any(|s| match s {
+        Stmt::Continue(l) => l.is_some() && l == label,
+        Stmt::If {
+            then, otherwise, ..
+        } => {
+            contains_labelled_continue(then, label) || contains_labelled_continue(otherwise, label)
+        }
+        Stmt::Switch { cases, default, .. } => {
+            cases
+                .iter()
+                .any(|c| contains_labelled_continue(&c.body, label))
+                || default
+                    .as_ref()
+                    .map(|d| contains_labelled_continue(d, label))
+                    .unwrap_or(false)
+        }
+        Stmt::Try { body, handler, .. } => {
+            contains_labelled_continue(body, label) || contains_labelled_continue(handler, label)
+        }
+        Stmt::While { body, .. } | Stmt::For { body, .. } => {
+            contains_labelled_continue(body, label)
+        }
+        _ => false,
+    })
+}
+
+fn filter_labels(stmts: Vec<Stmt>, targets: &HashSet<String>) -> Vec<Stmt> {
+    stmts
+        .into_iter()
+        .filter(|s| !matches!(s, Stmt::Label(l) if !targets.contains(l)))
+        .map(|s| match s {
+            Stmt::If {
+                condition,
+                then,
+                otherwise,
+            } => Stmt::If {
+                condition,
+                then: filter_labels(then, targets),
+                otherwise: filter_labels(otherwise, targets),
+            },
+            Stmt::While {
+                label,
+                condition,
+                body,
+            } => Stmt::While {
+                label,
+                condition,
+                body: filter_labels(body, targets),
+            },
+            Stmt::For {
+                label,
+                init,
+                condition,
+                update,
+                body,
+            } => Stmt::For {
+                label,
+                init,
+                condition,
+                update,
+                body: filter_labels(body, targets),
+            },
+            Stmt::Switch {
+                value,
+                cases,
+                default,
+            } => Stmt::Switch {
+                value,
+                cases: cases
+                    .into_iter()
+                    .map(|c| SwitchCase {
+                        values: c.values,
+                        body: filter_labels(c.body, targets),
+                    })
+                    .collect(),
+                default: default.map(|d| filter_labels(d, targets)),
+            },
+            Stmt::Try {
+                body,
+                catch_var,
+                handler,
+            } => Stmt::Try {
+                body: filter_labels(body, targets),
+                catch_var,
+                handler: filter_labels(handler, targets),
+            },
+            other => other,
+        })
+        .collect()
+}
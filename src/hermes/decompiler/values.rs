@@ -0,0 +1,523 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::hermes::decompiler::ast::{is_identifier, Expr, JsFunction, Stmt};
+use crate::hermes::ir::{
+    BinaryOp, Builtin, Constant, IrFunction, IrInstruction, IrOp, PropertyKey, UnaryOp, ValueId,
+};
+
+/*
+ * Turns SSA values into expressions.
+ *
+ * A value that's used exactly once, in the same block, is folded into the
+ * expression that uses it as long as that doesn't reorder it with another
+ * side effect. Constants, parameters and other trivial values are folded into
+ * every use. Everything else gets a `var vN` - phis included, they're assigned
+ * on the edges into their block.
+ */
+
+#[derive(Clone, Copy, PartialEq)]
+enum UseSite {
+    Instruction(usize, usize),
+    Terminator(usize),
+    Phi { block: usize, from: usize },
+}
+
+pub struct Values<'a> {
+    ir: &'a IrFunction,
+    defs: HashMap<ValueId, (usize, usize)>,
+    uses: HashMap<ValueId, Vec<UseSite>>,
+    inlined: HashSet<ValueId>,
+    pure: HashSet<ValueId>,
+    closures: HashMap<u32, JsFunction>,
+    function_names: HashMap<u32, String>,
+}
+
+impl<'a> Values<'a> {
+    pub fn new(
+        ir: &'a IrFunction,
+        closures: HashMap<u32, JsFunction>,
+        function_names: HashMap<u32, String>,
+    ) -> Self {
+        let mut defs = HashMap::new();
+        let mut uses: HashMap<ValueId, Vec<UseSite>> = HashMap::new();
+
+        for (b, block) in ir.blocks.iter().enumerate() {
+            for phi in block.phis.iter() {
+                for (from, v) in phi.incoming.iter() {
+                    uses.entry(*v).or_default().push(UseSite::Phi {
+                        block: b,
+                        from: from.0 as usize,
+                    });
+                }
+            }
+            for (i, ins) in block.instructions.iter().enumerate() {
+                if let Some(v) = ins.result {
+                    defs.insert(v, (b, i));
+                }
+                for v in ins.op.uses() {
+                    uses.entry(v).or_default().push(UseSite::Instruction(b, i));
+                }
+            }
+            for v in block.terminator.uses() {
+                uses.entry(v).or_default().push(UseSite::Terminator(b));
+            }
+        }
+
+        let mut values = Values {
+            ir,
+            defs,
+            uses,
+            inlined: HashSet::new(),
+            pure: HashSet::new(),
+            closures,
+            function_names,
+        };
+        values.find_inlined();
+        values
+    }
+
+    fn find_inlined(&mut self) {
+        for (b, block) in self.ir.blocks.iter().enumerate() {
+            for (i, ins) in block.instructions.iter().enumerate() {
+                let v = match ins.result {
+                    Some(v) => v,
+                    None => continue,
+                };
+                if matches!(ins.op, IrOp::Catch) {
+                    continue;
+                }
+
+                let pure = is_pure(&ins.op)
+                    && ins
+                        .op
+                        .uses()
+                        .iter()
+                        .all(|u| !self.inlined.contains(u) || self.pure.contains(u));
+                if pure {
+                    self.pure.insert(v);
+                }
+
+                if is_leaf(&ins.op) {
+                    self.inlined.insert(v);
+                    continue;
+                }
+
+                let sites = self.uses.get(&v).cloned().unwrap_or_default();
+                if sites.len() != 1 {
+                    continue;
+                }
+                let position = match sites[0] {
+                    // A closure's environment isn't printed, so it can't hide a side effect
+                    UseSite::Instruction(ub, j)
+                        if !pure
+                            && matches!(
+                                self.ir.blocks[ub].instructions[j].op,
+                                IrOp::CreateClosure { .. }
+                            ) =>
+                    {
+                        continue
+                    }
+                    UseSite::Instruction(ub, j) if ub == b && j > i => j,
+                    UseSite::Terminator(ub) if ub == b => block.instructions.len(),
+                    // Only pure values are moved into the copies at the end of the block
+                    UseSite::Phi { from, .. } if from == b && pure => {
+                        self.inlined.insert(v);
+                        continue;
+                    }
+                    _ => continue,
+                };
+
+                if pure || !self.has_side_effects(b, i + 1, position) {
+                    self.inlined.insert(v);
+                }
+            }
+        }
+    }
+
+    // Whether any instruction in [start, end) of a block might have side effects.
+    fn has_side_effects(&self, block: usize, start: usize, end: usize) -> bool {
+        self.ir.blocks[block].instructions[start..end]
+            .iter()
+            .any(|ins| !is_pure(&ins.op))
+    }
+
+    /*
+     * Folds a value into its only use even though it's used twice - the
+     * condition of a short-circuit expression is also the value of the phi.
+     */
+    pub fn force_inline(&mut self, v: ValueId) -> bool {
+        let (b, i) = match self.defs.get(&v) {
+            Some(def) => *def,
+            None => return false,
+        };
+        let len = self.ir.blocks[b].instructions.len();
+        if self.inlined.contains(&v) {
+            return true;
+        }
+        if self.pure.contains(&v) || !self.has_side_effects(b, i + 1, len) {
+            self.inlined.insert(v);
+            return true;
+        }
+        false
+    }
+
+    pub fn use_count(&self, v: ValueId) -> usize {
+        self.uses.get(&v).map(|u| u.len()).unwrap_or(0)
+    }
+
+    pub fn defined_in(&self, v: ValueId) -> Option<usize> {
+        self.defs.get(&v).map(|(b, _)| *b)
+    }
+
+    pub fn name(&self, v: ValueId) -> String {
+        format!("v{}", v.0)
+    }
+
+    fn definition(&self, v: ValueId) -> Option<&'a IrInstruction> {
+        let (b, i) = self.defs.get(&v)?;
+        Some(&self.ir.blocks[*b].instructions[*i])
+    }
+
+    fn op(&self, v: ValueId) -> Option<&'a IrOp> {
+        self.definition(v).map(|ins| &ins.op)
+    }
+
+    pub fn value(&self, v: ValueId) -> Expr {
+        if self.inlined.contains(&v) {
+            self.render_definition(v)
+        } else {
+            Expr::atom(self.name(v))
+        }
+    }
+
+    /// Renders the expression that defines a value, regardless of whether it's inlined.
+    pub fn render_definition(&self, v: ValueId) -> Expr {
+        match self.op(v) {
+            Some(op) => self.render(op),
+            None => Expr::atom(self.name(v)),
+        }
+    }
+
+    /// Values read by a value's expression that aren't folded into it.
+    pub fn reads(&self, v: ValueId) -> HashSet<ValueId> {
+        let mut out = HashSet::new();
+        let mut stack = vec![v];
+        while let Some(v) = stack.pop() {
+            if !self.inlined.contains(&v) {
+                out.insert(v);
+                continue;
+            }
+            if let Some(op) = self.op(v) {
+                stack.extend(op.uses());
+            }
+        }
+        out
+    }
+
+    /*
+     * Whether a block computes nothing but `value` (plus values folded into it),
+     * so it can be turned into an expression.
+     */
+    pub fn block_is_expression(&self, block: usize, value: ValueId) -> bool {
+        self.ir.blocks[block]
+            .instructions
+            .iter()
+            .all(|ins| match ins.result {
+                Some(v) if v == value => self.use_count(v) == 1,
+                Some(v) => self.inlined.contains(&v) && !matches!(ins.op, IrOp::Catch),
+                None => false,
+            })
+    }
+
+    /// The statements of a block, minus the terminator.
+    pub fn statements(&self, block: usize) -> Vec<Stmt> {
+        let mut out = vec![];
+        for ins in self.ir.blocks[block].instructions.iter() {
+            if let Some(stmt) = self.statement(ins) {
+                out.push(stmt);
+            }
+        }
+        out
+    }
+
+    fn statement(&self, ins: &IrInstruction) -> Option<Stmt> {
+        if let Some(v) = ins.result {
+            if self.inlined.contains(&v) || matches!(ins.op, IrOp::Catch) {
+                return None;
+            }
+            if self.use_count(v) > 0 {
+                return Some(Stmt::Var(self.name(v), Some(self.render(&ins.op))));
+            }
+            if is_pure(&ins.op) {
+                return None;
+            }
+            return Some(Stmt::Expr(self.render(&ins.op)));
+        }
+
+        Some(match &ins.op {
+            IrOp::PutProperty {
+                object, key, value, ..
+            } => Stmt::Expr(Expr::assign(self.member(*object, key), self.value(*value))),
+            IrOp::StoreEnv { env, slot, value } => Stmt::Expr(Expr::assign(
+                Expr::Index(
+                    Box::new(self.value(*env)),
+                    Box::new(Expr::atom(slot.to_string())),
+                ),
+                self.value(*value),
+            )),
+            IrOp::DeclareGlobalVar(name) if is_identifier(name) => Stmt::Var(name.clone(), None),
+            op => Stmt::Expr(self.render(op)),
+        })
+    }
+
+    fn is_global_object(&self, v: ValueId) -> bool {
+        matches!(self.op(v), Some(IrOp::GetGlobalObject))
+    }
+
+    fn is_undefined(&self, v: ValueId) -> bool {
+        matches!(self.op(v), Some(IrOp::Const(Constant::Undefined)))
+    }
+
+    fn member(&self, object: ValueId, key: &PropertyKey) -> Expr {
+        if let PropertyKey::Named(name) = key {
+            if self.is_global_object(object) && is_identifier(name) {
+                return Expr::atom(name.clone());
+            }
+        }
+        let object = Box::new(self.value(object));
+        match key {
+            PropertyKey::Named(name) if is_identifier(name) => Expr::Member(object, name.clone()),
+            PropertyKey::Named(name) => Expr::Index(object, Box::new(Expr::atom(quote(name)))),
+            PropertyKey::Index(idx) => Expr::Index(object, Box::new(Expr::atom(idx.to_string()))),
+            PropertyKey::Computed(v) => Expr::Index(object, Box::new(self.value(*v))),
+        }
+    }
+
+    fn args(&self, args: &[ValueId]) -> Vec<Expr> {
+        args.iter().map(|a| self.value(*a)).collect()
+    }
+
+    fn render(&self, op: &IrOp) -> Expr {
+        match op {
+            IrOp::Const(c) => constant(c),
+            IrOp::Param(0) => Expr::atom("this"),
+            IrOp::Param(n) => Expr::atom(format!("a{}", n - 1)),
+            IrOp::Unary { op, operand } => {
+                let operand = self.value(*operand);
+                match op {
+                    UnaryOp::Negate => Expr::Unary("-", Box::new(operand)),
+                    UnaryOp::Not => Expr::Unary("!", Box::new(operand)),
+                    UnaryOp::BitNot => Expr::Unary("~", Box::new(operand)),
+                    UnaryOp::TypeOf => Expr::Unary("typeof ", Box::new(operand)),
+                    UnaryOp::Inc => Expr::binary("+", operand, Expr::atom("1")),
+                    UnaryOp::Dec => Expr::binary("-", operand, Expr::atom("1")),
+                    UnaryOp::ToNumber | UnaryOp::ToNumeric => Expr::Unary("+", Box::new(operand)),
+                    UnaryOp::ToInt32 => Expr::binary("|", operand, Expr::atom("0")),
+                    UnaryOp::ToString => Expr::binary("+", Expr::atom("\"\""), operand),
+                }
+            }
+            IrOp::Binary { op, lhs, rhs } => {
+                let expr = Expr::binary(op.symbol(), self.value(*lhs), self.value(*rhs));
+                match op {
+                    BinaryOp::Divi32 | BinaryOp::Mul32 => Expr::binary("|", expr, Expr::atom("0")),
+                    BinaryOp::Divu32 => Expr::binary(">>>", expr, Expr::atom("0")),
+                    _ => expr,
+                }
+            }
+            IrOp::GetProperty { object, key, .. } => self.member(*object, key),
+            IrOp::PutProperty {
+                object, key, value, ..
+            } => Expr::assign(self.member(*object, key), self.value(*value)),
+            IrOp::DeleteProperty { object, key } => {
+                Expr::Unary("delete ", Box::new(self.member(*object, key)))
+            }
+            IrOp::Call { callee, this, args } => {
+                let target = self.value(*callee);
+                // `obj.method(...)` passes `obj` as `this`
+                let method_call = self.inlined.contains(callee)
+                    && matches!(self.op(*callee), Some(IrOp::GetProperty { object, .. }) if object == this);
+                if self.is_undefined(*this) || method_call || self.is_global_object(*this) {
+                    Expr::Call(Box::new(target), self.args(args))
+                } else {
+                    let mut call_args = vec![self.value(*this)];
+                    call_args.extend(self.args(args));
+                    Expr::Call(
+                        Box::new(Expr::Member(Box::new(target), String::from("call"))),
+                        call_args,
+                    )
+                }
+            }
+            IrOp::Construct { callee, args, .. } => {
+                Expr::New(Box::new(self.value(*callee)), self.args(args))
+            }
+            IrOp::CallDirect { function, args, .. } => {
+                let name = self
+                    .function_names
+                    .get(function)
+                    .filter(|n| is_identifier(n))
+                    .cloned()
+                    .unwrap_or_else(|| format!("$FUNC_{}", function));
+                Expr::Call(Box::new(Expr::atom(name)), self.args(args))
+            }
+            IrOp::CallBuiltin { builtin, args, .. } => {
+                Expr::Call(Box::new(builtin_expr(builtin)), self.args(args))
+            }
+            IrOp::GetBuiltinClosure(builtin) => builtin_expr(builtin),
+            IrOp::GetGlobalObject => Expr::atom("globalThis"),
+            IrOp::DeclareGlobalVar(name) => Expr::Call(
+                Box::new(Expr::atom("__DeclareGlobalVar")),
+                vec![Expr::atom(quote(name))],
+            ),
+            IrOp::GetEnvironment(level) => Expr::atom(format!("$env{}", level)),
+            IrOp::CreateEnvironment => {
+                Expr::Call(Box::new(Expr::atom("$createEnvironment")), vec![])
+            }
+            IrOp::CreateInnerEnvironment { parent, size } => Expr::Call(
+                Box::new(Expr::atom("$createInnerEnvironment")),
+                vec![self.value(*parent), Expr::atom(size.to_string())],
+            ),
+            IrOp::LoadEnv { env, slot } => Expr::Index(
+                Box::new(self.value(*env)),
+                Box::new(Expr::atom(slot.to_string())),
+            ),
+            IrOp::StoreEnv { env, slot, value } => Expr::assign(
+                Expr::Index(
+                    Box::new(self.value(*env)),
+                    Box::new(Expr::atom(slot.to_string())),
+                ),
+                self.value(*value),
+            ),
+            IrOp::CreateClosure { function, .. } => match self.closures.get(function) {
+                Some(f) => Expr::Function(Box::new(f.clone())),
+                None => Expr::atom(
+                    self.function_names
+                        .get(function)
+                        .filter(|n| is_identifier(n))
+                        .cloned()
+                        .unwrap_or_else(|| format!("$FUNC_{}", function)),
+                ),
+            },
+            IrOp::NewObject { parent, properties } => {
+                let mut props: Vec<(String, Expr)> = vec![];
+                if let Some(parent) = parent {
+                    props.push((String::from("__proto__"), self.value(*parent)));
+                }
+                for (key, value) in properties.iter() {
+                    let key = match key {
+                        Constant::String { value, .. } if is_identifier(value) => value.clone(),
+                        Constant::String { value, .. } => quote(value),
+                        other => constant(other).to_string(),
+                    };
+                    props.push((key, constant(value)));
+                }
+                Expr::Object(props)
+            }
+            IrOp::NewArray { elements, .. } => Expr::Array(elements.iter().map(constant).collect()),
+            IrOp::CreateRegExp { pattern, flags } => Expr::atom(format!("/{}/{}", pattern, flags)),
+            IrOp::LoadThis => Expr::atom("this"),
+            IrOp::CoerceThis(v) => self.value(*v),
+            IrOp::GetNewTarget => Expr::atom("new.target"),
+            IrOp::Catch => Expr::atom("exception"),
+            IrOp::Output { source, index } => Expr::Index(
+                Box::new(self.value(*source)),
+                Box::new(Expr::atom(index.to_string())),
+            ),
+            IrOp::Opaque {
+                name,
+                args,
+                immediates,
+            } => {
+                let mut call_args = self.args(args);
+                call_args.extend(immediates.iter().map(|i| Expr::atom(i.to_string())));
+                Expr::Call(Box::new(Expr::atom(format!("__{}", name))), call_args)
+            }
+        }
+    }
+}
+
+// Ops that can be moved past other instructions.
+fn is_pure(op: &IrOp) -> bool {
+    matches!(
+        op,
+        IrOp::Const(_)
+            | IrOp::Param(_)
+            | IrOp::Unary { .. }
+            | IrOp::Binary { .. }
+            | IrOp::LoadThis
+            | IrOp::GetGlobalObject
+            | IrOp::GetEnvironment(_)
+            | IrOp::GetBuiltinClosure(_)
+            | IrOp::CreateClosure { .. }
+            | IrOp::NewObject { .. }
+            | IrOp::NewArray { .. }
+            | IrOp::CreateRegExp { .. }
+            | IrOp::CoerceThis(_)
+            | IrOp::GetNewTarget
+            | IrOp::Output { .. }
+    )
+}
+
+// Ops that are cheap and readable enough to repeat at every use.
+fn is_leaf(op: &IrOp) -> bool {
+    matches!(
+        op,
+        IrOp::Const(_)
+            | IrOp::Param(_)
+            | IrOp::LoadThis
+            | IrOp::GetGlobalObject
+            | IrOp::GetEnvironment(_)
+            | IrOp::GetBuiltinClosure(_)
+            | IrOp::GetNewTarget
+    )
+}
+
+// Builtins print as their name (`Object.keys`) when the version's table has it
+fn builtin_expr(builtin: &Builtin) -> Expr {
+    match &builtin.name {
+        Some(name) => Expr::atom(name.clone()),
+        None => Expr::atom(format!("__builtin_{}", builtin.index)),
+    }
+}
+
+pub fn constant(c: &Constant) -> Expr {
+    Expr::atom(match c {
+        Constant::Undefined => String::from("undefined"),
+        Constant::Null => String::from("null"),
+        Constant::Empty => String::from("<empty>"),
+        Constant::Bool(b) => b.to_string(),
+        Constant::Number(n) => number(*n),
+        Constant::String { value, .. } => quote(value),
+        Constant::BigInt(n) => format!("{}n", n),
+    })
+}
+
+fn number(n: f64) -> String {
+    if n.is_nan() {
+        String::from("NaN")
+    } else if n.is_infinite() {
+        String::from(if n > 0.0 { "Infinity" } else { "-Infinity" })
+    } else if n == 0.0 && n.is_sign_negative() {
+        String::from("-0")
+    } else if n.fract() == 0.0 && n.abs() < 1e21 {
+        format!("{}", n as i128)
+    } else {
+        format!("{}", n)
+    }
+}
+
+pub fn quote(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
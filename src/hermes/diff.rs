@@ -0,0 +1,335 @@
+use std::collections::{BTreeSet, HashMap};
+use std::io;
+
+use crate::hermes::fingerprint::{
+    match_fingerprints, FunctionFingerprint, FunctionMatch, MatchKind,
+};
+use crate::hermes::hermes_file::HermesFile;
+use crate::hermes::signatures::LibraryLabels;
+
+/*
+ * Semantic diff between two builds of the same bundle.
+ *
+ * Functions are matched by fingerprint (see hermes::fingerprint), then the
+ * leftovers by unique name, then by position between two matched functions.
+ * Matched functions whose normalized instruction stream differs are reported
+ * as modified, with an instruction-level diff. Instructions are compared in
+ * normalized form, so renumbered strings, functions and registers don't show
+ * up as changes, and printed with strings and function names resolved.
+ */
+
+/// Larger functions are reported as entirely replaced rather than diffed
+/// instruction by instruction.
+pub const MAX_DIFF_CELLS: usize = 16_000_000;
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiffLine {
+    Same(String),
+    Removed(String),
+    Added(String),
+}
+
+impl DiffLine {
+    pub fn is_change(&self) -> bool {
+        !matches!(self, DiffLine::Same(_))
+    }
+}
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone)]
+pub struct FunctionDiff {
+    pub left: u32,
+    pub right: u32,
+    pub left_name: String,
+    pub right_name: String,
+    pub kind: MatchKind,
+    pub confidence: f64,
+    pub lines: Vec<DiffLine>,
+}
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, Default)]
+pub struct BundleDiff {
+    /// Every matched pair, sorted by the left function ID.
+    pub matches: Vec<FunctionMatch>,
+
+    /// Function IDs (in the left file) with no match in the right file.
+    pub removed: Vec<u32>,
+
+    /// Function IDs (in the right file) with no match in the left file.
+    pub added: Vec<u32>,
+
+    /// Matched functions whose code changed.
+    pub modified: Vec<FunctionDiff>,
+
+    pub strings_added: Vec<String>,
+    pub strings_removed: Vec<String>,
+}
+
+impl BundleDiff {
+    pub fn unchanged_count(&self) -> usize {
+        self.matches.len() - self.modified.len()
+    }
+
+    /*
+     * Drops functions labelled as library code (see hermes::signatures) from
+     * every list, so only first-party functions and changes are left.
+     */
+    pub fn hide_libraries(&mut self, left: &LibraryLabels, right: &LibraryLabels) {
+        self.matches
+            .retain(|m| !left.is_library(m.left) && !right.is_library(m.right));
+        self.removed.retain(|id| !left.is_library(*id));
+        self.added.retain(|id| !right.is_library(*id));
+        self.modified
+            .retain(|f| !left.is_library(f.left) && !right.is_library(f.right));
+    }
+}
+
+/*
+ * Matches functions by fingerprint, then by unique name, then by position.
+ */
+pub fn match_functions_for_diff(
+    left: &[FunctionFingerprint],
+    right: &[FunctionFingerprint],
+) -> Vec<FunctionMatch> {
+    let mut matches = match_fingerprints(left, right);
+
+    let mut left_matched = vec![false; left.len()];
+    let mut right_matched = vec![false; right.len()];
+    let left_index: HashMap<u32, usize> = left
+        .iter()
+        .enumerate()
+        .map(|(i, fp)| (fp.function_id, i))
+        .collect();
+    let right_index: HashMap<u32, usize> = right
+        .iter()
+        .enumerate()
+        .map(|(i, fp)| (fp.function_id, i))
+        .collect();
+    for m in matches.iter() {
+        left_matched[left_index[&m.left]] = true;
+        right_matched[right_index[&m.right]] = true;
+    }
+
+    // Unique names among the unmatched functions
+    let unmatched_names = |fps: &[FunctionFingerprint], matched: &[bool]| {
+        let mut names: HashMap<String, Vec<usize>> = HashMap::new();
+        for (i, fp) in fps.iter().enumerate() {
+            if !matched[i] && !fp.name.is_empty() {
+                names.entry(fp.name.clone()).or_default().push(i);
+            }
+        }
+        names
+    };
+    let left_names = unmatched_names(left, &left_matched);
+    let right_names = unmatched_names(right, &right_matched);
+    for (name, lefts) in left_names.iter() {
+        if let Some(rights) = right_names.get(name) {
+            if lefts.len() == 1 && rights.len() == 1 {
+                left_matched[lefts[0]] = true;
+                right_matched[rights[0]] = true;
+                matches.push(FunctionMatch {
+                    left: left[lefts[0]].function_id,
+                    right: right[rights[0]].function_id,
+                    kind: MatchKind::Name,
+                    confidence: 0.5,
+                });
+            }
+        }
+    }
+
+    // Gaps between two consecutive matches that hold the same number of
+    // unmatched functions on both sides are paired in order.
+    matches.sort_by_key(|m| m.left);
+    let mut anchors: Vec<(isize, isize)> = vec![(-1, -1)];
+    for m in matches.iter() {
+        let (l, r) = (left_index[&m.left] as isize, right_index[&m.right] as isize);
+        if r > anchors.last().unwrap().1 {
+            anchors.push((l, r));
+        }
+    }
+    anchors.push((left.len() as isize, right.len() as isize));
+
+    let mut positional = vec![];
+    for pair in anchors.windows(2) {
+        let ((l0, r0), (l1, r1)) = (pair[0], pair[1]);
+        let lefts: Vec<usize> = ((l0 + 1)..l1)
+            .map(|i| i as usize)
+            .filter(|i| !left_matched[*i])
+            .collect();
+        let rights: Vec<usize> = ((r0 + 1)..r1)
+            .map(|i| i as usize)
+            .filter(|i| !right_matched[*i])
+            .collect();
+        if lefts.is_empty() || lefts.len() != rights.len() {
+            continue;
+        }
+        for (l, r) in lefts.iter().zip(rights.iter()) {
+            if left[*l].param_count != right[*r].param_count {
+                continue;
+            }
+            left_matched[*l] = true;
+            right_matched[*r] = true;
+            positional.push(FunctionMatch {
+                left: left[*l].function_id,
+                right: right[*r].function_id,
+                kind: MatchKind::Position,
+                confidence: 0.3 + 0.4 * left[*l].similarity(&right[*r]),
+            });
+        }
+    }
+    matches.extend(positional);
+
+    matches.sort_by_key(|m| m.left);
+    matches
+}
+
+/*
+ * Diffs two sequences by longest common subsequence of their keys. `left` and
+ * `right` are (key, text) pairs - keys are compared, texts are reported.
+ */
+pub fn diff_lines(left: &[(String, String)], right: &[(String, String)]) -> Vec<DiffLine> {
+    // Common prefix and suffix don't need the table
+    let prefix = left
+        .iter()
+        .zip(right.iter())
+        .take_while(|(a, b)| a.0 == b.0)
+        .count();
+    let suffix = left[prefix..]
+        .iter()
+        .rev()
+        .zip(right[prefix..].iter().rev())
+        .take_while(|(a, b)| a.0 == b.0)
+        .count();
+    let a = &left[prefix..left.len() - suffix];
+    let b = &right[prefix..right.len() - suffix];
+
+    let mut lines: Vec<DiffLine> = left[..prefix]
+        .iter()
+        .map(|(_, text)| DiffLine::Same(text.clone()))
+        .collect();
+
+    if a.len() * b.len() > MAX_DIFF_CELLS {
+        lines.extend(a.iter().map(|(_, text)| DiffLine::Removed(text.clone())));
+        lines.extend(b.iter().map(|(_, text)| DiffLine::Added(text.clone())));
+    } else {
+        // lcs[i][j] = LCS length of a[i..] and b[j..]
+        let width = b.len() + 1;
+        let mut lcs = vec![0u32; (a.len() + 1) * width];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                lcs[i * width + j] = if a[i].0 == b[j].0 {
+                    lcs[(i + 1) * width + j + 1] + 1
+                } else {
+                    lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+                };
+            }
+        }
+
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() || j < b.len() {
+            if i < a.len() && j < b.len() && a[i].0 == b[j].0 {
+                lines.push(DiffLine::Same(b[j].1.clone()));
+                i += 1;
+                j += 1;
+            } else if j < b.len()
+                && (i == a.len() || lcs[i * width + j + 1] >= lcs[(i + 1) * width + j])
+            {
+                lines.push(DiffLine::Added(b[j].1.clone()));
+                j += 1;
+            } else {
+                lines.push(DiffLine::Removed(a[i].1.clone()));
+                i += 1;
+            }
+        }
+    }
+
+    lines.extend(
+        right[right.len() - suffix..]
+            .iter()
+            .map(|(_, text)| DiffLine::Same(text.clone())),
+    );
+    lines
+}
+
+impl<R> HermesFile<R>
+where
+    R: io::Read + io::BufRead + io::Seek,
+{
+    /*
+     * Returns each instruction of a function as a (normalized, display) pair -
+     * the normalized form is what gets compared, the display form (with
+     * strings and function names resolved) is what gets reported.
+     */
+    pub fn get_diffable_instructions(&mut self, idx: usize) -> Vec<(String, String)> {
+        let normalized = self.get_normalized_instructions(idx);
+        let instructions = self.get_function_instructions(idx);
+        normalized
+            .into_iter()
+            .zip(instructions.iter())
+            .map(|(key, ins)| {
+                let text = ins
+                    .display(self)
+                    .split_whitespace()
+                    .collect::<Vec<&str>>()
+                    .join(" ");
+                (key, text)
+            })
+            .collect()
+    }
+
+    /*
+     * Diffs this file (the old build) against `other` (the new build).
+     */
+    pub fn diff<R2>(&mut self, other: &mut HermesFile<R2>) -> BundleDiff
+    where
+        R2: io::Read + io::BufRead + io::Seek,
+    {
+        let left = self.get_fingerprints();
+        let right = other.get_fingerprints();
+        let matches = match_functions_for_diff(&left, &right);
+
+        let left_matched: BTreeSet<u32> = matches.iter().map(|m| m.left).collect();
+        let right_matched: BTreeSet<u32> = matches.iter().map(|m| m.right).collect();
+
+        let mut modified = vec![];
+        for m in matches.iter() {
+            if left[m.left as usize].hash == right[m.right as usize].hash {
+                continue;
+            }
+            let lines = diff_lines(
+                &self.get_diffable_instructions(m.left as usize),
+                &other.get_diffable_instructions(m.right as usize),
+            );
+            modified.push(FunctionDiff {
+                left: m.left,
+                right: m.right,
+                left_name: self.get_function_name(m.left as usize),
+                right_name: other.get_function_name(m.right as usize),
+                kind: m.kind,
+                confidence: m.confidence,
+                lines,
+            });
+        }
+
+        let left_strings: BTreeSet<String> = self.get_strings().into_iter().collect();
+        let right_strings: BTreeSet<String> = other.get_strings().into_iter().collect();
+
+        BundleDiff {
+            removed: (0..left.len() as u32)
+                .filter(|id| !left_matched.contains(id))
+                .collect(),
+            added: (0..right.len() as u32)
+                .filter(|id| !right_matched.contains(id))
+                .collect(),
+            modified,
+            strings_added: right_strings.difference(&left_strings).cloned().collect(),
+            strings_removed: left_strings.difference(&right_strings).cloned().collect(),
+            matches,
+        }
+    }
+}
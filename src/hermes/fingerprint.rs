@@ -0,0 +1,556 @@
+use std::collections::{BTreeSet, HashMap};
+use std::io;
+
+use crate::hermes::array_parser::ArrayTypes;
+use crate::hermes::hermes_file::HermesFile;
+use crate::hermes::types::{Operand, OperandKind, OperandValue};
+use crate::hermes::HermesInstruction;
+
+/*
+ * Function fingerprints for matching functions across builds of the same app.
+ *
+ * Between releases every function, string, literal buffer and property cache
+ * index gets renumbered, so a fingerprint is taken over a normalized
+ * instruction stream instead of the raw bytes:
+ *
+ * - opcodes lose their Short/Long/LongIndex suffix (operand widths change when
+ *   IDs grow)
+ * - registers, jump offsets, cache indices, function IDs and literal buffer
+ *   offsets are abstracted away
+ * - string, BigInt and literal buffer operands are replaced by their contents
+ * - other immediates (argument counts, environment slots, constants) are kept
+ *
+ * `hash` identifies functions whose normalized stream is identical. The
+ * remaining features (opcode trigrams, strings, callees, block count) are used
+ * to score near matches.
+ */
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone)]
+pub struct FunctionFingerprint {
+    pub function_id: u32,
+    pub name: String,
+
+    /// Hash of the normalized instruction stream.
+    pub hash: u64,
+
+    pub instruction_count: u32,
+    pub block_count: u32,
+    pub param_count: u32,
+
+    /// Strings referenced by the function, sorted and deduplicated.
+    pub strings: Vec<String>,
+
+    /// Names of the functions it creates or calls directly, sorted and
+    /// deduplicated. Anonymous functions show up as an empty string.
+    pub callees: Vec<String>,
+
+    /// Hashes of consecutive opcode triples, sorted and deduplicated.
+    pub trigrams: Vec<u64>,
+
+    /// Hashes of the normalized instructions, sorted and deduplicated.
+    pub tokens: Vec<u64>,
+}
+
+impl FunctionFingerprint {
+    /*
+     * Similarity between two fingerprints, from 0.0 to 1.0. Identical hashes
+     * always score 1.0.
+     */
+    pub fn similarity(&self, other: &FunctionFingerprint) -> f64 {
+        if self.hash == other.hash {
+            return 1.0;
+        }
+
+        let size = |a: u32, b: u32| -> f64 {
+            if a == b {
+                1.0
+            } else {
+                a.min(b) as f64 / a.max(b) as f64
+            }
+        };
+        let structure = (size(self.instruction_count, other.instruction_count)
+            + size(self.block_count, other.block_count))
+            / 2.0;
+
+        let mut score = 0.35 * jaccard(&self.trigrams, &other.trigrams)
+            + 0.25 * jaccard(&self.tokens, &other.tokens)
+            + 0.15 * jaccard(&self.strings, &other.strings)
+            + 0.10 * jaccard(&self.callees, &other.callees)
+            + 0.15 * structure;
+
+        // A differing parameter count is a strong hint that it's not the same function
+        if self.param_count != other.param_count {
+            score *= 0.8;
+        }
+        score
+    }
+}
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchKind {
+    /// Same normalized instruction stream.
+    Exact,
+    /// Close enough by features.
+    Similar,
+    /// Paired by function name only.
+    Name,
+    /// Paired by position between two other matches.
+    Position,
+}
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone)]
+pub struct FunctionMatch {
+    /// Function ID in the first file.
+    pub left: u32,
+    /// Function ID in the second file.
+    pub right: u32,
+    pub kind: MatchKind,
+    /// 0.0 to 1.0 - 1.0 is an exact match with a unique fingerprint on both sides.
+    pub confidence: f64,
+}
+
+/// Near matches scoring below this aren't reported.
+pub const MIN_SIMILARITY: f64 = 0.6;
+
+// How many functions of similar size are compared against each unmatched
+// function, on each side of it - keeps matching large bundles near-linear.
+const CANDIDATE_WINDOW: usize = 64;
+
+/*
+ * Pairs functions between two sets of fingerprints. Every function is matched
+ * at most once. Results are sorted by the left function ID.
+ *
+ * 1. Fingerprints with the same hash are paired. A hash that's unique on both
+ *    sides gives confidence 1.0; otherwise functions are paired by name, then
+ *    in order, with lower confidence.
+ * 2. The rest are compared by `similarity` against functions with the same
+ *    name and functions of similar size, and paired greedily best-first.
+ */
+pub fn match_fingerprints(
+    left: &[FunctionFingerprint],
+    right: &[FunctionFingerprint],
+) -> Vec<FunctionMatch> {
+    let mut pairs = Pairs {
+        left,
+        right,
+        left_matched: vec![false; left.len()],
+        right_matched: vec![false; right.len()],
+        matches: vec![],
+    };
+
+    // Exact matches
+    let mut right_by_hash: HashMap<u64, Vec<usize>> = HashMap::new();
+    for (i, fp) in right.iter().enumerate() {
+        right_by_hash.entry(fp.hash).or_default().push(i);
+    }
+    let mut left_by_hash: HashMap<u64, Vec<usize>> = HashMap::new();
+    for (i, fp) in left.iter().enumerate() {
+        left_by_hash.entry(fp.hash).or_default().push(i);
+    }
+
+    for (hash, lefts) in left_by_hash.iter() {
+        let rights = match right_by_hash.get(hash) {
+            Some(rights) => rights,
+            None => continue,
+        };
+
+        if lefts.len() == 1 && rights.len() == 1 {
+            pairs.pair(lefts[0], rights[0], MatchKind::Exact, 1.0);
+            continue;
+        }
+
+        // Duplicated bodies - prefer equal names, then keep the original order
+        for l in lefts.iter() {
+            if left[*l].name.is_empty() {
+                continue;
+            }
+            if let Some(r) = rights
+                .iter()
+                .find(|r| !pairs.right_matched[**r] && right[**r].name == left[*l].name)
+            {
+                pairs.pair(*l, *r, MatchKind::Exact, 0.9);
+            }
+        }
+        let remaining: Vec<usize> = rights
+            .iter()
+            .copied()
+            .filter(|r| !pairs.right_matched[*r])
+            .collect();
+        let unmatched_lefts: Vec<usize> = lefts
+            .iter()
+            .copied()
+            .filter(|l| !pairs.left_matched[*l])
+            .collect();
+        for (l, r) in unmatched_lefts.iter().zip(remaining.iter()) {
+            pairs.pair(*l, *r, MatchKind::Exact, 0.75);
+        }
+    }
+
+    // Near matches
+    let mut by_size: Vec<usize> = (0..right.len())
+        .filter(|r| !pairs.right_matched[*r])
+        .collect();
+    by_size.sort_by_key(|r| right[*r].instruction_count);
+    let mut right_by_name: HashMap<&str, Vec<usize>> = HashMap::new();
+    for r in by_size.iter() {
+        if !right[*r].name.is_empty() {
+            right_by_name
+                .entry(right[*r].name.as_str())
+                .or_default()
+                .push(*r);
+        }
+    }
+
+    let mut candidates: Vec<(f64, usize, usize)> = vec![];
+    for (l, fp) in left.iter().enumerate() {
+        if pairs.left_matched[l] {
+            continue;
+        }
+
+        let mut compared: BTreeSet<usize> = BTreeSet::new();
+        if let Some(named) = right_by_name.get(fp.name.as_str()) {
+            compared.extend(named.iter().copied());
+        }
+        let pos = by_size.partition_point(|r| right[*r].instruction_count < fp.instruction_count);
+        let start = pos.saturating_sub(CANDIDATE_WINDOW);
+        let end = (pos + CANDIDATE_WINDOW).min(by_size.len());
+        compared.extend(by_size[start..end].iter().copied());
+
+        for r in compared {
+            let mut score = fp.similarity(&right[r]);
+            if !fp.name.is_empty() && fp.name == right[r].name {
+                score = (score + 0.1).min(1.0);
+            }
+            if score >= MIN_SIMILARITY {
+                candidates.push((score, l, r));
+            }
+        }
+    }
+
+    candidates.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2)));
+    for (score, l, r) in candidates {
+        if pairs.left_matched[l] || pairs.right_matched[r] {
+            continue;
+        }
+        // Never report a near match as confident as an exact one
+        pairs.pair(l, r, MatchKind::Similar, score * 0.95);
+    }
+
+    pairs.matches.sort_by_key(|m| m.left);
+    pairs.matches
+}
+
+struct Pairs<'a> {
+    left: &'a [FunctionFingerprint],
+    right: &'a [FunctionFingerprint],
+    left_matched: Vec<bool>,
+    right_matched: Vec<bool>,
+    matches: Vec<FunctionMatch>,
+}
+
+impl Pairs<'_> {
+    fn pair(&mut self, l: usize, r: usize, kind: MatchKind, confidence: f64) {
+        self.left_matched[l] = true;
+        self.right_matched[r] = true;
+        self.matches.push(FunctionMatch {
+            left: self.left[l].function_id,
+            right: self.right[r].function_id,
+            kind,
+            confidence,
+        });
+    }
+}
+
+fn jaccard<T: Ord>(a: &[T], b: &[T]) -> f64 {
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+    let (mut i, mut j, mut common) = (0, 0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => {
+                common += 1;
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    common as f64 / (a.len() + b.len() - common) as f64
+}
+
+// 64-bit FNV-1a - stable across runs and Rust versions, unlike DefaultHasher
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in bytes {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/*
+ * Opcode name without the operand-width suffix, so e.g. GetByIdShort, GetById
+ * and GetByIdLong all normalize to GetById.
+ */
+pub fn normalize_opcode_name(name: &str) -> &str {
+    // The *L forms are the wide-index versions of the instruction without the L
+    if let "LoadFromEnvironmentL"
+    | "PutOwnByIndexL"
+    | "StoreNPToEnvironmentL"
+    | "StoreToEnvironmentL" = name
+    {
+        return &name[..name.len() - 1];
+    }
+    name.strip_suffix("LongIndex")
+        .or_else(|| name.strip_suffix("Long"))
+        .or_else(|| name.strip_suffix("Short"))
+        .unwrap_or(name)
+}
+
+impl<R> HermesFile<R>
+where
+    R: io::Read + io::BufRead + io::Seek,
+{
+    /*
+     * Returns the normalized form of each instruction in a function, one
+     * string per instruction, e.g. `GetById r, r, $cache, "fetch"`.
+     */
+    pub fn get_normalized_instructions(&mut self, idx: usize) -> Vec<String> {
+        self.get_function_instructions(idx)
+            .iter()
+            .map(|ins| self.normalize_instruction(ins))
+            .collect()
+    }
+
+    pub fn get_function_fingerprint(&mut self, idx: usize) -> FunctionFingerprint {
+        let instructions = self.get_function_instructions(idx);
+
+        let mut hash_input: Vec<u8> = vec![];
+        let mut tokens: Vec<u64> = vec![];
+        let mut opcodes: Vec<u64> = vec![];
+        let mut strings: BTreeSet<String> = BTreeSet::new();
+        let mut callees: BTreeSet<String> = BTreeSet::new();
+
+        // Block leaders - the entry, jump targets and instructions after a jump
+        let mut leaders: BTreeSet<i64> = BTreeSet::new();
+        leaders.insert(0);
+        let mut offset: i64 = 0;
+
+        for ins in instructions.iter() {
+            let normalized = self.normalize_instruction(ins);
+            hash_input.extend(normalized.as_bytes());
+            hash_input.push(b'\n');
+            tokens.push(fnv1a(normalized.as_bytes()));
+            opcodes.push(fnv1a(normalize_opcode_name(ins.name()).as_bytes()));
+
+            for op in ins.operands() {
+                if op.kind.is_string_id() {
+                    if let Some(string) = self.get_string_by_id(op.value.as_u32()) {
+                        strings.insert(string);
+                    }
+                }
+            }
+            if let Some(fid) = ins.function_id() {
+                if (fid as usize) < self.function_headers.len() {
+                    let fh = &self.function_headers[fid as usize];
+                    callees.insert(self.get_string_by_id(fh.func_name()).unwrap_or_default());
+                }
+            }
+
+            let size = ins.size() as i64;
+            if ins.is_jmp() {
+                let target = ins
+                    .operands()
+                    .iter()
+                    .find(|o| o.kind.is_address())
+                    .map(|o| o.value.as_i64());
+                if let Some(target) = target {
+                    leaders.insert(offset + target);
+                }
+                leaders.insert(offset + size);
+            }
+            offset += size;
+        }
+        leaders.retain(|l| *l < offset);
+
+        let mut trigrams: Vec<u64> = opcodes
+            .windows(3)
+            .map(|w| w[0] ^ w[1].rotate_left(21) ^ w[2].rotate_left(42))
+            .collect();
+        if opcodes.len() < 3 {
+            trigrams = opcodes.clone();
+        }
+        trigrams.sort();
+        trigrams.dedup();
+        tokens.sort();
+        tokens.dedup();
+
+        FunctionFingerprint {
+            function_id: idx as u32,
+            name: match self.function_headers.get(idx) {
+                Some(fh) if (fh.func_name() as usize) < self.string_storage.len() => {
+                    self.get_string_from_storage_by_index(fh.func_name() as usize)
+                }
+                _ => String::new(),
+            },
+            hash: fnv1a(&hash_input),
+            instruction_count: instructions.len() as u32,
+            block_count: leaders.len().max(1) as u32,
+            param_count: self
+                .function_headers
+                .get(idx)
+                .map(|fh| fh.param_count())
+                .unwrap_or(0),
+            strings: strings.into_iter().collect(),
+            callees: callees.into_iter().collect(),
+            trigrams,
+            tokens,
+        }
+    }
+
+    /*
+     * Returns the fingerprint of every function in the file.
+     */
+    pub fn get_fingerprints(&mut self) -> Vec<FunctionFingerprint> {
+        (0..self.function_headers.len())
+            .map(|idx| self.get_function_fingerprint(idx))
+            .collect()
+    }
+
+    /*
+     * Pairs the functions of this file with the functions of `other` - see
+     * `match_fingerprints`.
+     */
+    pub fn match_functions<R2>(&mut self, other: &mut HermesFile<R2>) -> Vec<FunctionMatch>
+    where
+        R2: io::Read + io::BufRead + io::Seek,
+    {
+        let left = self.get_fingerprints();
+        let right = other.get_fingerprints();
+        match_fingerprints(&left, &right)
+    }
+
+    fn normalize_instruction(&self, ins: &HermesInstruction) -> String {
+        let name = ins.name();
+        let function_operand = ins.function_id_operand().map(|o| o.name);
+        let operands = ins.operands();
+
+        let mut parts: Vec<String> = vec![];
+        for op in operands.iter() {
+            parts.push(self.normalize_operand(name, op, function_operand));
+        }
+
+        // Literal buffers are abstracted above - append their contents instead
+        let operand = |field: &str| -> usize {
+            operands
+                .iter()
+                .find(|o| o.name == field)
+                .map(|o| o.value.as_u32() as usize)
+                .unwrap_or(0)
+        };
+        match name {
+            "NewObjectWithBuffer" | "NewObjectWithBufferLong" => {
+                let keys = self.get_object_key_buffer_values(operand("p2"), operand("p1"));
+                let values = self.get_object_val_buffer_values(operand("p3"), operand("p1"));
+                parts.push(format!("{{{}}}", self.literal_values(&keys)));
+                parts.push(format!("{{{}}}", self.literal_values(&values)));
+            }
+            "NewArrayWithBuffer" | "NewArrayWithBufferLong" => {
+                let values = self.get_array_buffer_values(operand("p2"), operand("p1"));
+                parts.push(format!("[{}]", self.literal_values(&values)));
+            }
+            _ => {}
+        }
+
+        format!("{} {}", normalize_opcode_name(name), parts.join(", "))
+            .trim_end()
+            .to_string()
+    }
+
+    fn normalize_operand(
+        &self,
+        name: &str,
+        op: &Operand,
+        function_operand: Option<&str>,
+    ) -> String {
+        if Some(op.name) == function_operand {
+            return "$func".to_string();
+        }
+        if op.kind.is_register() {
+            return "r".to_string();
+        }
+        if op.kind.is_address() {
+            return "$addr".to_string();
+        }
+        if op.kind.is_string_id() {
+            if let Some(string) = self.get_string_by_id(op.value.as_u32()) {
+                return format!("{:?}", string);
+            }
+        }
+        if op.kind.is_big_int_id() {
+            return format!("{}n", self.get_big_int_string(op.value.as_u32() as usize));
+        }
+
+        let base = normalize_opcode_name(name);
+        let abstracted = match base {
+            // Property cache index
+            _ if base.ends_with("ById") && op.name == "p0" && op.kind == OperandKind::UInt8 => {
+                Some("$cache")
+            }
+            // Literal buffer offsets
+            "NewObjectWithBuffer" if op.name == "p2" || op.name == "p3" => Some("$buffer"),
+            "NewArrayWithBuffer" if op.name == "p2" => Some("$buffer"),
+            // RegExp table index
+            "CreateRegExp" if op.name == "p2" => Some("$regexp"),
+            // Switch jump table offset
+            "SwitchImm" if op.name == "p0" => Some("$table"),
+            // Builtin numbers differ between versions, names don't
+            "CallBuiltin" | "GetBuiltinClosure" if op.name == "p0" => {
+                self.get_builtin_name(op.value.as_u32())
+            }
+            _ => None,
+        };
+        if let Some(abstracted) = abstracted {
+            return abstracted.to_string();
+        }
+
+        match op.value {
+            OperandValue::Double(d) => format!("{}", d),
+            OperandValue::Int(i) => format!("{}", i),
+        }
+    }
+
+    fn literal_values(&self, values: &[ArrayTypes]) -> String {
+        values
+            .iter()
+            .map(|v| match v {
+                ArrayTypes::ShortStringValue { value } => self.literal_string(*value as u32),
+                ArrayTypes::LongStringValue { value } => self.literal_string(*value),
+                ArrayTypes::ByteStringValue { value } => self.literal_string(*value as u32),
+                ArrayTypes::NumberValue { value } => format!("{}", f64::from_bits(*value)),
+                ArrayTypes::IntegerValue { value } => format!("{}", *value as i32),
+                ArrayTypes::NullValue {} => "null".to_string(),
+                ArrayTypes::TrueValue { .. } => "true".to_string(),
+                ArrayTypes::FalseValue { .. } => "false".to_string(),
+                ArrayTypes::EmptyValueSized { .. } => "empty".to_string(),
+            })
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
+    // A string from a literal buffer, quoted - or its ID, if it isn't in the string table
+    fn literal_string(&self, id: u32) -> String {
+        match self.get_string_by_id(id) {
+            Some(string) => format!("{:?}", string),
+            None => format!("$string{}", id),
+        }
+    }
+}
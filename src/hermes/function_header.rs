@@ -67,6 +67,58 @@ impl SmallFunctionHeader {
         self.byte_size = new_size;
     }
 
+    /*
+     * Builds the small header that stands in for a large one in the function header
+     * table, the way Hermes does it: fields are copied in order up to the first one
+     * that doesn't fit, flags are cleared apart from overflowed, and offset/info_offset
+     * are replaced with the location of the large header.
+     * See: https://github.com/facebook/hermes/blob/main/include/hermes/BCGen/HBC/BytecodeFileFormat.h
+     */
+    pub fn overflow_stub(large: &LargeFunctionHeader, large_header_offset: u32) -> Self {
+        let values = [
+            large.offset,
+            large.param_count,
+            large.byte_size,
+            large.func_name,
+            large.info_offset,
+            large.frame_size,
+            large.env_size,
+            large.highest_read_cache_index,
+            large.highest_write_cache_index,
+        ];
+        let bits = [25, 7, 15, 17, 25, 7, 8, 8, 8];
+
+        let mut fields = [0u32; 9];
+        for (field, (value, bits)) in fields.iter_mut().zip(values.iter().zip(bits)) {
+            if *value > (1 << bits) - 1 {
+                break;
+            }
+            *field = *value;
+        }
+
+        let (offset, info_offset) = get_large_info_offset_pair(large_header_offset);
+        SmallFunctionHeader {
+            offset,
+            param_count: fields[1],
+            byte_size: fields[2],
+            func_name: fields[3],
+            info_offset,
+            frame_size: fields[5],
+            env_size: fields[6],
+            highest_read_cache_index: fields[7],
+            highest_write_cache_index: fields[8],
+            flags: FunctionHeaderFlag {
+                prohibit_invoke: FunctionHeaderFlagProhibitions::ProhibitCall,
+                strict_mode: false,
+                has_exception_handler: false,
+                has_debug_info: false,
+                overflowed: true,
+            },
+            exception_handlers: vec![],
+            debug_info: None,
+        }
+    }
+
     /**
      * Check if any of the fields in the header are overflowed per their defined bit size.
      * See: https://github.com/facebook/hermes/blob/main/include/hermes/BCGen/HBC/BytecodeFileFormat.h#L259
@@ -188,49 +240,23 @@ impl Serializable for SmallFunctionHeader {
         let mut func_header_bytes = [0u8; 16];
 
         write_bitfield(&mut func_header_bytes, 0, 25, self.offset);
-        write_bitfield(
-            &mut func_header_bytes,
-            25,
-            7,
-            if self.flags.overflowed {
-                0
-            } else {
-                self.param_count
-            },
-        );
+        write_bitfield(&mut func_header_bytes, 25, 7, self.param_count);
         write_bitfield(&mut func_header_bytes, 32, 15, self.byte_size);
         write_bitfield(&mut func_header_bytes, 47, 17, self.func_name);
         write_bitfield(&mut func_header_bytes, 64, 25, self.info_offset);
         write_bitfield(&mut func_header_bytes, 89, 7, self.frame_size);
-        write_bitfield(
-            &mut func_header_bytes,
-            96,
-            8,
-            if self.flags.overflowed {
-                0
-            } else {
-                self.env_size
-            },
-        );
+        write_bitfield(&mut func_header_bytes, 96, 8, self.env_size);
         write_bitfield(
             &mut func_header_bytes,
             104,
             8,
-            if self.flags.overflowed {
-                0
-            } else {
-                self.highest_read_cache_index
-            },
+            self.highest_read_cache_index,
         );
         write_bitfield(
             &mut func_header_bytes,
             112,
             8,
-            if self.flags.overflowed {
-                0
-            } else {
-                self.highest_write_cache_index
-            },
+            self.highest_write_cache_index,
         );
 
         // last byte for flags
@@ -603,6 +629,13 @@ impl FunctionHeader {
         }
     }
 
+    pub fn set_frame_size(&mut self, frame_size: u32) {
+        match self {
+            FunctionHeader::Small(fh) => fh.frame_size = frame_size,
+            FunctionHeader::Large(fh) => fh.frame_size = frame_size,
+        }
+    }
+
     pub fn env_size(&self) -> u32 {
         match self {
             FunctionHeader::Small(fh) => fh.env_size,
@@ -638,6 +671,13 @@ impl FunctionHeader {
         }
     }
 
+    pub fn set_has_exception_handler(&mut self, has_exception_handler: bool) {
+        match self {
+            FunctionHeader::Small(fh) => fh.flags.has_exception_handler = has_exception_handler,
+            FunctionHeader::Large(fh) => fh.flags.has_exception_handler = has_exception_handler,
+        }
+    }
+
     pub fn exception_handlers(&self) -> Vec<ExceptionHandlerInfo> {
         match self {
             FunctionHeader::Small(fh) => fh.exception_handlers.clone(),
@@ -0,0 +1,265 @@
+use std::collections::{BTreeMap, HashSet};
+use std::io;
+
+use crate::hermes::hermes_file::HermesFile;
+
+/*
+ * Global variable inventory - every global a bundle declares and every global
+ * property it reads or writes.
+ *
+ * Top-level `var`/`function` declarations show up as DeclareGlobalVar (and, for
+ * `let`/`const` in newer versions, ThrowIfHasRestrictedGlobalProperty). Every
+ * other global access goes through the global object: GetGlobalObject loads it
+ * into a register, then GetById/TryGetById/PutById/TryPutById/DelById use that
+ * register as the object. The register is tracked through Mov until it's
+ * overwritten.
+ */
+
+/// Globals installed by the Metro module runtime.
+pub const METRO_RUNTIME_GLOBALS: &[&str] = &[
+    "__d",
+    "__r",
+    "__c",
+    "__registerSegment",
+    "__accept",
+    "__METRO_GLOBAL_PREFIX__",
+];
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GlobalAccessKind {
+    /// DeclareGlobalVar
+    Declare,
+    /// ThrowIfHasRestrictedGlobalProperty
+    CheckRestricted,
+    /// GetById* on the global object
+    Get,
+    /// TryGetById* on the global object - throws if the global doesn't exist
+    TryGet,
+    /// PutById* on the global object
+    Put,
+    /// TryPutById* on the global object - throws if the global doesn't exist
+    TryPut,
+    /// DelById* on the global object
+    Delete,
+}
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone)]
+pub struct GlobalAccess {
+    pub function_id: u32,
+
+    /// Byte offset of the instruction within the function.
+    pub offset: u32,
+
+    pub kind: GlobalAccessKind,
+    pub string_id: u32,
+    pub name: String,
+}
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, Default)]
+pub struct GlobalVariable {
+    pub name: String,
+
+    /// Functions that declare it with DeclareGlobalVar/ThrowIfHasRestrictedGlobalProperty.
+    pub declared_in: Vec<u32>,
+
+    /// Functions that read it.
+    pub read_in: Vec<u32>,
+
+    /// Functions that assign or delete it.
+    pub written_in: Vec<u32>,
+}
+
+impl GlobalVariable {
+    pub fn is_declared(&self) -> bool {
+        !self.declared_in.is_empty()
+    }
+
+    pub fn is_written(&self) -> bool {
+        !self.written_in.is_empty()
+    }
+
+    pub fn is_metro_runtime(&self) -> bool {
+        METRO_RUNTIME_GLOBALS.contains(&self.name.as_str())
+    }
+
+    /// Every function that touches the global, sorted.
+    pub fn functions(&self) -> Vec<u32> {
+        let mut functions: Vec<u32> = self
+            .declared_in
+            .iter()
+            .chain(self.read_in.iter())
+            .chain(self.written_in.iter())
+            .copied()
+            .collect();
+        functions.sort();
+        functions.dedup();
+        functions
+    }
+}
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, Default)]
+pub struct GlobalInventory {
+    /// Global name -> usage, ordered by name.
+    pub globals: BTreeMap<String, GlobalVariable>,
+}
+
+impl GlobalInventory {
+    pub fn new(accesses: Vec<GlobalAccess>) -> Self {
+        let mut globals: BTreeMap<String, GlobalVariable> = BTreeMap::new();
+        for access in accesses {
+            let global = globals
+                .entry(access.name.clone())
+                .or_insert_with(|| GlobalVariable {
+                    name: access.name.clone(),
+                    ..Default::default()
+                });
+            let functions = match access.kind {
+                GlobalAccessKind::Declare | GlobalAccessKind::CheckRestricted => {
+                    &mut global.declared_in
+                }
+                GlobalAccessKind::Get | GlobalAccessKind::TryGet => &mut global.read_in,
+                GlobalAccessKind::Put | GlobalAccessKind::TryPut | GlobalAccessKind::Delete => {
+                    &mut global.written_in
+                }
+            };
+            if let Err(pos) = functions.binary_search(&access.function_id) {
+                functions.insert(pos, access.function_id);
+            }
+        }
+        GlobalInventory { globals }
+    }
+
+    /// Globals declared with `var`/`function`/`let`/`const` at the top level.
+    pub fn declared(&self) -> Vec<&GlobalVariable> {
+        self.globals.values().filter(|g| g.is_declared()).collect()
+    }
+
+    /// Globals assigned through the global object without being declared -
+    /// typically polyfills and runtime hooks.
+    pub fn assigned(&self) -> Vec<&GlobalVariable> {
+        self.globals
+            .values()
+            .filter(|g| !g.is_declared() && g.is_written())
+            .collect()
+    }
+
+    /// Globals that are only ever read - builtins and host-provided objects.
+    pub fn read_only(&self) -> Vec<&GlobalVariable> {
+        self.globals
+            .values()
+            .filter(|g| !g.is_declared() && !g.is_written())
+            .collect()
+    }
+}
+
+impl<R> HermesFile<R>
+where
+    R: io::Read + io::BufRead + io::Seek,
+{
+    /*
+     * Returns the global declarations and global object accesses in a function,
+     * in instruction order.
+     */
+    pub fn get_function_global_accesses(&mut self, idx: usize) -> Vec<GlobalAccess> {
+        let instructions = self.get_function_instructions(idx);
+
+        // Registers currently holding the global object
+        let mut global_regs: HashSet<u32> = HashSet::new();
+        let mut accesses = vec![];
+        let mut offset = 0;
+
+        for ins in instructions.iter() {
+            let operands = ins.operands();
+            let reg = |name: &str| -> u32 {
+                operands
+                    .iter()
+                    .find(|o| o.name == name)
+                    .map(|o| o.value.as_u32())
+                    .unwrap_or(0)
+            };
+            let string_id = operands
+                .iter()
+                .find(|o| o.kind.is_string_id())
+                .map(|o| o.value.as_u32());
+
+            // (access kind, register holding the object) for *ById instructions
+            let by_id = match ins.name() {
+                "GetByIdShort" | "GetById" | "GetByIdLong" => {
+                    Some((GlobalAccessKind::Get, reg("r1")))
+                }
+                "TryGetById" | "TryGetByIdLong" => Some((GlobalAccessKind::TryGet, reg("r1"))),
+                "PutById" | "PutByIdLong" => Some((GlobalAccessKind::Put, reg("r0"))),
+                "TryPutById" | "TryPutByIdLong" => Some((GlobalAccessKind::TryPut, reg("r0"))),
+                "DelById" | "DelByIdLong" => Some((GlobalAccessKind::Delete, reg("r1"))),
+                _ => None,
+            };
+
+            let kind = match (ins.name(), by_id) {
+                ("DeclareGlobalVar", _) => Some(GlobalAccessKind::Declare),
+                ("ThrowIfHasRestrictedGlobalProperty", _) => {
+                    Some(GlobalAccessKind::CheckRestricted)
+                }
+                (_, Some((kind, object))) if global_regs.contains(&object) => Some(kind),
+                _ => None,
+            };
+
+            let name = string_id.and_then(|id| self.get_string_by_id(id));
+            if let (Some(kind), Some(string_id), Some(name)) = (kind, string_id, name) {
+                accesses.push(GlobalAccess {
+                    function_id: idx as u32,
+                    offset,
+                    kind,
+                    string_id,
+                    name,
+                });
+            }
+
+            match ins.name() {
+                "GetGlobalObject" => {
+                    global_regs.insert(reg("r0"));
+                }
+                "Mov" | "MovLong" => {
+                    if global_regs.contains(&reg("r1")) {
+                        global_regs.insert(reg("r0"));
+                    } else {
+                        global_regs.remove(&reg("r0"));
+                    }
+                }
+                // These take an object in r0 and don't write a register
+                "PutById" | "PutByIdLong" | "TryPutById" | "TryPutByIdLong" | "PutByVal"
+                | "PutOwnByVal" | "PutOwnByIndex" | "PutOwnByIndexL" => {}
+                _ => {
+                    if let Some(op) = operands.first() {
+                        if op.name == "r0" {
+                            global_regs.remove(&op.value.as_u32());
+                        }
+                    }
+                }
+            }
+
+            offset += ins.size() as u32;
+        }
+
+        accesses
+    }
+
+    /*
+     * Returns every global declared or accessed in the bundle, with the
+     * functions that touch it.
+     */
+    pub fn get_global_inventory(&mut self) -> GlobalInventory {
+        let mut accesses = vec![];
+        for idx in 0..self.function_headers.len() {
+            accesses.extend(self.get_function_global_accesses(idx));
+        }
+        GlobalInventory::new(accesses)
+    }
+}
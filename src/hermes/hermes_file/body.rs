@@ -0,0 +1,730 @@
+use std::collections::HashMap;
+use std::io;
+
+use super::{FunctionInstructions, HermesFile};
+use crate::hermes::exception_handler::ExceptionHandlerInfo;
+use crate::hermes::function_header::FunctionHeader;
+use crate::hermes::types::OperandValue;
+use crate::hermes::HermesInstruction;
+
+/*
+ * A function body that can be changed and laid out again.
+ *
+ * Jumps, switch tables and exception handlers don't hold offsets here - they
+ * refer to instructions by label. Every instruction is labelled with the offset
+ * it had when the body was read, and jumps set up afterwards get labels of their
+ * own. `assemble` works out the new offsets and rewrites every one of them,
+ * switching jumps that no longer reach their target to the long form.
+ *
+ * Instructions can be inserted, removed and replaced anywhere. An instruction
+ * inserted before another takes over its labels, so whatever jumped to the old
+ * instruction (or started or ended a try there) now lands on the new one; the
+ * labels of a removed instruction move to the one after it.
+ */
+
+/// Offsets from when the body was read, then labels added since.
+type Label = u64;
+const FIRST_NEW_LABEL: Label = 1 << 32;
+
+#[derive(Debug, Clone)]
+struct Slot {
+    ins: HermesInstruction,
+    /// Labels that land on this instruction.
+    labels: Vec<Label>,
+    /// Address operands: (operand index, target label).
+    jumps: Vec<(usize, Label)>,
+    /// SwitchImm case targets.
+    table: Vec<Label>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FunctionBody {
+    slots: Vec<Slot>,
+    /// Labels that land on the end of the code.
+    end_labels: Vec<Label>,
+    /// Exception handlers: start, end and target labels.
+    handlers: Vec<(Label, Label, Label)>,
+    /// Where the body sits in the file, modulo 4. Switch tables are aligned
+    /// against the file, not the body.
+    base: u32,
+    next_label: Label,
+}
+
+/// The output of `FunctionBody::assemble`.
+#[derive(Debug, Clone)]
+pub struct AssembledBody {
+    /// The code followed by the switch tables.
+    pub bytes: Vec<u8>,
+    pub exception_handlers: Vec<ExceptionHandlerInfo>,
+    /// Where each instruction of the body as it was read ended up: (old offset,
+    /// new offset), by old offset.
+    pub addresses: Vec<(u32, u32)>,
+}
+
+impl Default for FunctionBody {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FunctionBody {
+    /// An empty body for a new function.
+    pub fn new() -> Self {
+        FunctionBody {
+            slots: vec![],
+            end_labels: vec![],
+            handlers: vec![],
+            base: 0,
+            next_label: FIRST_NEW_LABEL,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&HermesInstruction> {
+        self.slots.get(index).map(|slot| &slot.ins)
+    }
+
+    pub fn instructions(&self) -> Vec<HermesInstruction> {
+        self.slots.iter().map(|slot| slot.ins).collect()
+    }
+
+    /*
+     * Inserts an instruction before the one at `index` (or at the end if `index`
+     * is the length), taking over its labels. Address operands of the new
+     * instruction need a target from `set_jump_target` before the body can be
+     * assembled.
+     */
+    pub fn insert(&mut self, index: usize, ins: HermesInstruction) -> Result<(), String> {
+        let labels = match index.cmp(&self.slots.len()) {
+            std::cmp::Ordering::Less => std::mem::take(&mut self.slots[index].labels),
+            std::cmp::Ordering::Equal => std::mem::take(&mut self.end_labels),
+            std::cmp::Ordering::Greater => {
+                return Err(format!("can't insert at {}", index));
+            }
+        };
+        self.slots.insert(
+            index,
+            Slot {
+                ins,
+                labels,
+                jumps: vec![],
+                table: vec![],
+            },
+        );
+        Ok(())
+    }
+
+    /*
+     * Inserts an instruction before the one at `index` without taking over its
+     * labels - jumps to that instruction skip the new one.
+     */
+    pub fn insert_unlabelled(
+        &mut self,
+        index: usize,
+        ins: HermesInstruction,
+    ) -> Result<(), String> {
+        if index > self.slots.len() {
+            return Err(format!("can't insert at {}", index));
+        }
+        self.slots.insert(
+            index,
+            Slot {
+                ins,
+                labels: vec![],
+                jumps: vec![],
+                table: vec![],
+            },
+        );
+        Ok(())
+    }
+
+    pub fn push(&mut self, ins: HermesInstruction) {
+        self.insert(self.slots.len(), ins).unwrap();
+    }
+
+    /// Removes the instruction at `index`. Its labels move to the instruction after it.
+    pub fn remove(&mut self, index: usize) -> Result<HermesInstruction, String> {
+        if index >= self.slots.len() {
+            return Err(format!("there's no instruction {}", index));
+        }
+        let slot = self.slots.remove(index);
+        match self.slots.get_mut(index) {
+            Some(next) => next.labels.extend(slot.labels),
+            None => self.end_labels.extend(slot.labels),
+        }
+        Ok(slot.ins)
+    }
+
+    /*
+     * Replaces the instruction at `index`, keeping its labels. Jump targets carry
+     * over to the new instruction where it has an address operand in the same
+     * place, and a switch table where it's a SwitchImm too.
+     */
+    pub fn replace(
+        &mut self,
+        index: usize,
+        ins: HermesInstruction,
+    ) -> Result<HermesInstruction, String> {
+        let slot = self
+            .slots
+            .get_mut(index)
+            .ok_or_else(|| format!("there's no instruction {}", index))?;
+
+        let operands = ins.operands();
+        slot.jumps
+            .retain(|(operand, _)| operands.get(*operand).is_some_and(|o| o.kind.is_address()));
+        if ins.name() != "SwitchImm" {
+            slot.table.clear();
+        }
+        Ok(std::mem::replace(&mut slot.ins, ins))
+    }
+
+    /// The instruction an address operand jumps to, or the length if it jumps to the end.
+    pub fn jump_target(&self, index: usize, operand: usize) -> Option<usize> {
+        let (_, label) = self
+            .slots
+            .get(index)?
+            .jumps
+            .iter()
+            .find(|(op, _)| *op == operand)?;
+        self.find_label(*label)
+    }
+
+    /// Points an address operand of the instruction at `index` at instruction `target`.
+    pub fn set_jump_target(
+        &mut self,
+        index: usize,
+        operand: usize,
+        target: usize,
+    ) -> Result<(), String> {
+        let ins = *self
+            .get(index)
+            .ok_or_else(|| format!("there's no instruction {}", index))?;
+        if !ins
+            .operands()
+            .get(operand)
+            .is_some_and(|o| o.kind.is_address())
+        {
+            return Err(format!(
+                "operand {} of {} isn't an address",
+                operand,
+                ins.name()
+            ));
+        }
+
+        let label = self.new_label(target)?;
+        let jumps = &mut self.slots[index].jumps;
+        jumps.retain(|(op, _)| *op != operand);
+        jumps.push((operand, label));
+        Ok(())
+    }
+
+    /// The instructions the cases of the SwitchImm at `index` jump to, from its lowest case up.
+    pub fn switch_targets(&self, index: usize) -> Option<Vec<usize>> {
+        self.slots
+            .get(index)?
+            .table
+            .iter()
+            .map(|&label| self.find_label(label))
+            .collect()
+    }
+
+    /// Sets the case targets of the SwitchImm at `index`, from its lowest case up.
+    pub fn set_switch_targets(&mut self, index: usize, targets: &[usize]) -> Result<(), String> {
+        match self.get(index) {
+            Some(ins) if ins.name() == "SwitchImm" => {}
+            _ => return Err(format!("instruction {} isn't a SwitchImm", index)),
+        }
+
+        let table = targets
+            .iter()
+            .map(|&target| self.new_label(target))
+            .collect::<Result<Vec<Label>, String>>()?;
+        self.slots[index].table = table;
+        Ok(())
+    }
+
+    /// One past the highest register any instruction uses.
+    pub fn register_count(&self) -> u32 {
+        self.slots
+            .iter()
+            .flat_map(|slot| slot.ins.operands())
+            .filter(|op| op.kind.is_register())
+            .map(|op| op.value.as_u32() + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /*
+     * Moves every register up by `by`, leaving registers 0 to `by` - 1 free. The
+     * frame has to grow by as much. Registers keep their place relative to the
+     * end of the frame, where calls take their arguments from.
+     */
+    pub fn shift_registers(&mut self, by: u32) -> Result<(), String> {
+        for slot in self.slots.iter_mut() {
+            for (operand, op) in slot.ins.operands().iter().enumerate() {
+                if !op.kind.is_register() {
+                    continue;
+                }
+                let reg = op.value.as_i64() + by as i64;
+                slot.ins = slot
+                    .ins
+                    .with_operand(operand, OperandValue::Int(reg))
+                    .ok_or_else(|| format!("{} can't use r{}", slot.ins.name(), reg))?;
+            }
+        }
+        Ok(())
+    }
+
+    /*
+     * Takes instructions `start` to `end` (exclusive) out of every exception
+     * handler's range, splitting the ranges they're in the middle of. Exceptions
+     * thrown there are no longer caught by the function.
+     */
+    pub fn exclude_from_handlers(&mut self, start: usize, end: usize) -> Result<(), String> {
+        if start >= end {
+            return Ok(());
+        }
+        let start_label = self.new_label(start)?;
+        let end_label = self.new_label(end)?;
+
+        let mut handlers = vec![];
+        for (from, to, target) in std::mem::take(&mut self.handlers) {
+            let (Some(from_index), Some(to_index)) = (self.find_label(from), self.find_label(to))
+            else {
+                // Left for `assemble` to complain about
+                handlers.push((from, to, target));
+                continue;
+            };
+            if to_index <= start || from_index >= end {
+                handlers.push((from, to, target));
+                continue;
+            }
+            if from_index < start {
+                handlers.push((from, start_label, target));
+            }
+            if to_index > end {
+                handlers.push((end_label, to, target));
+            }
+        }
+        self.handlers = handlers;
+        Ok(())
+    }
+
+    /*
+     * Adds an exception handler for instructions `start` to `end` (exclusive),
+     * landing on the Catch at `target`. It goes ahead of the existing handlers, so
+     * it's the one that catches whatever is thrown in its range.
+     */
+    pub fn add_handler(&mut self, start: usize, end: usize, target: usize) -> Result<(), String> {
+        if start >= end {
+            return Err(format!("handler range {}..{} is empty", start, end));
+        }
+        let start = self.new_label(start)?;
+        let end = self.new_label(end)?;
+        let target = self.new_label(target)?;
+        self.handlers.insert(0, (start, end, target));
+        Ok(())
+    }
+
+    /// A new label on instruction `target`, or on the end if `target` is the length.
+    fn new_label(&mut self, target: usize) -> Result<Label, String> {
+        let label = self.next_label;
+        match target.cmp(&self.slots.len()) {
+            std::cmp::Ordering::Less => self.slots[target].labels.push(label),
+            std::cmp::Ordering::Equal => self.end_labels.push(label),
+            std::cmp::Ordering::Greater => {
+                return Err(format!("there's no instruction {}", target));
+            }
+        }
+        self.next_label += 1;
+        Ok(label)
+    }
+
+    fn find_label(&self, label: Label) -> Option<usize> {
+        if self.end_labels.contains(&label) {
+            return Some(self.slots.len());
+        }
+        self.slots
+            .iter()
+            .position(|slot| slot.labels.contains(&label))
+    }
+
+    /*
+     * Sets an operand of the instruction at `index`, switching it to a wider form
+     * if the value doesn't fit (see `HermesInstruction::with_operand`). Jump
+     * operands are set from their targets by `assemble`, so they can't be set here.
+     */
+    pub fn set_operand(
+        &mut self,
+        index: usize,
+        operand: usize,
+        value: OperandValue,
+    ) -> Result<(), String> {
+        let slot = self
+            .slots
+            .get_mut(index)
+            .ok_or_else(|| format!("there's no instruction {}", index))?;
+        if slot.jumps.iter().any(|(op, _)| *op == operand) {
+            return Err(format!(
+                "operand {} of {} is a jump target",
+                operand,
+                slot.ins.name()
+            ));
+        }
+
+        slot.ins = slot.ins.with_operand(operand, value).ok_or_else(|| {
+            format!(
+                "{} doesn't fit operand {} of any form of {}",
+                value,
+                operand,
+                slot.ins.name()
+            )
+        })?;
+        Ok(())
+    }
+
+    /*
+     * Lays the body out: offsets are worked out from the instruction sizes, jumps
+     * are pointed at their targets (growing into their long form if they have
+     * to, which moves everything after them, so this repeats until nothing
+     * grows), and the switch tables are written after the code.
+     */
+    pub fn assemble(&self) -> Result<AssembledBody, String> {
+        for (i, slot) in self.slots.iter().enumerate() {
+            for (operand, op) in slot.ins.operands().iter().enumerate() {
+                if op.kind.is_address() && !slot.jumps.iter().any(|(o, _)| *o == operand) {
+                    return Err(format!(
+                        "operand {} of {} at {} has no jump target",
+                        operand,
+                        slot.ins.name(),
+                        i
+                    ));
+                }
+            }
+        }
+
+        let mut instructions: Vec<HermesInstruction> =
+            self.slots.iter().map(|slot| slot.ins).collect();
+
+        let mut offsets: Vec<u32> = vec![];
+        let mut code_end: u32;
+        loop {
+            offsets.clear();
+            code_end = 0;
+            for ins in instructions.iter() {
+                offsets.push(code_end);
+                code_end += ins.size() as u32;
+            }
+            let labels = self.labels(&offsets, code_end);
+            let resolve = |target| resolve_label(&labels, target);
+
+            let mut grown = false;
+            for (i, slot) in self.slots.iter().enumerate() {
+                for &(operand, target) in slot.jumps.iter() {
+                    let relative = resolve(target)? as i64 - offsets[i] as i64;
+                    let ins = instructions[i]
+                        .with_operand(operand, OperandValue::Int(relative))
+                        .ok_or_else(|| {
+                            format!("{} can't jump {} bytes", instructions[i].name(), relative)
+                        })?;
+                    grown |= ins.size() != instructions[i].size();
+                    instructions[i] = ins;
+                }
+            }
+
+            if !grown {
+                break;
+            }
+        }
+        let labels = self.labels(&offsets, code_end);
+        let resolve = |target| resolve_label(&labels, target);
+
+        // Switch tables go after the code, each one aligned to 4 bytes in the file
+        let mut tables: Vec<(u32, Vec<u8>)> = vec![];
+        let mut table_offset = code_end;
+        for (i, slot) in self.slots.iter().enumerate() {
+            if slot.ins.name() != "SwitchImm" {
+                continue;
+            }
+            table_offset = (self.base + table_offset).div_ceil(4) * 4 - self.base;
+            let p0 = operand_index(&instructions[i], "p0");
+            instructions[i].set_operand(p0, OperandValue::Int((table_offset - offsets[i]) as i64));
+
+            let mut table = vec![];
+            for &target in slot.table.iter() {
+                let relative = resolve(target)? as i64 - offsets[i] as i64;
+                table.extend_from_slice(&(relative as i32).to_le_bytes());
+            }
+            let size = table.len() as u32;
+            tables.push((table_offset, table));
+            table_offset += size;
+        }
+
+        let mut bytes = vec![];
+        for ins in instructions.iter() {
+            ins.serialize(&mut bytes);
+        }
+        for (offset, table) in tables {
+            bytes.resize(offset as usize, 0);
+            bytes.extend_from_slice(&table);
+        }
+
+        let exception_handlers = self
+            .handlers
+            .iter()
+            .map(|&(start, end, target)| {
+                Ok(ExceptionHandlerInfo {
+                    start: resolve(start)?,
+                    end: resolve(end)?,
+                    target: resolve(target)?,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        let mut addresses: Vec<(u32, u32)> = labels
+            .iter()
+            .filter(|(label, _)| **label < FIRST_NEW_LABEL)
+            .map(|(label, offset)| (*label as u32, *offset))
+            .collect();
+        addresses.sort();
+
+        Ok(AssembledBody {
+            bytes,
+            exception_handlers,
+            addresses,
+        })
+    }
+
+    /// Where every label ends up, given the new offsets.
+    fn labels(&self, offsets: &[u32], code_end: u32) -> HashMap<Label, u32> {
+        let mut labels: HashMap<Label, u32> = HashMap::new();
+        for (slot, offset) in self.slots.iter().zip(offsets.iter()) {
+            for &label in slot.labels.iter() {
+                labels.insert(label, *offset);
+            }
+        }
+        for &label in self.end_labels.iter() {
+            labels.insert(label, code_end);
+        }
+        labels
+    }
+}
+
+fn resolve_label(labels: &HashMap<Label, u32>, target: Label) -> Result<u32, String> {
+    labels.get(&target).copied().ok_or_else(|| {
+        if target < FIRST_NEW_LABEL {
+            format!("offset {} isn't the start of an instruction", target)
+        } else {
+            "a jump target was lost".to_string()
+        }
+    })
+}
+
+fn operand_index(ins: &HermesInstruction, name: &str) -> usize {
+    ins.operands()
+        .iter()
+        .position(|o| o.name == name)
+        .unwrap_or(0)
+}
+
+impl<R> HermesFile<R>
+where
+    R: io::Read + io::BufRead + io::Seek,
+{
+    /*
+     * The body of a function, ready to be changed. Whatever follows the code
+     * (switch tables) is parsed into the instructions that use it.
+     */
+    pub fn function_body(&mut self, idx: usize) -> FunctionBody {
+        let instructions = self.get_function_instructions(idx);
+        let bytes = self.get_function_bytes(idx);
+        let fh = &self.function_headers[idx];
+        let base = fh.offset() % 4;
+        let code = self.code_instruction_count(idx, &instructions);
+
+        let mut slots = vec![];
+        let mut offset = 0u32;
+        for ins in instructions[..code].iter() {
+            let operands = ins.operands();
+            let value = |name: &str| {
+                operands
+                    .iter()
+                    .find(|o| o.name == name)
+                    .map(|o| o.value.as_i64())
+                    .unwrap_or(0)
+            };
+
+            let jumps = operands
+                .iter()
+                .enumerate()
+                .filter(|(_, op)| op.kind.is_address())
+                .map(|(i, op)| (i, (offset as i64 + op.value.as_i64()) as u32 as Label))
+                .collect();
+
+            // SwitchImm r0, p0, p1, p2, p3 - the table at p0 holds a 32-bit offset
+            // for each case from p2 to p3, relative to the SwitchImm
+            let mut table = vec![];
+            if ins.name() == "SwitchImm" {
+                let start = ((base as i64 + offset as i64 + value("p0") + 3) & !3) - base as i64;
+                for case in 0..=(value("p3") - value("p2")).max(-1) {
+                    let at = (start + case * 4) as usize;
+                    let Some(entry) = bytes.get(at..at + 4) else {
+                        break;
+                    };
+                    let relative = i32::from_le_bytes(entry.try_into().unwrap());
+                    table.push((offset as i64 + relative as i64) as u32 as Label);
+                }
+            }
+
+            slots.push(Slot {
+                ins: *ins,
+                labels: vec![offset as Label],
+                jumps,
+                table,
+            });
+            offset += ins.size() as u32;
+        }
+
+        let handlers = fh
+            .exception_handlers()
+            .iter()
+            .map(|h| (h.start as Label, h.end as Label, h.target as Label))
+            .collect();
+
+        FunctionBody {
+            slots,
+            end_labels: vec![offset as Label],
+            handlers,
+            base,
+            next_label: FIRST_NEW_LABEL,
+        }
+    }
+
+    /*
+     * Puts a body laid out by `FunctionBody::assemble` in place of a function's
+     * code, along with its exception handlers (and the flag saying it has any)
+     * and size. The function's source locations in the debug info are moved to
+     * the new addresses.
+     */
+    pub fn set_assembled_body(&mut self, idx: usize, body: AssembledBody) {
+        self.move_locations(idx, &body.addresses);
+
+        let instructions = self.decode_function_bytes(&body.bytes);
+        match self.function_bytecode_position(idx) {
+            Some(pos) => self.function_bytecode[pos].bytecode = instructions,
+            None => self.function_bytecode.push(FunctionInstructions {
+                func_index: idx as u32,
+                is_large: matches!(self.function_headers[idx], FunctionHeader::Large(_)),
+                bytecode: instructions,
+            }),
+        }
+
+        let fh = &mut self.function_headers[idx];
+        fh.set_has_exception_handler(!body.exception_handlers.is_empty());
+        fh.set_exception_handlers(body.exception_handlers);
+        fh.set_byte_size(body.bytes.len() as u32);
+    }
+
+    pub fn set_function_body(&mut self, idx: usize, body: &FunctionBody) -> Result<(), String> {
+        let assembled = body.assemble()?;
+        self.set_assembled_body(idx, assembled);
+        Ok(())
+    }
+
+    /*
+     * Rewrites the debug info locations of a function for its new addresses and
+     * moves everything after them if their size changed. Locations that can't be
+     * read are left alone. Textified callees (v91+) aren't moved.
+     */
+    fn move_locations(&mut self, idx: usize, addresses: &[(u32, u32)]) {
+        let Some(debug_info) = self.function_headers[idx].debug_info() else {
+            return;
+        };
+        let src = debug_info.src();
+
+        // They end where the next function's locations start
+        let end = self
+            .function_headers
+            .iter()
+            .filter_map(|fh| fh.debug_info())
+            .map(|d| d.src())
+            .filter(|&other| other > src)
+            .min()
+            .unwrap_or(self.debug_info.sources_data_storage.len() as u32);
+        let Some(mut locations) = self.debug_info.function_locations(src, end) else {
+            return;
+        };
+
+        let mut previous = 0;
+        for (address, _) in locations.entries.iter_mut() {
+            let moved = match addresses.binary_search_by_key(address, |(old, _)| *old) {
+                Ok(i) => addresses[i].1,
+                Err(0) => *address,
+                Err(i) => addresses[i - 1].1 + (*address - addresses[i - 1].0),
+            };
+            // Removed instructions can leave two locations on the same address
+            *address = moved.max(previous);
+            previous = *address;
+        }
+
+        let shift = self
+            .debug_info
+            .replace_function_locations(src, end, &locations);
+        if shift == 0 {
+            return;
+        }
+        for fh in self.function_headers.iter_mut() {
+            if let Some(mut debug_info) = fh.debug_info() {
+                if debug_info.src() > src {
+                    debug_info.set_src((debug_info.src() as i64 + shift) as u32);
+                    fh.set_debug_info(Some(debug_info));
+                }
+            }
+        }
+    }
+
+    /*
+     * How many of a function's decoded instructions are code. Switch jump tables
+     * are stored after the last instruction and decode as garbage, which has to be
+     * left alone when rewriting operands.
+     */
+    pub(crate) fn code_instruction_count(
+        &self,
+        idx: usize,
+        instructions: &[HermesInstruction],
+    ) -> usize {
+        let fh = &self.function_headers[idx];
+        let mut code_end = u32::MAX;
+        let mut offset = 0u32;
+
+        for (count, ins) in instructions.iter().enumerate() {
+            if offset >= code_end {
+                return count;
+            }
+            if ins.name() == "SwitchImm" {
+                // The table is at p0 from the SwitchImm, aligned to 4 bytes in the file
+                let p0 = ins
+                    .operands()
+                    .iter()
+                    .find(|o| o.name == "p0")
+                    .map(|o| o.value.as_i64())
+                    .unwrap_or(0);
+                let table_abs = (fh.offset() as i64 + offset as i64 + p0 + 3) & !3;
+                let table = table_abs - fh.offset() as i64;
+                if table > offset as i64 {
+                    code_end = code_end.min(table as u32);
+                }
+            }
+            offset += ins.size() as u32;
+        }
+        instructions.len()
+    }
+}
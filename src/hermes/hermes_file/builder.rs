@@ -6,13 +6,14 @@ use super::HermesFile;
 use crate::hermes::big_int_table::BigIntTableEntry;
 use crate::hermes::debug_info::DebugStringTable;
 use crate::hermes::function_header::{FunctionHeader, LargeFunctionHeader, SmallFunctionHeader};
-use crate::hermes::jenkins::hash_string;
 use crate::hermes::string_kind::{
     StringKind, StringKindEntry, StringKindEntryNew, StringKindEntryOld,
 };
+use crate::hermes::string_table::{
+    build_string_storage, build_string_tables, HermesString, StringTableBuilder,
+};
 use crate::hermes::IntoParentInstruction;
 use crate::hermes::OverflowStringTableEntry;
-use crate::hermes::SmallStringTableEntry;
 use crate::hermes::{HermesInstruction, InstructionParser};
 
 #[cfg_attr(feature = "specta", derive(specta::Type))]
@@ -38,246 +39,52 @@ where
         }
     }
 
-    pub fn set_string_pairs_unordered(&mut self, mut pairs: Vec<StringTypePair>) {
-        let mut string_storage: Vec<SmallStringTableEntry> = vec![];
-        let mut overflow_string_storage: Vec<OverflowStringTableEntry> = vec![];
-        let mut string_storage_bytes: Vec<u8> = vec![];
-        let mut identifier_hashes: Vec<u32> = vec![];
-
-        let mut string_count = 0;
-        let mut identifier_count = 0;
-        let mut predefined_count = 0;
-
-        let mut last_kind = StringKind::String;
-
-        for (pidx, pair) in pairs.iter_mut().enumerate() {
-            // Set the last_kind to the first kind in the list to build out the sections
-            if pidx == 0 {
-                last_kind = pair.kind;
-            }
-
-            let string = &pair.string;
-            let is_utf_16 = string.chars().any(|c| c as u32 > 0x10000);
-            let offset = string_storage_bytes.len() as u32;
-            let mut length = string.len() as u32;
-
-            if !is_utf_16 {
-                string_storage_bytes.extend(string.as_bytes());
-            } else {
-                let utf16_bytes = string.encode_utf16().collect::<Vec<u16>>();
-                let mut byte_length = 0;
-                for code_unit in &utf16_bytes {
-                    let b = &code_unit.to_le_bytes();
-                    string_storage_bytes.extend_from_slice(b);
-                    byte_length += 1;
-                }
-                length = byte_length as u32;
-            }
-
-            // If the string is >= 255 characters long, it needs to be stored in
-            // the overflow string storage area. The offset gets updated to the index
-            // of this string in the OverflowStringTable, and the length is set to 255
-            // so that the runtime knows to look in the overflow string storage area.
-            if length >= 255 {
-                // set the current string length to 255
-                let small_length = 255;
-                let small_offset = overflow_string_storage.len() as u32;
-
-                string_storage.push(SmallStringTableEntry {
-                    is_utf_16,
-                    offset: small_offset,
-                    length: small_length,
-                });
-
-                overflow_string_storage.push(OverflowStringTableEntry { offset, length });
-            } else {
-                string_storage.push(SmallStringTableEntry {
-                    is_utf_16,
-                    offset,
-                    length,
-                });
-            };
-
-            /*
-             * This logic looks kind of wonky, but I promise it needs to be here.
-             * Some strings get loaded up in chunks based on their kind.
-             * This is more common in larger React Native bundles, which is why it's here.
-             * Example:
-             *  - We have 10 strings of kind String, then 10 strings of kind Identifier.
-             *  - The first 5 Identifiers get defined first, then 10 Strings, followed by
-             *   the last 5 Identifiers.
-             *  I had assumed it was always String -> Identifier -> Predefined, but that's not the case.
-             */
-            match pair.kind {
-                StringKind::String => {
-                    match last_kind {
-                        StringKind::String => {
-                            string_count += 1;
-                            last_kind = StringKind::String;
-                        }
-                        _ => {
-                            if string_count > 0 {
-                                self.push_string_kind(last_kind, string_count);
-                                string_count = 0;
-                                continue;
-                            }
-                            string_count = 1;
-                        }
-                    };
-                }
-                StringKind::Identifier => {
-                    let ihash = hash_string(string.as_str());
-                    if !identifier_hashes.contains(&ihash) {
-                        identifier_hashes.push(ihash);
-                    }
-                    match last_kind {
-                        StringKind::Identifier => {
-                            identifier_count += 1;
-                            last_kind = StringKind::Identifier;
-                        }
-                        _ => {
-                            if identifier_count > 0 {
-                                self.push_string_kind(last_kind, identifier_count);
-                                identifier_count = 0;
-                                continue;
-                            }
-                            identifier_count = 1;
-                        }
-                    };
-                }
-                StringKind::Predefined => {
-                    match last_kind {
-                        StringKind::Predefined => {
-                            predefined_count += 1;
-                            last_kind = StringKind::Predefined;
-                        }
-                        _ => {
-                            if predefined_count > 0 {
-                                self.push_string_kind(last_kind, predefined_count);
-                                predefined_count = 0;
-                                continue;
-                            }
-                            predefined_count = 1;
-                        }
-                    };
-                }
-            }
+    /*
+     * Replaces the string table with `pairs`, in order. Strings aren't always
+     * grouped by kind - larger React Native bundles mix runs of Strings and
+     * Identifiers - so every run of the same kind gets its own StringKindEntry.
+     * Identifiers get their hashes, and every string-related header count is
+     * updated. Each pair gets its own ID, duplicates included.
+     *
+     * String references in the code aren't touched, see `replace_string_pairs`.
+     */
+    pub fn set_string_pairs_unordered(&mut self, pairs: Vec<StringTypePair>) {
+        let mut strings = StringTableBuilder::new();
+        strings.pack = self.pack_strings;
+        for pair in pairs.iter() {
+            strings.push(&pair.string, pair.kind);
         }
-
-        self.string_storage = string_storage;
-        self.overflow_string_storage = overflow_string_storage;
-
-        self.header.string_count = self.string_storage.len() as u32;
-        self.header.overflow_string_count = self.overflow_string_storage.len() as u32;
-
-        // self.identifier_hashes = identifier_hashes;
-
-        self.string_storage_bytes = string_storage_bytes;
+        self.set_string_table(&strings);
     }
 
-    // TODO: need to append identifiers
+    /*
+     * Same as `set_string_pairs_unordered` with the strings first, then the
+     * identifiers, then the predefined strings - each taking the kind of the
+     * list it's in.
+     */
     pub fn set_strings_ordered(
         &mut self,
         strings: Vec<StringTypePair>,
         identifiers: Vec<StringTypePair>,
         predefined: Vec<StringTypePair>,
     ) {
-        let mut string_storage: Vec<SmallStringTableEntry> = vec![];
-        let mut string_storage_bytes: Vec<u8> = vec![];
-
-        let mut string_count = 0;
-        let mut identifier_count = 0;
-        let mut predefined_count = 0;
-
-        for pair in strings {
-            let string = pair.string;
-            let is_utf_16 = string.chars().any(|c| c as u32 > 0x10000);
-            let offset = string_storage_bytes.len() as u32;
-            let length = string.len() as u32;
-
-            string_storage.push(SmallStringTableEntry {
-                is_utf_16,
-                offset,
-                length,
-            });
-
-            string_storage_bytes.extend(string.as_bytes());
-
-            string_count += 1;
-        }
-
-        for pair in identifiers {
-            let string = pair.string;
-            let is_utf_16 = string.chars().any(|c| c as u32 > 0x10000);
-            let offset = string_storage_bytes.len() as u32;
-            let length = string.len() as u32;
-
-            string_storage.push(SmallStringTableEntry {
-                is_utf_16,
-                offset,
-                length,
-            });
-
-            string_storage_bytes.extend(string.as_bytes());
-
-            identifier_count += 1;
-        }
-
-        for pair in predefined {
-            let string = pair.string;
-            let is_utf_16 = string.chars().any(|c| c as u32 > 0x10000);
-            let offset = string_storage_bytes.len() as u32;
-            let length = string.len() as u32;
-
-            string_storage.push(SmallStringTableEntry {
-                is_utf_16,
-                offset,
-                length,
-            });
-
-            string_storage_bytes.extend(string.as_bytes());
-
-            predefined_count += 1;
-        }
-
-        self.string_kinds = vec![];
-
-        self.push_string_kind(StringKind::String, string_count);
-        self.push_string_kind(StringKind::Identifier, identifier_count);
-        self.push_string_kind(StringKind::Predefined, predefined_count);
-
-        let ssl = string_storage.len() as u32;
-        self.string_storage = string_storage;
-        self.header.string_count = ssl;
-
-        self.string_storage_bytes = string_storage_bytes;
-
-        // self.set_strings(strings);
-        // self.set_overflow_string(overflow_strings);
+        let with_kind = |pairs: Vec<StringTypePair>, kind: StringKind| {
+            pairs
+                .into_iter()
+                .map(move |pair| StringTypePair { kind, ..pair })
+        };
+        let pairs = with_kind(strings, StringKind::String)
+            .chain(with_kind(identifiers, StringKind::Identifier))
+            .chain(with_kind(predefined, StringKind::Predefined))
+            .collect();
+        self.set_string_pairs_unordered(pairs);
     }
 
     // Set the strings present in the HermesFile, build out String Table, etc...
     pub fn set_strings(&mut self, strings: Vec<String>) {
-        let mut string_storage: Vec<SmallStringTableEntry> = vec![];
-        let mut string_storage_bytes = vec![];
-        for string in strings {
-            // check if string is utf-16
-            let is_utf_16 = string.chars().any(|c| c as u32 > 0x10000);
-            let offset = string_storage_bytes.len() as u32;
-            let length = string.len() as u32;
-            string_storage.push(SmallStringTableEntry {
-                is_utf_16,
-                offset,
-                length,
-            });
-            string_storage_bytes.extend(string.as_bytes());
-        }
+        let all: Vec<&str> = strings.iter().map(|s| s.as_str()).collect();
+        self.set_string_storage(&all);
 
-        // pad an extra 10000000 bytes to the end of the string storage for testing.
-        // string_storage_bytes.extend(vec![0; 10000000]);
-        let ssl = string_storage.len() as u32;
-        self.string_storage = string_storage;
-        self.header.string_count = ssl;
         for sk in self.string_kinds.iter_mut() {
             match sk {
                 StringKindEntry::Old(old) => match old.kind {
@@ -294,19 +101,70 @@ where
                 },
             }
         }
-
-        self.string_storage_bytes = string_storage_bytes;
     }
 
-    // Set the overflow strings, OverflowStringTable, ...
-    pub fn set_overflow_string(&mut self, overflow_strings: Vec<String>) {
-        let mut overflow_string_storage: Vec<OverflowStringTableEntry> = vec![];
+    /*
+     * Appends the strings to string storage with an overflow string table entry
+     * each, and returns the index of the first entry. Entries already in the
+     * table, like those `set_string_pairs_unordered` made, are kept.
+     */
+    pub fn set_overflow_string(&mut self, overflow_strings: Vec<String>) -> u32 {
+        let first = self.overflow_string_storage.len() as u32;
         for string in overflow_strings {
-            let offset = overflow_string_storage.len() as u32;
-            let length = string.len() as u32;
-            overflow_string_storage.push(OverflowStringTableEntry { offset, length });
+            let stored = HermesString::encode(&string);
+            if stored.is_utf_16() && !self.string_storage_bytes.len().is_multiple_of(2) {
+                self.string_storage_bytes.push(0);
+            }
+            let offset = self.string_storage_bytes.len() as u32;
+            let length = stored.len();
+            let (bytes, _) = build_string_storage(&[stored], false);
+            self.string_storage_bytes.extend(bytes);
+            self.overflow_string_storage
+                .push(OverflowStringTableEntry { offset, length });
         }
+
+        self.header.overflow_string_count = self.overflow_string_storage.len() as u32;
+        self.header.string_storage_size = self.string_storage_bytes.len() as u32;
+        first
+    }
+
+    /*
+     * Builds the string table, overflow string table and string storage for the
+     * strings, in order. Identical strings and strings that are a suffix of
+     * another share storage unless `pack_strings` is turned off.
+     */
+    fn set_string_storage(&mut self, strings: &[&str]) {
+        let stored: Vec<HermesString> = strings.iter().map(|s| HermesString::encode(s)).collect();
+        let (string_storage, overflow_string_storage, string_storage_bytes) =
+            build_string_tables(&stored, self.pack_strings);
+
+        self.string_storage = string_storage;
         self.overflow_string_storage = overflow_string_storage;
+        self.string_storage_bytes = string_storage_bytes;
+
+        self.header.string_count = self.string_storage.len() as u32;
+        self.header.overflow_string_count = self.overflow_string_storage.len() as u32;
+        self.header.string_storage_size = self.string_storage_bytes.len() as u32;
+    }
+
+    /*
+     * Replaces all the string sections with the ones built by `strings`, and updates
+     * every string-related header count to match.
+     */
+    pub fn set_string_table(&mut self, strings: &StringTableBuilder) {
+        let table = strings.build(self.header.version);
+
+        self.string_kinds = table.string_kinds;
+        self.identifier_hashes = table.identifier_hashes;
+        self.string_storage = table.string_storage;
+        self.overflow_string_storage = table.overflow_string_storage;
+        self.string_storage_bytes = table.string_storage_bytes;
+
+        self.header.string_kind_count = self.string_kinds.len() as u32;
+        self.header.identifier_count = self.identifier_hashes.len() as u32;
+        self.header.string_count = self.string_storage.len() as u32;
+        self.header.overflow_string_count = self.overflow_string_storage.len() as u32;
+        self.header.string_storage_size = self.string_storage_bytes.len() as u32;
     }
 
     pub fn set_big_ints(&mut self, big_ints: Vec<u64>) {
@@ -392,6 +250,12 @@ where
     pub fn update_header(&mut self) {
         self.header.function_count = self.function_headers.len() as u32;
 
+        self.header.string_kind_count = self.string_kinds.len() as u32;
+        self.header.identifier_count = self.identifier_hashes.len() as u32;
+        self.header.string_count = self.string_storage.len() as u32;
+        self.header.overflow_string_count = self.overflow_string_storage.len() as u32;
+        self.header.string_storage_size = self.string_storage_bytes.len() as u32;
+
         self.header.big_int_count = self.big_int_table.len() as u32;
         self.header.big_int_storage_size = self.big_int_storage.len() as u32;
         self.header.reg_exp_count = self.reg_exp_table.len() as u32;
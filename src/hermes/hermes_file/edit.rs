@@ -0,0 +1,286 @@
+use std::io;
+
+use super::body::AssembledBody;
+use super::builder::StringTypePair;
+use super::HermesFile;
+use crate::hermes::array_parser::remap_literal_strings;
+use crate::hermes::cjs_module::CJSModule;
+use crate::hermes::string_kind::{StringKind, StringKindEntry};
+use crate::hermes::string_table::StringTableBuilder;
+use crate::hermes::types::OperandValue;
+
+/*
+ * Editing a file in place.
+ *
+ * Strings are referred to by ID from instructions, the literal buffers, function
+ * headers and the CJS module and function source tables. Replacing the string
+ * table goes through `replace_strings`, which works out where every string ended
+ * up and rewrites all of those references to match.
+ */
+
+/*
+ * Looks up the new ID of a string. `used_by` describes the reference for the
+ * error message.
+ */
+fn remap_id<F>(map: &[Option<u32>], id: u32, used_by: F) -> Result<u32, String>
+where
+    F: FnOnce() -> String,
+{
+    match map.get(id as usize) {
+        Some(Some(new_id)) => Ok(*new_id),
+        Some(None) => Err(format!(
+            "string {} was removed, but {} still uses it",
+            id,
+            used_by()
+        )),
+        None => Err(format!(
+            "{} uses string {}, which doesn't exist",
+            used_by(),
+            id
+        )),
+    }
+}
+
+/*
+ * Which literal buffer an operand points into, if it's a buffer offset: 0 for
+ * the array buffer, 1 for object keys and 2 for object values.
+ */
+fn literal_buffer(name: &str, operand: &str) -> Option<usize> {
+    match (name, operand) {
+        ("NewArrayWithBuffer" | "NewArrayWithBufferLong", "p2") => Some(0),
+        ("NewObjectWithBuffer" | "NewObjectWithBufferLong", "p2") => Some(1),
+        ("NewObjectWithBuffer" | "NewObjectWithBufferLong", "p3") => Some(2),
+        _ => None,
+    }
+}
+
+impl<R> HermesFile<R>
+where
+    R: io::Read + io::BufRead + io::Seek,
+{
+    /*
+     * A StringTableBuilder holding the strings of this file, exactly as they're
+     * stored and with their kinds. Unless the file has duplicate strings, every
+     * string keeps its ID - add, remove or reorder strings on it and hand it to
+     * `replace_strings`. Fails if a string can't be read.
+     */
+    pub fn string_table_builder(&self) -> Result<StringTableBuilder, String> {
+        let mut builder = StringTableBuilder::new();
+        builder.pack = self.pack_strings;
+
+        let mut strings = self
+            .get_hermes_strings()
+            .map_err(|e| e.to_string())?
+            .into_iter();
+        for entry in self.string_kinds.iter() {
+            let kind = match entry {
+                StringKindEntry::Old(run) => run.kind,
+                StringKindEntry::New(run) => run.kind,
+            };
+            for string in strings.by_ref().take(entry.get_length() as usize) {
+                builder.intern_hermes_string(string, kind);
+            }
+        }
+        for string in strings {
+            builder.intern_hermes_string(string, StringKind::String);
+        }
+        Ok(builder)
+    }
+
+    /*
+     * Replaces the string table with `strings` and points every reference to a
+     * string at its new ID. Strings are matched by their contents.
+     *
+     * Returns the old -> new ID map, with `None` for strings that are gone.
+     * Instructions whose new string ID or literal buffer offset doesn't fit switch
+     * to their wider form. If a removed string is still referenced, nothing is
+     * changed and an error is returned.
+     */
+    pub fn replace_strings(
+        &mut self,
+        strings: &StringTableBuilder,
+    ) -> Result<Vec<Option<u32>>, String> {
+        let map: Vec<Option<u32>> = self
+            .get_hermes_strings()
+            .map_err(|e| e.to_string())?
+            .iter()
+            .map(|string| strings.find(string))
+            .collect();
+
+        self.remap_string_ids(&map)?;
+        self.set_string_table(strings);
+        Ok(map)
+    }
+
+    /*
+     * Adds strings to the string table, unless they're already there, and returns
+     * their IDs. The strings that were there keep their IDs.
+     */
+    pub fn add_strings(&mut self, strings: &[(&str, StringKind)]) -> Result<Vec<u32>, String> {
+        let mut builder = self.string_table_builder()?;
+        let ids = strings
+            .iter()
+            .map(|(string, kind)| builder.intern(string, *kind))
+            .collect();
+
+        // Nothing moved unless the file had duplicate strings
+        if self
+            .get_hermes_strings()
+            .map_err(|e| e.to_string())?
+            .iter()
+            .enumerate()
+            .all(|(id, string)| builder.find(string) == Some(id as u32))
+        {
+            self.set_string_table(&builder);
+        } else {
+            self.replace_strings(&builder)?;
+        }
+        Ok(ids)
+    }
+
+    /*
+     * Like `set_string_pairs_unordered`, but with every string reference in the
+     * file updated to match - see `replace_strings`.
+     */
+    pub fn replace_string_pairs(
+        &mut self,
+        pairs: Vec<StringTypePair>,
+    ) -> Result<Vec<Option<u32>>, String> {
+        let mut strings = StringTableBuilder::new();
+        strings.pack = self.pack_strings;
+        for pair in pairs.iter() {
+            strings.intern(&pair.string, pair.kind);
+        }
+        self.replace_strings(&strings)
+    }
+
+    /*
+     * Rewrites every string reference in the file through `map` (old ID -> new
+     * ID, `None` for a removed string) without touching the string table itself:
+     * string operands of every function, strings in the array/object literal
+     * buffers, function names, CJS module names and function sources. Literal
+     * buffers that grow move the buffer offsets of the instructions using them,
+     * widening `NewArrayWithBuffer` and `NewObjectWithBuffer` to their long forms
+     * if they have to.
+     *
+     * Everything is checked before anything is changed, so on error the file is
+     * left as it was.
+     */
+    pub fn remap_string_ids(&mut self, map: &[Option<u32>]) -> Result<(), String> {
+        // The literal buffers first: strings that outgrow their tag make a buffer
+        // grow, which moves the literals instructions point at
+        let mut buffers = vec![];
+        for (name, buf) in [
+            ("array buffer", &self.array_buffer_storage),
+            ("object key buffer", &self.object_key_buffer),
+            ("object value buffer", &self.object_val_buffer),
+        ] {
+            buffers.push(
+                remap_literal_strings(buf, |id| remap_id(map, id, || format!("the {}", name)))
+                    .map_err(|e| format!("{}: {}", name, e))?,
+            );
+        }
+
+        // Functions with a string or buffer operand that changed, laid out again. A
+        // value that doesn't fit its operand any more switches the instruction to a
+        // wider form.
+        let mut bodies: Vec<(usize, AssembledBody)> = vec![];
+        for idx in 0..self.function_headers.len() {
+            let mut body = self.function_body(idx);
+
+            let mut changed = false;
+            for i in 0..body.len() {
+                let ins = *body.get(i).unwrap();
+                let name = ins.name();
+                for (operand, op) in ins.operands().iter().enumerate() {
+                    let value = op.value.as_u32();
+                    let new_value = if op.kind.is_string_id() {
+                        remap_id(map, value, || format!("{} in function {}", name, idx))?
+                    } else if let Some(buffer) = literal_buffer(name, op.name) {
+                        let (_, offsets) = &buffers[buffer];
+                        *offsets.get(&value).ok_or_else(|| {
+                            format!(
+                                "{} in function {} points into the middle of a literal",
+                                name, idx
+                            )
+                        })?
+                    } else {
+                        continue;
+                    };
+
+                    if new_value != value {
+                        body.set_operand(i, operand, OperandValue::Int(new_value as i64))
+                            .map_err(|e| format!("function {}: {}", idx, e))?;
+                        changed = true;
+                    }
+                }
+            }
+
+            if changed {
+                let assembled = body
+                    .assemble()
+                    .map_err(|e| format!("function {}: {}", idx, e))?;
+                bodies.push((idx, assembled));
+            }
+        }
+
+        let func_names = self
+            .function_headers
+            .iter()
+            .enumerate()
+            .map(|(idx, fh)| {
+                remap_id(map, fh.func_name(), || {
+                    format!("the name of function {}", idx)
+                })
+            })
+            .collect::<Result<Vec<u32>, String>>()?;
+
+        let cjs_symbols = self
+            .cjs_modules
+            .iter()
+            .map(|module| match module {
+                CJSModule::CJSModuleEntry(entry) => {
+                    remap_id(map, entry.symbol_id, || "a CJS module".to_string()).map(Some)
+                }
+                // Statically resolved modules only hold a function ID
+                CJSModule::CJSModuleInt(_) => Ok(None),
+            })
+            .collect::<Result<Vec<Option<u32>>, String>>()?;
+
+        let sources = self
+            .function_source_entries
+            .iter()
+            .map(|entry| {
+                remap_id(map, entry.string_id, || {
+                    format!("the source of function {}", entry.function_id)
+                })
+            })
+            .collect::<Result<Vec<u32>, String>>()?;
+
+        // Everything fits, apply it
+        for (idx, body) in bodies {
+            self.set_assembled_body(idx, body);
+        }
+
+        let mut buffers = buffers.into_iter().map(|(buf, _)| buf);
+        self.array_buffer_storage = buffers.next().unwrap_or_default();
+        self.object_key_buffer = buffers.next().unwrap_or_default();
+        self.object_val_buffer = buffers.next().unwrap_or_default();
+
+        for (fh, func_name) in self.function_headers.iter_mut().zip(func_names) {
+            fh.set_func_name(func_name);
+        }
+
+        for (module, symbol_id) in self.cjs_modules.iter_mut().zip(cjs_symbols) {
+            if let (CJSModule::CJSModuleEntry(entry), Some(symbol_id)) = (module, symbol_id) {
+                entry.symbol_id = symbol_id;
+            }
+        }
+
+        for (entry, string_id) in self.function_source_entries.iter_mut().zip(sources) {
+            entry.string_id = string_id;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,601 @@
+use std::collections::HashMap;
+use std::io;
+
+use super::body::FunctionBody;
+use super::HermesFile;
+use crate::hermes::function_header::{
+    FunctionHeader, FunctionHeaderFlag, FunctionHeaderFlagProhibitions, SmallFunctionHeader,
+};
+use crate::hermes::string_kind::StringKind;
+use crate::hermes::types::{OperandKind, OperandValue};
+use crate::hermes::HermesInstruction;
+
+/*
+ * Adding code to a file: new functions, built from instructions or a snippet of
+ * disassembly, and calls to them from existing functions.
+ *
+ * Code added to an existing function gets registers of its own at the bottom of
+ * the frame. Every register the function already used moves up to make room,
+ * so they keep their place relative to the end of the frame - where Call takes
+ * its arguments from.
+ */
+
+/*
+ * A snippet is written like the disassembly, one instruction per line:
+ *
+ *   # comments start with # or //
+ *       GetGlobalObject r0
+ *       LoadParam r1, 1
+ *       JmpUndefined done, r1
+ *       PutById r0, r1, 1, "lastArgument"
+ *   done:
+ *       Ret r1
+ *
+ * Operands are separated by commas. Registers are `r3`, strings are quoted (and
+ * added to the string table if they aren't there yet), jump targets are labels
+ * and anything else is a number. An instruction switches to its wider form if an
+ * operand doesn't fit.
+ */
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Register(u32),
+    String(String),
+    Label(String),
+    Number(f64),
+}
+
+struct SnippetLine {
+    line_no: usize,
+    name: String,
+    operands: Vec<Token>,
+}
+
+fn parse_snippet(text: &str) -> Result<(Vec<SnippetLine>, HashMap<String, usize>), String> {
+    let mut lines = vec![];
+    let mut labels: HashMap<String, usize> = HashMap::new();
+
+    for (line_no, line) in text.lines().enumerate() {
+        let line_no = line_no + 1;
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(label) = line.strip_suffix(':') {
+            if !is_label(label) {
+                return Err(format!("line {}: `{}` isn't a valid label", line_no, label));
+            }
+            if labels.insert(label.to_string(), lines.len()).is_some() {
+                return Err(format!(
+                    "line {}: label `{}` is already used",
+                    line_no, label
+                ));
+            }
+            continue;
+        }
+
+        let (name, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let operands = split_operands(rest)
+            .map_err(|e| format!("line {}: {}", line_no, e))?
+            .iter()
+            .map(|operand| parse_token(operand))
+            .collect::<Result<Vec<Token>, String>>()
+            .map_err(|e| format!("line {}: {}", line_no, e))?;
+        lines.push(SnippetLine {
+            line_no,
+            name: name.to_string(),
+            operands,
+        });
+    }
+
+    Ok((lines, labels))
+}
+
+fn is_label(text: &str) -> bool {
+    text.chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && text.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// `#` and `//` start a comment, unless inside a string
+fn strip_comment(line: &str) -> &str {
+    let bytes = line.as_bytes();
+    let mut in_string = false;
+    let mut i = 0;
+    while i < bytes.len() {
+        match (in_string, bytes[i]) {
+            (true, b'\\') => i += 1,
+            (_, b'"') => in_string = !in_string,
+            (false, b'#') => return &line[..i],
+            (false, b'/') if bytes.get(i + 1) == Some(&b'/') => return &line[..i],
+            _ => {}
+        }
+        i += 1;
+    }
+    line
+}
+
+fn split_operands(text: &str) -> Result<Vec<String>, String> {
+    let mut operands = vec![];
+    let mut current = String::new();
+    let mut in_string = false;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if in_string => {
+                current.push(c);
+                current.extend(chars.next());
+            }
+            '"' => {
+                in_string = !in_string;
+                current.push(c);
+            }
+            ',' if !in_string => operands.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    if in_string {
+        return Err("unterminated string".to_string());
+    }
+    if !current.trim().is_empty() || !operands.is_empty() {
+        operands.push(current);
+    }
+    Ok(operands.iter().map(|o| o.trim().to_string()).collect())
+}
+
+fn parse_token(text: &str) -> Result<Token, String> {
+    if let Some(string) = text.strip_prefix('"') {
+        let string = string
+            .strip_suffix('"')
+            .ok_or_else(|| format!("unterminated string {}", text))?;
+        let mut unescaped = String::new();
+        let mut chars = string.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                unescaped.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('n') => unescaped.push('\n'),
+                Some('t') => unescaped.push('\t'),
+                Some(c) => unescaped.push(c),
+                None => return Err(format!("unterminated string {}", text)),
+            }
+        }
+        return Ok(Token::String(unescaped));
+    }
+    if let Some(reg) = text.strip_prefix('r') {
+        if let Ok(reg) = reg.parse::<u32>() {
+            return Ok(Token::Register(reg));
+        }
+    }
+    if let Ok(n) = text.parse::<f64>() {
+        return Ok(Token::Number(n));
+    }
+    if is_label(text) {
+        return Ok(Token::Label(text.to_string()));
+    }
+    Err(format!("can't parse operand `{}`", text))
+}
+
+// Property names are identifiers, like the compiler makes them
+fn string_kind(instruction: &str) -> StringKind {
+    if instruction.contains("ById") {
+        StringKind::Identifier
+    } else {
+        StringKind::String
+    }
+}
+
+fn instruction(version: u32, name: &str, operands: &[i64]) -> Result<HermesInstruction, String> {
+    let mut ins = HermesInstruction::named(version, name)
+        .ok_or_else(|| format!("HBC version {} doesn't have {}", version, name))?;
+    for (i, value) in operands.iter().enumerate() {
+        ins = ins
+            .with_operand(i, OperandValue::Int(*value))
+            .ok_or_else(|| format!("{} doesn't fit operand {} of {}", value, i, name))?;
+    }
+    Ok(ins)
+}
+
+/*
+ * `code` writing register `reg`, through `scratch` if `reg` is too high for the
+ * instructions it uses.
+ */
+fn write_register<F>(
+    version: u32,
+    reg: u32,
+    scratch: u32,
+    code: F,
+) -> Result<Vec<HermesInstruction>, String>
+where
+    F: Fn(u32) -> Result<Vec<HermesInstruction>, String>,
+{
+    if let Ok(code) = code(reg) {
+        return Ok(code);
+    }
+    let mut code = code(scratch)?;
+    code.push(instruction(version, "Mov", &[reg as i64, scratch as i64])?);
+    Ok(code)
+}
+
+impl<R> HermesFile<R>
+where
+    R: io::Read + io::BufRead + io::Seek,
+{
+    /*
+     * Builds a function body from a snippet of disassembly (see the top of this
+     * file), for this file's HBC version. Strings it uses are added to the string
+     * table.
+     */
+    pub fn assemble_snippet(&mut self, text: &str) -> Result<FunctionBody, String> {
+        let (lines, labels) = parse_snippet(text)?;
+
+        let strings: Vec<(&str, StringKind)> = lines
+            .iter()
+            .flat_map(|line| {
+                line.operands.iter().filter_map(|token| match token {
+                    Token::String(s) => Some((s.as_str(), string_kind(&line.name))),
+                    _ => None,
+                })
+            })
+            .collect();
+        let string_ids: HashMap<&str, u32> = strings
+            .iter()
+            .map(|(s, _)| *s)
+            .zip(self.add_strings(&strings)?)
+            .collect();
+
+        let mut body = FunctionBody::new();
+        let mut jumps = vec![];
+        for line in lines.iter() {
+            let at = |e: String| format!("line {}: {}", line.line_no, e);
+            let mut ins = HermesInstruction::named(self.header.version, &line.name)
+                .ok_or_else(|| at(format!("unknown instruction `{}`", line.name)))?;
+
+            let kinds: Vec<_> = ins.operands().iter().map(|op| op.kind).collect();
+            if kinds.len() != line.operands.len() {
+                return Err(at(format!(
+                    "{} takes {} operands, not {}",
+                    line.name,
+                    kinds.len(),
+                    line.operands.len()
+                )));
+            }
+
+            for (i, (kind, token)) in kinds.iter().zip(line.operands.iter()).enumerate() {
+                let value = match token {
+                    Token::Label(label) if kind.is_address() => {
+                        jumps.push((body.len(), i, label, line.line_no));
+                        continue;
+                    }
+                    _ if kind.is_address() => {
+                        return Err(at(format!("operand {} is a jump, give it a label", i)));
+                    }
+                    Token::Register(reg) if kind.is_register() => OperandValue::Int(*reg as i64),
+                    _ if kind.is_register() => {
+                        return Err(at(format!("operand {} is a register", i)));
+                    }
+                    Token::String(s) if kind.is_string_id() => {
+                        OperandValue::Int(string_ids[s.as_str()] as i64)
+                    }
+                    Token::Number(n) if *kind == OperandKind::Double => OperandValue::Double(*n),
+                    Token::Number(n) if n.fract() == 0.0 => OperandValue::Int(*n as i64),
+                    _ => return Err(at(format!("can't use {:?} as operand {}", token, i))),
+                };
+                ins = ins.with_operand(i, value).ok_or_else(|| {
+                    at(format!(
+                        "{} doesn't fit operand {} of any form of {}",
+                        value, i, line.name
+                    ))
+                })?;
+            }
+            body.push(ins);
+        }
+
+        for (index, operand, label, line_no) in jumps {
+            let target = labels
+                .get(label.as_str())
+                .ok_or_else(|| format!("line {}: there's no label `{}`", line_no, label))?;
+            body.set_jump_target(index, operand, *target)?;
+        }
+        Ok(body)
+    }
+
+    /*
+     * Adds a new function named `name` with `body` as its code, and returns its
+     * ID. `param_count` counts `this`. The frame is as big as the registers the
+     * body uses, and the property caches as big as its cache indices.
+     */
+    pub fn add_function_body(
+        &mut self,
+        name: &str,
+        param_count: u32,
+        body: &FunctionBody,
+    ) -> Result<u32, String> {
+        let assembled = body.assemble()?;
+        let func_name = self.add_strings(&[(name, StringKind::String)])?[0];
+
+        let mut highest_read_cache_index = 0;
+        let mut highest_write_cache_index = 0;
+        for ins in body.instructions() {
+            let cache_index = ins.operand("p0").map(|op| op.value.as_u32()).unwrap_or(0);
+            match ins.name() {
+                "GetByIdShort" | "GetById" | "GetByIdLong" | "TryGetById" | "TryGetByIdLong" => {
+                    highest_read_cache_index = highest_read_cache_index.max(cache_index);
+                }
+                "PutById" | "PutByIdLong" | "TryPutById" | "TryPutByIdLong" => {
+                    highest_write_cache_index = highest_write_cache_index.max(cache_index);
+                }
+                _ => {}
+            }
+        }
+
+        let fh = SmallFunctionHeader {
+            offset: 0,
+            param_count,
+            byte_size: 0,
+            func_name,
+            info_offset: 0,
+            frame_size: body.register_count(),
+            env_size: 0,
+            highest_read_cache_index,
+            highest_write_cache_index,
+            flags: FunctionHeaderFlag {
+                prohibit_invoke: FunctionHeaderFlagProhibitions::ProhibitNone,
+                strict_mode: true,
+                has_exception_handler: !assembled.exception_handlers.is_empty(),
+                has_debug_info: false,
+                overflowed: false,
+            },
+            exception_handlers: vec![],
+            debug_info: None,
+        };
+
+        let idx = self.function_headers.len();
+        self.function_headers.push(FunctionHeader::Small(fh));
+        self.header.function_count = self.function_headers.len() as u32;
+        self.set_assembled_body(idx, assembled);
+        Ok(idx as u32)
+    }
+
+    /*
+     * Makes function `idx` call function `callee` before anything else, with its
+     * own `this` and arguments. The call's result is dropped, and jumps to the
+     * function's first instruction land after it, so loops don't repeat it.
+     *
+     * The closure is created in the function's parent environment, so `callee`
+     * shouldn't expect an environment of its own. Generators are refused: they
+     * run from the top each time they're resumed, so the hook would run again.
+     */
+    pub fn call_on_entry(&mut self, idx: usize, callee: u32) -> Result<(), String> {
+        if callee as usize >= self.function_headers.len() {
+            return Err(format!("there's no function {}", callee));
+        }
+        let mut body = self.function_body(idx);
+        if body
+            .instructions()
+            .iter()
+            .any(|ins| ins.name() == "StartGenerator")
+        {
+            return Err(format!("function {} is a generator", idx));
+        }
+        let version = self.header.version;
+        let fh = &self.function_headers[idx];
+        let params = fh.param_count().max(1);
+        let frame_size = fh.frame_size();
+
+        // `this` and up to three arguments fit Call1-4, which take them in registers
+        // of their own. Call takes them from the end of the frame instead. Either
+        // way they're passed on in the last registers of the frame, which mustn't
+        // reach ours.
+        let explicit = params <= 4;
+        let own = if explicit { 1 + params } else { 1 };
+        let shift = own + params.saturating_sub(frame_size);
+        let new_frame_size = frame_size + shift;
+
+        let mut code = vec![
+            instruction(version, "GetEnvironment", &[0, 0])?,
+            instruction(version, "CreateClosure", &[0, 0, callee as i64])?,
+        ];
+        if explicit {
+            let mut operands = vec![0, 0];
+            for param in 0..params {
+                code.push(instruction(
+                    version,
+                    "LoadParam",
+                    &[1 + param as i64, param as i64],
+                )?);
+                operands.push(1 + param as i64);
+            }
+            code.push(instruction(version, &format!("Call{}", params), &operands)?);
+        } else {
+            for param in 0..params {
+                let reg = new_frame_size - 1 - param;
+                code.push(instruction(
+                    version,
+                    "LoadParam",
+                    &[reg as i64, param as i64],
+                )?);
+            }
+            code.push(instruction(version, "Call", &[0, 0, params as i64])?);
+        }
+
+        body.shift_registers(shift)
+            .map_err(|e| format!("function {}: {}", idx, e))?;
+        for (i, ins) in code.into_iter().enumerate() {
+            body.insert_unlabelled(i, ins)?;
+        }
+        self.set_function_body(idx, &body)?;
+        self.function_headers[idx].set_frame_size(new_frame_size);
+        Ok(())
+    }
+
+    /*
+     * Makes functions call the global function `hook` on entry, as
+     * `hook(funcId, name, arguments)`, and again before every Ret with the
+     * return value as a fourth argument. Only functions `filter` accepts (given
+     * their ID and name) are traced - leave out the hook itself if it's in the
+     * bundle. Returns the IDs of the traced functions.
+     *
+     * The calls can't change what a function does: if the hook is missing or
+     * throws, the exception is caught and dropped before the function's own
+     * handlers see it. Generators run from the top every time they resume, and
+     * some functions use registers too high to move up, so those are left alone.
+     */
+    pub fn trace_functions<F>(&mut self, hook: &str, filter: F) -> Result<Vec<u32>, String>
+    where
+        F: Fn(u32, &str) -> bool,
+    {
+        let hook = self.add_strings(&[(hook, StringKind::Identifier)])?[0];
+
+        let mut traced = vec![];
+        for idx in 0..self.function_headers.len() {
+            if !filter(idx as u32, &self.get_function_name(idx)) {
+                continue;
+            }
+            let Some((body, frame_size)) = self.traced_body(idx, hook)? else {
+                continue;
+            };
+            self.set_function_body(idx, &body)?;
+            self.function_headers[idx].set_frame_size(frame_size);
+            traced.push(idx as u32);
+        }
+        Ok(traced)
+    }
+
+    /*
+     * Function `idx` with the calls `trace_functions` adds, and the frame size it
+     * needs, or None if it can't be traced.
+     *
+     * Nothing the function computed is live yet on entry, nor at a Ret besides the
+     * return value, so the calls only need registers of their own to keep the
+     * hook and the return value in. r0-r4 hold the hook, `this` and the entry
+     * call's arguments (Call4 takes them in registers). The exit call has one
+     * argument more, so it takes them from the end of the frame like Call does -
+     * the last five registers, which mustn't reach r0-r2: the hook, the return
+     * value and the scratch register for registers too high for an instruction.
+     */
+    fn traced_body(
+        &mut self,
+        idx: usize,
+        hook: u32,
+    ) -> Result<Option<(FunctionBody, u32)>, String> {
+        let version = self.header.version;
+        let fh = &self.function_headers[idx];
+        let id = idx as i64;
+        let name = fh.func_name() as i64;
+        let frame_size = fh.frame_size();
+
+        let mut body = self.function_body(idx);
+        if body.is_empty()
+            || body
+                .instructions()
+                .iter()
+                .any(|ins| ins.name() == "StartGenerator")
+        {
+            return Ok(None);
+        }
+        let shift = 5.max(8u32.saturating_sub(frame_size));
+        if body.shift_registers(shift).is_err() {
+            return Ok(None);
+        }
+        let frame_size = frame_size + shift;
+        let last = frame_size - 1;
+
+        let load_id = |reg: u32| {
+            let load = if id <= u8::MAX as i64 {
+                "LoadConstUInt8"
+            } else {
+                "LoadConstInt"
+            };
+            Ok(vec![instruction(version, load, &[reg as i64, id])?])
+        };
+        let load_name = |reg: u32| {
+            Ok(vec![instruction(
+                version,
+                "LoadConstString",
+                &[reg as i64, name],
+            )?])
+        };
+        let load_undefined = |reg: u32| {
+            Ok(vec![instruction(
+                version,
+                "LoadConstUndefined",
+                &[reg as i64],
+            )?])
+        };
+        let load_arguments = |reg: u32| {
+            Ok(vec![
+                instruction(version, "LoadConstUndefined", &[reg as i64])?,
+                instruction(version, "ReifyArguments", &[reg as i64])?,
+            ])
+        };
+        let load_hook = || -> Result<Vec<HermesInstruction>, String> {
+            Ok(vec![
+                instruction(version, "GetGlobalObject", &[0])?,
+                instruction(version, "GetById", &[0, 0, 0, hook as i64])?,
+            ])
+        };
+        // The call is caught by a handler of its own, with a Catch that's jumped over
+        let catch = || -> Result<Vec<HermesInstruction>, String> {
+            Ok(vec![
+                instruction(version, "Jmp", &[0])?,
+                instruction(version, "Catch", &[0])?,
+            ])
+        };
+
+        // Before each Ret, from the last so the indices ahead stay put. The code
+        // takes over the Ret's labels, so whatever jumped to the Ret runs it too.
+        let rets: Vec<usize> = (0..body.len())
+            .filter(|&i| body.get(i).is_some_and(|ins| ins.name() == "Ret"))
+            .collect();
+        for &ret in rets.iter().rev() {
+            let value = body.get(ret).unwrap().operands()[0].value.as_i64();
+            let mut code = vec![instruction(version, "Mov", &[1, value])?];
+            code.extend(load_hook()?);
+            code.extend(write_register(version, last, 2, load_undefined)?);
+            code.extend(write_register(version, last - 1, 2, load_id)?);
+            code.extend(write_register(version, last - 2, 2, load_name)?);
+            code.extend(write_register(version, last - 3, 2, load_arguments)?);
+            code.extend(write_register(version, last - 4, 2, |reg| {
+                Ok(vec![instruction(version, "Mov", &[reg as i64, 1])?])
+            })?);
+            code.push(instruction(version, "Call", &[0, 0, 5])?);
+            let jmp = ret + code.len();
+            code.extend(catch()?);
+
+            let count = code.len();
+            for (i, ins) in code.into_iter().enumerate() {
+                if i == 0 {
+                    body.insert(ret, ins)?;
+                } else {
+                    body.insert_unlabelled(ret + i, ins)?;
+                }
+            }
+            body.replace(ret + count, instruction(version, "Ret", &[1])?)?;
+            body.set_jump_target(jmp, 0, ret + count)?;
+            body.add_handler(ret, jmp, jmp + 1)?;
+        }
+
+        let mut code = load_hook()?;
+        code.extend(load_undefined(1)?);
+        code.extend(load_id(2)?);
+        code.extend(load_name(3)?);
+        code.extend(load_arguments(4)?);
+        code.push(instruction(version, "Call4", &[0, 0, 1, 2, 3, 4])?);
+        let jmp = code.len();
+        code.extend(catch()?);
+
+        let count = code.len();
+        for (i, ins) in code.into_iter().enumerate() {
+            body.insert_unlabelled(i, ins)?;
+        }
+        body.set_jump_target(jmp, 0, count)?;
+        body.add_handler(0, jmp, jmp + 1)?;
+
+        Ok(Some((body, frame_size)))
+    }
+}
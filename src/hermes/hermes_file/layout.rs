@@ -0,0 +1,242 @@
+use std::fmt;
+use std::io;
+
+use super::{HermesFile, HermesStructReader};
+use crate::hermes::cjs_module::CJSModule;
+
+/*
+ * Where each section of a file lives.
+ *
+ * Sections are laid out back to back in the order the reader walks them, each
+ * one padded to its alignment. `section_layout` describes a file from its
+ * header and function headers, `plan_layout` works out where `serialize` will
+ * put everything - for an unmodified file the two are the same.
+ */
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SectionKind {
+    Header,
+    FunctionHeaders,
+    StringKinds,
+    IdentifierHashes,
+    StringTable,
+    OverflowStringTable,
+    StringStorage,
+    ArrayBuffer,
+    ObjectKeyBuffer,
+    ObjectValueBuffer,
+    /// v87 and up
+    BigIntTable,
+    /// v87 and up
+    BigIntStorage,
+    RegExpTable,
+    /// Follows the RegExp table without padding, and is only there if the table isn't empty.
+    RegExpStorage,
+    CJSModuleTable,
+    /// v84 and up
+    FunctionSourceTable,
+    /// The function bodies.
+    Bytecode,
+    /// Large function headers, exception handlers and debug info offsets.
+    FunctionInfo,
+    DebugInfo,
+    /// SHA-1 of everything before it.
+    Footer,
+}
+
+impl fmt::Display for SectionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SectionKind::Header => "header",
+            SectionKind::FunctionHeaders => "function headers",
+            SectionKind::StringKinds => "string kinds",
+            SectionKind::IdentifierHashes => "identifier hashes",
+            SectionKind::StringTable => "string table",
+            SectionKind::OverflowStringTable => "overflow string table",
+            SectionKind::StringStorage => "string storage",
+            SectionKind::ArrayBuffer => "array buffer",
+            SectionKind::ObjectKeyBuffer => "object key buffer",
+            SectionKind::ObjectValueBuffer => "object value buffer",
+            SectionKind::BigIntTable => "BigInt table",
+            SectionKind::BigIntStorage => "BigInt storage",
+            SectionKind::RegExpTable => "RegExp table",
+            SectionKind::RegExpStorage => "RegExp storage",
+            SectionKind::CJSModuleTable => "CJS module table",
+            SectionKind::FunctionSourceTable => "function source table",
+            SectionKind::Bytecode => "bytecode",
+            SectionKind::FunctionInfo => "function info",
+            SectionKind::DebugInfo => "debug info",
+            SectionKind::Footer => "footer",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    pub kind: SectionKind,
+    pub offset: u64,
+    pub size: u64,
+    pub alignment: u64,
+}
+
+impl Section {
+    pub fn end(&self) -> u64 {
+        self.offset + self.size
+    }
+}
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SectionLayout {
+    /// In file order. Sections the file's version doesn't have are left out.
+    pub sections: Vec<Section>,
+}
+
+impl SectionLayout {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a section after the last one, padded to `alignment`, and returns its offset.
+    pub fn push(&mut self, kind: SectionKind, size: u64, alignment: u64) -> u64 {
+        let offset = self.end().div_ceil(alignment) * alignment;
+        self.sections.push(Section {
+            kind,
+            offset,
+            size,
+            alignment,
+        });
+        offset
+    }
+
+    pub fn get(&self, kind: SectionKind) -> Option<&Section> {
+        self.sections.iter().find(|s| s.kind == kind)
+    }
+
+    pub fn offset(&self, kind: SectionKind) -> Option<u64> {
+        self.get(kind).map(|s| s.offset)
+    }
+
+    /// End of the last section.
+    pub fn end(&self) -> u64 {
+        self.sections.last().map(|s| s.end()).unwrap_or(0)
+    }
+}
+
+impl<R> HermesFile<R>
+where
+    R: io::Read + io::BufRead + io::Seek,
+{
+    /*
+     * The layout of the file as described by its header and function headers.
+     * For a file that was read, that's where everything is; for one that has
+     * been changed since, call `plan_layout` first to bring the header and
+     * function headers up to date.
+     */
+    pub fn section_layout(&self) -> SectionLayout {
+        let mut layout = self.table_layout();
+
+        let bytecode_start = layout.end().div_ceil(4) * 4;
+        let bytecode_end = self
+            .function_headers
+            .iter()
+            .map(|fh| fh.offset() as u64 + fh.byte_size() as u64)
+            .max()
+            .unwrap_or(0)
+            .max(bytecode_start);
+        layout.push(SectionKind::Bytecode, bytecode_end - bytecode_start, 4);
+
+        let info_start = bytecode_end.div_ceil(4) * 4;
+        let debug_info_offset = self.header.debug_info_offset as u64;
+        layout.push(
+            SectionKind::FunctionInfo,
+            debug_info_offset.saturating_sub(info_start),
+            4,
+        );
+
+        let footer_offset = (self.header.file_length as u64).saturating_sub(20);
+        layout.push(
+            SectionKind::DebugInfo,
+            footer_offset.saturating_sub(debug_info_offset),
+            4,
+        );
+        layout.push(SectionKind::Footer, 20, 1);
+        layout
+    }
+
+    /*
+     * The header and the fixed-size tables after it, up to and including the
+     * function source table, sized from the header counts.
+     */
+    pub(crate) fn table_layout(&self) -> SectionLayout {
+        let h = &self.header;
+        let mut layout = SectionLayout::new();
+
+        layout.push(SectionKind::Header, h.size() as u64, 1);
+        layout.push(
+            SectionKind::FunctionHeaders,
+            16 * h.function_count as u64, // 16 bytes per (small) function header
+            4,
+        );
+        layout.push(SectionKind::StringKinds, 4 * h.string_kind_count as u64, 4);
+        layout.push(
+            SectionKind::IdentifierHashes,
+            4 * h.identifier_count as u64,
+            4,
+        );
+        layout.push(SectionKind::StringTable, 4 * h.string_count as u64, 4);
+        layout.push(
+            SectionKind::OverflowStringTable,
+            8 * h.overflow_string_count as u64,
+            4,
+        );
+        layout.push(SectionKind::StringStorage, h.string_storage_size as u64, 4);
+        layout.push(SectionKind::ArrayBuffer, h.array_buffer_size as u64, 4);
+        layout.push(
+            SectionKind::ObjectKeyBuffer,
+            h.obj_key_buffer_size as u64,
+            4,
+        );
+        layout.push(
+            SectionKind::ObjectValueBuffer,
+            h.obj_value_buffer_size as u64,
+            4,
+        );
+
+        if h.version >= 87 {
+            layout.push(SectionKind::BigIntTable, 8 * h.big_int_count as u64, 4);
+            layout.push(SectionKind::BigIntStorage, h.big_int_storage_size as u64, 4);
+        }
+
+        layout.push(SectionKind::RegExpTable, 8 * h.reg_exp_count as u64, 4);
+        if h.reg_exp_count > 0 {
+            layout.push(SectionKind::RegExpStorage, h.reg_exp_storage_size as u64, 1);
+        }
+
+        let cjs_size = match self.cjs_modules.first() {
+            Some(CJSModule::CJSModuleInt(_)) => 4,
+            _ => 8,
+        };
+        layout.push(
+            SectionKind::CJSModuleTable,
+            cjs_size * h.cjs_module_count as u64,
+            4,
+        );
+
+        if h.version >= 84 {
+            layout.push(
+                SectionKind::FunctionSourceTable,
+                8 * h.function_source_count as u64,
+                4,
+            );
+        }
+
+        layout
+    }
+}
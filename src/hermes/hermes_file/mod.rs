@@ -1,5 +1,10 @@
+pub mod body;
 pub mod builder;
+pub mod edit;
+pub mod inject;
+pub mod layout;
 pub mod reader;
+pub mod validator;
 pub mod writer;
 
 use std::collections::HashMap;
@@ -30,6 +35,7 @@ pub struct HermesOffsets {
     small_string_table_offsets: HashMap<u32, u32>, // index of string in string storage -> offset in file
     overflow_string_table_offsets: HashMap<u32, u32>, // index of string in string storage -> offset in file
     bytecode_offsets: HashMap<u32, u32>, // before serializing bytecode, write current stream position to this value
+    original_bodies: HashMap<u32, (u32, u32)>, // function index -> offset + size of its bytecode in the file we read
     debug_info_offset: u32, // before serializing debug info, write current stream position to this value
     file_length: u32,       // after serializing the footer, write the file length to this value
 }
@@ -64,6 +70,13 @@ pub struct HermesFile<R> {
     // We use this to keep track of the bytecode for each function
     pub function_bytecode: Vec<FunctionInstructions>,
 
+    // Where each function's entry is in `function_bytecode`, by function index
+    function_bytecode_index: HashMap<u32, usize>,
+
+    // Whether the builder's string APIs share storage between identical strings and
+    // strings that are a suffix of another, like hermesc does. On by default.
+    pub pack_strings: bool,
+
     /*
      * Hermes file format
      */
@@ -1,7 +1,7 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io::{self};
 
-use crate::hermes::array_parser::ArrayTypes;
+use crate::hermes::array_parser::{parse_literal_buffer, ArrayTypes};
 use crate::hermes::big_int_table::BigIntTableEntry;
 use crate::hermes::debug_info::DebugInfoOffsets;
 use crate::hermes::exception_handler::ExceptionHandlerInfo;
@@ -16,12 +16,13 @@ use crate::hermes::function_header::{
 use crate::hermes::function_sources::FunctionSourceEntry;
 use crate::hermes::regexp_table::RegExpTableEntry;
 use crate::hermes::string_kind::StringKindEntry;
-use crate::hermes::string_table::{OverflowStringTableEntry, SmallStringTableEntry};
+use crate::hermes::string_table::{HermesString, OverflowStringTableEntry, SmallStringTableEntry};
 use crate::hermes::{print_array_val, print_array_vals};
 use crate::hermes::{HermesInstruction, InstructionParser, Serializable};
 use crate::match_instruction;
 
 use super::builder::StringTypePair;
+use super::validator::ValidationKind;
 use super::{FunctionBytecode, FunctionInstructions, HermesFile, HermesStructReader};
 
 impl<R> HermesFile<R>
@@ -35,10 +36,13 @@ where
                 small_string_table_offsets: HashMap::new(),
                 overflow_string_table_offsets: HashMap::new(),
                 bytecode_offsets: HashMap::new(),
+                original_bodies: HashMap::new(),
                 debug_info_offset: 0,
                 file_length: 0,
             },
             function_bytecode: vec![],
+            function_bytecode_index: HashMap::new(),
+            pack_strings: true,
             header: HermesHeader::new(),
             function_headers: vec![],
             string_kinds: vec![],
@@ -81,7 +85,15 @@ where
     }
 
     pub fn deserialize(r: &mut R) -> HermesFile<&mut R> {
-        let mut hermes_file: HermesFile<&mut R> = HermesFile::new(r);
+        HermesFile::from_reader(r)
+    }
+
+    /*
+     * Same as `deserialize`, but the file owns the reader - e.g. a Cursor over
+     * bytes that are already in memory.
+     */
+    pub fn from_reader(r: R) -> HermesFile<R> {
+        let mut hermes_file = HermesFile::new(r);
         hermes_file.visit_header();
         hermes_file.visit_function_headers();
         // The bytecode of all of the functions are in this section.
@@ -119,6 +131,7 @@ where
 
     pub fn visit_header(&mut self) {
         self.header = HermesHeader::deserialize(&mut self._reader, 0);
+        self.cjs_module_offset = self.header.cjs_module_offset;
     }
 
     pub fn visit_function_headers(&mut self) {
@@ -227,6 +240,13 @@ where
                 .seek(io::SeekFrom::Start(anchor_pos))
                 .expect("unable to seek to function header");
 
+            self.offsets.original_bodies.insert(
+                self.function_headers.len() as u32,
+                (
+                    function_header_val.offset(),
+                    function_header_val.byte_size(),
+                ),
+            );
             self.function_headers.push(function_header_val);
         }
     }
@@ -940,50 +960,274 @@ where
     }
 
     /*
-     * Returns a string from the string storage by index - UTF-16 or UTF-8
+     * Returns a string from the string storage by index, exactly as it's stored -
+     * Latin-1 or UTF-16, lone surrogates and all. A string table entry that points
+     * outside the overflow table or string storage is an error.
      */
-    pub fn get_string_from_storage_by_index(&self, index: usize) -> String {
-        let myfunc = self.string_storage.get(index).unwrap();
-
-        let is_utf16 = myfunc.is_utf_16;
-
-        let mut real_offset = myfunc.offset;
-        let mut real_length = myfunc.length;
+    pub fn get_hermes_string(&self, index: usize) -> Result<HermesString, ValidationKind> {
+        let string_id = index as u32;
+        let entry = self
+            .string_storage
+            .get(index)
+            .ok_or(ValidationKind::StringIdOutOfRange {
+                string_id,
+                count: self.string_storage.len() as u32,
+            })?;
 
         // String is overflowed, so we have to read the real offsets and length from the overflow table
-        if myfunc.length == 255 {
+        let (offset, length) = if entry.length == 255 {
             let overflow_entry = self
                 .overflow_string_storage
-                .get(myfunc.offset as usize)
-                .unwrap();
-            real_offset = overflow_entry.offset;
-            real_length = overflow_entry.length;
-        }
+                .get(entry.offset as usize)
+                .ok_or(ValidationKind::OverflowReferenceOutOfRange {
+                    string_id,
+                    index: entry.offset,
+                    count: self.overflow_string_storage.len() as u32,
+                })?;
+            (overflow_entry.offset, overflow_entry.length)
+        } else {
+            (entry.offset, entry.length)
+        };
 
-        if is_utf16 {
-            let bytes = &self.string_storage_bytes
-                [real_offset as usize..(real_offset + (real_length * 2)) as usize];
+        let start = offset as u64;
+        let end = start + length as u64 * if entry.is_utf_16 { 2 } else { 1 };
+        let bytes = self
+            .string_storage_bytes
+            .get(start as usize..end as usize)
+            .ok_or(ValidationKind::StringOutOfBounds {
+                string_id,
+                start,
+                end,
+                storage_size: self.string_storage_bytes.len() as u64,
+            })?;
+
+        Ok(if entry.is_utf_16 {
+            HermesString::Utf16(
+                bytes
+                    .chunks_exact(2)
+                    .map(|chunk| u16::from_le_bytes([chunk[0], chunk[1]]))
+                    .collect(),
+            )
+        } else {
+            HermesString::Latin1(bytes.to_vec())
+        })
+    }
 
-            let utf16_values: Vec<u16> = bytes
-                .chunks_exact(2)
-                .map(|chunk| u16::from_le_bytes([chunk[0], chunk[1]]))
-                .collect();
+    pub fn get_hermes_strings(&self) -> Result<Vec<HermesString>, ValidationKind> {
+        (0..self.string_storage.len())
+            .map(|idx| self.get_hermes_string(idx))
+            .collect()
+    }
+
+    /*
+     * Returns a string from the string storage by index, for display. Strings that
+     * aren't valid Unicode (lone surrogates) are converted lossily - use
+     * get_hermes_string to get them exactly. Strings that can't be read at all come
+     * back empty; `validate` reports why.
+     */
+    pub fn get_string_from_storage_by_index(&self, index: usize) -> String {
+        self.get_hermes_string(index)
+            .map(|string| string.to_string_lossy())
+            .unwrap_or_default()
+    }
 
-            // Commenting out due to: https://github.com/Pilfer/hermes_rs/issues/9
-            // String::from_utf16(&utf16_values).expect("Invalid UTF-16")
-            // in favor of from_utf16_lossy, which allows for "invalid" UTF-16.
-            // TODO: Actually fix this.
-            String::from_utf16_lossy(&utf16_values).to_string()
+    /*
+     * Returns a string by ID, or None if the ID is past the end of the string table.
+     * IDs taken from operands and literal buffers should be looked up with this.
+     */
+    pub(crate) fn get_string_by_id(&self, id: u32) -> Option<String> {
+        self.get_hermes_string(id as usize)
+            .ok()
+            .map(|string| string.to_string_lossy())
+    }
+
+    /*
+     * Returns the name of a function, or `$FUNC_{idx}` if it doesn't have one.
+     */
+    pub fn get_function_name(&self, idx: usize) -> String {
+        let func_name = self
+            .function_headers
+            .get(idx)
+            .and_then(|fh| self.get_string_by_id(fh.func_name()))
+            .unwrap_or_default();
+
+        if func_name.is_empty() {
+            format!("$FUNC_{}", idx)
         } else {
-            String::from_utf8(
-                self.string_storage_bytes
-                    [real_offset as usize..(real_offset + real_length) as usize]
-                    .to_vec(),
-            )
-            .expect("Invalid UTF-8")
+            func_name
+        }
+    }
+
+    /*
+     * Returns the name of a builtin (as used by CallBuiltin, CallBuiltinLong and
+     * GetBuiltinClosure) for this file's HBC version, e.g. `Object.keys`.
+     */
+    pub fn get_builtin_name(&self, idx: u32) -> Option<&'static str> {
+        crate::hermes::get_builtins(self.header.version)
+            .and_then(|builtins| builtins.get(idx as usize))
+            .copied()
+    }
+
+    /*
+     * Returns the instructions for a function by index - uses the already parsed
+     * instructions if `get_instructions` has been called, otherwise reads them from the file.
+     */
+    pub fn get_function_instructions(&mut self, idx: usize) -> Vec<HermesInstruction> {
+        match self.function_bytecode_position(idx) {
+            Some(pos) => self.function_bytecode[pos].bytecode.clone(),
+            None => self.get_func_bytecode(idx as u32),
         }
     }
 
+    /*
+     * Returns where a function's entry is in `function_bytecode`, if it has one.
+     * `function_bytecode` is public, so the index is checked against it and
+     * rebuilt when it has changed - the first entry for a function wins.
+     */
+    pub(crate) fn function_bytecode_position(&mut self, idx: usize) -> Option<usize> {
+        let is_at = |fb: &[FunctionInstructions], pos: usize| {
+            fb.get(pos).is_some_and(|fi| fi.func_index as usize == idx)
+        };
+        match self.function_bytecode_index.get(&(idx as u32)) {
+            Some(&pos) if is_at(&self.function_bytecode, pos) => return Some(pos),
+            None if self.function_bytecode_index.len() == self.function_bytecode.len() => {
+                return None
+            }
+            _ => {}
+        }
+
+        self.function_bytecode_index.clear();
+        for (pos, fi) in self.function_bytecode.iter().enumerate() {
+            self.function_bytecode_index
+                .entry(fi.func_index)
+                .or_insert(pos);
+        }
+        self.function_bytecode_index.get(&(idx as u32)).copied()
+    }
+
+    /*
+     * Returns the IDs of all functions created (directly or transitively) inside a function -
+     * closures, generators and async functions - in the order they're first referenced.
+     * The function itself isn't included.
+     */
+    pub fn get_nested_functions(&mut self, idx: usize) -> Vec<u32> {
+        let mut nested: Vec<u32> = vec![];
+        let mut seen: HashSet<u32> = HashSet::new();
+        let mut queue: Vec<u32> = vec![idx as u32];
+
+        while let Some(current) = queue.pop() {
+            for ins in self.get_function_instructions(current as usize) {
+                if ins.name().starts_with("CallDirect") {
+                    continue;
+                }
+                if let Some(fid) = ins.function_id() {
+                    if fid as usize != idx
+                        && (fid as usize) < self.function_headers.len()
+                        && seen.insert(fid)
+                    {
+                        nested.push(fid);
+                        queue.push(fid);
+                    }
+                }
+            }
+        }
+
+        nested
+    }
+
+    /*
+     * Returns `count` values from the array buffer, starting at `offset`.
+     * Unlike `get_array_buffer`, this follows the literal across multiple tag sequences.
+     */
+    pub fn get_array_buffer_values(&self, offset: usize, count: usize) -> Vec<ArrayTypes> {
+        parse_literal_buffer(&self.array_buffer_storage, offset, count).1
+    }
+
+    /*
+     * Returns `count` keys from the object key buffer, starting at `offset`.
+     */
+    pub fn get_object_key_buffer_values(&self, offset: usize, count: usize) -> Vec<ArrayTypes> {
+        parse_literal_buffer(&self.object_key_buffer, offset, count).1
+    }
+
+    /*
+     * Returns `count` values from the object value buffer, starting at `offset`.
+     */
+    pub fn get_object_val_buffer_values(&self, offset: usize, count: usize) -> Vec<ArrayTypes> {
+        parse_literal_buffer(&self.object_val_buffer, offset, count).1
+    }
+
+    /*
+     * Returns a BigInt from the BigInt table as a decimal string.
+     * BigInts are stored as little-endian two's complement bytes.
+     */
+    pub fn get_big_int_string(&self, idx: usize) -> String {
+        let entry = match self.big_int_table.get(idx) {
+            Some(entry) => entry,
+            None => return String::from("0"),
+        };
+        let start = entry.offset as usize;
+        let end = (start + entry.length as usize).min(self.big_int_storage.len());
+        let bytes = &self.big_int_storage[start.min(end)..end];
+
+        let negative = bytes.last().map(|b| b & 0x80 != 0).unwrap_or(false);
+        let mut magnitude: Vec<u8> = bytes.to_vec();
+        if negative {
+            // two's complement negation
+            let mut carry = 1u16;
+            for b in magnitude.iter_mut() {
+                let v = (!*b) as u16 + carry;
+                *b = v as u8;
+                carry = v >> 8;
+            }
+        }
+
+        // repeated division by 10, most significant byte first
+        let mut digits = vec![];
+        let mut be: Vec<u8> = magnitude.into_iter().rev().collect();
+        while be.iter().any(|b| *b != 0) {
+            let mut rem = 0u16;
+            for b in be.iter_mut() {
+                let cur = (rem << 8) | *b as u16;
+                *b = (cur / 10) as u8;
+                rem = cur % 10;
+            }
+            digits.push(b'0' + rem as u8);
+        }
+        if digits.is_empty() {
+            digits.push(b'0');
+        }
+        if negative {
+            digits.push(b'-');
+        }
+        digits.reverse();
+        String::from_utf8(digits).unwrap()
+    }
+
+    /*
+     * Returns the raw bytecode of a function, including any jump tables after the last
+     * instruction. Functions that have been modified in memory are re-encoded instead.
+     */
+    pub fn get_function_bytes(&mut self, idx: usize) -> Vec<u8> {
+        if let Some(pos) = self.function_bytecode_position(idx) {
+            let mut out = vec![];
+            for ins in self.function_bytecode[pos].bytecode.iter() {
+                ins.serialize(&mut out);
+            }
+            return out;
+        }
+
+        let fh = &self.function_headers[idx];
+        let mut bytes = vec![0u8; fh.byte_size() as usize];
+        self._reader
+            .seek(io::SeekFrom::Start(fh.offset() as u64))
+            .unwrap();
+        self._reader
+            .read_exact(&mut bytes)
+            .expect("unable to read function bytecode");
+        bytes
+    }
+
     /*
      * Returns the instructions for a function by index
      */
@@ -998,6 +1242,15 @@ where
             .read_exact(&mut bytecode_buf)
             .expect("unable to read first functions bytecode");
 
+        self.decode_function_bytes(&bytecode_buf)
+        // ---------------------------------------------------------------------------------------- //
+    }
+
+    /*
+     * Decodes a function body into instructions. Anything after the last
+     * instruction (e.g. switch jump tables) is decoded as well.
+     */
+    pub fn decode_function_bytes(&self, bytecode_buf: &[u8]) -> Vec<HermesInstruction> {
         let mut instructions_list = vec![];
 
         let mut byte_iter = bytecode_buf.iter();
@@ -1063,7 +1316,6 @@ where
         }
 
         instructions_list
-        // ---------------------------------------------------------------------------------------- //
     }
 
     pub fn parse_bytecode_for_fn(&mut self, idx: u32) {
@@ -1076,14 +1328,8 @@ where
             .read_exact(&mut bytecode_buf)
             .expect("unable to read first functions bytecode");
 
-        let myfunc = self.string_storage.get(fh.func_name() as usize).unwrap();
         println!("------------------------------------------------");
-        let func_start = myfunc.offset;
-        let mut func_name = String::from_utf8(
-            self.string_storage_bytes[func_start as usize..(func_start + myfunc.length) as usize]
-                .to_vec(),
-        )
-        .unwrap();
+        let mut func_name = self.get_string_from_storage_by_index(fh.func_name() as usize);
 
         if func_name.is_empty() {
             func_name = format!("$FUNC_{}", idx);
@@ -1219,15 +1465,8 @@ where
 
                 let func_name_idx = fh.func_name() as usize;
 
-                let myfunc = self.string_storage.get(func_name_idx).unwrap();
                 println!("------------------------------------------------");
-                let func_start = myfunc.offset;
-                let mut func_name = String::from_utf8(
-                    self.string_storage_bytes
-                        [func_start as usize..(func_start + myfunc.length) as usize]
-                        .to_vec(),
-                )
-                .unwrap();
+                let mut func_name = self.get_string_from_storage_by_index(func_name_idx);
 
                 if func_name.is_empty() {
                     func_name = format!("$FUNC_{}", fidx);
@@ -1402,15 +1641,8 @@ where
 
                 let func_name_idx = fh.func_name() as usize;
 
-                let myfunc = self.string_storage.get(func_name_idx).unwrap();
                 println!("------------------------------------------------");
-                let func_start = myfunc.offset;
-                let mut func_name = String::from_utf8(
-                    self.string_storage_bytes
-                        [func_start as usize..(func_start + myfunc.length) as usize]
-                        .to_vec(),
-                )
-                .unwrap();
+                let mut func_name = self.get_string_from_storage_by_index(func_name_idx);
 
                 if func_name.is_empty() {
                     func_name = format!("$FUNC_{}", fidx);
@@ -1563,15 +1795,8 @@ where
 
                 let func_name_idx = fh.func_name() as usize;
 
-                let myfunc = self.string_storage.get(func_name_idx).unwrap();
                 output_buf.push("------------------------------------------------\n".to_string());
-                let func_start = myfunc.offset;
-                let mut func_name = String::from_utf8(
-                    self.string_storage_bytes
-                        [func_start as usize..(func_start + myfunc.length) as usize]
-                        .to_vec(),
-                )
-                .unwrap();
+                let mut func_name = self.get_string_from_storage_by_index(func_name_idx);
 
                 if func_name.is_empty() {
                     func_name = format!("$FUNC_{}", fidx);
@@ -1722,15 +1947,8 @@ where
 
             let func_name_idx = fh.func_name() as usize;
 
-            let myfunc = self.string_storage.get(func_name_idx).unwrap();
             output_buf.push("------------------------------------------------\n".to_string());
-            let func_start = myfunc.offset;
-            let mut func_name = String::from_utf8(
-                self.string_storage_bytes
-                    [func_start as usize..(func_start + myfunc.length) as usize]
-                    .to_vec(),
-            )
-            .unwrap();
+            let mut func_name = self.get_string_from_storage_by_index(func_name_idx);
 
             if func_name.is_empty() {
                 func_name = format!("$FUNC_{}", fidx);
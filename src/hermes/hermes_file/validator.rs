@@ -0,0 +1,521 @@
+use sha1::{Digest, Sha1};
+use std::collections::HashSet;
+use std::fmt;
+use std::io;
+
+use super::layout::SectionKind;
+use super::HermesFile;
+use crate::hermes::string_kind::{StringKind, StringKindEntry};
+use crate::hermes::string_table::HermesString;
+use crate::hermes::verifier::Severity;
+
+pub const HERMES_MAGIC: u64 = 0x1F1903C103BC1FC6;
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationKind {
+    BadMagic {
+        magic: u64,
+    },
+    /// A header count or size that doesn't match the section it describes.
+    CountMismatch {
+        field: String,
+        header: u64,
+        actual: u64,
+    },
+    FileLengthMismatch {
+        header: u32,
+        actual: u64,
+    },
+    FooterHashMismatch {
+        stored: String,
+        computed: String,
+    },
+    SectionOutOfBounds {
+        section: String,
+        start: u64,
+        end: u64,
+        limit: u64,
+    },
+    SectionMisaligned {
+        section: String,
+        offset: u64,
+    },
+    SectionsOverlap {
+        first: String,
+        second: String,
+        offset: u64,
+    },
+    /// The string kind runs don't add up to `string_count`.
+    StringKindCountMismatch {
+        total: u64,
+        string_count: u32,
+    },
+    /// The header's `identifier_count`, the identifiers the string kinds
+    /// describe and the identifier hash table don't all agree.
+    IdentifierCountMismatch {
+        header: u32,
+        identifiers: u64,
+        hashes: u64,
+    },
+    IdentifierHashMismatch {
+        string_id: u32,
+        stored: u32,
+        computed: u32,
+    },
+    /// A string table entry that points outside of string storage.
+    StringOutOfBounds {
+        string_id: u32,
+        start: u64,
+        end: u64,
+        storage_size: u64,
+    },
+    /// An overflowed string table entry that points outside the overflow table.
+    OverflowReferenceOutOfRange {
+        string_id: u32,
+        index: u32,
+        count: u32,
+    },
+    /// A string ID past the end of the string table.
+    StringIdOutOfRange {
+        string_id: u32,
+        count: u32,
+    },
+}
+
+impl fmt::Display for ValidationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationKind::BadMagic { magic } => {
+                write!(
+                    f,
+                    "bad magic {:#018x} (expected {:#018x})",
+                    magic, HERMES_MAGIC
+                )
+            }
+            ValidationKind::CountMismatch {
+                field,
+                header,
+                actual,
+            } => write!(f, "header {} is {}, section has {}", field, header, actual),
+            ValidationKind::FileLengthMismatch { header, actual } => write!(
+                f,
+                "header file_length is {}, file is {} bytes",
+                header, actual
+            ),
+            ValidationKind::FooterHashMismatch { stored, computed } => write!(
+                f,
+                "footer SHA-1 is {}, contents hash to {}",
+                stored, computed
+            ),
+            ValidationKind::SectionOutOfBounds {
+                section,
+                start,
+                end,
+                limit,
+            } => write!(f, "{} ({}..{}) extends past {}", section, start, end, limit),
+            ValidationKind::SectionMisaligned { section, offset } => {
+                write!(f, "{} at {} is not 4-byte aligned", section, offset)
+            }
+            ValidationKind::SectionsOverlap {
+                first,
+                second,
+                offset,
+            } => write!(f, "{} overlaps {} at {}", first, second, offset),
+            ValidationKind::StringKindCountMismatch {
+                total,
+                string_count,
+            } => write!(
+                f,
+                "string kinds cover {} strings, string_count is {}",
+                total, string_count
+            ),
+            ValidationKind::IdentifierCountMismatch {
+                header,
+                identifiers,
+                hashes,
+            } => write!(
+                f,
+                "header identifier_count is {}, string kinds have {} identifiers, {} identifier hashes",
+                header, identifiers, hashes
+            ),
+            ValidationKind::IdentifierHashMismatch {
+                string_id,
+                stored,
+                computed,
+            } => write!(
+                f,
+                "identifier hash for string {} is {:#010x}, expected {:#010x}",
+                string_id, stored, computed
+            ),
+            ValidationKind::StringOutOfBounds {
+                string_id,
+                start,
+                end,
+                storage_size,
+            } => write!(
+                f,
+                "string {} ({}..{}) is outside string storage ({} bytes)",
+                string_id, start, end, storage_size
+            ),
+            ValidationKind::OverflowReferenceOutOfRange {
+                string_id,
+                index,
+                count,
+            } => write!(
+                f,
+                "string {} refers to overflow entry {} ({} entries)",
+                string_id, index, count
+            ),
+            ValidationKind::StringIdOutOfRange { string_id, count } => write!(
+                f,
+                "string {} is past the end of the string table ({} strings)",
+                string_id, count
+            ),
+        }
+    }
+}
+
+impl ValidationKind {
+    pub fn severity(&self) -> Severity {
+        match self {
+            // Hermes doesn't check the footer when loading a bundle
+            ValidationKind::FooterHashMismatch { .. } => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone)]
+pub struct ValidationFinding {
+    pub severity: Severity,
+    pub kind: ValidationKind,
+}
+
+impl fmt::Display for ValidationFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{}: {}", severity, self.kind)
+    }
+}
+
+// A named byte range in the file
+struct Section {
+    name: String,
+    start: u64,
+    end: u64,
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+impl<R> HermesFile<R>
+where
+    R: io::Read + io::BufRead + io::Seek,
+{
+    /*
+     * Checks the container, as opposed to the bytecode (see `verify`): the
+     * magic number, header counts against the sections they describe, section
+     * layout, file length and footer hash, identifier hashes, string kinds and
+     * the string/overflow tables.
+     *
+     * The layout, file length and footer checks need the bytes the file was
+     * read from, so they're skipped for files built in memory.
+     */
+    pub fn validate(&mut self) -> Vec<ValidationFinding> {
+        let mut kinds = vec![];
+
+        if self.header.magic != HERMES_MAGIC {
+            kinds.push(ValidationKind::BadMagic {
+                magic: self.header.magic,
+            });
+        }
+
+        self.validate_counts(&mut kinds);
+        self.validate_strings(&mut kinds);
+
+        let raw = self.read_raw_bytes();
+        if !raw.is_empty() {
+            self.validate_file(&raw, &mut kinds);
+        }
+
+        kinds
+            .into_iter()
+            .map(|kind| ValidationFinding {
+                severity: kind.severity(),
+                kind,
+            })
+            .collect()
+    }
+
+    // The whole file as read, leaving the reader where it was
+    fn read_raw_bytes(&mut self) -> Vec<u8> {
+        let pos = self._reader.stream_position().unwrap_or(0);
+        let mut raw = vec![];
+        if self._reader.seek(io::SeekFrom::Start(0)).is_ok() {
+            let _ = self._reader.read_to_end(&mut raw);
+        }
+        let _ = self._reader.seek(io::SeekFrom::Start(pos));
+        raw
+    }
+
+    fn validate_counts(&self, kinds: &mut Vec<ValidationKind>) {
+        let h = &self.header;
+        let mut counts: Vec<(&str, u64, u64)> = vec![
+            (
+                "function_count",
+                h.function_count as u64,
+                self.function_headers.len() as u64,
+            ),
+            (
+                "string_kind_count",
+                h.string_kind_count as u64,
+                self.string_kinds.len() as u64,
+            ),
+            (
+                "string_count",
+                h.string_count as u64,
+                self.string_storage.len() as u64,
+            ),
+            (
+                "overflow_string_count",
+                h.overflow_string_count as u64,
+                self.overflow_string_storage.len() as u64,
+            ),
+            (
+                "string_storage_size",
+                h.string_storage_size as u64,
+                self.string_storage_bytes.len() as u64,
+            ),
+            (
+                "array_buffer_size",
+                h.array_buffer_size as u64,
+                self.array_buffer_storage.len() as u64,
+            ),
+            (
+                "obj_key_buffer_size",
+                h.obj_key_buffer_size as u64,
+                self.object_key_buffer.len() as u64,
+            ),
+            (
+                "obj_value_buffer_size",
+                h.obj_value_buffer_size as u64,
+                self.object_val_buffer.len() as u64,
+            ),
+            (
+                "reg_exp_count",
+                h.reg_exp_count as u64,
+                self.reg_exp_table.len() as u64,
+            ),
+            (
+                "reg_exp_storage_size",
+                h.reg_exp_storage_size as u64,
+                self.reg_exp_storage.len() as u64,
+            ),
+            (
+                "cjs_module_count",
+                h.cjs_module_count as u64,
+                self.cjs_modules.len() as u64,
+            ),
+        ];
+        if h.version >= 87 {
+            counts.push((
+                "big_int_count",
+                h.big_int_count as u64,
+                self.big_int_table.len() as u64,
+            ));
+            counts.push((
+                "big_int_storage_size",
+                h.big_int_storage_size as u64,
+                self.big_int_storage.len() as u64,
+            ));
+        }
+        if h.version >= 84 {
+            counts.push((
+                "function_source_count",
+                h.function_source_count as u64,
+                self.function_source_entries.len() as u64,
+            ));
+        }
+
+        for (field, header, actual) in counts {
+            if header != actual {
+                kinds.push(ValidationKind::CountMismatch {
+                    field: field.to_string(),
+                    header,
+                    actual,
+                });
+            }
+        }
+
+        let total: u64 = self
+            .string_kinds
+            .iter()
+            .map(|k| k.get_length() as u64)
+            .sum();
+        if total != h.string_count as u64 {
+            kinds.push(ValidationKind::StringKindCountMismatch {
+                total,
+                string_count: h.string_count,
+            });
+        }
+    }
+
+    fn validate_strings(&self, kinds: &mut Vec<ValidationKind>) {
+        let strings: Vec<Option<HermesString>> = (0..self.string_storage.len())
+            .map(|idx| match self.get_hermes_string(idx) {
+                Ok(string) => Some(string),
+                Err(kind) => {
+                    kinds.push(kind);
+                    None
+                }
+            })
+            .collect();
+
+        // Identifier hashes follow the string kind runs, one per identifier
+        let mut identifiers = vec![];
+        let mut string_id = 0u32;
+        for entry in self.string_kinds.iter() {
+            let (kind, count) = match entry {
+                StringKindEntry::Old(e) => (e.kind, e.count),
+                StringKindEntry::New(e) => (e.kind, e.count),
+            };
+            if kind != StringKind::String {
+                identifiers.extend(string_id..string_id + count);
+            }
+            string_id += count;
+        }
+
+        let hashes = self.identifier_hashes.len() as u64;
+        if identifiers.len() as u64 != hashes || self.header.identifier_count as u64 != hashes {
+            kinds.push(ValidationKind::IdentifierCountMismatch {
+                header: self.header.identifier_count,
+                identifiers: identifiers.len() as u64,
+                hashes,
+            });
+        }
+
+        // Whichever hashes there are are still checked
+        for (string_id, stored) in identifiers.iter().zip(self.identifier_hashes.iter()) {
+            let Some(Some(string)) = strings.get(*string_id as usize) else {
+                continue;
+            };
+            let computed = string.identifier_hash();
+            if computed != *stored {
+                kinds.push(ValidationKind::IdentifierHashMismatch {
+                    string_id: *string_id,
+                    stored: *stored,
+                    computed,
+                });
+            }
+        }
+    }
+
+    fn validate_file(&self, raw: &[u8], kinds: &mut Vec<ValidationKind>) {
+        let h = &self.header;
+        let file_size = raw.len() as u64;
+
+        if h.file_length as u64 != file_size {
+            kinds.push(ValidationKind::FileLengthMismatch {
+                header: h.file_length,
+                actual: file_size,
+            });
+        }
+
+        if file_size >= 20 {
+            let (contents, footer) = raw.split_at(raw.len() - 20);
+            let mut hasher = Sha1::new();
+            hasher.update(contents);
+            let computed: [u8; 20] = hasher.finalize().into();
+            if computed != footer {
+                kinds.push(ValidationKind::FooterHashMismatch {
+                    stored: hex(footer),
+                    computed: hex(&computed),
+                });
+            }
+        }
+
+        // The fixed-size sections, laid out from the header counts the same
+        // way the reader walks them
+        let mut sections: Vec<Section> = self
+            .table_layout()
+            .sections
+            .iter()
+            .filter(|s| s.kind != SectionKind::Header)
+            .map(|s| Section {
+                name: s.kind.to_string(),
+                start: s.offset,
+                end: s.end(),
+            })
+            .collect();
+
+        let data_end = file_size.saturating_sub(20);
+        let debug_info = h.debug_info_offset as u64;
+        if !debug_info.is_multiple_of(4) {
+            kinds.push(ValidationKind::SectionMisaligned {
+                section: "debug info".to_string(),
+                offset: debug_info,
+            });
+        }
+        if debug_info > data_end {
+            kinds.push(ValidationKind::SectionOutOfBounds {
+                section: "debug info".to_string(),
+                start: debug_info,
+                end: debug_info,
+                limit: data_end,
+            });
+        }
+
+        // Functions may share a body, like the writer keeps deduplicated ones -
+        // the same range twice is one body, not an overlap
+        let mut bodies = HashSet::new();
+        for (idx, fh) in self.function_headers.iter().enumerate() {
+            if !bodies.insert((fh.offset(), fh.byte_size())) {
+                continue;
+            }
+            sections.push(Section {
+                name: format!("function #{} bytecode", idx),
+                start: fh.offset() as u64,
+                end: fh.offset() as u64 + fh.byte_size() as u64,
+            });
+        }
+
+        // Everything but the debug info has to end before it
+        for section in sections.iter() {
+            if section.end > debug_info.min(data_end) && section.end > section.start {
+                kinds.push(ValidationKind::SectionOutOfBounds {
+                    section: section.name.clone(),
+                    start: section.start,
+                    end: section.end,
+                    limit: debug_info.min(data_end),
+                });
+            }
+        }
+
+        // Empty sections can't overlap anything
+        let mut sorted: Vec<&Section> = sections.iter().filter(|s| s.end > s.start).collect();
+        sorted.sort_by_key(|s| (s.start, s.end));
+        let mut furthest: Option<&Section> = None;
+        for section in sorted {
+            if let Some(prev) = furthest {
+                if section.start < prev.end {
+                    kinds.push(ValidationKind::SectionsOverlap {
+                        first: prev.name.clone(),
+                        second: section.name.clone(),
+                        offset: section.start,
+                    });
+                }
+            }
+            if furthest.is_none_or(|prev| section.end > prev.end) {
+                furthest = Some(section);
+            }
+        }
+    }
+}
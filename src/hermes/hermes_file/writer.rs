@@ -1,12 +1,13 @@
 use sha1::{Digest, Sha1};
+use std::collections::HashMap;
 use std::io::{self, BufRead, Read, Seek, Write};
 use std::vec;
 
+use super::layout::{SectionKind, SectionLayout};
 use super::{HermesFile, HermesStructReader};
 use crate::hermes::cjs_module::CJSModule;
 use crate::hermes::encode::align_writer;
 use crate::hermes::encode::encode_u32;
-use crate::hermes::function_header::get_large_info_offset_pair;
 use crate::hermes::function_header::{FunctionHeader, LargeFunctionHeader, SmallFunctionHeader};
 use crate::hermes::Serializable;
 
@@ -19,376 +20,365 @@ fn align_offset(v: u64) -> u64 {
     }
 }
 
+// write zeroes up to the given offset
+fn pad_to<W>(w: &mut W, offset: u64)
+where
+    W: Write + io::Seek,
+{
+    let pos = w.stream_position().unwrap();
+    assert!(pos <= offset, "wrote past offset {} (at {})", offset, pos);
+    w.write_all(&vec![0; (offset - pos) as usize]).unwrap();
+}
+
+// The layout, along with the function bodies at their offsets and the encoded debug
+// info, which have to be produced to be sized anyway
+struct WritePlan {
+    layout: SectionLayout,
+    bodies: Vec<(u64, Vec<u8>)>,
+    debug_info: Vec<u8>,
+}
+
+/*
+ * Keeps track of how much has been written and hashes it on the way through, so the
+ * footer can be computed without reading the output back. The file is written front
+ * to back, so the only seek it can answer is for the current position.
+ */
+struct FooterHasher<'a, W> {
+    inner: &'a mut W,
+    position: u64,
+    hasher: Sha1,
+}
+
+impl<W> Write for FooterHasher<'_, W>
+where
+    W: Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        self.position += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W> Seek for FooterHasher<'_, W> {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            io::SeekFrom::Start(offset) => Some(offset),
+            io::SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+            io::SeekFrom::End(_) => None,
+        };
+        match target {
+            Some(offset) if offset == self.position => Ok(offset),
+            _ => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "the file is serialized front to back",
+            )),
+        }
+    }
+}
+
 impl<R> HermesFile<R>
 where
     R: Read + BufRead + Seek,
 {
     pub fn serialize<W>(&mut self, w: &mut W)
     where
-        W: Write + io::Seek + Read + BufRead,
+        W: Write,
     {
-        // This function could definitely use some refactoring.
-        // Currently we're manually calculating the offsets of each section of the file and using that as
-        // a jumping point. This obviously isn't ideal, but we're still hammering out bugs.
-        // It's also worth noting that I'm still learning rust, so excuse any bad practices.
-        // Please open an issue or PR with suggestions!
-
-        let _base_offset = w.stream_position().unwrap() + self.header.size() as u64; // should always be 128
+        // The whole layout is worked out first - section offsets, function bytecode and
+        // function info locations, debug info offset and file length - so the header can
+        // be written up front and the rest of the file streamed out in order.
+        let WritePlan {
+            layout,
+            bodies,
+            debug_info,
+        } = self.plan();
+        let offset = |kind: SectionKind| layout.offset(kind).unwrap();
+
+        let mut w = FooterHasher {
+            inner: w,
+            position: 0,
+            hasher: Sha1::new(),
+        };
 
-        let mut c = _base_offset;
+        self.write_header(&mut w);
 
-        c = align_offset(c);
-        let function_headers_offset = c;
-        c += 16 * self.function_headers.len() as u64; // 16 bytes per (small) function header
+        pad_to(&mut w, offset(SectionKind::FunctionHeaders));
+        for fh in self.function_headers.iter() {
+            match fh {
+                FunctionHeader::Small(sfh) => sfh.serialize(&mut w),
+                FunctionHeader::Large(lfh) => {
+                    SmallFunctionHeader::overflow_stub(lfh, lfh.info_offset).serialize(&mut w)
+                }
+            }
+        }
 
-        c = align_offset(c);
-        let string_kind_offset = c;
-        c += 4 * self.header.string_kind_count as u64;
+        pad_to(&mut w, offset(SectionKind::StringKinds));
+        self.write_string_kinds(&mut w);
 
-        c = align_offset(c);
-        let identifier_hash_offset = c;
-        c += 4 * self.header.identifier_count as u64;
+        pad_to(&mut w, offset(SectionKind::IdentifierHashes));
+        self.write_identifier_hashes(&mut w);
 
-        c = align_offset(c);
-        let string_table_offset = c;
-        c += 4 * self.header.string_count as u64;
+        pad_to(&mut w, offset(SectionKind::StringTable));
+        self.write_small_string_table(&mut w);
 
-        c = align_offset(c);
-        let overflow_string_table_offset = c;
-        c += 8 * self.overflow_string_storage.len() as u64;
+        pad_to(&mut w, offset(SectionKind::OverflowStringTable));
+        self.write_overflow_string_table(&mut w);
 
-        c = align_offset(c);
-        let string_storage_offset = c;
-        c += self.string_storage_bytes.len() as u64;
+        pad_to(&mut w, offset(SectionKind::StringStorage));
+        self.write_string_storage(&mut w);
 
-        c = align_offset(c);
-        let array_buffer_offset = c;
-        c += self.array_buffer_storage.len() as u64;
+        pad_to(&mut w, offset(SectionKind::ArrayBuffer));
+        self.write_array_buffer(&mut w);
 
-        c = align_offset(c);
-        let object_key_buffer_offset = c;
-        c += self.object_key_buffer.len() as u64;
+        pad_to(&mut w, offset(SectionKind::ObjectKeyBuffer));
+        self.write_object_key_buffer(&mut w);
 
-        c = align_offset(c);
-        let object_val_buffer_offset = c;
-        c += self.object_val_buffer.len() as u64;
+        pad_to(&mut w, offset(SectionKind::ObjectValueBuffer));
+        self.write_object_value_buffer(&mut w);
 
-        let mut big_int_table_offset = 0;
-        if self.header.version >= 87 {
-            c = align_offset(c);
-            big_int_table_offset = c;
-            c += 8 * self.big_int_table.len() as u64;
+        if let Some(big_int_table_offset) = layout.offset(SectionKind::BigIntTable) {
+            pad_to(&mut w, big_int_table_offset);
+            self.write_big_int_table(&mut w);
+            pad_to(&mut w, offset(SectionKind::BigIntStorage));
+            self.write_big_int_storage(&mut w);
         }
 
-        c = align_offset(c);
-        let reg_exp_table_offset = c;
-        c += 8 * self.header.reg_exp_count as u64;
-        let _reg_exp_storage_offset = c;
-        c += self.reg_exp_storage.len() as u64;
+        pad_to(&mut w, offset(SectionKind::RegExpTable));
+        self.write_reg_exp_table(&mut w); // This writes the reg_exp_storage value as well
 
-        c = align_offset(c);
-        let cjs_size = match self.cjs_modules.first() {
-            Some(CJSModule::CJSModuleEntry(_)) => 8,
-            Some(CJSModule::CJSModuleInt(_)) => 4,
-            None => 0,
-        };
-        let cjs_module_table_offset = c;
-        c += cjs_size * self.header.cjs_module_count as u64;
-
-        c = align_offset(c);
-        let function_source_table_offset = c;
+        // The reader expects the CJS module table for every version
+        pad_to(&mut w, offset(SectionKind::CJSModuleTable));
+        self.write_cjs_module_table(&mut w);
 
-        if self.header.version >= 84 {
-            c += 8 * self.header.function_source_count as u64;
+        if let Some(function_source_table_offset) = layout.offset(SectionKind::FunctionSourceTable)
+        {
+            pad_to(&mut w, function_source_table_offset);
+            self.write_function_source_table(&mut w);
         }
 
-        c = align_offset(c);
-        let bytecode_offset = c;
-        let bytecode_length: u64 = self
-            .function_bytecode
-            .iter()
-            .flat_map(|pair| pair.bytecode.iter())
-            .map(|instruction| instruction.size() as u64)
-            .sum();
-
-        c += bytecode_length;
-
-        c = align_offset(c);
+        for (offset, bytes) in bodies.iter() {
+            pad_to(&mut w, *offset);
+            w.write_all(bytes).unwrap();
+        }
 
-        // Mostly using this as a debugging value. Compiler will remove it.
-        let _function_header_offset = c;
+        for fh in self.function_headers.iter() {
+            pad_to(&mut w, fh.info_offset() as u64);
 
-        // Now that we have all the offset locations, we can get to writing the actual data at those locations.
-        // This whole process will be rewritten once all the bugs are hammered out.
-        w.seek(io::SeekFrom::Start(string_kind_offset)).unwrap();
-        self.write_string_kinds(w);
+            if let FunctionHeader::Large(lfh) = fh {
+                lfh.serialize(&mut w);
+                align_writer(&mut w, 4);
+            }
 
-        w.seek(io::SeekFrom::Start(identifier_hash_offset)).unwrap();
-        self.write_identifier_hashes(w);
+            let flags = fh.flags();
+            if flags.has_exception_handler {
+                align_writer(&mut w, 4);
+                let exception_handlers = fh.exception_handlers();
+                encode_u32(&mut w, exception_handlers.len() as u32);
+                for eh in exception_handlers.iter() {
+                    eh.serialize(&mut w);
+                }
+            }
 
-        w.seek(io::SeekFrom::Start(string_table_offset)).unwrap();
-        self.write_small_string_table(w);
+            if flags.has_debug_info {
+                if let Some(debug_info) = fh.debug_info() {
+                    align_writer(&mut w, 4);
+                    debug_info.serialize(&mut w);
+                }
+            }
+        }
 
-        w.seek(io::SeekFrom::Start(overflow_string_table_offset))
-            .unwrap();
-        self.write_overflow_string_table(w);
+        pad_to(&mut w, offset(SectionKind::DebugInfo));
+        w.write_all(&debug_info).unwrap();
 
-        w.seek(io::SeekFrom::Start(string_storage_offset)).unwrap();
-        self.write_string_storage(w);
+        self.footer = w.hasher.finalize().into();
+        self.write_footer(w.inner);
+    }
 
-        w.seek(io::SeekFrom::Start(array_buffer_offset)).unwrap();
-        self.write_array_buffer(w);
+    /*
+     * Serializes the file into a new buffer.
+     */
+    pub fn to_bytes(&mut self) -> Vec<u8> {
+        let mut out = vec![];
+        self.serialize(&mut out);
+        out
+    }
 
-        w.seek(io::SeekFrom::Start(object_key_buffer_offset))
-            .unwrap();
-        self.write_object_key_buffer(w);
+    /*
+     * Works out where serialize() will put every section, and updates the header and
+     * function headers (offsets, sizes, info offsets) to match. Comparing this against
+     * section_layout() of a file that was just read shows whether writing it back out
+     * would move anything.
+     */
+    pub fn plan_layout(&mut self) -> SectionLayout {
+        self.plan().layout
+    }
 
-        w.seek(io::SeekFrom::Start(object_val_buffer_offset))
-            .unwrap();
-        self.write_object_value_buffer(w);
+    fn plan(&mut self) -> WritePlan {
+        // Sections are laid out in the same order and with the same alignment the reader
+        // expects. Every section is sized from the data we hold rather than the header,
+        // which is brought up to date first.
+        self.update_header();
 
-        if self.header.version >= 87 {
-            w.seek(io::SeekFrom::Start(big_int_table_offset)).unwrap();
-            self.write_big_int_table(w);
-            align_writer(w, 4);
-            self.write_big_int_storage(w);
+        // The extra debug info header fields only exist from v91 on
+        let debug_header = &mut self.debug_info.header;
+        if self.header.version >= 91 {
+            let textified_callee_offset = debug_header.scope_desc_data_offset
+                + self.debug_info.scope_desc_data_storage.len() as u32;
+            debug_header
+                .textified_callee_offset
+                .get_or_insert(textified_callee_offset);
+            debug_header.string_table_offset.get_or_insert(
+                textified_callee_offset + self.debug_info.textified_callee_storage.len() as u32,
+            );
+        } else {
+            debug_header.textified_callee_offset = None;
+            debug_header.string_table_offset = None;
         }
 
-        w.seek(io::SeekFrom::Start(reg_exp_table_offset)).unwrap();
-        self.write_reg_exp_table(w); // This writes the reg_exp_storage value as well
-
-        if self.header.version >= 84 {
-            w.seek(io::SeekFrom::Start(cjs_module_table_offset))
-                .unwrap();
-            self.write_cjs_module_table(w);
-        }
+        let mut layout = self.table_layout();
+        let bytecode_offset = align_offset(layout.end());
+        let mut c = bytecode_offset;
+
+        // Lay out the bytecode for each function and record its offset in the function header.
+        // Functions that are unchanged since the file was read are copied byte for byte,
+        // and functions that shared their bytecode still share it.
+        let mut bodies: Vec<(u64, Vec<u8>)> = vec![];
+        let mut shared_bodies: HashMap<u32, usize> = HashMap::new();
+        for fidx in 0..self.function_headers.len() {
+            let (bytes, original_offset) = self.get_function_body_for_write(fidx);
+            let byte_size = bytes.len() as u32;
+
+            let shared = original_offset
+                .and_then(|original| shared_bodies.get(&original))
+                .filter(|&&body| bodies[body].1 == bytes);
+            let offset = match shared {
+                Some(&body) => bodies[body].0,
+                None => {
+                    // Bodies keep the alignment their header gives them, as switch
+                    // tables are aligned against the file
+                    let alignment = self.function_headers[fidx].offset() as u64 % 4;
+                    c += (alignment + 4 - c % 4) % 4;
+                    if let Some(original) = original_offset {
+                        shared_bodies.entry(original).or_insert(bodies.len());
+                    }
+                    bodies.push((c, bytes));
+                    c += byte_size as u64;
+                    bodies.last().unwrap().0
+                }
+            };
 
-        if self.header.function_source_count > 0 {
-            w.seek(io::SeekFrom::Start(function_source_table_offset))
-                .unwrap();
-            self.write_function_source_table(w);
+            let fh = &mut self.function_headers[fidx];
+            fh.set_offset(offset as u32);
+            fh.set_byte_size(byte_size);
         }
+        layout.push(SectionKind::Bytecode, c - bytecode_offset, 4);
 
-        w.seek(io::SeekFrom::Start(bytecode_offset)).unwrap();
-
-        // Debug...
-        // println!("string_kind_offset: {:?}", string_kind_offset);
-        // println!("identifier_hash_offset: {:?}", identifier_hash_offset);
-        // println!("string_table_offset: {:?}", string_table_offset);
-        // println!("overflow_string_table_offset: {:?}", overflow_string_table_offset);
-        // println!("string_storage_offset: {:?}", string_storage_offset);
-        // println!("array_buffer_offset: {:?}", array_buffer_offset);
-        // println!("object_key_buffer_offset: {:?}", object_key_buffer_offset);
-        // println!("object_val_buffer_offset: {:?}", object_val_buffer_offset);
-        // println!("big_int_table_offset: {:?}", big_int_table_offset);
-        // println!("reg_exp_table_offset: {:?}", reg_exp_table_offset);
-        // println!("cjs_module_table_offset: {:?}", cjs_module_table_offset);
-        // println!("function_source_table_offset: {:?}", function_source_table_offset);
-        // println!("bytecode_offset: {:?}", bytecode_offset);
-
-        align_writer(w, 4);
-
-        // Function index : offset of the bytecode insns
-        let mut function_bytecode_offsets = vec![];
-
-        // Write the bytecode for each function here, and keep a record of the offset
-        // so we can write it to the function header later
-        for func_pair in &mut self.function_bytecode {
-            let current_offset = w.stream_position().unwrap();
-            function_bytecode_offsets.push((func_pair.func_index, current_offset));
-            // Update the SmallFunctionHeader offset and info_offset with the new bytecode offset
-            for instruction in &func_pair.bytecode {
-                instruction.serialize(w);
+        // Function info: the LargeFunctionHeader of overflowed functions, then exception
+        // handlers and debug info offsets. Each function's info_offset points at its own
+        // (aligned) info, whether it has any or not.
+        c = align_offset(c);
+        let function_info_offset = c;
+        for fh in self.function_headers.iter_mut() {
+            // Anything that no longer fits a SmallFunctionHeader is promoted. Headers that
+            // were large to begin with stay large.
+            if let FunctionHeader::Small(sfh) = fh {
+                if sfh.flags.overflowed || sfh.is_overflowed_check() {
+                    *fh = FunctionHeader::Large(LargeFunctionHeader::from(sfh.clone()));
+                }
             }
-        }
 
-        align_writer(w, 4);
-
-        // Store the individual SmallFunctionHeader offsets for each function header
-        // We need to know where they are so we can go back to them and update values
-        let mut small_func_header_offsets = vec![];
-        let mut sfhoc = function_headers_offset;
-        for func_pair in &mut self.function_bytecode {
-            small_func_header_offsets.push((func_pair.func_index, sfhoc));
-            sfhoc += 16;
-        }
+            c = align_offset(c);
+            fh.set_info_offset(c as u32);
 
-        // This is the offset where LargeFunctionHeaders will be written.
-        let large_function_header_offset = w.stream_position().unwrap() as u64;
+            if let FunctionHeader::Large(lfh) = fh {
+                c += lfh.size() as u64;
+            }
 
-        // Manually override any LargeFunctionHeaders that exist to be SmallFunctionHeaders
-        for fh in &mut self.function_headers {
-            let _current_offset = w.stream_position().unwrap();
+            let flags = fh.flags();
+            if flags.has_exception_handler {
+                c = align_offset(c);
+                c += 4 + 12 * fh.exception_handlers().len() as u64;
+            }
 
-            // fh.set_offset(bytecode_offset as u32); // bytecode
-            // fh.set_info_offset(current_offset as u32); // exception handlers, debug info
-            match fh {
-                FunctionHeader::Large(lfh) => {
-                    // If we catch a Large, we need to convert it to a small.
-                    // The data, at this point in the code, is still valid as it hasn't been modified yet.
-                    let mut sfh: SmallFunctionHeader = SmallFunctionHeader::from(lfh.clone());
-                    sfh.flags.overflowed = true;
-
-                    // We're reassigning the Large to a Small here.
-                    // Below this section in the code, we're going to check for overflows
-                    // and actually write the LargeFunctionHeader based on the data within this.
-                    fh.set_overflowed(true);
-                    *fh = FunctionHeader::Small(sfh);
+            if flags.has_debug_info {
+                if let Some(debug_info) = fh.debug_info() {
+                    c = align_offset(c);
+                    c += debug_info.size() as u64;
                 }
-                _ => { /* Do nothing */ }
             }
         }
+        // The debug info is aligned, so the padding before it counts as function info
+        c = align_offset(c);
+        layout.push(SectionKind::FunctionInfo, c - function_info_offset, 4);
 
-        let mut large_write_offset = large_function_header_offset;
-        for (_fidx, func_pair) in &mut self.function_bytecode.iter().enumerate() {
-            let current_pos = w.stream_position().unwrap();
-            // Check if the function header is overflowed
-            let fh = self
-                .function_headers
-                .get_mut(func_pair.func_index as usize)
-                .unwrap();
+        let mut debug_info = vec![];
+        self.debug_info
+            .serialize(&mut io::Cursor::new(&mut debug_info));
+        self.offsets.debug_info_offset =
+            layout.push(SectionKind::DebugInfo, debug_info.len() as u64, 4) as u32;
 
-            // Write the SmallFunctionHeader
-            let sfh_offset = small_func_header_offsets
-                .iter()
-                .find(|(index, _)| *index == func_pair.func_index)
-                .unwrap()
-                .1;
+        layout.push(SectionKind::Footer, 20, 1);
+        self.header.file_length = layout.end() as u32;
+        self.update_header();
 
-            // Get bytecode offset for this function
-            let bytecode_offset = function_bytecode_offsets
-                .iter()
-                .find(|(index, _)| *index == func_pair.func_index)
-                .unwrap()
-                .1;
+        WritePlan {
+            layout,
+            bodies,
+            debug_info,
+        }
+    }
 
-            match fh {
-                FunctionHeader::Small(sfh) => {
-                    let is_overflowed = sfh.flags.overflowed || sfh.is_overflowed_check();
-                    if is_overflowed {
-                        let mut lfh = LargeFunctionHeader::from(sfh.clone());
-
-                        // Seek to large_write_offset and serialize the LargeFunctionHeader + exception handlers + debug info
-                        w.seek(io::SeekFrom::Start(large_write_offset)).unwrap();
-
-                        // Update the offsets of this LargeFunctionHeader to reflect the correct values.
-                        // Possibly superfluous, but it's here for now.
-                        lfh.offset = bytecode_offset as u32;
-                        lfh.info_offset = large_write_offset as u32;
-
-                        // Keep track of where this is being written to so we can calculate the overflow offsets
-                        // for the SmallFunctionHeader
-                        let large_offset = w.stream_position().unwrap();
-
-                        // Serialize the LargeFunctionHeader struct
-                        lfh.serialize(w);
-
-                        // Serialize the exception handlers and debug info
-                        if lfh.flags.has_exception_handler {
-                            align_writer(w, 4);
-                            encode_u32(w, lfh.exception_handlers.len() as u32);
-                            for eh in lfh.exception_handlers.iter() {
-                                eh.serialize(w);
-                            }
-                        }
-
-                        if lfh.flags.has_debug_info && lfh.debug_info.is_some() {
-                            lfh.debug_info.as_mut().unwrap().serialize(w);
-                        }
-
-                        // Update the offset for large writes so we don't let them step on one another
-                        large_write_offset = w.stream_position().unwrap() as u64;
-
-                        // Seek back to current_offset to write the small header
-                        w.seek(io::SeekFrom::Start(current_pos)).unwrap();
-
-                        // Update SFH offset + info_offset
-                        let (new_large_offset, new_large_info_offset) =
-                            get_large_info_offset_pair(large_offset as u32);
-                        sfh.offset = new_large_offset as u32;
-                        sfh.info_offset = new_large_info_offset as u32;
-                        sfh.flags.overflowed = true;
-                        sfh.serialize(w);
-                    } else {
-                        w.seek(io::SeekFrom::Start(sfh_offset)).unwrap(); // string kind off fbbe0
-                                                                          // Write the SmallFunctionHeader as per usual
-                        sfh.offset = bytecode_offset as u32;
-                        sfh.info_offset = large_write_offset as u32;
-                        sfh.serialize(w);
-
-                        let current_offset = w.stream_position().unwrap();
-                        w.seek(io::SeekFrom::Start(sfh.info_offset as u64)).unwrap();
-                        if !sfh.flags.overflowed {
-                            // Write the exception handlers and debug info for true SmallFunctionHeaders.
-                            // At this point we've already written the LargeFunctionHeader exceptions and debug info
-                            if sfh.flags.has_exception_handler {
-                                // println!("writing exception/debug info for {:?} at {:?}", _fidx, w.stream_position().unwrap());
-                                align_writer(w, 4);
-                                encode_u32(w, sfh.exception_handlers.len() as u32);
-                                for eh in sfh.exception_handlers.iter() {
-                                    eh.serialize(w);
-                                    large_write_offset = w.stream_position().unwrap() as u64;
-                                }
-                            }
-
-                            if sfh.flags.has_debug_info && sfh.debug_info.is_some() {
-                                sfh.debug_info.as_mut().unwrap().serialize(w);
-                                large_write_offset = w.stream_position().unwrap() as u64;
-                            }
-                        }
-                        // Go back to the position of the SmallFunctionHeader after the write
-                        w.seek(io::SeekFrom::Start(current_offset)).unwrap();
+    /*
+     * Returns the bytes to write for a function, along with where its bytecode was in the
+     * file we read (if it came from one). Unless the function's instructions have been
+     * changed, those are the original bytes - re-encoding decoded instructions isn't
+     * lossless, as jump tables and padding after the last instruction get decoded too.
+     */
+    fn get_function_body_for_write(&mut self, idx: usize) -> (Vec<u8>, Option<u32>) {
+        let original = self.offsets.original_bodies.get(&(idx as u32)).copied();
+        let original_bytes = original.map(|(offset, size)| {
+            let mut bytes = vec![0u8; size as usize];
+            self._reader
+                .seek(io::SeekFrom::Start(offset as u64))
+                .unwrap();
+            self._reader
+                .read_exact(&mut bytes)
+                .expect("unable to read function bytecode");
+            (offset, bytes)
+        });
+
+        let instructions = self.function_bytecode_position(idx).map(|pos| {
+            let mut out = vec![];
+            for ins in self.function_bytecode[pos].bytecode.iter() {
+                ins.serialize(&mut out);
+            }
+            out
+        });
+
+        match (instructions, original_bytes) {
+            (None, Some((offset, bytes))) => (bytes, Some(offset)),
+            (Some(encoded), Some((offset, bytes))) => {
+                let mut reencoded = vec![];
+                if encoded != bytes {
+                    for ins in self.decode_function_bytes(&bytes).iter() {
+                        ins.serialize(&mut reencoded);
                     }
                 }
-                _ => {
-                    panic!("Function header is not a SmallFunctionHeader. Use the correct type.");
+                if encoded == bytes || encoded == reencoded {
+                    (bytes, Some(offset))
+                } else {
+                    (encoded, None)
                 }
             }
+            (Some(encoded), None) => (encoded, None),
+            (None, None) => panic!("No bytecode for function {}", idx),
         }
-
-        // Seek to the large_write_offset, as thats the last place we wrote a LargeFunctionHeader or SmallFunctionHeader Exception info/debug info
-        w.seek(io::SeekFrom::Start(large_write_offset)).unwrap();
-        println!("1111111 large_write_offset: {:?}", large_write_offset);
-
-        // Write large function headers in a nicer way
-        // for (offset, mut lfh) in large_headers {
-        //     w.seek(io::SeekFrom::Start(offset)).unwrap();
-        //     let current_offset = w.stream_position().unwrap();
-        //     println!("Writing large function header at {:?}", offset);
-        //     lfh.info_offset = current_offset as u32;
-        //     println!("LFH: {:?}", lfh);
-        //     lfh.serialize(w);
-
-        //     if lfh.flags.has_exception_handler {
-        //         align_writer(w, 4);
-
-        //         // Well this was a stupid fucking bug.
-        //         encode_u32(w, lfh.exception_handlers.len() as u32);
-
-        //         for eh in lfh.exception_handlers {
-        //             eh.serialize(w);
-        //         }
-        //     }
-
-        //     if lfh.flags.has_debug_info && lfh.debug_info.is_some() {
-        //         // align_writer(w, 4);
-        //         lfh.debug_info.unwrap().serialize(w);
-        //     }
-        // }
-
-        self.offsets.debug_info_offset = w.stream_position().unwrap() as u32;
-        self.write_debug_info(w);
-
-        let footer_offset = w.stream_position().unwrap();
-
-        self.header.file_length = w.stream_position().unwrap() as u32 + 20; // plus sha1 footer size
-
-        w.seek(io::SeekFrom::Start(0)).unwrap();
-        self.update_header();
-        self.write_header(w);
-
-        w.seek(io::SeekFrom::Start(footer_offset)).unwrap();
-        self.write_footer(w);
     }
 
     // pub fn write_<W>(&self, w: &mut W) where W: Write + io::Seek, {}
@@ -604,28 +594,14 @@ where
         self.debug_info.serialize(w);
     }
 
-    pub fn write_footer<W>(&mut self, w: &mut W)
+    /*
+     * Writes the footer. serialize() computes the SHA-1 of everything before it as the
+     * file is written out.
+     */
+    pub fn write_footer<W>(&self, w: &mut W)
     where
-        W: Write + io::Seek + Read + BufRead,
+        W: Write,
     {
-        // align_writer(w, 4);
-        let pos = w.stream_position().unwrap();
-        let mut executable_bytes = vec![];
-        w.seek(io::SeekFrom::Start(0)).unwrap();
-        w.take(pos).read_to_end(&mut executable_bytes).unwrap();
-
-        // calculate the footer hash
-        let mut hasher = Sha1::new();
-        hasher.update(&executable_bytes);
-        self.footer = hasher.finalize().into();
-
-        // Debug
-        let _footer_hash_hex = self
-            .footer
-            .iter()
-            .map(|b| format!("{:02x}", b))
-            .collect::<String>();
-
         w.write_all(&self.footer).unwrap();
     }
 }
@@ -0,0 +1,1327 @@
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::io;
+
+use crate::hermes::array_parser::ArrayTypes;
+use crate::hermes::exception_handler::ExceptionHandlerInfo;
+use crate::hermes::hermes_file::HermesFile;
+use crate::hermes::ir::{
+    BinaryOp, BlockId, Builtin, ClosureKind, Constant, IrBlock, IrExceptionHandler, IrFunction,
+    IrInstruction, IrOp, IrPhi, IrTerminator, PropertyKey, UnaryOp, ValueId,
+};
+use crate::hermes::{get_builtins, HermesInstruction};
+
+/*
+ * Lifts a function's bytecode into the IR.
+ *
+ * 1. Decode the instructions and find basic block leaders - jump/switch targets,
+ *    instructions following a branch or exit, exception handler boundaries and
+ *    generator resume points.
+ * 2. Drop blocks that can't be reached from the entry (this also skips the
+ *    SwitchImm jump tables that Hermes stores after the function body).
+ * 3. Translate each block, building SSA form on the fly as described in
+ *    "Simple and Efficient Construction of Static Single Assignment Form"
+ *    (Braun et al.). Registers are the variables; phis are only created where
+ *    a register is read and has more than one reaching definition.
+ *
+ * Exception handlers get an edge from every block in their try range, using the
+ * register values at the end of those blocks. That's an approximation - a throw
+ * in the middle of a block sees the values at that point - but it's what makes
+ * the handler's reads resolvable without splitting every instruction into its
+ * own block.
+ */
+
+// How an instruction affects control flow. Targets are byte offsets within the function.
+enum Control {
+    None,
+    Jump(i64),
+    // Jump to the target when the condition holds, otherwise fall through.
+    Conditional(i64),
+    Switch {
+        cases: Vec<(i64, i64)>,
+        default: i64,
+    },
+    // Ret, Throw, Unreachable
+    Exit,
+    // SaveGenerator - falls through, and execution resumes at the target later.
+    Resume(i64),
+}
+
+struct RawBlock {
+    start: u32,
+    end: u32,
+    // Instruction index range [first, last)
+    first: usize,
+    last: usize,
+    successors: Vec<usize>,
+    handler: Option<usize>,
+}
+
+impl<R> HermesFile<R>
+where
+    R: io::Read + io::BufRead + io::Seek,
+{
+    /*
+     * Lifts a function into the version-independent IR.
+     */
+    pub fn lift_function(&mut self, idx: usize) -> IrFunction {
+        let instructions = self.get_function_instructions(idx);
+        let bytes = self.get_function_bytes(idx);
+        Lifter::new(self, idx, instructions, bytes).lift()
+    }
+}
+
+struct Lifter<'a, R> {
+    file: &'a HermesFile<R>,
+    function_id: u32,
+    frame_size: u32,
+    function_offset: u32,
+
+    instructions: Vec<HermesInstruction>,
+    offsets: Vec<u32>,
+    bytes: Vec<u8>,
+    handlers: Vec<ExceptionHandlerInfo>,
+
+    blocks: Vec<RawBlock>,
+    block_at: HashMap<u32, usize>,
+    preds: Vec<Vec<usize>>,
+
+    // SSA construction state, indexed by block
+    defs: Vec<HashMap<u32, ValueId>>,
+    sealed: Vec<bool>,
+    filled: Vec<bool>,
+    incomplete: Vec<Vec<(u32, ValueId)>>,
+    phis: Vec<Vec<IrPhi>>,
+    aliases: HashMap<ValueId, ValueId>,
+    next_value: u32,
+    undefined: Option<ValueId>,
+
+    out: Vec<Vec<IrInstruction>>,
+    current: usize,
+    current_offset: u32,
+}
+
+impl<'a, R> Lifter<'a, R>
+where
+    R: io::Read + io::BufRead + io::Seek,
+{
+    fn new(
+        file: &'a HermesFile<R>,
+        idx: usize,
+        instructions: Vec<HermesInstruction>,
+        bytes: Vec<u8>,
+    ) -> Self {
+        let fh = &file.function_headers[idx];
+
+        let mut offsets = vec![];
+        let mut offset = 0u32;
+        for ins in instructions.iter() {
+            offsets.push(offset);
+            offset += ins.size() as u32;
+        }
+
+        Lifter {
+            file,
+            function_id: idx as u32,
+            frame_size: fh.frame_size(),
+            function_offset: fh.offset(),
+            instructions,
+            offsets,
+            bytes,
+            handlers: if fh.flags().has_exception_handler {
+                fh.exception_handlers()
+            } else {
+                vec![]
+            },
+            blocks: vec![],
+            block_at: HashMap::new(),
+            preds: vec![],
+            defs: vec![],
+            sealed: vec![],
+            filled: vec![],
+            incomplete: vec![],
+            phis: vec![],
+            aliases: HashMap::new(),
+            next_value: 0,
+            undefined: None,
+            out: vec![],
+            current: 0,
+            current_offset: 0,
+        }
+    }
+
+    fn lift(mut self) -> IrFunction {
+        self.build_blocks();
+
+        let count = self.blocks.len();
+        self.defs = vec![HashMap::new(); count];
+        self.sealed = vec![false; count];
+        self.filled = vec![false; count];
+        self.incomplete = vec![vec![]; count];
+        self.phis = vec![vec![]; count];
+        self.out = vec![vec![]; count];
+
+        for b in 0..count {
+            if self.preds[b].is_empty() {
+                self.sealed[b] = true;
+            }
+        }
+
+        let mut terminators = vec![];
+        for b in 0..count {
+            terminators.push(self.fill_block(b));
+            self.filled[b] = true;
+
+            let mut candidates = self.blocks[b].successors.clone();
+            candidates.extend(self.blocks[b].handler);
+            for s in candidates {
+                if !self.sealed[s] && self.preds[s].iter().all(|p| self.filled[*p]) {
+                    self.seal(s);
+                }
+            }
+        }
+
+        // Anything left unsealed only has unfilled predecessors through edges we
+        // never walk, but seal it anyway so every phi is complete.
+        for b in 0..count {
+            if !self.sealed[b] {
+                self.seal(b);
+            }
+        }
+
+        self.remove_trivial_phis();
+        self.finish(terminators)
+    }
+
+    // ---------------------------------------------------------------------- //
+    // CFG construction
+    // ---------------------------------------------------------------------- //
+
+    fn code_size(&self) -> u32 {
+        match self.instructions.last() {
+            Some(ins) => *self.offsets.last().unwrap() + ins.size() as u32,
+            None => 0,
+        }
+    }
+
+    fn operand(&self, idx: usize, name: &str) -> i64 {
+        self.instructions[idx]
+            .operand(name)
+            .map(|o| o.value.as_i64())
+            .unwrap_or(0)
+    }
+
+    fn address(&self, idx: usize) -> i64 {
+        let rel = self.instructions[idx]
+            .operands()
+            .iter()
+            .find(|o| o.kind.is_address())
+            .map(|o| o.value.as_i64())
+            .unwrap_or(0);
+        self.offsets[idx] as i64 + rel
+    }
+
+    fn control(&self, idx: usize) -> Control {
+        let name = self.instructions[idx].name();
+        match name {
+            "Jmp" | "JmpLong" => Control::Jump(self.address(idx)),
+            "SaveGenerator" | "SaveGeneratorLong" => Control::Resume(self.address(idx)),
+            "SwitchImm" => self.switch_table(idx),
+            "Ret" | "Throw" | "Unreachable" => Control::Exit,
+            _ if name.starts_with("Jmp") || (name.starts_with('J') && name != "J") => {
+                if self.instructions[idx].is_jmp() {
+                    Control::Conditional(self.address(idx))
+                } else {
+                    Control::None
+                }
+            }
+            _ => Control::None,
+        }
+    }
+
+    /*
+     * SwitchImm r0, p0, p1, p2, p3 - p0 is the offset of the jump table (aligned
+     * to 4 bytes in the file), p1 the default target, p2/p3 the min/max case values.
+     * The table holds (max - min + 1) 32-bit offsets relative to the SwitchImm.
+     */
+    fn switch_table(&self, idx: usize) -> Control {
+        let offset = self.offsets[idx] as i64;
+        let default = self.address(idx);
+        let min = self.operand(idx, "p2");
+        let max = self.operand(idx, "p3");
+
+        let table_abs = (self.function_offset as i64 + offset + self.operand(idx, "p0") + 3) & !3;
+        let table = table_abs - self.function_offset as i64;
+
+        let mut cases = vec![];
+        if max >= min && table >= 0 {
+            for (i, value) in (min..=max).enumerate() {
+                let start = table as usize + i * 4;
+                if start + 4 > self.bytes.len() {
+                    break;
+                }
+                let rel = i32::from_le_bytes(self.bytes[start..start + 4].try_into().unwrap());
+                cases.push((value, offset + rel as i64));
+            }
+        }
+
+        Control::Switch { cases, default }
+    }
+
+    fn build_blocks(&mut self) {
+        let size = self.code_size();
+        let valid: HashMap<u32, usize> = self
+            .offsets
+            .iter()
+            .enumerate()
+            .map(|(i, o)| (*o, i))
+            .collect();
+
+        let mut leaders: BTreeSet<u32> = BTreeSet::new();
+        leaders.insert(0);
+        let add = |target: i64, leaders: &mut BTreeSet<u32>| {
+            if target >= 0 && valid.contains_key(&(target as u32)) {
+                leaders.insert(target as u32);
+            }
+        };
+
+        for idx in 0..self.instructions.len() {
+            let next = self.offsets[idx] as i64 + self.instructions[idx].size() as i64;
+            match self.control(idx) {
+                Control::None => continue,
+                Control::Jump(t) | Control::Conditional(t) | Control::Resume(t) => {
+                    add(t, &mut leaders)
+                }
+                Control::Switch { cases, default } => {
+                    for (_, t) in cases {
+                        add(t, &mut leaders);
+                    }
+                    add(default, &mut leaders);
+                }
+                Control::Exit => {}
+            }
+            add(next, &mut leaders);
+        }
+        for h in self.handlers.iter() {
+            add(h.start as i64, &mut leaders);
+            add(h.end as i64, &mut leaders);
+            add(h.target as i64, &mut leaders);
+        }
+
+        // Split the instructions at the leaders
+        let leaders: Vec<u32> = leaders.into_iter().collect();
+        let mut raw: Vec<RawBlock> = vec![];
+        for (i, start) in leaders.iter().enumerate() {
+            let first = valid[start];
+            let last = match leaders.get(i + 1) {
+                Some(next) => valid[next],
+                None => self.instructions.len(),
+            };
+            let end = leaders.get(i + 1).copied().unwrap_or(size);
+            raw.push(RawBlock {
+                start: *start,
+                end,
+                first,
+                last,
+                successors: vec![],
+                handler: None,
+            });
+        }
+        let raw_at: HashMap<u32, usize> =
+            raw.iter().enumerate().map(|(i, b)| (b.start, i)).collect();
+        let lookup = |target: i64| -> Option<usize> {
+            if target < 0 {
+                None
+            } else {
+                raw_at.get(&(target as u32)).copied()
+            }
+        };
+
+        for b in raw.iter_mut() {
+            if b.first == b.last {
+                continue;
+            }
+            let last = b.last - 1;
+            let mut succ: Vec<Option<usize>> = match self.control(last) {
+                Control::None => vec![lookup(b.end as i64)],
+                Control::Jump(t) => vec![lookup(t)],
+                Control::Conditional(t) => vec![lookup(t), lookup(b.end as i64)],
+                Control::Resume(t) => vec![lookup(b.end as i64), lookup(t)],
+                Control::Switch { cases, default } => {
+                    let mut out: Vec<Option<usize>> =
+                        cases.into_iter().map(|(_, t)| lookup(t)).collect();
+                    out.push(lookup(default));
+                    out
+                }
+                Control::Exit => vec![],
+            };
+            succ.retain(|s| s.is_some());
+            for s in succ.into_iter().flatten() {
+                if !b.successors.contains(&s) {
+                    b.successors.push(s);
+                }
+            }
+
+            b.handler = self
+                .handlers
+                .iter()
+                .find(|h| b.start >= h.start && b.start < h.end)
+                .and_then(|h| raw_at.get(&h.target).copied());
+        }
+
+        // Keep the blocks reachable from the entry
+        let mut reachable = vec![false; raw.len()];
+        let mut queue: VecDeque<usize> = VecDeque::new();
+        if !raw.is_empty() {
+            reachable[0] = true;
+            queue.push_back(0);
+        }
+        while let Some(b) = queue.pop_front() {
+            let mut next = raw[b].successors.clone();
+            next.extend(raw[b].handler);
+            for s in next {
+                if !reachable[s] {
+                    reachable[s] = true;
+                    queue.push_back(s);
+                }
+            }
+        }
+
+        let mut remap: HashMap<usize, usize> = HashMap::new();
+        for (i, _) in raw.iter().enumerate().filter(|(i, _)| reachable[*i]) {
+            remap.insert(i, remap.len());
+        }
+
+        for (i, mut b) in raw.into_iter().enumerate() {
+            if !reachable[i] {
+                continue;
+            }
+            b.successors = b.successors.iter().map(|s| remap[s]).collect();
+            b.handler = b.handler.map(|h| remap[&h]);
+            self.block_at.insert(b.start, self.blocks.len());
+            self.blocks.push(b);
+        }
+
+        self.preds = vec![vec![]; self.blocks.len()];
+        for (i, b) in self.blocks.iter().enumerate() {
+            let mut targets = b.successors.clone();
+            targets.extend(b.handler);
+            for s in targets {
+                if !self.preds[s].contains(&i) {
+                    self.preds[s].push(i);
+                }
+            }
+        }
+    }
+
+    fn target_block(&self, target: i64) -> Option<BlockId> {
+        if target < 0 {
+            return None;
+        }
+        self.block_at
+            .get(&(target as u32))
+            .map(|b| BlockId(*b as u32))
+    }
+
+    // ---------------------------------------------------------------------- //
+    // SSA construction
+    // ---------------------------------------------------------------------- //
+
+    fn new_value(&mut self) -> ValueId {
+        let v = ValueId(self.next_value);
+        self.next_value += 1;
+        v
+    }
+
+    fn resolve(&self, mut v: ValueId) -> ValueId {
+        while let Some(next) = self.aliases.get(&v) {
+            v = *next;
+        }
+        v
+    }
+
+    fn undefined(&mut self) -> ValueId {
+        if let Some(v) = self.undefined {
+            return v;
+        }
+        let v = self.new_value();
+        self.out[0].insert(
+            0,
+            IrInstruction {
+                offset: 0,
+                result: Some(v),
+                op: IrOp::Const(Constant::Undefined),
+            },
+        );
+        self.undefined = Some(v);
+        v
+    }
+
+    fn write_variable(&mut self, reg: u32, block: usize, value: ValueId) {
+        self.defs[block].insert(reg, value);
+    }
+
+    fn read_variable(&mut self, reg: u32, block: usize) -> ValueId {
+        if let Some(v) = self.defs[block].get(&reg) {
+            return self.resolve(*v);
+        }
+
+        let v = if self.preds[block].is_empty() {
+            // Registers start out as undefined
+            self.undefined()
+        } else if !self.sealed[block] {
+            let phi = self.new_phi(reg, block);
+            self.incomplete[block].push((reg, phi));
+            phi
+        } else if self.preds[block].len() == 1 {
+            let pred = self.preds[block][0];
+            self.read_variable(reg, pred)
+        } else {
+            let phi = self.new_phi(reg, block);
+            self.write_variable(reg, block, phi);
+            self.add_phi_operands(reg, block, phi)
+        };
+
+        self.write_variable(reg, block, v);
+        v
+    }
+
+    fn new_phi(&mut self, reg: u32, block: usize) -> ValueId {
+        let v = self.new_value();
+        self.phis[block].push(IrPhi {
+            result: v,
+            register: reg,
+            incoming: vec![],
+        });
+        v
+    }
+
+    fn add_phi_operands(&mut self, reg: u32, block: usize, phi: ValueId) -> ValueId {
+        let mut incoming = vec![];
+        for pred in self.preds[block].clone() {
+            let v = self.read_variable(reg, pred);
+            incoming.push((BlockId(pred as u32), v));
+        }
+        if let Some(p) = self.phis[block].iter_mut().find(|p| p.result == phi) {
+            p.incoming = incoming;
+        }
+        self.try_remove_trivial_phi(block, phi)
+    }
+
+    // A phi whose operands are all the same value (or the phi itself) is replaced by that value.
+    fn try_remove_trivial_phi(&mut self, block: usize, phi: ValueId) -> ValueId {
+        let incoming = match self.phis[block].iter().find(|p| p.result == phi) {
+            Some(p) => p.incoming.clone(),
+            None => return self.resolve(phi),
+        };
+
+        let mut same: Option<ValueId> = None;
+        for (_, v) in incoming {
+            let v = self.resolve(v);
+            if Some(v) == same || v == phi {
+                continue;
+            }
+            if same.is_some() {
+                return phi;
+            }
+            same = Some(v);
+        }
+
+        let same = match same {
+            Some(v) => v,
+            None => self.undefined(),
+        };
+        self.phis[block].retain(|p| p.result != phi);
+        self.aliases.insert(phi, same);
+        same
+    }
+
+    fn seal(&mut self, block: usize) {
+        let pending = std::mem::take(&mut self.incomplete[block]);
+        for (reg, phi) in pending {
+            self.add_phi_operands(reg, block, phi);
+        }
+        self.sealed[block] = true;
+    }
+
+    fn remove_trivial_phis(&mut self) {
+        loop {
+            let mut changed = false;
+            for block in 0..self.phis.len() {
+                let results: Vec<ValueId> = self.phis[block].iter().map(|p| p.result).collect();
+                for phi in results {
+                    if self.try_remove_trivial_phi(block, phi) != phi {
+                        changed = true;
+                    }
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+    }
+
+    // ---------------------------------------------------------------------- //
+    // Translation
+    // ---------------------------------------------------------------------- //
+
+    fn read(&mut self, reg: i64) -> ValueId {
+        self.read_variable(reg as u32, self.current)
+    }
+
+    fn write(&mut self, reg: i64, value: ValueId) {
+        self.write_variable(reg as u32, self.current, value);
+    }
+
+    fn emit(&mut self, op: IrOp) -> ValueId {
+        let v = self.new_value();
+        self.out[self.current].push(IrInstruction {
+            offset: self.current_offset,
+            result: Some(v),
+            op,
+        });
+        v
+    }
+
+    fn emit_void(&mut self, op: IrOp) {
+        self.out[self.current].push(IrInstruction {
+            offset: self.current_offset,
+            result: None,
+            op,
+        });
+    }
+
+    fn string(&self, id: i64) -> String {
+        if (id as usize) < self.file.string_storage.len() {
+            self.file.get_string_from_storage_by_index(id as usize)
+        } else {
+            String::new()
+        }
+    }
+
+    fn literal(&self, value: &ArrayTypes) -> Constant {
+        match value {
+            ArrayTypes::EmptyValueSized { .. } => Constant::Empty,
+            ArrayTypes::NullValue {} => Constant::Null,
+            ArrayTypes::TrueValue { .. } => Constant::Bool(true),
+            ArrayTypes::FalseValue { .. } => Constant::Bool(false),
+            ArrayTypes::NumberValue { value } => Constant::Number(f64::from_bits(*value)),
+            ArrayTypes::IntegerValue { value } => Constant::Number(*value as i32 as f64),
+            ArrayTypes::ShortStringValue { value } => self.string_constant(*value as i64),
+            ArrayTypes::LongStringValue { value } => self.string_constant(*value as i64),
+            ArrayTypes::ByteStringValue { value } => self.string_constant(*value as i64),
+        }
+    }
+
+    fn builtin(&self, idx: i64) -> Builtin {
+        Builtin {
+            index: idx as u32,
+            name: get_builtins(self.file.header.version)
+                .and_then(|builtins| builtins.get(idx as usize))
+                .map(|name| name.to_string()),
+        }
+    }
+
+    fn string_constant(&self, id: i64) -> Constant {
+        Constant::String {
+            id: id as u32,
+            value: self.string(id),
+        }
+    }
+
+    // Registers holding `this` and the arguments of Call/Construct/CallDirect/CallBuiltin,
+    // which are passed in reverse order from the end of the frame.
+    fn frame_args(&mut self, argc: i64) -> (ValueId, Vec<ValueId>) {
+        let mut values = vec![];
+        for i in 0..argc.max(1) {
+            let reg = self.frame_size as i64 - 1 - i;
+            values.push(self.read(reg.max(0)));
+        }
+        let this = values.remove(0);
+        (this, values)
+    }
+
+    fn fill_block(&mut self, block: usize) -> IrTerminator {
+        self.current = block;
+        let (first, last, end) = {
+            let b = &self.blocks[block];
+            (b.first, b.last, b.end)
+        };
+
+        for idx in first..last {
+            self.current_offset = self.offsets[idx];
+            if idx + 1 == last {
+                if let Some(term) = self.translate_control(idx) {
+                    return term;
+                }
+            }
+            self.translate(idx);
+        }
+
+        match self.target_block(end as i64) {
+            Some(next) => IrTerminator::Jump(next),
+            None => IrTerminator::Unreachable,
+        }
+    }
+
+    fn translate_control(&mut self, idx: usize) -> Option<IrTerminator> {
+        let name = self.instructions[idx].name();
+        let next = self.offsets[idx] as i64 + self.instructions[idx].size() as i64;
+
+        match self.control(idx) {
+            Control::None | Control::Resume(_) => None,
+            Control::Exit => Some(match name {
+                "Ret" => IrTerminator::Return(self.read(self.operand(idx, "r0"))),
+                "Throw" => IrTerminator::Throw(self.read(self.operand(idx, "r0"))),
+                _ => IrTerminator::Unreachable,
+            }),
+            Control::Jump(t) => Some(match self.target_block(t) {
+                Some(target) => IrTerminator::Jump(target),
+                None => IrTerminator::Unreachable,
+            }),
+            Control::Conditional(t) => {
+                let (condition, when) = self.condition(idx);
+                let target = self.target_block(t);
+                let fallthrough = self.target_block(next);
+                Some(match (target, fallthrough) {
+                    (Some(target), Some(fallthrough)) => {
+                        let (true_target, false_target) = if when {
+                            (target, fallthrough)
+                        } else {
+                            (fallthrough, target)
+                        };
+                        IrTerminator::Branch {
+                            condition,
+                            true_target,
+                            false_target,
+                        }
+                    }
+                    (Some(only), None) | (None, Some(only)) => IrTerminator::Jump(only),
+                    (None, None) => IrTerminator::Unreachable,
+                })
+            }
+            Control::Switch { cases, default } => {
+                let value = self.read(self.operand(idx, "r0"));
+                let cases = cases
+                    .into_iter()
+                    .filter_map(|(v, t)| self.target_block(t).map(|b| (v, b)))
+                    .collect();
+                Some(match self.target_block(default) {
+                    Some(default) => IrTerminator::Switch {
+                        value,
+                        cases,
+                        default,
+                    },
+                    None => IrTerminator::Unreachable,
+                })
+            }
+        }
+    }
+
+    // Returns the branch condition of a conditional jump, and whether the jump
+    // is taken when the condition is true.
+    fn condition(&mut self, idx: usize) -> (ValueId, bool) {
+        let name = self.instructions[idx].name();
+        let base = name.trim_end_matches("Long");
+
+        match base {
+            "JmpTrue" => (self.read(self.operand(idx, "r0")), true),
+            "JmpFalse" => (self.read(self.operand(idx, "r0")), false),
+            "JmpUndefined" => {
+                let lhs = self.read(self.operand(idx, "r0"));
+                let rhs = self.emit(IrOp::Const(Constant::Undefined));
+                let cond = self.emit(IrOp::Binary {
+                    op: BinaryOp::StrictEq,
+                    lhs,
+                    rhs,
+                });
+                (cond, true)
+            }
+            _ => {
+                let (negated, cmp) = match base.strip_prefix("JNot") {
+                    Some(rest) => (true, rest),
+                    None => (false, &base[1..]),
+                };
+                let op = match cmp.trim_end_matches('N') {
+                    "Less" => BinaryOp::Less,
+                    "LessEqual" => BinaryOp::LessEq,
+                    "Greater" => BinaryOp::Greater,
+                    "GreaterEqual" => BinaryOp::GreaterEq,
+                    "Equal" => BinaryOp::Eq,
+                    "StrictEqual" => BinaryOp::StrictEq,
+                    "StrictNotEqual" => BinaryOp::StrictNeq,
+                    _ => BinaryOp::Eq,
+                };
+                // JNotEqual is a plain comparison rather than a negated JEqual
+                let (op, negated) = match (base, op) {
+                    ("JNotEqual", _) => (BinaryOp::Neq, false),
+                    _ => (op, negated),
+                };
+                let lhs = self.read(self.operand(idx, "r0"));
+                let rhs = self.read(self.operand(idx, "r1"));
+                let cond = self.emit(IrOp::Binary { op, lhs, rhs });
+                (cond, !negated)
+            }
+        }
+    }
+
+    fn translate(&mut self, idx: usize) {
+        let name = self.instructions[idx].name();
+        let op = |n: &str| self.operand(idx, n);
+        let (r0, r1, r2, r3, p0, p1, p2, p3) = (
+            op("r0"),
+            op("r1"),
+            op("r2"),
+            op("r3"),
+            op("p0"),
+            op("p1"),
+            op("p2"),
+            op("p3"),
+        );
+
+        match name {
+            "Mov" | "MovLong" => {
+                let v = self.read(r1);
+                self.write(r0, v);
+            }
+
+            // Constants
+            "LoadConstUndefined" => self.load_const(r0, Constant::Undefined),
+            "LoadConstNull" => self.load_const(r0, Constant::Null),
+            "LoadConstEmpty" => self.load_const(r0, Constant::Empty),
+            "LoadConstTrue" => self.load_const(r0, Constant::Bool(true)),
+            "LoadConstFalse" => self.load_const(r0, Constant::Bool(false)),
+            "LoadConstZero" => self.load_const(r0, Constant::Number(0.0)),
+            "LoadConstUInt8" | "LoadConstInt" | "LoadConstDouble" => {
+                let value = self.instructions[idx].operands()[1].value.as_f64();
+                self.load_const(r0, Constant::Number(value))
+            }
+            "LoadConstString" | "LoadConstStringLongIndex" => {
+                let c = self.string_constant(p0);
+                self.load_const(r0, c)
+            }
+            "LoadConstBigInt" | "LoadConstBigIntLongIndex" => {
+                let c = Constant::BigInt(self.file.get_big_int_string(p0 as usize));
+                self.load_const(r0, c)
+            }
+            "LoadParam" | "LoadParamLong" => self.def(r0, IrOp::Param(p0 as u32)),
+
+            // Unary
+            "Negate" => self.unary(r0, r1, UnaryOp::Negate),
+            "Not" => self.unary(r0, r1, UnaryOp::Not),
+            "BitNot" => self.unary(r0, r1, UnaryOp::BitNot),
+            "TypeOf" => self.unary(r0, r1, UnaryOp::TypeOf),
+            "Inc" => self.unary(r0, r1, UnaryOp::Inc),
+            "Dec" => self.unary(r0, r1, UnaryOp::Dec),
+            "ToNumber" => self.unary(r0, r1, UnaryOp::ToNumber),
+            "ToNumeric" => self.unary(r0, r1, UnaryOp::ToNumeric),
+            "ToInt32" => self.unary(r0, r1, UnaryOp::ToInt32),
+            "AddEmptyString" => self.unary(r0, r1, UnaryOp::ToString),
+
+            // Binary
+            "Eq" => self.binary(r0, r1, r2, BinaryOp::Eq),
+            "StrictEq" => self.binary(r0, r1, r2, BinaryOp::StrictEq),
+            "Neq" => self.binary(r0, r1, r2, BinaryOp::Neq),
+            "StrictNeq" => self.binary(r0, r1, r2, BinaryOp::StrictNeq),
+            "Less" => self.binary(r0, r1, r2, BinaryOp::Less),
+            "LessEq" => self.binary(r0, r1, r2, BinaryOp::LessEq),
+            "Greater" => self.binary(r0, r1, r2, BinaryOp::Greater),
+            "GreaterEq" => self.binary(r0, r1, r2, BinaryOp::GreaterEq),
+            "Add" | "AddN" => self.binary(r0, r1, r2, BinaryOp::Add),
+            "Sub" | "SubN" => self.binary(r0, r1, r2, BinaryOp::Sub),
+            "Mul" | "MulN" => self.binary(r0, r1, r2, BinaryOp::Mul),
+            "Div" | "DivN" => self.binary(r0, r1, r2, BinaryOp::Div),
+            "Mod" => self.binary(r0, r1, r2, BinaryOp::Mod),
+            "LShift" => self.binary(r0, r1, r2, BinaryOp::LShift),
+            "RShift" => self.binary(r0, r1, r2, BinaryOp::RShift),
+            "URshift" => self.binary(r0, r1, r2, BinaryOp::URShift),
+            "BitAnd" => self.binary(r0, r1, r2, BinaryOp::BitAnd),
+            "BitXor" => self.binary(r0, r1, r2, BinaryOp::BitXor),
+            "BitOr" => self.binary(r0, r1, r2, BinaryOp::BitOr),
+            "InstanceOf" => self.binary(r0, r1, r2, BinaryOp::InstanceOf),
+            "IsIn" => self.binary(r0, r1, r2, BinaryOp::In),
+            "Add32" => self.binary(r0, r1, r2, BinaryOp::Add32),
+            "Sub32" => self.binary(r0, r1, r2, BinaryOp::Sub32),
+            "Mul32" => self.binary(r0, r1, r2, BinaryOp::Mul32),
+            "Divi32" => self.binary(r0, r1, r2, BinaryOp::Divi32),
+            "Divu32" => self.binary(r0, r1, r2, BinaryOp::Divu32),
+
+            // Properties
+            "GetByIdShort" | "GetById" | "GetByIdLong" | "TryGetById" | "TryGetByIdLong" => {
+                let object = self.read(r1);
+                let key = PropertyKey::Named(self.string(p1));
+                self.def(
+                    r0,
+                    IrOp::GetProperty {
+                        object,
+                        key,
+                        must_exist: name.starts_with("Try"),
+                    },
+                )
+            }
+            "GetByVal" => {
+                let object = self.read(r1);
+                let key = PropertyKey::Computed(self.read(r2));
+                self.def(
+                    r0,
+                    IrOp::GetProperty {
+                        object,
+                        key,
+                        must_exist: false,
+                    },
+                )
+            }
+            "PutById" | "PutByIdLong" | "TryPutById" | "TryPutByIdLong" => {
+                let key = PropertyKey::Named(self.string(p1));
+                self.put(r0, key, r1, false)
+            }
+            "PutNewOwnByIdShort"
+            | "PutNewOwnById"
+            | "PutNewOwnByIdLong"
+            | "PutNewOwnNEById"
+            | "PutNewOwnNEByIdLong" => {
+                let key = PropertyKey::Named(self.string(p0));
+                self.put(r0, key, r1, true)
+            }
+            "PutOwnByIndex" | "PutOwnByIndexL" => {
+                self.put(r0, PropertyKey::Index(p0 as u32), r1, true)
+            }
+            "PutOwnByVal" => {
+                let key = PropertyKey::Computed(self.read(r2));
+                self.put(r0, key, r1, true)
+            }
+            "PutByVal" => {
+                let key = PropertyKey::Computed(self.read(r1));
+                self.put(r0, key, r2, false)
+            }
+            "DelById" | "DelByIdLong" => {
+                let object = self.read(r1);
+                let key = PropertyKey::Named(self.string(p0));
+                self.def(r0, IrOp::DeleteProperty { object, key })
+            }
+            "DelByVal" => {
+                let object = self.read(r1);
+                let key = PropertyKey::Computed(self.read(r2));
+                self.def(r0, IrOp::DeleteProperty { object, key })
+            }
+
+            // Calls
+            "Call" | "CallLong" | "Construct" | "ConstructLong" => {
+                let callee = self.read(r1);
+                let (this, args) = self.frame_args(p0);
+                let op = if name.starts_with("Construct") {
+                    IrOp::Construct { callee, this, args }
+                } else {
+                    IrOp::Call { callee, this, args }
+                };
+                self.def(r0, op)
+            }
+            "Call1" | "Call2" | "Call3" | "Call4" => {
+                let callee = self.read(r1);
+                let regs: Vec<i64> = self.instructions[idx]
+                    .operands()
+                    .iter()
+                    .filter(|o| o.kind.is_register())
+                    .skip(2)
+                    .map(|o| o.value.as_i64())
+                    .collect();
+                let mut values: Vec<ValueId> = regs.iter().map(|r| self.read(*r)).collect();
+                let this = values.remove(0);
+                self.def(
+                    r0,
+                    IrOp::Call {
+                        callee,
+                        this,
+                        args: values,
+                    },
+                )
+            }
+            "CallDirect" | "CallDirectLongIndex" => {
+                let (this, args) = self.frame_args(p0);
+                self.def(
+                    r0,
+                    IrOp::CallDirect {
+                        function: p1 as u32,
+                        this,
+                        args,
+                    },
+                )
+            }
+            "CallBuiltin" | "CallBuiltinLong" => {
+                let (this, args) = self.frame_args(p1);
+                self.def(
+                    r0,
+                    IrOp::CallBuiltin {
+                        builtin: self.builtin(p0),
+                        this,
+                        args,
+                    },
+                )
+            }
+            "GetBuiltinClosure" => self.def(r0, IrOp::GetBuiltinClosure(self.builtin(p0))),
+
+            // Globals and environments
+            "GetGlobalObject" => self.def(r0, IrOp::GetGlobalObject),
+            "DeclareGlobalVar" => {
+                let name = self.string(p0);
+                self.emit_void(IrOp::DeclareGlobalVar(name))
+            }
+            "GetEnvironment" => self.def(r0, IrOp::GetEnvironment(p0 as u32)),
+            "CreateEnvironment" => self.def(r0, IrOp::CreateEnvironment),
+            "CreateInnerEnvironment" => {
+                let parent = self.read(r1);
+                self.def(
+                    r0,
+                    IrOp::CreateInnerEnvironment {
+                        parent,
+                        size: p0 as u32,
+                    },
+                )
+            }
+            "LoadFromEnvironment" | "LoadFromEnvironmentL" => {
+                let env = self.read(r1);
+                self.def(
+                    r0,
+                    IrOp::LoadEnv {
+                        env,
+                        slot: p0 as u32,
+                    },
+                )
+            }
+            "StoreToEnvironment"
+            | "StoreToEnvironmentL"
+            | "StoreNPToEnvironment"
+            | "StoreNPToEnvironmentL" => {
+                let env = self.read(r0);
+                let value = self.read(r1);
+                self.emit_void(IrOp::StoreEnv {
+                    env,
+                    slot: p0 as u32,
+                    value,
+                })
+            }
+
+            // Closures
+            "CreateClosure" | "CreateClosureLongIndex" => {
+                self.closure(idx, r0, r1, ClosureKind::Function)
+            }
+            "CreateGeneratorClosure" | "CreateGeneratorClosureLongIndex" => {
+                self.closure(idx, r0, r1, ClosureKind::Generator)
+            }
+            "CreateAsyncClosure" | "CreateAsyncClosureLongIndex" => {
+                self.closure(idx, r0, r1, ClosureKind::Async)
+            }
+            "CreateGenerator" | "CreateGeneratorLongIndex" => {
+                self.closure(idx, r0, r1, ClosureKind::GeneratorObject)
+            }
+
+            // Literals
+            "NewObject" => self.def(
+                r0,
+                IrOp::NewObject {
+                    parent: None,
+                    properties: vec![],
+                },
+            ),
+            "NewObjectWithParent" => {
+                let parent = self.read(r1);
+                self.def(
+                    r0,
+                    IrOp::NewObject {
+                        parent: Some(parent),
+                        properties: vec![],
+                    },
+                )
+            }
+            "NewObjectWithBuffer" | "NewObjectWithBufferLong" => {
+                let keys = self
+                    .file
+                    .get_object_key_buffer_values(p2 as usize, p1 as usize);
+                let values = self
+                    .file
+                    .get_object_val_buffer_values(p3 as usize, p1 as usize);
+                let properties = keys
+                    .iter()
+                    .zip(values.iter())
+                    .map(|(k, v)| (self.literal(k), self.literal(v)))
+                    .collect();
+                self.def(
+                    r0,
+                    IrOp::NewObject {
+                        parent: None,
+                        properties,
+                    },
+                )
+            }
+            "NewArray" => self.def(
+                r0,
+                IrOp::NewArray {
+                    size: p0 as u32,
+                    elements: vec![],
+                },
+            ),
+            "NewArrayWithBuffer" | "NewArrayWithBufferLong" => {
+                let elements = self
+                    .file
+                    .get_array_buffer_values(p2 as usize, p1 as usize)
+                    .iter()
+                    .map(|v| self.literal(v))
+                    .collect();
+                self.def(
+                    r0,
+                    IrOp::NewArray {
+                        size: p0 as u32,
+                        elements,
+                    },
+                )
+            }
+            "CreateRegExp" => {
+                let pattern = self.string(p0);
+                let flags = self.string(p1);
+                self.def(r0, IrOp::CreateRegExp { pattern, flags })
+            }
+
+            // this / new.target / exceptions
+            "LoadThisNS" => self.def(r0, IrOp::LoadThis),
+            "CoerceThisNS" => {
+                let v = self.read(r1);
+                self.def(r0, IrOp::CoerceThis(v))
+            }
+            "GetNewTarget" => self.def(r0, IrOp::GetNewTarget),
+            "Catch" => self.def(r0, IrOp::Catch),
+
+            // Instructions that only matter to the VM
+            "AsyncBreakCheck" | "ProfilePoint" => {}
+
+            // Instructions that write more than one register
+            "GetPNameList" => {
+                let obj = self.read(r1);
+                self.multi(name, &[obj], &[r0, r1, r2, r3])
+            }
+            "GetNextPName" => {
+                let args: Vec<ValueId> = [r1, r2, r3, op("r4")]
+                    .iter()
+                    .map(|r| self.read(*r))
+                    .collect();
+                self.multi(name, &args, &[r0, r3])
+            }
+            "IteratorBegin" => {
+                let source = self.read(r1);
+                self.multi(name, &[source], &[r0, r1])
+            }
+            "IteratorNext" => {
+                let iterator = self.read(r1);
+                let source = self.read(r2);
+                self.multi(name, &[iterator, source], &[r0, r1])
+            }
+            "ResumeGenerator" => self.multi(name, &[], &[r0, r1]),
+            "ReifyArguments" => {
+                let lazy = self.read(r0);
+                self.multi(name, &[lazy], &[r0])
+            }
+            "DeclareGlobalVarLong" | "ThrowIfHasRestrictedGlobalProperty" => {
+                let name_value = self.load_string(p0);
+                self.emit_void(IrOp::Opaque {
+                    name,
+                    args: vec![name_value],
+                    immediates: vec![],
+                })
+            }
+            "SaveGenerator" | "SaveGeneratorLong" => self.emit_void(IrOp::Opaque {
+                name,
+                args: vec![],
+                immediates: vec![self.address(idx)],
+            }),
+
+            _ => self.opaque(idx),
+        }
+    }
+
+    fn def(&mut self, reg: i64, op: IrOp) {
+        let v = self.emit(op);
+        self.write(reg, v);
+    }
+
+    fn load_const(&mut self, reg: i64, c: Constant) {
+        self.def(reg, IrOp::Const(c))
+    }
+
+    fn load_string(&mut self, id: i64) -> ValueId {
+        let c = self.string_constant(id);
+        self.emit(IrOp::Const(c))
+    }
+
+    fn unary(&mut self, dst: i64, src: i64, op: UnaryOp) {
+        let operand = self.read(src);
+        self.def(dst, IrOp::Unary { op, operand })
+    }
+
+    fn binary(&mut self, dst: i64, lhs: i64, rhs: i64, op: BinaryOp) {
+        let lhs = self.read(lhs);
+        let rhs = self.read(rhs);
+        self.def(dst, IrOp::Binary { op, lhs, rhs })
+    }
+
+    fn put(&mut self, object: i64, key: PropertyKey, value: i64, own: bool) {
+        let object = self.read(object);
+        let value = self.read(value);
+        self.emit_void(IrOp::PutProperty {
+            object,
+            key,
+            value,
+            own,
+        })
+    }
+
+    fn closure(&mut self, idx: usize, dst: i64, env: i64, kind: ClosureKind) {
+        let function = self.instructions[idx].function_id().unwrap_or(0);
+        let env = self.read(env);
+        self.def(
+            dst,
+            IrOp::CreateClosure {
+                function,
+                env,
+                kind,
+            },
+        )
+    }
+
+    // The first register gets the op's result, the others an `Output` of it.
+    fn multi(&mut self, name: &'static str, args: &[ValueId], defs: &[i64]) {
+        let v = self.emit(IrOp::Opaque {
+            name,
+            args: args.to_vec(),
+            immediates: vec![],
+        });
+        self.write(defs[0], v);
+        for (i, reg) in defs.iter().enumerate().skip(1) {
+            let out = self.emit(IrOp::Output {
+                source: v,
+                index: i as u32,
+            });
+            self.write(*reg, out);
+        }
+    }
+
+    /*
+     * Fallback for everything else. Instructions with no register operands, or
+     * that are known to only read them, produce no value; otherwise r0 is the
+     * destination and the remaining registers are inputs.
+     */
+    fn opaque(&mut self, idx: usize) {
+        let name = self.instructions[idx].name();
+        let operands = self.instructions[idx].operands();
+        let reads_only = matches!(
+            name,
+            "Throw"
+                | "ThrowIfUndefinedInst"
+                | "IteratorClose"
+                | "PutOwnGetterSetterByVal"
+                | "Store8"
+                | "Store16"
+                | "Store32"
+                | "Debugger"
+                | "StartGenerator"
+                | "CompleteGenerator"
+                | "Unreachable"
+        );
+
+        let mut regs: Vec<i64> = operands
+            .iter()
+            .filter(|o| o.kind.is_register())
+            .map(|o| o.value.as_i64())
+            .collect();
+        let immediates: Vec<i64> = operands
+            .iter()
+            .filter(|o| !o.kind.is_register())
+            .map(|o| o.value.as_i64())
+            .collect();
+
+        let dst = if reads_only || regs.is_empty() {
+            None
+        } else {
+            Some(regs.remove(0))
+        };
+        let args: Vec<ValueId> = regs.iter().map(|r| self.read(*r)).collect();
+        let op = IrOp::Opaque {
+            name,
+            args,
+            immediates,
+        };
+
+        match dst {
+            Some(dst) => self.def(dst, op),
+            None => self.emit_void(op),
+        }
+    }
+
+    // ---------------------------------------------------------------------- //
+    // Output
+    // ---------------------------------------------------------------------- //
+
+    fn finish(mut self, terminators: Vec<IrTerminator>) -> IrFunction {
+        let aliases = std::mem::take(&mut self.aliases);
+        let resolve = |mut v: ValueId| {
+            while let Some(next) = aliases.get(&v) {
+                v = *next;
+            }
+            v
+        };
+
+        let mut blocks = vec![];
+        for (i, mut terminator) in terminators.into_iter().enumerate() {
+            let mut instructions = std::mem::take(&mut self.out[i]);
+            for ins in instructions.iter_mut() {
+                ins.op.map_uses(resolve);
+            }
+
+            let mut phis = std::mem::take(&mut self.phis[i]);
+            for phi in phis.iter_mut() {
+                for (_, v) in phi.incoming.iter_mut() {
+                    *v = resolve(*v);
+                }
+            }
+
+            match &mut terminator {
+                IrTerminator::Branch { condition, .. } => *condition = resolve(*condition),
+                IrTerminator::Switch { value, .. } => *value = resolve(*value),
+                IrTerminator::Return(v) | IrTerminator::Throw(v) => *v = resolve(*v),
+                _ => {}
+            }
+
+            let raw = &self.blocks[i];
+            blocks.push(IrBlock {
+                id: BlockId(i as u32),
+                start_offset: raw.start,
+                end_offset: raw.end,
+                phis,
+                instructions,
+                successors: raw.successors.iter().map(|s| BlockId(*s as u32)).collect(),
+                terminator,
+                predecessors: self.preds[i].iter().map(|p| BlockId(*p as u32)).collect(),
+                handler: raw.handler.map(|h| BlockId(h as u32)),
+            });
+        }
+
+        let mut exception_handlers: Vec<IrExceptionHandler> = vec![];
+        for block in blocks.iter() {
+            if let Some(handler) = block.handler {
+                match exception_handlers.iter_mut().find(|h| h.handler == handler) {
+                    Some(h) => h.try_blocks.push(block.id),
+                    None => exception_handlers.push(IrExceptionHandler {
+                        try_blocks: vec![block.id],
+                        handler,
+                    }),
+                }
+            }
+        }
+
+        IrFunction {
+            function_id: self.function_id,
+            name: self.file.get_function_name(self.function_id as usize),
+            param_count: self.file.function_headers[self.function_id as usize].param_count(),
+            blocks,
+            exception_handlers,
+            value_count: self.next_value,
+        }
+    }
+}
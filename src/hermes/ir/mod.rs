@@ -0,0 +1,759 @@
+pub mod lifter;
+
+use std::fmt;
+
+/*
+ * A version-independent intermediate representation of a function's bytecode.
+ *
+ * The lifter (see lifter.rs) turns the `HermesInstruction`s of a single function
+ * into basic blocks of typed operations. Registers are replaced by SSA values:
+ * every operation that produces something defines a new `ValueId`, `Mov`s
+ * disappear, and values that merge at control flow joins get a phi node.
+ * Constants (strings, numbers, literal buffers, bigints) are resolved while
+ * lifting, so consumers never have to look at the string table or the
+ * per-version opcode sets.
+ */
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ValueId(pub u32);
+
+impl fmt::Display for ValueId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "%{}", self.0)
+    }
+}
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub u32);
+
+impl fmt::Display for BlockId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bb{}", self.0)
+    }
+}
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    Undefined,
+    Null,
+    Empty,
+    Bool(bool),
+    Number(f64),
+    /// Resolved string along with its ID in the string table.
+    String {
+        id: u32,
+        value: String,
+    },
+    /// BigInt rendered as a decimal literal.
+    BigInt(String),
+}
+
+impl fmt::Display for Constant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Constant::Undefined => write!(f, "undefined"),
+            Constant::Null => write!(f, "null"),
+            Constant::Empty => write!(f, "empty"),
+            Constant::Bool(b) => write!(f, "{}", b),
+            Constant::Number(n) => write!(f, "{}", n),
+            Constant::String { value, .. } => write!(f, "{:?}", value),
+            Constant::BigInt(n) => write!(f, "{}n", n),
+        }
+    }
+}
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BinaryOp {
+    Eq,
+    StrictEq,
+    Neq,
+    StrictNeq,
+    Less,
+    LessEq,
+    Greater,
+    GreaterEq,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    LShift,
+    RShift,
+    URShift,
+    BitAnd,
+    BitXor,
+    BitOr,
+    InstanceOf,
+    In,
+    // asm.js style 32-bit integer arithmetic
+    Add32,
+    Sub32,
+    Mul32,
+    Divi32,
+    Divu32,
+}
+
+impl BinaryOp {
+    /// The JavaScript operator, for the ops that have one.
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOp::Eq => "==",
+            BinaryOp::StrictEq => "===",
+            BinaryOp::Neq => "!=",
+            BinaryOp::StrictNeq => "!==",
+            BinaryOp::Less => "<",
+            BinaryOp::LessEq => "<=",
+            BinaryOp::Greater => ">",
+            BinaryOp::GreaterEq => ">=",
+            BinaryOp::Add | BinaryOp::Add32 => "+",
+            BinaryOp::Sub | BinaryOp::Sub32 => "-",
+            BinaryOp::Mul | BinaryOp::Mul32 => "*",
+            BinaryOp::Div | BinaryOp::Divi32 | BinaryOp::Divu32 => "/",
+            BinaryOp::Mod => "%",
+            BinaryOp::LShift => "<<",
+            BinaryOp::RShift => ">>",
+            BinaryOp::URShift => ">>>",
+            BinaryOp::BitAnd => "&",
+            BinaryOp::BitXor => "^",
+            BinaryOp::BitOr => "|",
+            BinaryOp::InstanceOf => "instanceof",
+            BinaryOp::In => "in",
+        }
+    }
+
+    /// The negated comparison (`a < b` -> `a >= b`), for ops where that's
+    /// valid for every input. Relational ops aren't included since `!(a < b)`
+    /// differs from `a >= b` when either side is NaN.
+    pub fn negate(&self) -> Option<BinaryOp> {
+        match self {
+            BinaryOp::Eq => Some(BinaryOp::Neq),
+            BinaryOp::Neq => Some(BinaryOp::Eq),
+            BinaryOp::StrictEq => Some(BinaryOp::StrictNeq),
+            BinaryOp::StrictNeq => Some(BinaryOp::StrictEq),
+            _ => None,
+        }
+    }
+}
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum UnaryOp {
+    Negate,
+    Not,
+    BitNot,
+    TypeOf,
+    Inc,
+    Dec,
+    ToNumber,
+    ToNumeric,
+    ToInt32,
+    ToString,
+}
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub enum PropertyKey {
+    Named(String),
+    Index(u32),
+    Computed(ValueId),
+}
+
+impl fmt::Display for PropertyKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PropertyKey::Named(name) => write!(f, "{:?}", name),
+            PropertyKey::Index(idx) => write!(f, "{}", idx),
+            PropertyKey::Computed(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ClosureKind {
+    Function,
+    Generator,
+    Async,
+    /// CreateGenerator - the generator object created inside a generator's outer function.
+    GeneratorObject,
+}
+
+/// A builtin function by number, with its name from the version's builtin
+/// table when it's known.
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Builtin {
+    pub index: u32,
+    pub name: Option<String>,
+}
+
+impl fmt::Display for Builtin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "builtin#{}", self.index),
+        }
+    }
+}
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub enum IrOp {
+    Const(Constant),
+    /// Parameter by index - 0 is `this`.
+    Param(u32),
+    Unary {
+        op: UnaryOp,
+        operand: ValueId,
+    },
+    Binary {
+        op: BinaryOp,
+        lhs: ValueId,
+        rhs: ValueId,
+    },
+    GetProperty {
+        object: ValueId,
+        key: PropertyKey,
+        /// TryGetById - throws a ReferenceError when the property doesn't exist (used for globals).
+        must_exist: bool,
+    },
+    PutProperty {
+        object: ValueId,
+        key: PropertyKey,
+        value: ValueId,
+        /// Defines an own property (object/array literals) instead of doing a regular assignment.
+        own: bool,
+    },
+    DeleteProperty {
+        object: ValueId,
+        key: PropertyKey,
+    },
+    Call {
+        callee: ValueId,
+        this: ValueId,
+        args: Vec<ValueId>,
+    },
+    Construct {
+        callee: ValueId,
+        this: ValueId,
+        args: Vec<ValueId>,
+    },
+    CallDirect {
+        function: u32,
+        this: ValueId,
+        args: Vec<ValueId>,
+    },
+    CallBuiltin {
+        builtin: Builtin,
+        this: ValueId,
+        args: Vec<ValueId>,
+    },
+    GetBuiltinClosure(Builtin),
+    GetGlobalObject,
+    DeclareGlobalVar(String),
+    /// The environment `level` scopes up from the current function's.
+    GetEnvironment(u32),
+    CreateEnvironment,
+    CreateInnerEnvironment {
+        parent: ValueId,
+        size: u32,
+    },
+    LoadEnv {
+        env: ValueId,
+        slot: u32,
+    },
+    StoreEnv {
+        env: ValueId,
+        slot: u32,
+        value: ValueId,
+    },
+    CreateClosure {
+        function: u32,
+        env: ValueId,
+        kind: ClosureKind,
+    },
+    NewObject {
+        parent: Option<ValueId>,
+        properties: Vec<(Constant, Constant)>,
+    },
+    NewArray {
+        size: u32,
+        elements: Vec<Constant>,
+    },
+    CreateRegExp {
+        pattern: String,
+        flags: String,
+    },
+    LoadThis,
+    CoerceThis(ValueId),
+    GetNewTarget,
+    /// The exception caught by a handler.
+    Catch,
+    /// Secondary result of an instruction that writes more than one register.
+    Output {
+        source: ValueId,
+        index: u32,
+    },
+    /// Everything without a dedicated op (generators, iterators, for-in, asm.js
+    /// heap access, ...). `name` is the original opcode name.
+    Opaque {
+        name: &'static str,
+        args: Vec<ValueId>,
+        immediates: Vec<i64>,
+    },
+}
+
+impl IrOp {
+    /// Returns every value the op reads.
+    pub fn uses(&self) -> Vec<ValueId> {
+        let key_use = |key: &PropertyKey| match key {
+            PropertyKey::Computed(v) => vec![*v],
+            _ => vec![],
+        };
+
+        match self {
+            IrOp::Unary { operand, .. } => vec![*operand],
+            IrOp::Binary { lhs, rhs, .. } => vec![*lhs, *rhs],
+            IrOp::GetProperty { object, key, .. } | IrOp::DeleteProperty { object, key } => {
+                let mut out = vec![*object];
+                out.extend(key_use(key));
+                out
+            }
+            IrOp::PutProperty {
+                object, key, value, ..
+            } => {
+                let mut out = vec![*object];
+                out.extend(key_use(key));
+                out.push(*value);
+                out
+            }
+            IrOp::Call { callee, this, args } | IrOp::Construct { callee, this, args } => {
+                let mut out = vec![*callee, *this];
+                out.extend(args.iter().copied());
+                out
+            }
+            IrOp::CallDirect { this, args, .. } | IrOp::CallBuiltin { this, args, .. } => {
+                let mut out = vec![*this];
+                out.extend(args.iter().copied());
+                out
+            }
+            IrOp::CreateInnerEnvironment { parent, .. } => vec![*parent],
+            IrOp::LoadEnv { env, .. } => vec![*env],
+            IrOp::StoreEnv { env, value, .. } => vec![*env, *value],
+            IrOp::CreateClosure { env, .. } => vec![*env],
+            IrOp::NewObject { parent, .. } => parent.iter().copied().collect(),
+            IrOp::CoerceThis(v) => vec![*v],
+            IrOp::Output { source, .. } => vec![*source],
+            IrOp::Opaque { args, .. } => args.clone(),
+            _ => vec![],
+        }
+    }
+
+    /// Rewrites every value the op reads.
+    pub fn map_uses<F: FnMut(ValueId) -> ValueId>(&mut self, mut f: F) {
+        let map_key = |key: &mut PropertyKey, f: &mut F| {
+            if let PropertyKey::Computed(v) = key {
+                *v = f(*v);
+            }
+        };
+
+        match self {
+            IrOp::Unary { operand, .. } => *operand = f(*operand),
+            IrOp::Binary { lhs, rhs, .. } => {
+                *lhs = f(*lhs);
+                *rhs = f(*rhs);
+            }
+            IrOp::GetProperty { object, key, .. } | IrOp::DeleteProperty { object, key } => {
+                *object = f(*object);
+                map_key(key, &mut f);
+            }
+            IrOp::PutProperty {
+                object, key, value, ..
+            } => {
+                *object = f(*object);
+                map_key(key, &mut f);
+                *value = f(*value);
+            }
+            IrOp::Call { callee, this, args } | IrOp::Construct { callee, this, args } => {
+                *callee = f(*callee);
+                *this = f(*this);
+                args.iter_mut().for_each(|a| *a = f(*a));
+            }
+            IrOp::CallDirect { this, args, .. } | IrOp::CallBuiltin { this, args, .. } => {
+                *this = f(*this);
+                args.iter_mut().for_each(|a| *a = f(*a));
+            }
+            IrOp::CreateInnerEnvironment { parent, .. } => *parent = f(*parent),
+            IrOp::LoadEnv { env, .. } => *env = f(*env),
+            IrOp::StoreEnv { env, value, .. } => {
+                *env = f(*env);
+                *value = f(*value);
+            }
+            IrOp::CreateClosure { env, .. } => *env = f(*env),
+            IrOp::NewObject {
+                parent: Some(p), ..
+            } => *p = f(*p),
+            IrOp::CoerceThis(v) => *v = f(*v),
+            IrOp::Output { source, .. } => *source = f(*source),
+            IrOp::Opaque { args, .. } => args.iter_mut().for_each(|a| *a = f(*a)),
+            _ => {}
+        }
+    }
+}
+
+fn join_values(values: &[ValueId]) -> String {
+    values
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+impl fmt::Display for IrOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IrOp::Const(c) => write!(f, "const {}", c),
+            IrOp::Param(idx) => write!(f, "param {}", idx),
+            IrOp::Unary { op, operand } => write!(f, "{:?} {}", op, operand),
+            IrOp::Binary { op, lhs, rhs } => write!(f, "{:?} {}, {}", op, lhs, rhs),
+            IrOp::GetProperty {
+                object,
+                key,
+                must_exist,
+            } => write!(
+                f,
+                "get_property{} {}[{}]",
+                if *must_exist { "_try" } else { "" },
+                object,
+                key
+            ),
+            IrOp::PutProperty {
+                object,
+                key,
+                value,
+                own,
+            } => write!(
+                f,
+                "put_property{} {}[{}] = {}",
+                if *own { "_own" } else { "" },
+                object,
+                key,
+                value
+            ),
+            IrOp::DeleteProperty { object, key } => {
+                write!(f, "delete_property {}[{}]", object, key)
+            }
+            IrOp::Call { callee, this, args } => {
+                write!(f, "call {}, this={}, ({})", callee, this, join_values(args))
+            }
+            IrOp::Construct { callee, this, args } => write!(
+                f,
+                "construct {}, this={}, ({})",
+                callee,
+                this,
+                join_values(args)
+            ),
+            IrOp::CallDirect {
+                function,
+                this,
+                args,
+            } => write!(
+                f,
+                "call_direct #{}, this={}, ({})",
+                function,
+                this,
+                join_values(args)
+            ),
+            IrOp::CallBuiltin {
+                builtin,
+                this,
+                args,
+            } => write!(
+                f,
+                "call_builtin {}, this={}, ({})",
+                builtin,
+                this,
+                join_values(args)
+            ),
+            IrOp::GetBuiltinClosure(builtin) => write!(f, "get_builtin_closure {}", builtin),
+            IrOp::GetGlobalObject => write!(f, "global_object"),
+            IrOp::DeclareGlobalVar(name) => write!(f, "declare_global {:?}", name),
+            IrOp::GetEnvironment(level) => write!(f, "get_environment {}", level),
+            IrOp::CreateEnvironment => write!(f, "create_environment"),
+            IrOp::CreateInnerEnvironment { parent, size } => {
+                write!(f, "create_inner_environment {}, {}", parent, size)
+            }
+            IrOp::LoadEnv { env, slot } => write!(f, "load_env {}[{}]", env, slot),
+            IrOp::StoreEnv { env, slot, value } => {
+                write!(f, "store_env {}[{}] = {}", env, slot, value)
+            }
+            IrOp::CreateClosure {
+                function,
+                env,
+                kind,
+            } => write!(f, "create_closure {:?} #{}, env={}", kind, function, env),
+            IrOp::NewObject { parent, properties } => {
+                let props: Vec<String> = properties
+                    .iter()
+                    .map(|(k, v)| format!("{}: {}", k, v))
+                    .collect();
+                match parent {
+                    Some(p) => write!(f, "new_object {{{}}}, parent={}", props.join(", "), p),
+                    None => write!(f, "new_object {{{}}}", props.join(", ")),
+                }
+            }
+            IrOp::NewArray { size, elements } => {
+                let elements: Vec<String> = elements.iter().map(|e| e.to_string()).collect();
+                write!(f, "new_array [{}], size={}", elements.join(", "), size)
+            }
+            IrOp::CreateRegExp { pattern, flags } => write!(f, "regexp /{}/{}", pattern, flags),
+            IrOp::LoadThis => write!(f, "load_this"),
+            IrOp::CoerceThis(v) => write!(f, "coerce_this {}", v),
+            IrOp::GetNewTarget => write!(f, "new_target"),
+            IrOp::Catch => write!(f, "catch"),
+            IrOp::Output { source, index } => write!(f, "output {}.{}", source, index),
+            IrOp::Opaque {
+                name,
+                args,
+                immediates,
+            } => {
+                let mut parts: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+                parts.extend(immediates.iter().map(|i| i.to_string()));
+                write!(f, "{} {}", name, parts.join(", "))
+            }
+        }
+    }
+}
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone)]
+pub struct IrInstruction {
+    /// Byte offset of the bytecode instruction this came from.
+    pub offset: u32,
+    pub result: Option<ValueId>,
+    pub op: IrOp,
+}
+
+impl fmt::Display for IrInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.result {
+            Some(result) => write!(f, "{} = {}", result, self.op),
+            None => write!(f, "{}", self.op),
+        }
+    }
+}
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone)]
+pub struct IrPhi {
+    pub result: ValueId,
+    /// The bytecode register this phi merges.
+    pub register: u32,
+    pub incoming: Vec<(BlockId, ValueId)>,
+}
+
+impl fmt::Display for IrPhi {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let incoming: Vec<String> = self
+            .incoming
+            .iter()
+            .map(|(b, v)| format!("{}: {}", b, v))
+            .collect();
+        write!(
+            f,
+            "{} = phi [{}] # r{}",
+            self.result,
+            incoming.join(", "),
+            self.register
+        )
+    }
+}
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone)]
+pub enum IrTerminator {
+    Jump(BlockId),
+    Branch {
+        condition: ValueId,
+        true_target: BlockId,
+        false_target: BlockId,
+    },
+    Switch {
+        value: ValueId,
+        cases: Vec<(i64, BlockId)>,
+        default: BlockId,
+    },
+    Return(ValueId),
+    Throw(ValueId),
+    Unreachable,
+}
+
+impl IrTerminator {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            IrTerminator::Jump(target) => vec![*target],
+            IrTerminator::Branch {
+                true_target,
+                false_target,
+                ..
+            } => vec![*true_target, *false_target],
+            IrTerminator::Switch { cases, default, .. } => {
+                let mut out: Vec<BlockId> = vec![];
+                for target in cases.iter().map(|(_, b)| b).chain(Some(default)) {
+                    if !out.contains(target) {
+                        out.push(*target);
+                    }
+                }
+                out
+            }
+            _ => vec![],
+        }
+    }
+
+    pub fn uses(&self) -> Vec<ValueId> {
+        match self {
+            IrTerminator::Branch { condition, .. } => vec![*condition],
+            IrTerminator::Switch { value, .. } => vec![*value],
+            IrTerminator::Return(v) | IrTerminator::Throw(v) => vec![*v],
+            _ => vec![],
+        }
+    }
+}
+
+impl fmt::Display for IrTerminator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IrTerminator::Jump(target) => write!(f, "jump {}", target),
+            IrTerminator::Branch {
+                condition,
+                true_target,
+                false_target,
+            } => write!(f, "branch {}, {}, {}", condition, true_target, false_target),
+            IrTerminator::Switch {
+                value,
+                cases,
+                default,
+            } => {
+                let cases: Vec<String> =
+                    cases.iter().map(|(v, b)| format!("{}: {}", v, b)).collect();
+                write!(
+                    f,
+                    "switch {} [{}], default: {}",
+                    value,
+                    cases.join(", "),
+                    default
+                )
+            }
+            IrTerminator::Return(v) => write!(f, "return {}", v),
+            IrTerminator::Throw(v) => write!(f, "throw {}", v),
+            IrTerminator::Unreachable => write!(f, "unreachable"),
+        }
+    }
+}
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone)]
+pub struct IrBlock {
+    pub id: BlockId,
+    /// Byte range [start_offset, end_offset) of the bytecode this block was lifted from.
+    pub start_offset: u32,
+    pub end_offset: u32,
+    pub phis: Vec<IrPhi>,
+    pub instructions: Vec<IrInstruction>,
+    pub terminator: IrTerminator,
+    /// Predecessors, including the try blocks of a handler block.
+    pub predecessors: Vec<BlockId>,
+    /// The terminator's successors, plus the resume point of a `SaveGenerator`.
+    /// Exception edges are in `handler`.
+    pub successors: Vec<BlockId>,
+    /// Handler block that catches exceptions thrown in this block, if any.
+    pub handler: Option<BlockId>,
+}
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone)]
+pub struct IrExceptionHandler {
+    /// Blocks covered by the try range, in bytecode order.
+    pub try_blocks: Vec<BlockId>,
+    /// Block that starts with `Catch`.
+    pub handler: BlockId,
+}
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone)]
+pub struct IrFunction {
+    pub function_id: u32,
+    pub name: String,
+    /// Includes `this`.
+    pub param_count: u32,
+    /// Reachable blocks in bytecode order. The first block is the entry.
+    pub blocks: Vec<IrBlock>,
+    pub exception_handlers: Vec<IrExceptionHandler>,
+    /// Number of SSA values defined in the function.
+    pub value_count: u32,
+}
+
+impl IrFunction {
+    pub fn block(&self, id: BlockId) -> &IrBlock {
+        self.blocks
+            .iter()
+            .find(|b| b.id == id)
+            .expect("block does not exist")
+    }
+
+    /// Returns the instruction that defines a value, if it isn't a phi.
+    pub fn definition(&self, value: ValueId) -> Option<&IrInstruction> {
+        self.blocks
+            .iter()
+            .flat_map(|b| b.instructions.iter())
+            .find(|i| i.result == Some(value))
+    }
+}
+
+impl fmt::Display for IrFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "function {}(#{}, {} params):",
+            self.name, self.function_id, self.param_count
+        )?;
+        for block in self.blocks.iter() {
+            let preds: Vec<String> = block.predecessors.iter().map(|p| p.to_string()).collect();
+            write!(f, "{}: # offset {}", block.id, block.start_offset)?;
+            if !preds.is_empty() {
+                write!(f, ", preds: {}", preds.join(", "))?;
+            }
+            if let Some(handler) = block.handler {
+                write!(f, ", handler: {}", handler)?;
+            }
+            writeln!(f)?;
+            for phi in block.phis.iter() {
+                writeln!(f, "  {}", phi)?;
+            }
+            for ins in block.instructions.iter() {
+                writeln!(f, "  {}", ins)?;
+            }
+            writeln!(f, "  {}", block.terminator)?;
+        }
+        Ok(())
+    }
+}
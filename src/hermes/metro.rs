@@ -0,0 +1,392 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+
+use crate::hermes::array_parser::ArrayTypes;
+use crate::hermes::hermes_file::HermesFile;
+
+/*
+ * Metro (the React Native bundler) wraps every module in a factory function and
+ * registers it from the global function with a call to `__d`:
+ *
+ *   __d(factory, moduleId, dependencyMap, verboseName?)
+ *
+ * verboseName is only emitted for dev bundles. The helpers below walk the global
+ * function and recover the arguments of each of those calls.
+ */
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone)]
+pub struct MetroModule {
+    /// The module id - what other modules use in their dependency maps.
+    pub id: u32,
+
+    /// Function ID of the factory function (the module body).
+    pub factory_function: u32,
+
+    /// Module ids from the dependency map, in order.
+    pub dependencies: Vec<u32>,
+
+    /// The module path, only present in dev bundles.
+    pub verbose_name: Option<String>,
+}
+
+/*
+ * Module dependency graph. `dependencies` holds the forward edges (module id ->
+ * the modules it requires) and `dependents` the reverse edges (module id -> the
+ * modules that require it). Both are keyed and ordered by module id.
+ */
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, Default)]
+pub struct MetroDependencyGraph {
+    pub modules: BTreeMap<u32, MetroModule>,
+    pub dependencies: BTreeMap<u32, Vec<u32>>,
+    pub dependents: BTreeMap<u32, Vec<u32>>,
+
+    // Factory function ID -> module id
+    #[cfg_attr(feature = "serde", serde(skip))]
+    factories: HashMap<u32, u32>,
+}
+
+impl MetroDependencyGraph {
+    pub fn new(modules: Vec<MetroModule>) -> Self {
+        let mut graph = MetroDependencyGraph::default();
+
+        for module in modules.iter() {
+            graph.dependents.entry(module.id).or_default();
+            let deps = graph.dependencies.entry(module.id).or_default();
+            for dep in module.dependencies.iter() {
+                if !deps.contains(dep) {
+                    deps.push(*dep);
+                }
+            }
+        }
+
+        for (id, deps) in graph.dependencies.iter() {
+            for dep in deps {
+                let dependents = graph.dependents.entry(*dep).or_default();
+                if !dependents.contains(id) {
+                    dependents.push(*id);
+                }
+            }
+        }
+
+        for module in modules {
+            graph.factories.insert(module.factory_function, module.id);
+            graph.modules.insert(module.id, module);
+        }
+
+        graph
+    }
+
+    /*
+     * Returns the module a function is the factory of, if any. Look modules up
+     * here rather than scanning the global function again for each one.
+     */
+    pub fn module_by_factory(&self, func_idx: u32) -> Option<&MetroModule> {
+        self.factories
+            .get(&func_idx)
+            .and_then(|id| self.modules.get(id))
+    }
+
+    /*
+     * Serializes the graph as JSON:
+     *
+     * {"modules": [{"id": 0, "factory_function": 73, "verbose_name": null,
+     *   "dependencies": [1, 2], "dependents": []}, ...]}
+     *
+     * Modules that are only referenced as a dependency (never defined) are included
+     * with a null factory_function.
+     */
+    pub fn to_json(&self) -> String {
+        let join = |ids: Option<&Vec<u32>>| -> String {
+            ids.map(|ids| {
+                ids.iter()
+                    .map(|id| id.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            })
+            .unwrap_or_default()
+        };
+
+        let entries: Vec<String> = self
+            .dependents
+            .keys()
+            .map(|id| {
+                let module = self.modules.get(id);
+                format!(
+                    "    {{\"id\": {}, \"factory_function\": {}, \"verbose_name\": {}, \"dependencies\": [{}], \"dependents\": [{}]}}",
+                    id,
+                    module
+                        .map(|m| m.factory_function.to_string())
+                        .unwrap_or("null".to_string()),
+                    module
+                        .and_then(|m| m.verbose_name.as_ref())
+                        .map(|name| json_string(name))
+                        .unwrap_or("null".to_string()),
+                    join(self.dependencies.get(id)),
+                    join(self.dependents.get(id)),
+                )
+            })
+            .collect();
+
+        format!("{{\n  \"modules\": [\n{}\n  ]\n}}\n", entries.join(",\n"))
+    }
+
+    /*
+     * Serializes the graph in Graphviz DOT format. Edges point from a module to
+     * the modules it depends on.
+     */
+    pub fn to_dot(&self) -> String {
+        let mut out = vec!["digraph metro {".to_string()];
+
+        for (id, module) in self.modules.iter() {
+            let label = match &module.verbose_name {
+                Some(name) => format!("{}: {}", id, name),
+                None => format!("{}", id),
+            };
+            out.push(format!("  {} [label={}];", id, json_string(&label)));
+        }
+
+        for (id, deps) in self.dependencies.iter() {
+            for dep in deps {
+                out.push(format!("  {} -> {};", id, dep));
+            }
+        }
+
+        out.push("}".to_string());
+        out.join("\n") + "\n"
+    }
+}
+
+// Quotes and escapes a string for JSON (the escaping also works for DOT labels).
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+// What we know about a register while scanning the global function.
+#[derive(Debug, Clone)]
+enum RegisterValue {
+    Define,
+    Closure(u32),
+    Number(f64),
+    String(String),
+    Array(Vec<Option<f64>>),
+}
+
+impl<R> HermesFile<R>
+where
+    R: io::Read + io::BufRead + io::Seek,
+{
+    /*
+     * Returns every module registered with `__d` in the global function.
+     *
+     * This tracks constants through registers rather than relying on the
+     * instructions being in a fixed order, so it handles `__d` being loaded once
+     * and reused, arguments being shuffled with Mov, and dependency maps built
+     * with NewArray + PutOwnByIndex as well as NewArrayWithBuffer.
+     */
+    pub fn get_metro_modules(&mut self) -> Vec<MetroModule> {
+        let global_idx = self.header.global_code_index as usize;
+        if global_idx >= self.function_headers.len() {
+            return vec![];
+        }
+
+        let frame_size = self.function_headers[global_idx].frame_size();
+        let instructions = self.get_function_instructions(global_idx);
+
+        let mut regs: HashMap<u32, RegisterValue> = HashMap::new();
+        let mut modules: Vec<MetroModule> = vec![];
+
+        for ins in instructions.iter() {
+            let operands = ins.operands();
+            let reg = |name: &str| -> u32 {
+                operands
+                    .iter()
+                    .find(|o| o.name == name)
+                    .map(|o| o.value.as_u32())
+                    .unwrap_or(0)
+            };
+
+            match ins.name() {
+                "GetByIdShort" | "GetById" | "GetByIdLong" | "TryGetById" | "TryGetByIdLong" => {
+                    let string_id = reg("p1") as usize;
+                    if string_id < self.string_storage.len()
+                        && self.get_string_from_storage_by_index(string_id) == "__d"
+                    {
+                        regs.insert(reg("r0"), RegisterValue::Define);
+                    } else {
+                        regs.remove(&reg("r0"));
+                    }
+                }
+                "CreateClosure" | "CreateClosureLongIndex" => {
+                    if let Some(fid) = ins.function_id() {
+                        regs.insert(reg("r0"), RegisterValue::Closure(fid));
+                    }
+                }
+                "LoadConstZero" => {
+                    regs.insert(reg("r0"), RegisterValue::Number(0.0));
+                }
+                "LoadConstUInt8" | "LoadConstInt" | "LoadConstDouble" => {
+                    let value = operands[1].value.as_f64();
+                    regs.insert(reg("r0"), RegisterValue::Number(value));
+                }
+                "LoadConstString" | "LoadConstStringLongIndex" => {
+                    let string_id = reg("p0") as usize;
+                    if string_id < self.string_storage.len() {
+                        let s = self.get_string_from_storage_by_index(string_id);
+                        regs.insert(reg("r0"), RegisterValue::String(s));
+                    } else {
+                        regs.remove(&reg("r0"));
+                    }
+                }
+                "NewArray" => {
+                    regs.insert(reg("r0"), RegisterValue::Array(vec![]));
+                }
+                "NewArrayWithBuffer" | "NewArrayWithBufferLong" => {
+                    let values = self
+                        .get_array_buffer_values(reg("p2") as usize, reg("p1") as usize)
+                        .iter()
+                        .map(|v| match v {
+                            ArrayTypes::IntegerValue { value } => Some(*value as i32 as f64),
+                            ArrayTypes::NumberValue { value } => Some(f64::from_bits(*value)),
+                            _ => None,
+                        })
+                        .collect();
+                    regs.insert(reg("r0"), RegisterValue::Array(values));
+                }
+                "PutOwnByIndex" | "PutOwnByIndexL" => {
+                    let value = match regs.get(&reg("r1")) {
+                        Some(RegisterValue::Number(n)) => Some(*n),
+                        _ => None,
+                    };
+                    let index = reg("p0") as usize;
+                    if let Some(RegisterValue::Array(arr)) = regs.get_mut(&reg("r0")) {
+                        if arr.len() <= index {
+                            arr.resize(index + 1, None);
+                        }
+                        arr[index] = value;
+                    }
+                }
+                "Mov" | "MovLong" => match regs.get(&reg("r1")).cloned() {
+                    Some(value) => {
+                        regs.insert(reg("r0"), value);
+                    }
+                    None => {
+                        regs.remove(&reg("r0"));
+                    }
+                },
+                "Call1" | "Call2" | "Call3" | "Call4" | "Call" | "CallLong" => {
+                    if let Some(RegisterValue::Define) = regs.get(&reg("r1")) {
+                        // Call1-4 take `this` and the arguments as explicit registers.
+                        // Call/CallLong take them from the end of the frame, in reverse order.
+                        let args: Vec<u32> = match ins.name() {
+                            "Call" | "CallLong" => (1..reg("p0"))
+                                .filter(|i| *i < frame_size)
+                                .map(|i| frame_size - 1 - i)
+                                .collect(),
+                            _ => operands
+                                .iter()
+                                .filter(|o| o.kind.is_register())
+                                .skip(3)
+                                .map(|o| o.value.as_u32())
+                                .collect(),
+                        };
+
+                        if let Some(module) = metro_module_from_args(&regs, &args) {
+                            modules.push(module);
+                        }
+                    }
+                    regs.remove(&reg("r0"));
+                }
+                _ => {
+                    // Anything else might overwrite its destination register, so
+                    // forget what we knew about it.
+                    if let Some(op) = operands.first() {
+                        if op.name == "r0" {
+                            regs.remove(&op.value.as_u32());
+                        }
+                    }
+                }
+            }
+        }
+
+        modules
+    }
+
+    /*
+     * Returns the module dependency graph of the bundle.
+     */
+    pub fn get_metro_dependency_graph(&mut self) -> MetroDependencyGraph {
+        MetroDependencyGraph::new(self.get_metro_modules())
+    }
+
+    /*
+     * Returns the metro module that a function belongs to, if the function is a
+     * module factory. To look up many functions, build the graph once and use
+     * `MetroDependencyGraph::module_by_factory`.
+     */
+    pub fn get_metro_module_by_factory(&mut self, func_idx: u32) -> Option<MetroModule> {
+        self.get_metro_dependency_graph()
+            .module_by_factory(func_idx)
+            .cloned()
+    }
+
+    /*
+     * Returns the disassembly of a module's factory function followed by every
+     * closure nested inside it.
+     */
+    pub fn get_disassembled_metro_module(&mut self, module: &MetroModule) -> String {
+        let mut functions = vec![module.factory_function];
+        functions.extend(self.get_nested_functions(module.factory_function as usize));
+
+        let mut out = vec![];
+        for fidx in functions {
+            out.push(self.get_disassembled_bytecode_for_function(fidx as usize));
+        }
+        out.join("\n")
+    }
+}
+
+fn metro_module_from_args(regs: &HashMap<u32, RegisterValue>, args: &[u32]) -> Option<MetroModule> {
+    let factory_function = match regs.get(args.first()?) {
+        Some(RegisterValue::Closure(fid)) => *fid,
+        _ => return None,
+    };
+
+    let id = match regs.get(args.get(1)?) {
+        Some(RegisterValue::Number(n)) => *n as u32,
+        _ => return None,
+    };
+
+    let dependencies = match args.get(2).and_then(|r| regs.get(r)) {
+        Some(RegisterValue::Array(arr)) => arr.iter().flatten().map(|d| *d as u32).collect(),
+        _ => vec![],
+    };
+
+    let verbose_name = match args.get(3).and_then(|r| regs.get(r)) {
+        Some(RegisterValue::String(s)) => Some(s.clone()),
+        _ => None,
+    };
+
+    Some(MetroModule {
+        id,
+        factory_function,
+        dependencies,
+        verbose_name,
+    })
+}
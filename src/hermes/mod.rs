@@ -4,17 +4,28 @@ pub mod bytecode_options;
 pub mod cjs_module;
 pub mod debug_info;
 pub mod decode;
+pub mod decompiler;
+pub mod diff;
 pub mod encode;
 pub mod exception_handler;
+pub mod fingerprint;
 pub mod function_header;
 pub mod function_sources;
+pub mod globals;
 pub mod header;
 pub mod hermes_file;
+pub mod ir;
 pub mod jenkins;
+pub mod metro;
+pub mod pattern;
+pub mod properties;
+pub mod reachability;
 pub mod regexp_table;
+pub mod signatures;
 pub mod string_kind;
 pub mod string_table;
 pub mod types;
+pub mod verifier;
 
 use std::io;
 
@@ -216,6 +227,13 @@ pub trait InstructionParser {
     fn is_jmp(&self) -> bool;
 
     fn get_address_field(&self) -> u32;
+
+    /// Returns every operand of the instruction in encoding order.
+    fn operands(&self) -> Vec<types::Operand>;
+
+    /// Overwrites the operand at `index` (as returned by `operands`). Values
+    /// that don't fit the operand's encoded type are truncated.
+    fn set_operand(&mut self, index: usize, value: types::OperandValue);
 }
 
 // Start macros
@@ -614,6 +632,29 @@ macro_rules! define_opcode {
       0 // Return 0 if no address field found
     }
 
+    fn operands(&self) -> Vec<hermes::types::Operand> {
+      vec![
+        $(
+          hermes::types::Operand {
+            name: stringify!($field),
+            kind: hermes::types::OperandKind::$arg,
+            value: self.$field.into(),
+          },
+        )*
+      ]
+    }
+
+    #[allow(unused_mut, unused_variables, unused_assignments)]
+    fn set_operand(&mut self, index: usize, value: hermes::types::OperandValue) {
+      let mut i = 0;
+      $(
+        if i == index {
+          self.$field = <map_type!($arg) as hermes::types::FromOperandValue>::from_operand_value(value).into();
+        }
+        i += 1;
+      )*
+    }
+
     fn get_string_field_names(&self) -> Vec<&str> {
       #[allow(unused_mut)]
       let mut fields = vec![];
@@ -665,7 +706,17 @@ macro_rules! define_opcode {
               format!("{} {} ", display_string, bigint_addr)
             },
             _ => {
+              let is_builtin = stringify!($field) == "p0"
+                && matches!(op_to_str(self.op), "CallBuiltin" | "CallBuiltinLong" | "GetBuiltinClosure");
               let tmp = match stringify!($arg) {
+                _ if is_builtin => {
+                  // builtin number -> name from the version's Builtins.def
+                  let value: hermes::types::OperandValue = self.$field.into();
+                  match builtin_name(value.as_u32()) {
+                    Some(name) => format!("{} {},", display_string, name),
+                    None => format!("{} {},", display_string, self.$field),
+                  }
+                }
                 "Reg8" | "Reg32" => {
                   format!("{} r{},", display_string, self.$field )
                 },
@@ -772,6 +823,22 @@ macro_rules! impl_instruction_parser {
           )*
         }
       }
+
+      fn operands(&self) -> Vec<hermes::types::Operand> {
+        match self {
+          $(
+            Instruction::$insn(insn) => insn.operands(),
+          )*
+        }
+      }
+
+      fn set_operand(&mut self, index: usize, value: hermes::types::OperandValue) {
+        match self {
+          $(
+            Instruction::$insn(insn) => insn.set_operand(index, value),
+          )*
+        }
+      }
   }
 }
 }
@@ -922,6 +989,280 @@ impl HermesInstruction {
         }
     }
 
+    /// The raw opcode byte of the instruction.
+    pub fn opcode(&self) -> u8 {
+        match self {
+            #[cfg(feature = "v76")]
+            HermesInstruction::V76(instruction) => v76::instr_to_op(*instruction),
+            #[cfg(feature = "v84")]
+            HermesInstruction::V84(instruction) => v84::instr_to_op(*instruction),
+            #[cfg(feature = "v89")]
+            HermesInstruction::V89(instruction) => v89::instr_to_op(*instruction),
+            #[cfg(feature = "v90")]
+            HermesInstruction::V90(instruction) => v90::instr_to_op(*instruction),
+            #[cfg(feature = "v93")]
+            HermesInstruction::V93(instruction) => v93::instr_to_op(*instruction),
+            #[cfg(feature = "v94")]
+            HermesInstruction::V94(instruction) => v94::instr_to_op(*instruction),
+            #[cfg(feature = "v95")]
+            HermesInstruction::V95(instruction) => v95::instr_to_op(*instruction),
+            #[cfg(feature = "v96")]
+            HermesInstruction::V96(instruction) => v96::instr_to_op(*instruction),
+        }
+    }
+
+    /// The opcode name (e.g. `GetByIdShort`) for this instruction's version.
+    pub fn name(&self) -> &'static str {
+        match self {
+            #[cfg(feature = "v76")]
+            HermesInstruction::V76(instruction) => v76::op_to_str(v76::instr_to_op(*instruction)),
+            #[cfg(feature = "v84")]
+            HermesInstruction::V84(instruction) => v84::op_to_str(v84::instr_to_op(*instruction)),
+            #[cfg(feature = "v89")]
+            HermesInstruction::V89(instruction) => v89::op_to_str(v89::instr_to_op(*instruction)),
+            #[cfg(feature = "v90")]
+            HermesInstruction::V90(instruction) => v90::op_to_str(v90::instr_to_op(*instruction)),
+            #[cfg(feature = "v93")]
+            HermesInstruction::V93(instruction) => v93::op_to_str(v93::instr_to_op(*instruction)),
+            #[cfg(feature = "v94")]
+            HermesInstruction::V94(instruction) => v94::op_to_str(v94::instr_to_op(*instruction)),
+            #[cfg(feature = "v95")]
+            HermesInstruction::V95(instruction) => v95::op_to_str(v95::instr_to_op(*instruction)),
+            #[cfg(feature = "v96")]
+            HermesInstruction::V96(instruction) => v96::op_to_str(v96::instr_to_op(*instruction)),
+        }
+    }
+
+    pub fn operands(&self) -> Vec<types::Operand> {
+        match self {
+            #[cfg(feature = "v76")]
+            HermesInstruction::V76(instruction) => instruction.operands(),
+            #[cfg(feature = "v84")]
+            HermesInstruction::V84(instruction) => instruction.operands(),
+            #[cfg(feature = "v89")]
+            HermesInstruction::V89(instruction) => instruction.operands(),
+            #[cfg(feature = "v90")]
+            HermesInstruction::V90(instruction) => instruction.operands(),
+            #[cfg(feature = "v93")]
+            HermesInstruction::V93(instruction) => instruction.operands(),
+            #[cfg(feature = "v94")]
+            HermesInstruction::V94(instruction) => instruction.operands(),
+            #[cfg(feature = "v95")]
+            HermesInstruction::V95(instruction) => instruction.operands(),
+            #[cfg(feature = "v96")]
+            HermesInstruction::V96(instruction) => instruction.operands(),
+        }
+    }
+
+    pub fn set_operand(&mut self, index: usize, value: types::OperandValue) {
+        match self {
+            #[cfg(feature = "v76")]
+            HermesInstruction::V76(instruction) => instruction.set_operand(index, value),
+            #[cfg(feature = "v84")]
+            HermesInstruction::V84(instruction) => instruction.set_operand(index, value),
+            #[cfg(feature = "v89")]
+            HermesInstruction::V89(instruction) => instruction.set_operand(index, value),
+            #[cfg(feature = "v90")]
+            HermesInstruction::V90(instruction) => instruction.set_operand(index, value),
+            #[cfg(feature = "v93")]
+            HermesInstruction::V93(instruction) => instruction.set_operand(index, value),
+            #[cfg(feature = "v94")]
+            HermesInstruction::V94(instruction) => instruction.set_operand(index, value),
+            #[cfg(feature = "v95")]
+            HermesInstruction::V95(instruction) => instruction.set_operand(index, value),
+            #[cfg(feature = "v96")]
+            HermesInstruction::V96(instruction) => instruction.set_operand(index, value),
+        }
+    }
+
+    /// An instruction of an HBC version by opcode name, with every operand set to
+    /// zero - `None` if the version isn't supported or doesn't have it.
+    pub fn named(version: u32, name: &str) -> Option<HermesInstruction> {
+        match version {
+            #[cfg(feature = "v76")]
+            76 => {
+                let op = v76::str_to_op(name);
+                (v76::op_to_str(op) == name).then(|| HermesInstruction::V76(v76::op_to_instr(op)))
+            }
+            #[cfg(feature = "v84")]
+            84 => {
+                let op = v84::str_to_op(name);
+                (v84::op_to_str(op) == name).then(|| HermesInstruction::V84(v84::op_to_instr(op)))
+            }
+            #[cfg(feature = "v89")]
+            89 => {
+                let op = v89::str_to_op(name);
+                (v89::op_to_str(op) == name).then(|| HermesInstruction::V89(v89::op_to_instr(op)))
+            }
+            #[cfg(feature = "v90")]
+            90 => {
+                let op = v90::str_to_op(name);
+                (v90::op_to_str(op) == name).then(|| HermesInstruction::V90(v90::op_to_instr(op)))
+            }
+            #[cfg(feature = "v93")]
+            93 => {
+                let op = v93::str_to_op(name);
+                (v93::op_to_str(op) == name).then(|| HermesInstruction::V93(v93::op_to_instr(op)))
+            }
+            #[cfg(feature = "v94")]
+            94 => {
+                let op = v94::str_to_op(name);
+                (v94::op_to_str(op) == name).then(|| HermesInstruction::V94(v94::op_to_instr(op)))
+            }
+            #[cfg(feature = "v95")]
+            95 => {
+                let op = v95::str_to_op(name);
+                (v95::op_to_str(op) == name).then(|| HermesInstruction::V95(v95::op_to_instr(op)))
+            }
+            #[cfg(feature = "v96")]
+            96 => {
+                let op = v96::str_to_op(name);
+                (v96::op_to_str(op) == name).then(|| HermesInstruction::V96(v96::op_to_instr(op)))
+            }
+            _ => None,
+        }
+    }
+
+    /// An instruction of the same version by opcode name, with every operand set
+    /// to zero - `None` if the version doesn't have it.
+    pub fn variant_named(&self, name: &str) -> Option<HermesInstruction> {
+        match self {
+            #[cfg(feature = "v76")]
+            HermesInstruction::V76(_) => {
+                let op = v76::str_to_op(name);
+                (v76::op_to_str(op) == name).then(|| HermesInstruction::V76(v76::op_to_instr(op)))
+            }
+            #[cfg(feature = "v84")]
+            HermesInstruction::V84(_) => {
+                let op = v84::str_to_op(name);
+                (v84::op_to_str(op) == name).then(|| HermesInstruction::V84(v84::op_to_instr(op)))
+            }
+            #[cfg(feature = "v89")]
+            HermesInstruction::V89(_) => {
+                let op = v89::str_to_op(name);
+                (v89::op_to_str(op) == name).then(|| HermesInstruction::V89(v89::op_to_instr(op)))
+            }
+            #[cfg(feature = "v90")]
+            HermesInstruction::V90(_) => {
+                let op = v90::str_to_op(name);
+                (v90::op_to_str(op) == name).then(|| HermesInstruction::V90(v90::op_to_instr(op)))
+            }
+            #[cfg(feature = "v93")]
+            HermesInstruction::V93(_) => {
+                let op = v93::str_to_op(name);
+                (v93::op_to_str(op) == name).then(|| HermesInstruction::V93(v93::op_to_instr(op)))
+            }
+            #[cfg(feature = "v94")]
+            HermesInstruction::V94(_) => {
+                let op = v94::str_to_op(name);
+                (v94::op_to_str(op) == name).then(|| HermesInstruction::V94(v94::op_to_instr(op)))
+            }
+            #[cfg(feature = "v95")]
+            HermesInstruction::V95(_) => {
+                let op = v95::str_to_op(name);
+                (v95::op_to_str(op) == name).then(|| HermesInstruction::V95(v95::op_to_instr(op)))
+            }
+            #[cfg(feature = "v96")]
+            HermesInstruction::V96(_) => {
+                let op = v96::str_to_op(name);
+                (v96::op_to_str(op) == name).then(|| HermesInstruction::V96(v96::op_to_instr(op)))
+            }
+        }
+    }
+
+    /*
+     * Sets an operand, switching to a wider form of the instruction if the value
+     * doesn't fit: `GetByIdShort` becomes `GetById` or `GetByIdLong`,
+     * `LoadConstString` becomes `LoadConstStringLongIndex`, `Jmp` becomes
+     * `JmpLong` and so on. The instruction is kept as it is if the value fits,
+     * otherwise the smallest form that fits every operand is used. Returns `None`
+     * if there's no such form.
+     */
+    pub fn with_operand(
+        &self,
+        index: usize,
+        value: types::OperandValue,
+    ) -> Option<HermesInstruction> {
+        let operands = self.operands();
+        let mut values: Vec<types::OperandValue> = operands.iter().map(|o| o.value).collect();
+        *values.get_mut(index)? = value;
+
+        let name = self.name();
+        let base = ["LongIndex", "Long", "Short"]
+            .iter()
+            .find_map(|suffix| name.strip_suffix(suffix))
+            .unwrap_or(name);
+        let candidates = [
+            name.to_string(),
+            base.to_string(),
+            format!("{}Short", base),
+            format!("{}Long", base),
+            format!("{}LongIndex", base),
+        ];
+
+        let mut best: Option<HermesInstruction> = None;
+        for candidate in candidates.iter() {
+            let Some(mut ins) = self.variant_named(candidate) else {
+                continue;
+            };
+            let kinds = ins.operands();
+            let fits = kinds.len() == operands.len()
+                && kinds.iter().zip(operands.iter()).zip(values.iter()).all(
+                    |((wide, narrow), value)| {
+                        wide.kind.same_family(&narrow.kind) && wide.kind.fits(*value)
+                    },
+                );
+            if !fits {
+                continue;
+            }
+
+            for (i, value) in values.iter().enumerate() {
+                ins.set_operand(i, *value);
+            }
+            if candidate == name {
+                return Some(ins);
+            }
+            if best.is_none_or(|b| ins.size() < b.size()) {
+                best = Some(ins);
+            }
+        }
+        best
+    }
+
+    /// Looks up an operand by its field name (`r0`, `p1`, ...).
+    pub fn operand(&self, name: &str) -> Option<types::Operand> {
+        self.operands().into_iter().find(|o| o.name == name)
+    }
+
+    /// Returns the function ID referenced by the instruction, if any.
+    pub fn function_id(&self) -> Option<u32> {
+        self.function_id_operand().map(|o| o.value.as_u32())
+    }
+
+    /// Returns the operand holding the function ID referenced by the instruction.
+    /// Older versions (< v89) encode function IDs as plain UInt16/UInt32, so
+    /// closure creation and direct calls are checked by name as well.
+    pub fn function_id_operand(&self) -> Option<types::Operand> {
+        let operands = self.operands();
+        if let Some(op) = operands.iter().find(|o| o.kind.is_function_id()) {
+            return Some(*op);
+        }
+
+        let field = match self.name() {
+            "CreateClosure"
+            | "CreateClosureLongIndex"
+            | "CreateGeneratorClosure"
+            | "CreateGeneratorClosureLongIndex"
+            | "CreateAsyncClosure"
+            | "CreateAsyncClosureLongIndex"
+            | "CreateGenerator"
+            | "CreateGeneratorLongIndex" => "p0",
+            "CallDirect" | "CallDirectLongIndex" => "p1",
+            _ => return None,
+        };
+        operands.into_iter().find(|o| o.name == field)
+    }
+
     pub fn is_jmp(&self) -> bool {
         match self {
             #[cfg(feature = "v76")]
@@ -1035,6 +1376,64 @@ impl HermesInstruction {
             }
         }
     }
+
+    /// Returns the name of the builtin used by CallBuiltin, CallBuiltinLong and
+    /// GetBuiltinClosure (e.g. `Object.keys`), or `None` for other instructions.
+    pub fn builtin_name(&self) -> Option<&'static str> {
+        if !matches!(
+            self.name(),
+            "CallBuiltin" | "CallBuiltinLong" | "GetBuiltinClosure"
+        ) {
+            return None;
+        }
+        let idx = self.operand("p0")?.value.as_u32();
+        match self {
+            #[cfg(feature = "v76")]
+            HermesInstruction::V76(_) => v76::builtin_name(idx),
+            #[cfg(feature = "v84")]
+            HermesInstruction::V84(_) => v84::builtin_name(idx),
+            #[cfg(feature = "v89")]
+            HermesInstruction::V89(_) => v89::builtin_name(idx),
+            #[cfg(feature = "v90")]
+            HermesInstruction::V90(_) => v90::builtin_name(idx),
+            #[cfg(feature = "v93")]
+            HermesInstruction::V93(_) => v93::builtin_name(idx),
+            #[cfg(feature = "v94")]
+            HermesInstruction::V94(_) => v94::builtin_name(idx),
+            #[cfg(feature = "v95")]
+            HermesInstruction::V95(_) => v95::builtin_name(idx),
+            #[cfg(feature = "v96")]
+            HermesInstruction::V96(_) => v96::builtin_name(idx),
+        }
+    }
+}
+
+/*
+ * Returns the builtin table for an HBC version, indexed by the builtin number
+ * CallBuiltin, CallBuiltinLong and GetBuiltinClosure take - or `None` if the
+ * version isn't enabled. The public builtins (`Object.keys`) come first, then the
+ * private ones (`HermesBuiltin.*`), then the builtins implemented in JS.
+ */
+pub fn get_builtins(version: u32) -> Option<&'static [&'static str]> {
+    match version {
+        #[cfg(feature = "v76")]
+        76 => Some(v76::builtins::BUILTINS),
+        #[cfg(feature = "v84")]
+        84 => Some(v84::builtins::BUILTINS),
+        #[cfg(feature = "v89")]
+        89 => Some(v89::builtins::BUILTINS),
+        #[cfg(feature = "v90")]
+        90 => Some(v90::builtins::BUILTINS),
+        #[cfg(feature = "v93")]
+        93 => Some(v93::builtins::BUILTINS),
+        #[cfg(feature = "v94")]
+        94 => Some(v94::builtins::BUILTINS),
+        #[cfg(feature = "v95")]
+        95 => Some(v95::builtins::BUILTINS),
+        #[cfg(feature = "v96")]
+        96 => Some(v96::builtins::BUILTINS),
+        _ => None,
+    }
 }
 
 pub trait IntoParentInstruction {
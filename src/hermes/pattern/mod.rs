@@ -0,0 +1,385 @@
+pub mod parser;
+pub mod regex;
+
+use std::collections::HashMap;
+use std::io;
+
+use crate::hermes::hermes_file::HermesFile;
+use crate::hermes::types::{OperandKind, OperandValue};
+
+pub use parser::{parse_pattern, parse_rules};
+pub use regex::Regex;
+
+/*
+ * Instruction sequence patterns - YARA-like rules for Hermes bytecode.
+ *
+ * A rule is a sequence of steps. Each step matches one instruction by opcode
+ * name (as returned by `op_to_str`, with `*` wildcards) and operands, either
+ * positionally or by the field names the instruction macros generate (`r0`,
+ * `p1`, ...). Since everything goes through names rather than opcode numbers,
+ * the same rule works on every supported HBC version.
+ *
+ * Operand patterns:
+ *
+ *   _          any operand
+ *   ...        any number of operands
+ *   r3         register 3
+ *   42         that number
+ *   "fetch"    a string operand equal to `fetch`
+ *   /^https/i  a string operand matching the regex (see regex.rs)
+ *   $name      any operand - the first match binds its value, later uses must
+ *              have the same value (e.g. the register a string was loaded into)
+ *   p1="fetch" the operand named p1 - any of the above after the `=`
+ *
+ * See parser.rs for the rule file syntax.
+ */
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StepGap {
+    /// The instruction right after the previous step's.
+    Next,
+    /// One of the next N instructions after the previous step's.
+    Within(u32),
+    /// Anywhere after the previous step's - or anywhere at all for the first step.
+    Anywhere,
+}
+
+/// Opcode names separated by `|`, each of which may contain `*` wildcards.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpcodePattern {
+    pub alternatives: Vec<String>,
+}
+
+impl OpcodePattern {
+    pub fn new(pattern: &str) -> Result<OpcodePattern, String> {
+        let alternatives: Vec<String> = pattern.split('|').map(|s| s.to_string()).collect();
+        if alternatives.iter().any(|a| a.is_empty()) {
+            return Err(format!("invalid opcode pattern `{}`", pattern));
+        }
+        Ok(OpcodePattern { alternatives })
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        self.alternatives.iter().any(|a| glob_match(a, name))
+    }
+}
+
+// `*` matches any run of characters
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+    if parts.len() == 1 {
+        return pattern == text;
+    }
+
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if !text.starts_with(first) || text.len() < first.len() + last.len() || !text.ends_with(last) {
+        return false;
+    }
+
+    let mut rest = &text[first.len()..text.len() - last.len()];
+    for part in parts[1..parts.len() - 1].iter() {
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValuePattern {
+    Any,
+    Register(u32),
+    Number(f64),
+    String(String),
+    Regex(Regex),
+    Variable(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OperandPattern {
+    /// The next operand.
+    Value(ValuePattern),
+    /// The operand with this field name, wherever it is.
+    Named(String, ValuePattern),
+    /// Zero or more operands.
+    Rest,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatternStep {
+    pub gap: StepGap,
+    pub opcode: OpcodePattern,
+    pub operands: Vec<OperandPattern>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatternRule {
+    pub name: String,
+    pub steps: Vec<PatternStep>,
+}
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone)]
+pub struct PatternMatch {
+    pub rule: String,
+    pub function_id: u32,
+    pub function_name: String,
+
+    /// Byte offset of the instruction matched by each step.
+    pub offsets: Vec<u32>,
+
+    /// The matched instructions, strings resolved.
+    pub instructions: Vec<String>,
+}
+
+impl PatternMatch {
+    /// Byte offset of the first matched instruction.
+    pub fn offset(&self) -> u32 {
+        self.offsets.first().copied().unwrap_or(0)
+    }
+}
+
+/// An operand with its string resolved, so patterns can be matched without
+/// going back to the file.
+#[derive(Debug, Clone)]
+pub struct PatternOperand {
+    pub name: &'static str,
+    pub kind: OperandKind,
+    pub value: OperandValue,
+    pub string: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PatternInstruction {
+    pub name: &'static str,
+    pub offset: u32,
+    pub operands: Vec<PatternOperand>,
+}
+
+type Bindings = HashMap<String, OperandValue>;
+
+fn value_matches(
+    pattern: &ValuePattern,
+    operand: &PatternOperand,
+    bindings: &mut Bindings,
+) -> bool {
+    match pattern {
+        ValuePattern::Any => true,
+        ValuePattern::Register(reg) => operand.kind.is_register() && operand.value.as_u32() == *reg,
+        ValuePattern::Number(n) => !operand.kind.is_register() && operand.value.as_f64() == *n,
+        ValuePattern::String(s) => operand.string.as_deref() == Some(s.as_str()),
+        ValuePattern::Regex(re) => operand.string.as_deref().is_some_and(|s| re.is_match(s)),
+        ValuePattern::Variable(var) => match bindings.get(var) {
+            Some(value) => *value == operand.value,
+            None => {
+                bindings.insert(var.clone(), operand.value);
+                true
+            }
+        },
+    }
+}
+
+/*
+ * Every way the operand patterns can match an instruction's operands, as the
+ * variable bindings each one leaves behind.
+ */
+fn match_operands(
+    patterns: &[OperandPattern],
+    operands: &[PatternOperand],
+    all: &[PatternOperand],
+    bindings: &Bindings,
+    out: &mut Vec<Bindings>,
+) {
+    let Some(pattern) = patterns.first() else {
+        if operands.is_empty() {
+            out.push(bindings.clone());
+        }
+        return;
+    };
+
+    match pattern {
+        OperandPattern::Rest => {
+            for skip in 0..=operands.len() {
+                match_operands(&patterns[1..], &operands[skip..], all, bindings, out);
+            }
+        }
+        OperandPattern::Value(value) => {
+            if let Some(operand) = operands.first() {
+                let mut bindings = bindings.clone();
+                if value_matches(value, operand, &mut bindings) {
+                    match_operands(&patterns[1..], &operands[1..], all, &bindings, out);
+                }
+            }
+        }
+        OperandPattern::Named(name, value) => {
+            if let Some(operand) = all.iter().find(|o| o.name == name) {
+                let mut bindings = bindings.clone();
+                if value_matches(value, operand, &mut bindings) {
+                    match_operands(&patterns[1..], operands, all, &bindings, out);
+                }
+            }
+        }
+    }
+}
+
+fn match_step(step: &PatternStep, ins: &PatternInstruction, bindings: &Bindings) -> Vec<Bindings> {
+    let mut out = vec![];
+    if !step.opcode.matches(ins.name) {
+        return out;
+    }
+
+    // Named patterns don't consume positional operands, so a step with only
+    // named patterns doesn't need to account for every operand
+    let positional = step
+        .operands
+        .iter()
+        .any(|p| !matches!(p, OperandPattern::Named(..)));
+    let mut patterns = step.operands.clone();
+    if !positional {
+        patterns.push(OperandPattern::Rest);
+    }
+
+    match_operands(&patterns, &ins.operands, &ins.operands, bindings, &mut out);
+    out
+}
+
+// Finds the rest of a match from `step_idx` on, after the instruction at `prev`
+fn match_from(
+    rule: &PatternRule,
+    instructions: &[PatternInstruction],
+    step_idx: usize,
+    prev: usize,
+    bindings: &Bindings,
+) -> Option<Vec<usize>> {
+    let Some(step) = rule.steps.get(step_idx) else {
+        return Some(vec![]);
+    };
+
+    let end = match step.gap {
+        StepGap::Next => prev + 2,
+        StepGap::Within(n) => prev + 1 + n as usize,
+        StepGap::Anywhere => instructions.len(),
+    }
+    .min(instructions.len());
+
+    for idx in (prev + 1)..end {
+        for bindings in match_step(step, &instructions[idx], bindings) {
+            if let Some(mut rest) = match_from(rule, instructions, step_idx + 1, idx, &bindings) {
+                rest.insert(0, idx);
+                return Some(rest);
+            }
+        }
+    }
+    None
+}
+
+/*
+ * Matches a rule against a function's instructions. Returns the indices of the
+ * instructions matched by each step, for every instruction the first step
+ * matches from.
+ */
+pub fn match_rule(rule: &PatternRule, instructions: &[PatternInstruction]) -> Vec<Vec<usize>> {
+    let mut matches = vec![];
+    let Some(first) = rule.steps.first() else {
+        return matches;
+    };
+
+    for (idx, ins) in instructions.iter().enumerate() {
+        for bindings in match_step(first, ins, &HashMap::new()) {
+            if let Some(mut rest) = match_from(rule, instructions, 1, idx, &bindings) {
+                rest.insert(0, idx);
+                matches.push(rest);
+                break;
+            }
+        }
+    }
+    matches
+}
+
+impl<R> HermesFile<R>
+where
+    R: io::Read + io::BufRead + io::Seek,
+{
+    /*
+     * Returns a function's instructions with their string operands resolved,
+     * ready for `match_rule`.
+     */
+    pub fn get_pattern_instructions(&mut self, idx: usize) -> Vec<PatternInstruction> {
+        let mut offset = 0;
+        let mut out = vec![];
+
+        for ins in self.get_function_instructions(idx).iter() {
+            let operands = ins
+                .operands()
+                .iter()
+                .map(|op| PatternOperand {
+                    name: op.name,
+                    kind: op.kind,
+                    value: op.value,
+                    string: if op.kind.is_string_id()
+                        && (op.value.as_u32() as usize) < self.string_storage.len()
+                    {
+                        Some(self.get_string_from_storage_by_index(op.value.as_u32() as usize))
+                    } else {
+                        None
+                    },
+                })
+                .collect();
+            out.push(PatternInstruction {
+                name: ins.name(),
+                offset,
+                operands,
+            });
+            offset += ins.size() as u32;
+        }
+
+        out
+    }
+
+    /*
+     * Searches one function for every rule.
+     */
+    pub fn search_function(&mut self, idx: usize, rules: &[PatternRule]) -> Vec<PatternMatch> {
+        let instructions = self.get_pattern_instructions(idx);
+        let mut results = vec![];
+        let mut display: Option<Vec<String>> = None;
+
+        for rule in rules.iter() {
+            for indices in match_rule(rule, &instructions) {
+                let display = display.get_or_insert_with(|| {
+                    self.get_function_instructions(idx)
+                        .iter()
+                        .map(|ins| {
+                            ins.display(self)
+                                .split_whitespace()
+                                .collect::<Vec<&str>>()
+                                .join(" ")
+                        })
+                        .collect()
+                });
+                results.push(PatternMatch {
+                    rule: rule.name.clone(),
+                    function_id: idx as u32,
+                    function_name: self.get_function_name(idx),
+                    offsets: indices.iter().map(|i| instructions[*i].offset).collect(),
+                    instructions: indices.iter().map(|i| display[*i].clone()).collect(),
+                });
+            }
+        }
+
+        results
+    }
+
+    /*
+     * Searches every function in the bundle for every rule.
+     */
+    pub fn search(&mut self, rules: &[PatternRule]) -> Vec<PatternMatch> {
+        let mut results = vec![];
+        for idx in 0..self.function_headers.len() {
+            results.extend(self.search_function(idx, rules));
+        }
+        results
+    }
+}
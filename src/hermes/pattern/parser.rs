@@ -0,0 +1,277 @@
+use crate::hermes::pattern::regex::Regex;
+use crate::hermes::pattern::{
+    OpcodePattern, OperandPattern, PatternRule, PatternStep, StepGap, ValuePattern,
+};
+
+/*
+ * Parser for the pattern rule syntax:
+ *
+ *   # comments start with # or //
+ *   rule fetch_https
+ *       GetById* $fetch _ _ "fetch"
+ *       within 5 LoadConstString* $url /^https/
+ *       within 5 Call* _ $fetch ... $url ...
+ *   end
+ *
+ * Each line inside a rule is one step - an opcode pattern followed by operand
+ * patterns (commas between operands are optional). The first step can match
+ * anywhere; later steps are prefixed with how far they can be from the
+ * previous one: nothing or `then` (the very next instruction), `within N`
+ * (one of the next N instructions) or `after` (anywhere later in the function).
+ *
+ * Steps can also be written on one line, separated by `;` - see `parse_pattern`.
+ */
+
+/*
+ * Parses every rule in a rule file.
+ */
+pub fn parse_rules(text: &str) -> Result<Vec<PatternRule>, String> {
+    let mut rules = vec![];
+    let mut current: Option<(String, Vec<String>, usize)> = None;
+
+    for (line_no, line) in text.lines().enumerate() {
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let mut words = line.split_whitespace();
+        match (words.next(), &mut current) {
+            (Some("rule"), None) => {
+                let name = words
+                    .next()
+                    .ok_or_else(|| format!("line {}: rule without a name", line_no + 1))?;
+                current = Some((name.to_string(), vec![], line_no + 1));
+            }
+            (Some("rule"), Some(_)) => {
+                return Err(format!(
+                    "line {}: `rule` inside a rule - missing `end`?",
+                    line_no + 1
+                ))
+            }
+            (Some("end"), Some(_)) => {
+                let (name, steps, start) = current.take().unwrap();
+                let rule = parse_pattern(&name, &steps.join(";"))
+                    .map_err(|e| format!("rule {} (line {}): {}", name, start, e))?;
+                rules.push(rule);
+            }
+            (_, Some((_, steps, _))) => steps.push(line.to_string()),
+            (_, None) => {
+                return Err(format!("line {}: expected `rule <name>`", line_no + 1));
+            }
+        }
+    }
+
+    if let Some((name, _, start)) = current {
+        return Err(format!("rule {} (line {}): missing `end`", name, start));
+    }
+
+    Ok(rules)
+}
+
+/*
+ * Parses a single pattern with its steps separated by `;`, e.g.
+ * `GetById* _ _ _ "fetch"; within 5 Call*`.
+ */
+pub fn parse_pattern(name: &str, text: &str) -> Result<PatternRule, String> {
+    let tokens = tokenize(text)?;
+
+    let mut steps = vec![];
+    for step_tokens in tokens.split(|t| t == ";") {
+        if step_tokens.is_empty() {
+            continue;
+        }
+        steps.push(parse_step(step_tokens, steps.is_empty())?);
+    }
+
+    if steps.is_empty() {
+        return Err("empty pattern".to_string());
+    }
+
+    Ok(PatternRule {
+        name: name.to_string(),
+        steps,
+    })
+}
+
+fn parse_step(tokens: &[String], first: bool) -> Result<PatternStep, String> {
+    let (gap, rest) = match tokens[0].as_str() {
+        "then" => (StepGap::Next, &tokens[1..]),
+        "after" => (StepGap::Anywhere, &tokens[1..]),
+        "within" => {
+            let n = tokens
+                .get(1)
+                .and_then(|n| n.parse::<u32>().ok())
+                .filter(|n| *n > 0)
+                .ok_or("`within` needs a positive instruction count")?;
+            (StepGap::Within(n), &tokens[2..])
+        }
+        _ => (StepGap::Next, tokens),
+    };
+    if first && rest.len() != tokens.len() {
+        return Err(format!("the first step can't start with `{}`", tokens[0]));
+    }
+
+    let opcode = rest.first().ok_or("missing opcode")?;
+    let opcode = OpcodePattern::new(opcode)?;
+
+    let mut operands = vec![];
+    for token in rest[1..].iter() {
+        operands.push(parse_operand(token)?);
+    }
+
+    Ok(PatternStep {
+        gap: if first { StepGap::Anywhere } else { gap },
+        opcode,
+        operands,
+    })
+}
+
+fn parse_operand(token: &str) -> Result<OperandPattern, String> {
+    if token == "..." {
+        return Ok(OperandPattern::Rest);
+    }
+
+    // Named operand - p1="fetch", r0=$x
+    if let Some((name, value)) = token.split_once('=') {
+        if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Ok(OperandPattern::Named(name.to_string(), parse_value(value)?));
+        }
+    }
+
+    Ok(OperandPattern::Value(parse_value(token)?))
+}
+
+fn parse_value(token: &str) -> Result<ValuePattern, String> {
+    if token == "_" {
+        return Ok(ValuePattern::Any);
+    }
+    if let Some(var) = token.strip_prefix('$') {
+        if var.is_empty() {
+            return Err("`$` without a variable name".to_string());
+        }
+        return Ok(ValuePattern::Variable(var.to_string()));
+    }
+    if let Some(s) = token.strip_prefix('"') {
+        return Ok(ValuePattern::String(
+            s.strip_suffix('"').unwrap_or(s).to_string(),
+        ));
+    }
+    if token.starts_with('/') {
+        let end = token
+            .rfind('/')
+            .filter(|e| *e > 0)
+            .ok_or("unterminated regex")?;
+        let flags = &token[end + 1..];
+        if !flags.chars().all(|c| c == 'i') {
+            return Err(format!("unknown regex flags `{}`", flags));
+        }
+        return Ok(ValuePattern::Regex(Regex::new(
+            &token[1..end],
+            !flags.is_empty(),
+        )?));
+    }
+    if let Some(reg) = token.strip_prefix('r') {
+        if let Ok(reg) = reg.parse::<u32>() {
+            return Ok(ValuePattern::Register(reg));
+        }
+    }
+    if let Ok(n) = token.parse::<f64>() {
+        return Ok(ValuePattern::Number(n));
+    }
+    Err(format!("can't parse operand `{}`", token))
+}
+
+// `#` and `//` start a comment, unless inside a string or regex
+fn strip_comment(line: &str) -> &str {
+    let bytes = line.as_bytes();
+    let mut quote: Option<u8> = None;
+    let mut i = 0;
+    while i < bytes.len() {
+        match (quote, bytes[i]) {
+            (Some(_), b'\\') => i += 1,
+            (Some(q), c) if c == q => quote = None,
+            (None, b'"') => quote = Some(b'"'),
+            (None, b'#') => return &line[..i],
+            (None, b'/') if bytes.get(i + 1) == Some(&b'/') => return &line[..i],
+            (None, b'/') => quote = Some(b'/'),
+            _ => {}
+        }
+        i += 1;
+    }
+    line
+}
+
+/*
+ * Splits a pattern into tokens. Strings keep their opening quote (escapes are
+ * resolved), regexes keep both slashes and their flags, `;` is its own token and
+ * commas are ignored.
+ */
+fn tokenize(text: &str) -> Result<Vec<String>, String> {
+    let mut tokens = vec![];
+    let mut chars = text.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() || c == ',' {
+            chars.next();
+            continue;
+        }
+        if c == ';' {
+            chars.next();
+            tokens.push(";".to_string());
+            continue;
+        }
+
+        let mut token = String::new();
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() || c == ',' || c == ';' {
+                break;
+            }
+            chars.next();
+            match c {
+                // "..." - escapes resolved, closing quote kept
+                '"' => {
+                    token.push('"');
+                    loop {
+                        match chars.next() {
+                            Some('\\') => match chars.next() {
+                                Some('n') => token.push('\n'),
+                                Some('t') => token.push('\t'),
+                                Some(other) => token.push(other),
+                                None => return Err("unterminated string".to_string()),
+                            },
+                            Some('"') => break,
+                            Some(other) => token.push(other),
+                            None => return Err("unterminated string".to_string()),
+                        }
+                    }
+                    token.push('"');
+                }
+                // /.../flags - escapes kept for the regex parser, except \/
+                '/' => {
+                    token.push('/');
+                    loop {
+                        match chars.next() {
+                            Some('\\') => match chars.next() {
+                                Some('/') => token.push('/'),
+                                Some(other) => {
+                                    token.push('\\');
+                                    token.push(other);
+                                }
+                                None => return Err("unterminated regex".to_string()),
+                            },
+                            Some('/') => break,
+                            Some(other) => token.push(other),
+                            None => return Err("unterminated regex".to_string()),
+                        }
+                    }
+                    token.push('/');
+                }
+                _ => token.push(c),
+            }
+        }
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
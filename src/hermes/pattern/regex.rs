@@ -0,0 +1,283 @@
+/*
+ * A small backtracking regular expression matcher for string operands in
+ * patterns - enough for things like /^https?:\/\// without pulling in a regex
+ * dependency.
+ *
+ * Supported: literals, `.`, character classes (`[a-z]`, `[^"]`), `\d` `\w` `\s`
+ * and their negations, escaped metacharacters, `*` `+` `?` quantifiers (greedy),
+ * `^` and `$` anchors and top-level `|` alternation. Matching is unanchored
+ * unless `^`/`$` are used, and optionally case-insensitive.
+ *
+ * Anything else - groups, `{n,m}`, lazy quantifiers, `\b`, backreferences and
+ * other escaped letters or digits - is an error rather than being matched as
+ * something it isn't.
+ */
+
+#[derive(Debug, Clone, PartialEq)]
+enum ClassItem {
+    Char(char),
+    Range(char, char),
+    Digit(bool),
+    Word(bool),
+    Space(bool),
+}
+
+impl ClassItem {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            ClassItem::Char(x) => *x == c,
+            ClassItem::Range(lo, hi) => *lo <= c && c <= *hi,
+            ClassItem::Digit(negated) => c.is_ascii_digit() != *negated,
+            ClassItem::Word(negated) => (c.is_alphanumeric() || c == '_') != *negated,
+            ClassItem::Space(negated) => c.is_whitespace() != *negated,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Atom {
+    Any,
+    Class {
+        negated: bool,
+        items: Vec<ClassItem>,
+    },
+}
+
+impl Atom {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Atom::Any => true,
+            Atom::Class { negated, items } => items.iter().any(|i| i.matches(c)) != *negated,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Piece {
+    atom: Atom,
+    min: usize,
+    max: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Branch {
+    anchored_start: bool,
+    anchored_end: bool,
+    pieces: Vec<Piece>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Regex {
+    source: String,
+    case_insensitive: bool,
+    branches: Vec<Branch>,
+}
+
+impl Regex {
+    pub fn new(source: &str, case_insensitive: bool) -> Result<Regex, String> {
+        let mut branches = vec![];
+        for branch in split_alternatives(source) {
+            branches.push(
+                parse_branch(&branch, case_insensitive)
+                    .map_err(|e| format!("/{}/: {}", source, e))?,
+            );
+        }
+
+        Ok(Regex {
+            source: source.to_string(),
+            case_insensitive,
+            branches,
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        let text: Vec<char> = if self.case_insensitive {
+            text.to_lowercase().chars().collect()
+        } else {
+            text.chars().collect()
+        };
+
+        self.branches.iter().any(|branch| {
+            if branch.anchored_start {
+                match_here(&branch.pieces, &text, 0, branch.anchored_end)
+            } else {
+                (0..=text.len())
+                    .any(|start| match_here(&branch.pieces, &text, start, branch.anchored_end))
+            }
+        })
+    }
+}
+
+fn match_here(pieces: &[Piece], text: &[char], pos: usize, anchored_end: bool) -> bool {
+    let Some(piece) = pieces.first() else {
+        return !anchored_end || pos == text.len();
+    };
+
+    // Take as many as allowed, then back off
+    let mut count = 0;
+    while piece.max.is_none_or(|max| count < max)
+        && pos + count < text.len()
+        && piece.atom.matches(text[pos + count])
+    {
+        count += 1;
+    }
+
+    loop {
+        if count >= piece.min && match_here(&pieces[1..], text, pos + count, anchored_end) {
+            return true;
+        }
+        if count == 0 || count <= piece.min {
+            return false;
+        }
+        count -= 1;
+    }
+}
+
+// Splits on `|` outside of character classes, keeping escapes intact
+fn split_alternatives(pattern: &str) -> Vec<String> {
+    let mut branches = vec![String::new()];
+    let mut in_class = false;
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                let last = branches.last_mut().unwrap();
+                last.push(c);
+                if let Some(next) = chars.next() {
+                    last.push(next);
+                }
+            }
+            '[' => {
+                in_class = true;
+                branches.last_mut().unwrap().push(c);
+            }
+            ']' => {
+                in_class = false;
+                branches.last_mut().unwrap().push(c);
+            }
+            '|' if !in_class => branches.push(String::new()),
+            _ => branches.last_mut().unwrap().push(c),
+        }
+    }
+    branches
+}
+
+fn escape_item(c: char) -> Result<ClassItem, String> {
+    Ok(match c {
+        'd' => ClassItem::Digit(false),
+        'D' => ClassItem::Digit(true),
+        'w' => ClassItem::Word(false),
+        'W' => ClassItem::Word(true),
+        's' => ClassItem::Space(false),
+        'S' => ClassItem::Space(true),
+        'n' => ClassItem::Char('\n'),
+        't' => ClassItem::Char('\t'),
+        'r' => ClassItem::Char('\r'),
+        other if other.is_ascii_alphanumeric() => {
+            return Err(format!("unsupported escape `\\{}`", other))
+        }
+        other => ClassItem::Char(other),
+    })
+}
+
+// Case-insensitive patterns are lowered along with the text they're matched against
+fn lower(item: ClassItem, case_insensitive: bool) -> ClassItem {
+    let l = |c: char| c.to_lowercase().next().unwrap_or(c);
+    match item {
+        ClassItem::Char(c) if case_insensitive => ClassItem::Char(l(c)),
+        ClassItem::Range(lo, hi) if case_insensitive => ClassItem::Range(l(lo), l(hi)),
+        item => item,
+    }
+}
+
+fn parse_branch(pattern: &str, case_insensitive: bool) -> Result<Branch, String> {
+    let mut chars: Vec<char> = pattern.chars().collect();
+
+    let anchored_start = chars.first() == Some(&'^');
+    if anchored_start {
+        chars.remove(0);
+    }
+    let anchored_end =
+        chars.last() == Some(&'$') && !(chars.len() >= 2 && chars[chars.len() - 2] == '\\');
+    if anchored_end {
+        chars.pop();
+    }
+
+    let mut pieces: Vec<Piece> = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let atom = match chars[i] {
+            '.' => Atom::Any,
+            '\\' => {
+                i += 1;
+                let c = *chars.get(i).ok_or("trailing backslash")?;
+                Atom::Class {
+                    negated: false,
+                    items: vec![lower(escape_item(c)?, case_insensitive)],
+                }
+            }
+            '[' => {
+                i += 1;
+                let negated = chars.get(i) == Some(&'^');
+                if negated {
+                    i += 1;
+                }
+                let mut items = vec![];
+                let mut first = true;
+                loop {
+                    let c = *chars.get(i).ok_or("unterminated character class")?;
+                    if c == ']' && !first {
+                        break;
+                    }
+                    first = false;
+                    let item = if c == '\\' {
+                        i += 1;
+                        escape_item(*chars.get(i).ok_or("trailing backslash")?)?
+                    } else {
+                        ClassItem::Char(c)
+                    };
+                    // a-z ranges
+                    match (item, chars.get(i + 1), chars.get(i + 2)) {
+                        (ClassItem::Char(lo), Some('-'), Some(hi)) if *hi != ']' => {
+                            items.push(lower(ClassItem::Range(lo, *hi), case_insensitive));
+                            i += 3;
+                        }
+                        (item, _, _) => {
+                            items.push(lower(item, case_insensitive));
+                            i += 1;
+                        }
+                    }
+                }
+                Atom::Class { negated, items }
+            }
+            '*' | '+' | '?' => return Err(format!("nothing to repeat at {}", i)),
+            '(' | ')' | '{' | '}' => return Err(format!("unsupported `{}` at {}", chars[i], i)),
+            c => Atom::Class {
+                negated: false,
+                items: vec![lower(ClassItem::Char(c), case_insensitive)],
+            },
+        };
+        i += 1;
+
+        let (min, max) = match chars.get(i) {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            _ => (1, Some(1)),
+        };
+        if max != Some(1) || min == 0 {
+            i += 1;
+        }
+        pieces.push(Piece { atom, min, max });
+    }
+
+    Ok(Branch {
+        anchored_start,
+        anchored_end,
+        pieces,
+    })
+}
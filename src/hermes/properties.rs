@@ -0,0 +1,368 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+
+use crate::hermes::array_parser::ArrayTypes;
+use crate::hermes::hermes_file::HermesFile;
+use crate::hermes::HermesInstruction;
+
+/*
+ * Property access inventory - every property name a function reads, writes or
+ * deletes through the *ById instructions, and the shapes (key sets) of the
+ * object literals it builds.
+ *
+ * Object literals are built either from the object key/value buffers with
+ * NewObjectWithBuffer, or with NewObject followed by PutNewOwnById for each key
+ * (Hermes mixes both when some of the values aren't constants). Both are
+ * tracked per register, so `{url, method, headers}` comes out as one shape no
+ * matter how it was emitted.
+ */
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PropertyAccessKind {
+    /// GetByIdShort, GetById, GetByIdLong
+    Get,
+    /// TryGetById, TryGetByIdLong - global reads that throw if the name is missing
+    TryGet,
+    /// PutById, PutByIdLong
+    Put,
+    /// TryPutById, TryPutByIdLong - global writes in strict mode
+    TryPut,
+    /// PutNewOwnById*, PutNewOwnNEById* - defining a property on a new object
+    PutNewOwn,
+    /// DelById, DelByIdLong
+    Delete,
+}
+
+impl PropertyAccessKind {
+    pub fn from_instruction_name(name: &str) -> Option<Self> {
+        match name.trim_end_matches("Long").trim_end_matches("Short") {
+            "GetById" => Some(PropertyAccessKind::Get),
+            "TryGetById" => Some(PropertyAccessKind::TryGet),
+            "PutById" => Some(PropertyAccessKind::Put),
+            "TryPutById" => Some(PropertyAccessKind::TryPut),
+            "PutNewOwnById" | "PutNewOwnNEById" => Some(PropertyAccessKind::PutNewOwn),
+            "DelById" => Some(PropertyAccessKind::Delete),
+            _ => None,
+        }
+    }
+
+    pub fn is_read(&self) -> bool {
+        matches!(self, PropertyAccessKind::Get | PropertyAccessKind::TryGet)
+    }
+
+    pub fn is_write(&self) -> bool {
+        matches!(
+            self,
+            PropertyAccessKind::Put | PropertyAccessKind::TryPut | PropertyAccessKind::PutNewOwn
+        )
+    }
+}
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone)]
+pub struct PropertyAccess {
+    pub function_id: u32,
+
+    /// Byte offset of the instruction within the function.
+    pub offset: u32,
+
+    pub kind: PropertyAccessKind,
+    pub string_id: u32,
+    pub name: String,
+}
+
+/*
+ * One object literal built by a function. `keys` are in the order they're
+ * defined.
+ */
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone)]
+pub struct ObjectLiteral {
+    pub function_id: u32,
+
+    /// Byte offset of the NewObject/NewObjectWithBuffer instruction.
+    pub offset: u32,
+
+    pub keys: Vec<String>,
+}
+
+/*
+ * Object literals grouped by their key set. `keys` is sorted so literals that
+ * define the same keys in a different order share a shape.
+ */
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone)]
+pub struct ObjectShape {
+    pub keys: Vec<String>,
+    pub literals: Vec<ObjectLiteral>,
+}
+
+impl ObjectShape {
+    /// Function IDs that build this shape, sorted and deduplicated.
+    pub fn functions(&self) -> Vec<u32> {
+        let mut functions: Vec<u32> = self.literals.iter().map(|l| l.function_id).collect();
+        functions.sort();
+        functions.dedup();
+        functions
+    }
+}
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, Default)]
+pub struct PropertyUsage {
+    pub reads: u32,
+    pub writes: u32,
+    pub deletes: u32,
+
+    /// Function IDs that access the property, sorted.
+    pub functions: Vec<u32>,
+}
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, Default)]
+pub struct PropertyInventory {
+    /// Property name -> how it's used, ordered by name.
+    pub properties: BTreeMap<String, PropertyUsage>,
+
+    /// Object literal shapes, most common first.
+    pub shapes: Vec<ObjectShape>,
+}
+
+impl PropertyInventory {
+    pub fn new(accesses: Vec<PropertyAccess>, literals: Vec<ObjectLiteral>) -> Self {
+        let mut properties: BTreeMap<String, PropertyUsage> = BTreeMap::new();
+        for access in accesses {
+            let usage = properties.entry(access.name).or_default();
+            if access.kind.is_read() {
+                usage.reads += 1;
+            } else if access.kind.is_write() {
+                usage.writes += 1;
+            } else {
+                usage.deletes += 1;
+            }
+            if let Err(pos) = usage.functions.binary_search(&access.function_id) {
+                usage.functions.insert(pos, access.function_id);
+            }
+        }
+
+        PropertyInventory {
+            properties,
+            shapes: group_object_literals(literals),
+        }
+    }
+
+    /*
+     * Returns the shapes that define every one of `keys` (and possibly more).
+     */
+    pub fn shapes_with_keys(&self, keys: &[&str]) -> Vec<&ObjectShape> {
+        self.shapes
+            .iter()
+            .filter(|shape| keys.iter().all(|k| shape.keys.iter().any(|sk| sk == k)))
+            .collect()
+    }
+}
+
+/*
+ * Groups object literals by key set. Literals without keys (`{}`) are dropped.
+ * Shapes are ordered by how many literals use them, then by keys.
+ */
+pub fn group_object_literals(literals: Vec<ObjectLiteral>) -> Vec<ObjectShape> {
+    let mut groups: BTreeMap<Vec<String>, Vec<ObjectLiteral>> = BTreeMap::new();
+    for literal in literals {
+        if literal.keys.is_empty() {
+            continue;
+        }
+        let mut keys = literal.keys.clone();
+        keys.sort();
+        keys.dedup();
+        groups.entry(keys).or_default().push(literal);
+    }
+
+    let mut shapes: Vec<ObjectShape> = groups
+        .into_iter()
+        .map(|(keys, literals)| ObjectShape { keys, literals })
+        .collect();
+    shapes.sort_by_key(|shape| std::cmp::Reverse(shape.literals.len()));
+    shapes
+}
+
+impl<R> HermesFile<R>
+where
+    R: io::Read + io::BufRead + io::Seek,
+{
+    /*
+     * Returns every GetById/TryGetById/PutById/TryPutById/PutNewOwnById/DelById
+     * in a function, in instruction order.
+     */
+    pub fn get_function_property_accesses(&mut self, idx: usize) -> Vec<PropertyAccess> {
+        let instructions = self.get_function_instructions(idx);
+
+        let mut accesses = vec![];
+        let mut offset = 0;
+        for ins in instructions.iter() {
+            if let Some(kind) = PropertyAccessKind::from_instruction_name(ins.name()) {
+                if let Some(string_id) = property_string_id(ins) {
+                    if let Some(name) = self.get_string_by_id(string_id) {
+                        accesses.push(PropertyAccess {
+                            function_id: idx as u32,
+                            offset,
+                            kind,
+                            string_id,
+                            name,
+                        });
+                    }
+                }
+            }
+            offset += ins.size() as u32;
+        }
+
+        accesses
+    }
+
+    /*
+     * Returns the object literals built in a function. Keys added with
+     * PutNewOwnById are attributed to the NewObject/NewObjectWithBuffer that
+     * created the object, as long as its register hasn't been overwritten.
+     */
+    pub fn get_function_object_literals(&mut self, idx: usize) -> Vec<ObjectLiteral> {
+        let instructions = self.get_function_instructions(idx);
+
+        // Register -> index into `literals` of the object it holds
+        let mut regs: HashMap<u32, usize> = HashMap::new();
+        let mut literals: Vec<ObjectLiteral> = vec![];
+        let mut offset = 0;
+
+        for ins in instructions.iter() {
+            let operands = ins.operands();
+            let reg = |name: &str| -> u32 {
+                operands
+                    .iter()
+                    .find(|o| o.name == name)
+                    .map(|o| o.value.as_u32())
+                    .unwrap_or(0)
+            };
+
+            match ins.name() {
+                "NewObject" | "NewObjectWithParent" => {
+                    regs.insert(reg("r0"), literals.len());
+                    literals.push(ObjectLiteral {
+                        function_id: idx as u32,
+                        offset,
+                        keys: vec![],
+                    });
+                }
+                "NewObjectWithBuffer" | "NewObjectWithBufferLong" => {
+                    let keys = self
+                        .get_object_key_buffer_values(reg("p2") as usize, reg("p1") as usize)
+                        .iter()
+                        .map(|k| self.object_key_to_string(k))
+                        .collect();
+                    regs.insert(reg("r0"), literals.len());
+                    literals.push(ObjectLiteral {
+                        function_id: idx as u32,
+                        offset,
+                        keys,
+                    });
+                }
+                name if PropertyAccessKind::from_instruction_name(name)
+                    == Some(PropertyAccessKind::PutNewOwn) =>
+                {
+                    // r0 is the object, not a destination
+                    let key = property_string_id(ins).and_then(|id| self.get_string_by_id(id));
+                    if let (Some(literal), Some(key)) = (regs.get(&reg("r0")), key) {
+                        literals[*literal].keys.push(key);
+                    }
+                }
+                "Mov" | "MovLong" => match regs.get(&reg("r1")).copied() {
+                    Some(literal) => {
+                        regs.insert(reg("r0"), literal);
+                    }
+                    None => {
+                        regs.remove(&reg("r0"));
+                    }
+                },
+                "PutById"
+                | "PutByIdLong"
+                | "TryPutById"
+                | "TryPutByIdLong"
+                | "DelById"
+                | "DelByIdLong"
+                | "PutOwnByIndex"
+                | "PutOwnByIndexL"
+                | "PutOwnByVal"
+                | "PutOwnGetterSetterByVal"
+                | "PutByVal" => {
+                    // These take the object in r0 and don't write a register
+                }
+                _ => {
+                    if let Some(op) = operands.first() {
+                        if op.name == "r0" {
+                            regs.remove(&op.value.as_u32());
+                        }
+                    }
+                }
+            }
+
+            offset += ins.size() as u32;
+        }
+
+        literals
+    }
+
+    /*
+     * Returns the property accesses and object shapes of every function in the
+     * bundle.
+     */
+    pub fn get_property_inventory(&mut self) -> PropertyInventory {
+        let mut accesses = vec![];
+        let mut literals = vec![];
+        for idx in 0..self.function_headers.len() {
+            accesses.extend(self.get_function_property_accesses(idx));
+            literals.extend(self.get_function_object_literals(idx));
+        }
+        PropertyInventory::new(accesses, literals)
+    }
+
+    /*
+     * Returns the property accesses and object shapes of a single function.
+     */
+    pub fn get_function_property_inventory(&mut self, idx: usize) -> PropertyInventory {
+        let accesses = self.get_function_property_accesses(idx);
+        let literals = self.get_function_object_literals(idx);
+        PropertyInventory::new(accesses, literals)
+    }
+
+    // Object keys are strings or (for `{0: x}`) integers
+    fn object_key_to_string(&self, key: &ArrayTypes) -> String {
+        let string = match key {
+            ArrayTypes::ShortStringValue { value } => Some(*value as u32),
+            ArrayTypes::LongStringValue { value } => Some(*value),
+            ArrayTypes::ByteStringValue { value } => Some(*value as u32),
+            _ => None,
+        };
+        if let Some(string) = string.and_then(|id| self.get_string_by_id(id)) {
+            return string;
+        }
+
+        match key {
+            ArrayTypes::IntegerValue { value } => format!("{}", *value as i32),
+            ArrayTypes::NumberValue { value } => format!("{}", f64::from_bits(*value)),
+            other => format!("{:?}", other),
+        }
+    }
+}
+
+// The string operand of a *ById instruction
+fn property_string_id(ins: &HermesInstruction) -> Option<u32> {
+    ins.operands()
+        .iter()
+        .find(|o| o.kind.is_string_id())
+        .map(|o| o.value.as_u32())
+}
@@ -0,0 +1,187 @@
+use std::collections::BTreeSet;
+use std::io;
+
+use crate::hermes::cjs_module::CJSModule;
+use crate::hermes::hermes_file::HermesFile;
+
+/*
+ * Unreferenced function detection.
+ *
+ * The only ways a function gets run are being the global function, being a
+ * CommonJS module in the CJS module table, or being referenced by an
+ * instruction - CreateClosure, CreateGeneratorClosure, CreateAsyncClosure and
+ * CreateGenerator create a closure for it, CallDirect calls it directly.
+ * Following those references from the entry points gives every function that
+ * can run; the rest is dead code.
+ */
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FunctionReferenceKind {
+    /// CreateClosure, CreateGeneratorClosure, CreateAsyncClosure, CreateGenerator
+    Closure,
+    /// CallDirect
+    DirectCall,
+}
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone)]
+pub struct FunctionReference {
+    /// The function containing the instruction.
+    pub from: u32,
+
+    /// Byte offset of the instruction within `from`.
+    pub offset: u32,
+
+    /// The referenced function.
+    pub to: u32,
+
+    pub kind: FunctionReferenceKind,
+}
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone)]
+pub struct UnreferencedFunction {
+    pub function_id: u32,
+    pub name: String,
+
+    /// Bytecode size in bytes.
+    pub byte_size: u32,
+
+    /// Other unreferenced functions that reference this one. Empty if nothing
+    /// in the file references it at all.
+    pub referenced_by: Vec<u32>,
+}
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, Default)]
+pub struct ReachabilityReport {
+    /// The global function and every function in the CJS module table.
+    pub entry_points: Vec<u32>,
+
+    /// Number of functions reachable from the entry points.
+    pub reachable_count: u32,
+
+    /// Functions that can't be reached, by function ID.
+    pub unreferenced: Vec<UnreferencedFunction>,
+}
+
+impl ReachabilityReport {
+    /// Total bytecode size of the unreferenced functions.
+    pub fn unreferenced_size(&self) -> u32 {
+        self.unreferenced.iter().map(|f| f.byte_size).sum()
+    }
+}
+
+impl<R> HermesFile<R>
+where
+    R: io::Read + io::BufRead + io::Seek,
+{
+    /*
+     * Returns the global function followed by the function of every CommonJS
+     * module in the CJS module table.
+     */
+    pub fn get_entry_functions(&self) -> Vec<u32> {
+        let mut entries = vec![self.header.global_code_index];
+        for module in self.cjs_modules.iter() {
+            let function_id = match module {
+                CJSModule::CJSModuleInt(m) => m.value,
+                CJSModule::CJSModuleEntry(m) => m.offset,
+            };
+            if !entries.contains(&function_id) {
+                entries.push(function_id);
+            }
+        }
+        entries
+            .into_iter()
+            .filter(|fid| (*fid as usize) < self.function_headers.len())
+            .collect()
+    }
+
+    /*
+     * Returns every reference a function makes to other functions, in
+     * instruction order.
+     */
+    pub fn get_function_references(&mut self, idx: usize) -> Vec<FunctionReference> {
+        let mut references = vec![];
+        let mut offset = 0;
+
+        for ins in self.get_function_instructions(idx).iter() {
+            if let Some(to) = ins.function_id() {
+                references.push(FunctionReference {
+                    from: idx as u32,
+                    offset,
+                    to,
+                    kind: if ins.name().starts_with("CallDirect") {
+                        FunctionReferenceKind::DirectCall
+                    } else {
+                        FunctionReferenceKind::Closure
+                    },
+                });
+            }
+            offset += ins.size() as u32;
+        }
+
+        references
+    }
+
+    /*
+     * Finds the functions that can't be reached from the entry points through
+     * closure creation or direct calls.
+     */
+    pub fn get_reachability_report(&mut self) -> ReachabilityReport {
+        let function_count = self.function_headers.len();
+        let references: Vec<Vec<FunctionReference>> = (0..function_count)
+            .map(|idx| self.get_function_references(idx))
+            .collect();
+
+        let entry_points = self.get_entry_functions();
+        let mut reachable = vec![false; function_count];
+        let mut queue = entry_points.clone();
+        for fid in queue.iter() {
+            reachable[*fid as usize] = true;
+        }
+        while let Some(fid) = queue.pop() {
+            for reference in references[fid as usize].iter() {
+                let to = reference.to as usize;
+                if to < function_count && !reachable[to] {
+                    reachable[to] = true;
+                    queue.push(reference.to);
+                }
+            }
+        }
+
+        // Dead functions referencing other dead functions
+        let mut referenced_by: Vec<BTreeSet<u32>> = vec![BTreeSet::new(); function_count];
+        for (fid, refs) in references.iter().enumerate() {
+            if reachable[fid] {
+                continue;
+            }
+            for reference in refs.iter() {
+                if (reference.to as usize) < function_count && reference.to as usize != fid {
+                    referenced_by[reference.to as usize].insert(fid as u32);
+                }
+            }
+        }
+
+        let unreferenced = (0..function_count)
+            .filter(|fid| !reachable[*fid])
+            .map(|fid| UnreferencedFunction {
+                function_id: fid as u32,
+                name: self.get_function_name(fid),
+                byte_size: self.function_headers[fid].byte_size(),
+                referenced_by: referenced_by[fid].iter().copied().collect(),
+            })
+            .collect();
+
+        ReachabilityReport {
+            entry_points,
+            reachable_count: reachable.iter().filter(|r| **r).count() as u32,
+            unreferenced,
+        }
+    }
+}
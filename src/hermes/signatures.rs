@@ -0,0 +1,484 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+
+use crate::hermes::fingerprint::fnv1a;
+use crate::hermes::hermes_file::HermesFile;
+
+/*
+ * Known-library signatures.
+ *
+ * A signature database is generated from a reference bundle whose Metro module
+ * paths are known - either a dev bundle (which passes the path to `__d`) or a
+ * release bundle plus a module id -> path mapping. Every function in each module
+ * is recorded by its fingerprint hash (see hermes::fingerprint), which survives
+ * renumbering of strings and functions between builds, along with a hash of the
+ * module as a whole.
+ *
+ * Matching functions in a target bundle are labelled with the library they
+ * came from. A Metro module is labelled when its module hash matches, or when
+ * most of its functions match the same library; every function in a labelled
+ * module is labelled too.
+ *
+ * The database is stored as tab-separated text:
+ *
+ *   # hermes_rs signatures
+ *   F <hash> <instruction count> <library> <module path> <function name>
+ *   M <hash> <function count> <library> <module path>
+ */
+
+/// Functions shorter than this (getters, `return undefined`, ...) look the same
+/// everywhere, so they're neither recorded nor matched on their own.
+pub const MIN_SIGNATURE_INSTRUCTIONS: u32 = 5;
+
+/// Fraction of a module's functions that must match one library for the whole
+/// module to be labelled.
+pub const MODULE_MATCH_RATIO: f64 = 0.75;
+
+/// Library name recorded for hashes that appear in more than one library.
+pub const AMBIGUOUS_LIBRARY: &str = "*";
+
+const HEADER: &str = "# hermes_rs signatures";
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionSignature {
+    pub hash: u64,
+    pub instruction_count: u32,
+    pub library: String,
+    pub module: String,
+    pub name: String,
+}
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModuleSignature {
+    pub hash: u64,
+    pub function_count: u32,
+    pub library: String,
+    pub module: String,
+}
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, Default)]
+pub struct SignatureDatabase {
+    /// Fingerprint hash -> function signature.
+    pub functions: BTreeMap<u64, FunctionSignature>,
+
+    /// Module hash -> module signature.
+    pub modules: BTreeMap<u64, ModuleSignature>,
+}
+
+impl SignatureDatabase {
+    /*
+     * Adds a function signature. A hash that's already recorded for another
+     * library is marked as ambiguous and won't be matched.
+     */
+    pub fn add_function(&mut self, signature: FunctionSignature) {
+        match self.functions.get_mut(&signature.hash) {
+            Some(existing) if existing.library != signature.library => {
+                existing.library = AMBIGUOUS_LIBRARY.to_string();
+            }
+            Some(_) => {}
+            None => {
+                self.functions.insert(signature.hash, signature);
+            }
+        }
+    }
+
+    pub fn add_module(&mut self, signature: ModuleSignature) {
+        match self.modules.get_mut(&signature.hash) {
+            Some(existing) if existing.library != signature.library => {
+                existing.library = AMBIGUOUS_LIBRARY.to_string();
+            }
+            Some(_) => {}
+            None => {
+                self.modules.insert(signature.hash, signature);
+            }
+        }
+    }
+
+    /*
+     * Adds every signature from another database - e.g. one generated from a
+     * different reference bundle.
+     */
+    pub fn merge(&mut self, other: SignatureDatabase) {
+        for (_, signature) in other.functions {
+            self.add_function(signature);
+        }
+        for (_, signature) in other.modules {
+            self.add_module(signature);
+        }
+    }
+
+    /// Returns the function signature for a hash, unless it's ambiguous.
+    pub fn get_function(&self, hash: u64) -> Option<&FunctionSignature> {
+        self.functions
+            .get(&hash)
+            .filter(|s| s.library != AMBIGUOUS_LIBRARY)
+    }
+
+    /// Returns the module signature for a hash, unless it's ambiguous.
+    pub fn get_module(&self, hash: u64) -> Option<&ModuleSignature> {
+        self.modules
+            .get(&hash)
+            .filter(|s| s.library != AMBIGUOUS_LIBRARY)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut out = format!("{}\n", HEADER);
+        for m in self.modules.values() {
+            out.push_str(&format!(
+                "M\t{:016x}\t{}\t{}\t{}\n",
+                m.hash,
+                m.function_count,
+                escape(&m.library),
+                escape(&m.module)
+            ));
+        }
+        for f in self.functions.values() {
+            out.push_str(&format!(
+                "F\t{:016x}\t{}\t{}\t{}\t{}\n",
+                f.hash,
+                f.instruction_count,
+                escape(&f.library),
+                escape(&f.module),
+                escape(&f.name)
+            ));
+        }
+        out
+    }
+
+    /*
+     * Parses a database written by `to_text`. Blank lines and lines starting
+     * with `#` are skipped; malformed lines are an error.
+     */
+    pub fn from_text(text: &str) -> Result<SignatureDatabase, String> {
+        let mut db = SignatureDatabase::default();
+        for (line_no, line) in text.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split('\t').collect();
+            let bad_line = || format!("Invalid signature on line {}: {}", line_no + 1, line);
+            let hash = fields
+                .get(1)
+                .and_then(|h| u64::from_str_radix(h, 16).ok())
+                .ok_or_else(bad_line)?;
+            let count = fields
+                .get(2)
+                .and_then(|c| c.parse::<u32>().ok())
+                .ok_or_else(bad_line)?;
+            match (fields[0], fields.len()) {
+                ("F", 6) => db.add_function(FunctionSignature {
+                    hash,
+                    instruction_count: count,
+                    library: unescape(fields[3]),
+                    module: unescape(fields[4]),
+                    name: unescape(fields[5]),
+                }),
+                ("M", 5) => db.add_module(ModuleSignature {
+                    hash,
+                    function_count: count,
+                    library: unescape(fields[3]),
+                    module: unescape(fields[4]),
+                }),
+                _ => return Err(bad_line()),
+            }
+        }
+        Ok(db)
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+fn unescape(s: &str) -> String {
+    let mut out = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('t') => out.push('\t'),
+                Some('n') => out.push('\n'),
+                Some(other) => out.push(other),
+                None => out.push('\\'),
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/*
+ * Library name for a module path - the package under the last `node_modules`
+ * (including its scope, e.g. `@babel/runtime`), or the path itself for modules
+ * outside `node_modules`.
+ */
+pub fn library_name(module_path: &str) -> String {
+    let path = module_path.replace('\\', "/");
+    let Some(pos) = path.rfind("node_modules/") else {
+        return path;
+    };
+    let mut parts = path[pos + "node_modules/".len()..].split('/');
+    match parts.next() {
+        Some(scope) if scope.starts_with('@') => match parts.next() {
+            Some(package) => format!("{}/{}", scope, package),
+            None => scope.to_string(),
+        },
+        Some(package) => package.to_string(),
+        None => path,
+    }
+}
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LabelKind {
+    /// The function's own fingerprint matched.
+    Function,
+    /// The function belongs to a module that was labelled as a whole.
+    Module,
+}
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone)]
+pub struct LibraryLabel {
+    pub library: String,
+
+    /// Module path in the reference bundle.
+    pub module: String,
+
+    pub kind: LabelKind,
+}
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, Default)]
+pub struct LibraryLabels {
+    /// Function ID -> label.
+    pub functions: BTreeMap<u32, LibraryLabel>,
+
+    /// Metro module id -> label.
+    pub modules: BTreeMap<u32, LibraryLabel>,
+}
+
+impl LibraryLabels {
+    pub fn is_library(&self, function_id: u32) -> bool {
+        self.functions.contains_key(&function_id)
+    }
+
+    pub fn get(&self, function_id: u32) -> Option<&LibraryLabel> {
+        self.functions.get(&function_id)
+    }
+
+    /// Library name -> number of labelled functions.
+    pub fn libraries(&self) -> BTreeMap<String, usize> {
+        let mut libraries = BTreeMap::new();
+        for label in self.functions.values() {
+            *libraries.entry(label.library.clone()).or_insert(0) += 1;
+        }
+        libraries
+    }
+}
+
+impl<R> HermesFile<R>
+where
+    R: io::Read + io::BufRead + io::Seek,
+{
+    /*
+     * Generates signatures for every Metro module with a known path. Paths
+     * come from `module_names` (module id -> path) and fall back to the
+     * verbose name passed to `__d` in dev bundles, for modules under
+     * `node_modules`; other modules are skipped.
+     */
+    pub fn generate_signatures(
+        &mut self,
+        module_names: &HashMap<u32, String>,
+    ) -> SignatureDatabase {
+        let mut db = SignatureDatabase::default();
+
+        for module in self.get_metro_modules() {
+            let path = match (module_names.get(&module.id), module.verbose_name.as_ref()) {
+                (Some(path), _) => path.clone(),
+                // Dev bundles name every module, first-party ones included -
+                // only the ones under node_modules are library code
+                (None, Some(path)) if path.replace('\\', "/").contains("node_modules/") => {
+                    path.clone()
+                }
+                _ => continue,
+            };
+            let library = library_name(&path);
+
+            let functions = self.get_metro_module_functions(module.factory_function);
+            let mut hashes = vec![];
+            for fid in functions.iter() {
+                let fp = self.get_function_fingerprint(*fid as usize);
+                hashes.push(fp.hash);
+                if fp.instruction_count >= MIN_SIGNATURE_INSTRUCTIONS {
+                    db.add_function(FunctionSignature {
+                        hash: fp.hash,
+                        instruction_count: fp.instruction_count,
+                        library: library.clone(),
+                        module: path.clone(),
+                        name: fp.name,
+                    });
+                }
+            }
+
+            db.add_module(ModuleSignature {
+                hash: module_hash(&hashes),
+                function_count: functions.len() as u32,
+                library,
+                module: path,
+            });
+        }
+
+        db
+    }
+
+    /*
+     * Labels the functions and Metro modules of this file that match a
+     * signature database.
+     */
+    pub fn identify_libraries(&mut self, db: &SignatureDatabase) -> LibraryLabels {
+        let mut labels = LibraryLabels::default();
+        let mut hashes: Vec<(u64, u32)> = vec![];
+
+        for fid in 0..self.function_headers.len() {
+            let fp = self.get_function_fingerprint(fid);
+            hashes.push((fp.hash, fp.instruction_count));
+            if fp.instruction_count < MIN_SIGNATURE_INSTRUCTIONS {
+                continue;
+            }
+            if let Some(signature) = db.get_function(fp.hash) {
+                labels.functions.insert(
+                    fid as u32,
+                    LibraryLabel {
+                        library: signature.library.clone(),
+                        module: signature.module.clone(),
+                        kind: LabelKind::Function,
+                    },
+                );
+            }
+        }
+
+        for module in self.get_metro_modules() {
+            let functions = self.get_metro_module_functions(module.factory_function);
+            let module_hashes: Vec<u64> = functions
+                .iter()
+                .map(|fid| hashes[*fid as usize].0)
+                .collect();
+
+            let label = match db.get_module(module_hash(&module_hashes)) {
+                Some(signature) => Some(LibraryLabel {
+                    library: signature.library.clone(),
+                    module: signature.module.clone(),
+                    kind: LabelKind::Module,
+                }),
+                None => {
+                    // Most of the module's own (non-trivial) functions come from one library
+                    let candidates: Vec<u32> = functions
+                        .iter()
+                        .copied()
+                        .filter(|fid| hashes[*fid as usize].1 >= MIN_SIGNATURE_INSTRUCTIONS)
+                        .collect();
+                    let mut votes: HashMap<&str, (usize, &str)> = HashMap::new();
+                    for fid in candidates.iter() {
+                        if let Some(label) = labels.functions.get(fid) {
+                            let vote = votes.entry(&label.library).or_insert((0, &label.module));
+                            vote.0 += 1;
+                        }
+                    }
+                    votes
+                        .into_iter()
+                        .max_by_key(|(library, (count, _))| (*count, std::cmp::Reverse(*library)))
+                        .filter(|(_, (count, _))| {
+                            *count as f64 >= candidates.len() as f64 * MODULE_MATCH_RATIO
+                        })
+                        .map(|(library, (_, module))| LibraryLabel {
+                            library: library.to_string(),
+                            module: module.to_string(),
+                            kind: LabelKind::Module,
+                        })
+                }
+            };
+
+            if let Some(label) = label {
+                // Functions that matched on their own keep their more specific label
+                for fid in functions.iter() {
+                    labels
+                        .functions
+                        .entry(*fid)
+                        .or_insert_with(|| label.clone());
+                }
+                labels.modules.insert(module.id, label);
+            }
+        }
+
+        labels
+    }
+
+    /*
+     * Returns the disassembly of every function that isn't labelled as library
+     * code. Each run of hidden functions is replaced with a one-line note.
+     */
+    pub fn get_disassembled_bytecode_without_libraries(
+        &mut self,
+        labels: &LibraryLabels,
+    ) -> String {
+        let mut out = vec![];
+        let mut hidden: Vec<u32> = vec![];
+
+        let flush = |hidden: &mut Vec<u32>, out: &mut Vec<String>| {
+            if let (Some(first), Some(last)) = (hidden.first(), hidden.last()) {
+                out.push(format!(
+                    "# {} library function(s) hidden (funcID {}..{})",
+                    hidden.len(),
+                    first,
+                    last
+                ));
+                hidden.clear();
+            }
+        };
+
+        for fid in 0..self.function_headers.len() as u32 {
+            if labels.is_library(fid) {
+                hidden.push(fid);
+                continue;
+            }
+            flush(&mut hidden, &mut out);
+            out.push(self.get_disassembled_bytecode_for_function(fid as usize));
+        }
+        flush(&mut hidden, &mut out);
+
+        out.join("\n")
+    }
+
+    // A module factory followed by every function nested inside it
+    fn get_metro_module_functions(&mut self, factory_function: u32) -> Vec<u32> {
+        let mut functions = vec![factory_function];
+        if (factory_function as usize) < self.function_headers.len() {
+            functions.extend(self.get_nested_functions(factory_function as usize));
+        }
+        functions
+            .into_iter()
+            .filter(|fid| (*fid as usize) < self.function_headers.len())
+            .collect()
+    }
+}
+
+fn module_hash(function_hashes: &[u64]) -> u64 {
+    let bytes: Vec<u8> = function_hashes
+        .iter()
+        .flat_map(|h| h.to_le_bytes())
+        .collect();
+    fnv1a(&bytes)
+}
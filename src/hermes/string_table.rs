@@ -1,7 +1,13 @@
+use std::collections::HashMap;
+use std::fmt;
 use std::io;
 
 use crate::hermes::decode::{decode_u32, read_bitfield};
 use crate::hermes::encode::{encode_u32, encode_u8, write_bitfield};
+use crate::hermes::jenkins::{update_jenkins_hash, JenkinsHash, JENKINS_HASH_INIT};
+use crate::hermes::string_kind::{
+    StringKind, StringKindEntry, StringKindEntryNew, StringKindEntryOld,
+};
 use crate::hermes::Serializable;
 
 #[cfg_attr(feature = "specta", derive(specta::Type))]
@@ -92,3 +98,358 @@ impl Serializable for OverflowStringTableEntry {
         encode_u32(w, self.length);
     }
 }
+
+/// A string exactly the way it's kept in string storage: one Latin-1 byte or
+/// one UTF-16 code unit per character. Unlike a `String`, this holds anything
+/// Hermes can, lone surrogates included, so writing it back out gives the same
+/// bytes.
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum HermesString {
+    Latin1(Vec<u8>),
+    Utf16(Vec<u16>),
+}
+
+impl HermesString {
+    /// Picks the storage the way hermesc does: one byte per character if the
+    /// string is all ASCII, UTF-16 otherwise.
+    pub fn encode(string: &str) -> Self {
+        if string.is_ascii() {
+            HermesString::Latin1(string.as_bytes().to_vec())
+        } else {
+            HermesString::Utf16(string.encode_utf16().collect())
+        }
+    }
+
+    pub fn is_utf_16(&self) -> bool {
+        matches!(self, HermesString::Utf16(_))
+    }
+
+    /// For display. Latin-1 maps straight onto the first 256 code points, lone
+    /// surrogates are replaced with U+FFFD.
+    pub fn to_string_lossy(&self) -> String {
+        match self {
+            HermesString::Latin1(bytes) => bytes.iter().map(|b| *b as char).collect(),
+            HermesString::Utf16(units) => String::from_utf16_lossy(units),
+        }
+    }
+
+    /// Length in characters, which is what the string table records.
+    pub fn len(&self) -> u32 {
+        match self {
+            HermesString::Latin1(bytes) => bytes.len() as u32,
+            HermesString::Utf16(units) => units.len() as u32,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The identifier hash Hermes keeps for it - over bytes or UTF-16 code units,
+    /// as stored.
+    pub fn identifier_hash(&self) -> JenkinsHash {
+        match self {
+            HermesString::Latin1(bytes) => bytes
+                .iter()
+                .fold(JENKINS_HASH_INIT, |hash, c| update_jenkins_hash(hash, *c)),
+            HermesString::Utf16(units) => units
+                .iter()
+                .fold(JENKINS_HASH_INIT, |hash, c| update_jenkins_hash(hash, *c)),
+        }
+    }
+}
+
+impl From<&str> for HermesString {
+    fn from(string: &str) -> Self {
+        HermesString::encode(string)
+    }
+}
+
+impl fmt::Display for HermesString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_string_lossy())
+    }
+}
+
+/*
+ * Lays the strings out in string storage, returning the storage and the byte
+ * offset of each string in it.
+ *
+ * Unpacked, every string is written in order. Packed, it works like hermesc:
+ * one-byte strings go first and UTF-16 strings after them (2-byte aligned),
+ * identical strings are only stored once and a string that is the suffix of
+ * another one points into it - "car" is the last three characters of "racecar".
+ */
+pub fn build_string_storage(strings: &[HermesString], pack: bool) -> (Vec<u8>, Vec<u32>) {
+    let mut storage: Vec<u8> = vec![];
+    let mut offsets: Vec<u32> = vec![0; strings.len()];
+
+    if !pack {
+        for (idx, string) in strings.iter().enumerate() {
+            offsets[idx] = storage.len() as u32;
+            match string {
+                HermesString::Latin1(bytes) => storage.extend(bytes),
+                HermesString::Utf16(units) => {
+                    storage.extend(units.iter().flat_map(|u| u.to_le_bytes()))
+                }
+            }
+        }
+        return (storage, offsets);
+    }
+
+    let mut latin1: Vec<(usize, &[u8])> = vec![];
+    let mut utf16: Vec<(usize, &[u16])> = vec![];
+    for (idx, string) in strings.iter().enumerate() {
+        match string {
+            HermesString::Latin1(bytes) => latin1.push((idx, bytes)),
+            HermesString::Utf16(units) => utf16.push((idx, units)),
+        }
+    }
+
+    let (packed, unit_offsets) = pack_suffixes(&latin1);
+    storage.extend(packed);
+    for (idx, offset) in unit_offsets {
+        offsets[idx] = offset as u32;
+    }
+
+    if !utf16.is_empty() {
+        if !storage.len().is_multiple_of(2) {
+            storage.push(0);
+        }
+        let base = storage.len();
+        let (packed, unit_offsets) = pack_suffixes(&utf16);
+        storage.extend(packed.iter().flat_map(|u| u.to_le_bytes()));
+        for (idx, offset) in unit_offsets {
+            offsets[idx] = (base + 2 * offset) as u32;
+        }
+    }
+
+    (storage, offsets)
+}
+
+/*
+ * The string table, overflow string table and string storage for the strings,
+ * in order. If a string is >= 255 characters long (or starts past what the 23
+ * bit offset can hold), it needs to be stored in the overflow string table: its
+ * string table entry gets the index of the overflow entry as the offset and
+ * 255 as the length, so that the runtime knows to look there.
+ */
+pub fn build_string_tables(
+    strings: &[HermesString],
+    pack: bool,
+) -> (
+    Vec<SmallStringTableEntry>,
+    Vec<OverflowStringTableEntry>,
+    Vec<u8>,
+) {
+    let (string_storage_bytes, offsets) = build_string_storage(strings, pack);
+
+    let mut string_storage: Vec<SmallStringTableEntry> = vec![];
+    let mut overflow_string_storage: Vec<OverflowStringTableEntry> = vec![];
+    for (string, offset) in strings.iter().zip(offsets) {
+        let is_utf_16 = string.is_utf_16();
+        let length = string.len();
+        if length >= 255 || offset >= 1 << 23 {
+            string_storage.push(SmallStringTableEntry {
+                is_utf_16,
+                offset: overflow_string_storage.len() as u32,
+                length: 255,
+            });
+            overflow_string_storage.push(OverflowStringTableEntry { offset, length });
+        } else {
+            string_storage.push(SmallStringTableEntry {
+                is_utf_16,
+                offset,
+                length,
+            });
+        }
+    }
+
+    (
+        string_storage,
+        overflow_string_storage,
+        string_storage_bytes,
+    )
+}
+
+// Sorting by the reversed contents puts every string right before the ones it
+// is a suffix of, so each string only has to be compared with the next one.
+// Returns the packed units and the (index, unit offset) of each string.
+fn pack_suffixes<T>(strings: &[(usize, &[T])]) -> (Vec<T>, Vec<(usize, usize)>)
+where
+    T: Ord + Copy,
+{
+    let mut order: Vec<usize> = (0..strings.len()).collect();
+    order.sort_by(|&a, &b| strings[a].1.iter().rev().cmp(strings[b].1.iter().rev()));
+
+    let mut packed: Vec<T> = vec![];
+    let mut offsets: Vec<usize> = vec![0; strings.len()];
+    for k in (0..order.len()).rev() {
+        let string = strings[order[k]].1;
+        offsets[order[k]] = match order.get(k + 1) {
+            Some(&next) if strings[next].1.ends_with(string) => {
+                offsets[next] + strings[next].1.len() - string.len()
+            }
+            _ => {
+                packed.extend_from_slice(string);
+                packed.len() - string.len()
+            }
+        };
+    }
+
+    let offsets = strings
+        .iter()
+        .zip(offsets)
+        .map(|((idx, _), offset)| (*idx, offset))
+        .collect();
+    (packed, offsets)
+}
+
+/*
+ * Builds the string-related sections of a file: string kinds, identifier
+ * hashes, the string and overflow string tables and string storage.
+ *
+ * Strings are interned - adding a string that's already there gives back its
+ * ID (unless it's added with `push`), and IDs are handed out in order and only
+ * change when strings are removed with `retain`. A string that is used as both a
+ * string and an identifier is kept as an identifier.
+ */
+#[derive(Debug, Clone)]
+pub struct StringTableBuilder {
+    strings: Vec<(HermesString, StringKind)>,
+    ids: HashMap<HermesString, u32>,
+
+    /// Share storage between identical strings and suffixes, see `build_string_storage`.
+    pub pack: bool,
+}
+
+impl Default for StringTableBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The output of `StringTableBuilder::build`.
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug)]
+pub struct StringTable {
+    pub string_kinds: Vec<StringKindEntry>,
+    pub identifier_hashes: Vec<u32>,
+    pub string_storage: Vec<SmallStringTableEntry>,
+    pub overflow_string_storage: Vec<OverflowStringTableEntry>,
+    pub string_storage_bytes: Vec<u8>,
+}
+
+impl StringTableBuilder {
+    pub fn new() -> Self {
+        StringTableBuilder {
+            strings: vec![],
+            ids: HashMap::new(),
+            pack: true,
+        }
+    }
+
+    /// Adds a string (if it isn't there yet) and returns its ID.
+    pub fn intern(&mut self, string: &str, kind: StringKind) -> u32 {
+        self.intern_hermes_string(HermesString::encode(string), kind)
+    }
+
+    /// Same as `intern`, keeping the string exactly as it is.
+    pub fn intern_hermes_string(&mut self, string: HermesString, kind: StringKind) -> u32 {
+        if let Some(&id) = self.ids.get(&string) {
+            let existing = &mut self.strings[id as usize].1;
+            if *existing == StringKind::String {
+                *existing = kind;
+            }
+            return id;
+        }
+
+        let id = self.strings.len() as u32;
+        self.ids.insert(string.clone(), id);
+        self.strings.push((string, kind));
+        id
+    }
+
+    /// Adds a string even if it's already there, for tables that keep
+    /// duplicates. `find` and `intern` keep giving the first copy's ID.
+    pub fn push(&mut self, string: &str, kind: StringKind) -> u32 {
+        let string = HermesString::encode(string);
+        let id = self.strings.len() as u32;
+        self.ids.entry(string.clone()).or_insert(id);
+        self.strings.push((string, kind));
+        id
+    }
+
+    pub fn get(&self, string: &str) -> Option<u32> {
+        self.find(&HermesString::encode(string))
+    }
+
+    pub fn find(&self, string: &HermesString) -> Option<u32> {
+        self.ids.get(string).copied()
+    }
+
+    /// Removes every string `keep` returns false for. The strings after a removed
+    /// one move up, so their IDs change.
+    pub fn retain<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(&HermesString, StringKind) -> bool,
+    {
+        self.strings.retain(|(string, kind)| keep(string, *kind));
+        self.ids = HashMap::new();
+        for (id, (string, _)) in self.strings.iter().enumerate() {
+            self.ids.entry(string.clone()).or_insert(id as u32);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+
+    /*
+     * Lays everything out. Every run of strings of the same kind gets its own
+     * string kind entry, and every identifier gets a hash. Predefined strings
+     * are identifiers after version 71, which only has room for those two kinds.
+     */
+    pub fn build(&self, version: u32) -> StringTable {
+        let mut string_kinds: Vec<StringKindEntry> = vec![];
+        let mut identifier_hashes: Vec<u32> = vec![];
+        for (string, kind) in self.strings.iter() {
+            let kind = match *kind {
+                StringKind::Predefined if version > 71 => StringKind::Identifier,
+                kind => kind,
+            };
+            if kind != StringKind::String {
+                identifier_hashes.push(string.identifier_hash());
+            }
+
+            match string_kinds.last_mut() {
+                Some(StringKindEntry::Old(run)) if run.kind == kind => run.count += 1,
+                Some(StringKindEntry::New(run)) if run.kind == kind => run.count += 1,
+                _ => string_kinds.push(if version <= 71 {
+                    StringKindEntry::Old(StringKindEntryOld { kind, count: 1 })
+                } else {
+                    StringKindEntry::New(StringKindEntryNew { kind, count: 1 })
+                }),
+            }
+        }
+
+        let stored: Vec<HermesString> = self.strings.iter().map(|(s, _)| s.clone()).collect();
+        let (string_storage, overflow_string_storage, string_storage_bytes) =
+            build_string_tables(&stored, self.pack);
+
+        StringTable {
+            string_kinds,
+            identifier_hashes,
+            string_storage,
+            overflow_string_storage,
+            string_storage_bytes,
+        }
+    }
+}
@@ -503,3 +503,233 @@ impl_from_for_usize!(
     BigIntIDUInt16,
     BigIntIDUInt32
 );
+
+/// The encoded type of an instruction operand. The variants mirror the operand
+/// types used in the BytecodeList.def files (and the wrapper types above), so the
+/// `build_instructions!` macro can tag every field with its kind.
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum OperandKind {
+    Reg8,
+    Reg32,
+    UInt8,
+    UInt16,
+    UInt32,
+    Addr8,
+    Addr32,
+    Imm32,
+    Double,
+    StringIDUInt8,
+    StringIDUInt16,
+    StringIDUInt32,
+    FunctionIDUInt8,
+    FunctionIDUInt16,
+    FunctionIDUInt32,
+    BigIntIDUInt16,
+    BigIntIDUInt32,
+}
+
+impl OperandKind {
+    pub fn is_register(&self) -> bool {
+        matches!(self, OperandKind::Reg8 | OperandKind::Reg32)
+    }
+
+    pub fn is_address(&self) -> bool {
+        matches!(self, OperandKind::Addr8 | OperandKind::Addr32)
+    }
+
+    pub fn is_string_id(&self) -> bool {
+        matches!(
+            self,
+            OperandKind::StringIDUInt8 | OperandKind::StringIDUInt16 | OperandKind::StringIDUInt32
+        )
+    }
+
+    pub fn is_function_id(&self) -> bool {
+        matches!(
+            self,
+            OperandKind::FunctionIDUInt8
+                | OperandKind::FunctionIDUInt16
+                | OperandKind::FunctionIDUInt32
+        )
+    }
+
+    pub fn is_big_int_id(&self) -> bool {
+        matches!(
+            self,
+            OperandKind::BigIntIDUInt16 | OperandKind::BigIntIDUInt32
+        )
+    }
+
+    /// Size of the operand when encoded, in bytes.
+    pub fn size(&self) -> usize {
+        match self {
+            OperandKind::Reg8
+            | OperandKind::UInt8
+            | OperandKind::Addr8
+            | OperandKind::StringIDUInt8
+            | OperandKind::FunctionIDUInt8 => 1,
+            OperandKind::UInt16
+            | OperandKind::StringIDUInt16
+            | OperandKind::FunctionIDUInt16
+            | OperandKind::BigIntIDUInt16 => 2,
+            OperandKind::Reg32
+            | OperandKind::UInt32
+            | OperandKind::Addr32
+            | OperandKind::Imm32
+            | OperandKind::StringIDUInt32
+            | OperandKind::FunctionIDUInt32
+            | OperandKind::BigIntIDUInt32 => 4,
+            OperandKind::Double => 8,
+        }
+    }
+
+    /// Whether `value` can be encoded as this kind without being truncated.
+    /// Addresses and Imm32 are signed, everything else is unsigned.
+    pub fn fits(&self, value: OperandValue) -> bool {
+        match (self, value) {
+            (OperandKind::Double, _) => true,
+            (_, OperandValue::Double(_)) => false,
+            (OperandKind::Addr8, OperandValue::Int(v)) => i8::try_from(v).is_ok(),
+            (OperandKind::Addr32 | OperandKind::Imm32, OperandValue::Int(v)) => {
+                i32::try_from(v).is_ok()
+            }
+            (kind, OperandValue::Int(v)) => v >= 0 && (v as u64) >> (kind.size() * 8) == 0,
+        }
+    }
+
+    /// Whether both kinds hold the same sort of value and only differ in width,
+    /// like the operands of `GetByIdShort` and `GetByIdLong`.
+    pub fn same_family(&self, other: &OperandKind) -> bool {
+        let family = |kind: &OperandKind| match kind {
+            OperandKind::Reg8 | OperandKind::Reg32 => 0,
+            OperandKind::UInt8 | OperandKind::UInt16 | OperandKind::UInt32 => 1,
+            OperandKind::Addr8 | OperandKind::Addr32 => 2,
+            OperandKind::Imm32 => 3,
+            OperandKind::Double => 4,
+            OperandKind::StringIDUInt8
+            | OperandKind::StringIDUInt16
+            | OperandKind::StringIDUInt32 => 5,
+            OperandKind::FunctionIDUInt8
+            | OperandKind::FunctionIDUInt16
+            | OperandKind::FunctionIDUInt32 => 6,
+            OperandKind::BigIntIDUInt16 | OperandKind::BigIntIDUInt32 => 7,
+        };
+        family(self) == family(other)
+    }
+}
+
+/// The decoded value of an operand. Everything except `Double` fits in an i64.
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum OperandValue {
+    Int(i64),
+    Double(f64),
+}
+
+impl OperandValue {
+    pub fn as_i64(&self) -> i64 {
+        match self {
+            OperandValue::Int(v) => *v,
+            OperandValue::Double(v) => *v as i64,
+        }
+    }
+
+    pub fn as_u32(&self) -> u32 {
+        self.as_i64() as u32
+    }
+
+    pub fn as_f64(&self) -> f64 {
+        match self {
+            OperandValue::Int(v) => *v as f64,
+            OperandValue::Double(v) => *v,
+        }
+    }
+}
+
+impl std::fmt::Display for OperandValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OperandValue::Int(v) => write!(f, "{}", v),
+            OperandValue::Double(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+macro_rules! impl_operand_value {
+  ($($t:ty),*) => {
+      $(
+          impl From<$t> for OperandValue {
+              fn from(value: $t) -> Self {
+                  OperandValue::Int(value.0 as i64)
+              }
+          }
+      )*
+  };
+}
+
+impl_operand_value!(
+    Reg8,
+    Reg32,
+    UInt8,
+    UInt16,
+    UInt32,
+    Addr8,
+    Addr32,
+    Imm32,
+    StringIDUInt8,
+    StringIDUInt16,
+    StringIDUInt32,
+    FunctionIDUInt8,
+    FunctionIDUInt16,
+    FunctionIDUInt32,
+    BigIntIDUInt16,
+    BigIntIDUInt32
+);
+
+impl From<Double> for OperandValue {
+    fn from(value: Double) -> Self {
+        OperandValue::Double(value.0)
+    }
+}
+
+/// A single operand of a decoded instruction - the field name generated by the
+/// instruction macros (`r0`, `p1`, ...), its encoded kind, and its value.
+/// This gives version-independent code a way to look at any instruction
+/// without matching on every version's `Instruction` enum.
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Copy, Clone)]
+pub struct Operand {
+    pub name: &'static str,
+    pub kind: OperandKind,
+    pub value: OperandValue,
+}
+
+/// Converts an `OperandValue` back into the raw type an operand is encoded as.
+/// Used by `InstructionParser::set_operand`.
+pub trait FromOperandValue {
+    fn from_operand_value(value: OperandValue) -> Self;
+}
+
+macro_rules! impl_from_operand_value {
+  ($($t:ty),*) => {
+      $(
+          impl FromOperandValue for $t {
+              fn from_operand_value(value: OperandValue) -> Self {
+                  value.as_i64() as $t
+              }
+          }
+      )*
+  };
+}
+
+impl_from_operand_value!(u8, u16, u32, i8, i32);
+
+impl FromOperandValue for f64 {
+    fn from_operand_value(value: OperandValue) -> Self {
+        value.as_f64()
+    }
+}
@@ -1,5 +1,8 @@
 use crate::hermes;
 
+pub mod builtins;
+pub use builtins::builtin_name;
+
 build_instructions!(
   (0, NewObjectWithBuffer, r0: Reg8, p0: UInt16, p1: UInt16, p2: UInt16, p3: UInt16),
   (1, NewObjectWithBufferLong, r0: Reg8, p0: UInt16, p1: UInt16, p2: UInt32, p3: UInt32),
@@ -1,5 +1,8 @@
 use crate::hermes;
 
+pub mod builtins;
+pub use builtins::builtin_name;
+
 build_instructions!(
   (0, Unreachable, ),
   (1, NewObjectWithBuffer, r0: Reg8, p0: UInt16, p1: UInt16, p2: UInt16, p3: UInt16),
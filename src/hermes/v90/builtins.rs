@@ -0,0 +1,60 @@
+/// Builtin functions by builtin number, from Hermes' Builtins.def for bytecode version 90.
+pub const BUILTINS: &[&str] = &[
+    "Array.isArray",
+    "Date.UTC",
+    "Date.parse",
+    "JSON.parse",
+    "JSON.stringify",
+    "Math.abs",
+    "Math.acos",
+    "Math.asin",
+    "Math.atan",
+    "Math.atan2",
+    "Math.ceil",
+    "Math.cos",
+    "Math.exp",
+    "Math.floor",
+    "Math.hypot",
+    "Math.imul",
+    "Math.log",
+    "Math.max",
+    "Math.min",
+    "Math.pow",
+    "Math.round",
+    "Math.sin",
+    "Math.sqrt",
+    "Math.tan",
+    "Math.trunc",
+    "Object.create",
+    "Object.defineProperties",
+    "Object.defineProperty",
+    "Object.freeze",
+    "Object.getOwnPropertyDescriptor",
+    "Object.getOwnPropertyNames",
+    "Object.getPrototypeOf",
+    "Object.isExtensible",
+    "Object.isFrozen",
+    "Object.keys",
+    "Object.seal",
+    "String.fromCharCode",
+    "HermesBuiltin.silentSetPrototypeOf",
+    "HermesBuiltin.requireFast",
+    "HermesBuiltin.getTemplateObject",
+    "HermesBuiltin.ensureObject",
+    "HermesBuiltin.getMethod",
+    "HermesBuiltin.throwTypeError",
+    "HermesBuiltin.generatorSetDelegated",
+    "HermesBuiltin.copyDataProperties",
+    "HermesBuiltin.copyRestArgs",
+    "HermesBuiltin.arraySpread",
+    "HermesBuiltin.apply",
+    "HermesBuiltin.exportAll",
+    "HermesBuiltin.exponentiationOperator",
+    "HermesBuiltin.initRegexNamedGroups",
+    "HermesBuiltin.getOriginalNativeErrorConstructor",
+    "spawnAsync",
+];
+
+pub fn builtin_name(idx: u32) -> Option<&'static str> {
+    BUILTINS.get(idx as usize).copied()
+}
@@ -0,0 +1,642 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::io;
+
+use crate::hermes::hermes_file::HermesFile;
+use crate::hermes::HermesInstruction;
+
+/*
+ * Function-level bytecode verifier.
+ *
+ * Checks each function's instructions against what the Hermes VM assumes
+ * without checking at runtime, so a patched bundle can be checked before it's
+ * shipped:
+ *
+ * - register operands are below the function's frame size
+ * - string, function, BigInt and RegExp IDs are in range
+ * - jump and switch targets land on an instruction boundary inside the function
+ * - exception handler ranges are ordered and on instruction boundaries
+ * - environment slots fit the environment they're used on
+ * - property cache indices don't exceed the highest read/write cache index
+ *
+ * Environments are tracked through registers: CreateEnvironment gives the
+ * function's own environment (`env_size` slots), CreateInnerEnvironment one of
+ * the given size, and GetEnvironment N the environment N + 1 levels up the
+ * chain of functions creating each other's closures - which is only checked when
+ * that chain is unambiguous.
+ */
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Likely to crash the VM or read garbage.
+    Error,
+    /// Valid bytecode, but not something the Hermes compiler emits.
+    Warning,
+}
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiagnosticKind {
+    RegisterOutOfRange {
+        register: u32,
+        frame_size: u32,
+    },
+    StringIdOutOfRange {
+        id: u32,
+        count: u32,
+    },
+    FunctionIdOutOfRange {
+        id: u32,
+        count: u32,
+    },
+    BigIntIdOutOfRange {
+        id: u32,
+        count: u32,
+    },
+    RegExpIdOutOfRange {
+        id: u32,
+        count: u32,
+    },
+    /// A jump, switch case or handler target outside the function's code.
+    TargetOutOfBounds {
+        target: i64,
+        code_size: u32,
+    },
+    /// A jump, switch case or handler target inside another instruction.
+    TargetNotOnBoundary {
+        target: i64,
+    },
+    /// An exception handler whose try range is empty or reversed.
+    InvalidHandlerRange {
+        start: u32,
+        end: u32,
+    },
+    EnvironmentSlotOutOfRange {
+        slot: u32,
+        env_size: u32,
+    },
+    ReadCacheIndexOutOfRange {
+        index: u32,
+        highest: u32,
+    },
+    WriteCacheIndexOutOfRange {
+        index: u32,
+        highest: u32,
+    },
+    /// The instructions don't add up to the function's byte size.
+    SizeMismatch {
+        decoded: u32,
+        byte_size: u32,
+    },
+    /// The last instruction can fall through past the end of the function.
+    FallsOffEnd,
+}
+
+impl fmt::Display for DiagnosticKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiagnosticKind::RegisterOutOfRange {
+                register,
+                frame_size,
+            } => write!(
+                f,
+                "register r{} is outside the frame ({} registers)",
+                register, frame_size
+            ),
+            DiagnosticKind::StringIdOutOfRange { id, count } => {
+                write!(f, "string ID {} out of range ({} strings)", id, count)
+            }
+            DiagnosticKind::FunctionIdOutOfRange { id, count } => {
+                write!(f, "function ID {} out of range ({} functions)", id, count)
+            }
+            DiagnosticKind::BigIntIdOutOfRange { id, count } => {
+                write!(f, "BigInt ID {} out of range ({} BigInts)", id, count)
+            }
+            DiagnosticKind::RegExpIdOutOfRange { id, count } => {
+                write!(f, "RegExp ID {} out of range ({} regexps)", id, count)
+            }
+            DiagnosticKind::TargetOutOfBounds { target, code_size } => write!(
+                f,
+                "target {} is outside the function ({} bytes of code)",
+                target, code_size
+            ),
+            DiagnosticKind::TargetNotOnBoundary { target } => {
+                write!(f, "target {} is not on an instruction boundary", target)
+            }
+            DiagnosticKind::InvalidHandlerRange { start, end } => {
+                write!(
+                    f,
+                    "exception handler range {}..{} is empty or reversed",
+                    start, end
+                )
+            }
+            DiagnosticKind::EnvironmentSlotOutOfRange { slot, env_size } => write!(
+                f,
+                "environment slot {} out of range ({} slots)",
+                slot, env_size
+            ),
+            DiagnosticKind::ReadCacheIndexOutOfRange { index, highest } => write!(
+                f,
+                "read cache index {} above highest_read_cache_index {}",
+                index, highest
+            ),
+            DiagnosticKind::WriteCacheIndexOutOfRange { index, highest } => write!(
+                f,
+                "write cache index {} above highest_write_cache_index {}",
+                index, highest
+            ),
+            DiagnosticKind::SizeMismatch { decoded, byte_size } => write!(
+                f,
+                "instructions take {} bytes but byte_size is {}",
+                decoded, byte_size
+            ),
+            DiagnosticKind::FallsOffEnd => {
+                write!(
+                    f,
+                    "the last instruction falls through past the end of the function"
+                )
+            }
+        }
+    }
+}
+
+impl DiagnosticKind {
+    pub fn severity(&self) -> Severity {
+        match self {
+            DiagnosticKind::FallsOffEnd => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub function_id: u32,
+
+    /// Byte offset of the offending instruction, if there is one.
+    pub offset: Option<u32>,
+
+    /// The offending instruction, if there is one.
+    pub instruction: Option<String>,
+
+    pub severity: Severity,
+    pub kind: DiagnosticKind,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        match (self.offset, &self.instruction) {
+            (Some(offset), Some(ins)) => write!(
+                f,
+                "{}: function #{} @ {} ({}): {}",
+                severity, self.function_id, offset, ins, self.kind
+            ),
+            (Some(offset), None) => write!(
+                f,
+                "{}: function #{} @ {}: {}",
+                severity, self.function_id, offset, self.kind
+            ),
+            _ => write!(
+                f,
+                "{}: function #{}: {}",
+                severity, self.function_id, self.kind
+            ),
+        }
+    }
+}
+
+// What's known about the environment a register holds
+#[derive(Clone, Copy)]
+enum EnvRegister {
+    // Slot count
+    Sized(u32),
+    Unknown,
+}
+
+// `display` resolves strings and would panic on the bad IDs being reported
+fn raw_display(ins: &HermesInstruction) -> String {
+    let operands: Vec<String> = ins
+        .operands()
+        .iter()
+        .map(|op| {
+            if op.kind.is_register() {
+                format!("r{}", op.value.as_u32())
+            } else {
+                format!("{}", op.value.as_i64())
+            }
+        })
+        .collect();
+    format!("{} {}", ins.name(), operands.join(", "))
+        .trim_end()
+        .to_string()
+}
+
+impl<R> HermesFile<R>
+where
+    R: io::Read + io::BufRead + io::Seek,
+{
+    /*
+     * Verifies every function in the file.
+     */
+    pub fn verify(&mut self) -> Vec<Diagnostic> {
+        let parents = self.get_closure_parents();
+        let mut diagnostics = vec![];
+        for idx in 0..self.function_headers.len() {
+            diagnostics.extend(self.verify_function_with_parents(idx, &parents));
+        }
+        diagnostics
+    }
+
+    /*
+     * Verifies a single function.
+     */
+    pub fn verify_function(&mut self, idx: usize) -> Vec<Diagnostic> {
+        let parents = self.get_closure_parents();
+        self.verify_function_with_parents(idx, &parents)
+    }
+
+    // Function ID -> the one function that creates closures of it. Functions
+    // created from several places (or none) are left out, as are functions
+    // created by one using inner environments, since which of its environments
+    // they capture isn't known.
+    fn get_closure_parents(&mut self) -> HashMap<u32, u32> {
+        let mut parents: HashMap<u32, Option<u32>> = HashMap::new();
+        let mut inner_envs = HashSet::new();
+        for idx in 0..self.function_headers.len() {
+            for ins in self.get_function_instructions(idx).iter() {
+                if ins.name() == "CreateInnerEnvironment" {
+                    inner_envs.insert(idx as u32);
+                }
+                if ins.name().starts_with("CallDirect") {
+                    continue;
+                }
+                if let Some(fid) = ins.function_id() {
+                    parents
+                        .entry(fid)
+                        .and_modify(|p| {
+                            if *p != Some(idx as u32) {
+                                *p = None
+                            }
+                        })
+                        .or_insert(Some(idx as u32));
+                }
+            }
+        }
+        parents
+            .into_iter()
+            .filter_map(|(fid, parent)| parent.map(|p| (fid, p)))
+            .filter(|(_, parent)| !inner_envs.contains(parent))
+            .collect()
+    }
+
+    fn verify_function_with_parents(
+        &mut self,
+        idx: usize,
+        parents: &HashMap<u32, u32>,
+    ) -> Vec<Diagnostic> {
+        let fh = self.function_headers[idx].clone();
+        let instructions = self.get_function_instructions(idx);
+        let bytes = self.get_function_bytes(idx);
+
+        let mut diagnostics = vec![];
+        let mut report =
+            |offset: Option<u32>, ins: Option<&HermesInstruction>, kind: DiagnosticKind| {
+                diagnostics.push(Diagnostic {
+                    function_id: idx as u32,
+                    offset,
+                    instruction: ins.map(raw_display),
+                    severity: kind.severity(),
+                    kind,
+                });
+            };
+
+        // Instruction offsets, and where the code ends - switch tables are
+        // stored after the last instruction and would decode as garbage
+        let mut offsets = vec![];
+        let mut offset = 0u32;
+        let mut tables: Vec<(usize, u32, i64, i64)> = vec![];
+        let mut code_end = fh.byte_size();
+        for (i, ins) in instructions.iter().enumerate() {
+            if offset >= code_end {
+                break;
+            }
+            offsets.push(offset);
+            if ins.name() == "SwitchImm" {
+                let operand = |name: &str| {
+                    ins.operands()
+                        .iter()
+                        .find(|o| o.name == name)
+                        .map(|o| o.value.as_i64())
+                        .unwrap_or(0)
+                };
+                let table_abs = (fh.offset() as i64 + offset as i64 + operand("p0") + 3) & !3;
+                let table = (table_abs - fh.offset() as i64) as u32;
+                if table > offset && table < code_end {
+                    code_end = table;
+                }
+                tables.push((i, table, operand("p2"), operand("p3")));
+            }
+            offset += ins.size() as u32;
+        }
+        let instructions = &instructions[..offsets.len()];
+        let boundaries: BTreeSet<u32> = offsets.iter().copied().collect();
+
+        if tables.is_empty() && offset != fh.byte_size() {
+            report(
+                None,
+                None,
+                DiagnosticKind::SizeMismatch {
+                    decoded: offset,
+                    byte_size: fh.byte_size(),
+                },
+            );
+        }
+
+        let check_target = |target: i64| -> Option<DiagnosticKind> {
+            if target < 0 || target >= code_end as i64 {
+                Some(DiagnosticKind::TargetOutOfBounds {
+                    target,
+                    code_size: code_end,
+                })
+            } else if !boundaries.contains(&(target as u32)) {
+                Some(DiagnosticKind::TargetNotOnBoundary { target })
+            } else {
+                None
+            }
+        };
+
+        let string_count = self.string_storage.len() as u32;
+        let function_count = self.function_headers.len() as u32;
+        let bigint_count = self.big_int_table.len() as u32;
+        let regexp_count = self.reg_exp_table.len() as u32;
+
+        // Environment sizes of the functions above this one
+        let mut ancestors: Vec<Option<u32>> = vec![];
+        let mut current = idx as u32;
+        while let Some(parent) = parents.get(&current) {
+            if ancestors.len() > function_count as usize {
+                break;
+            }
+            ancestors.push(
+                self.function_headers
+                    .get(*parent as usize)
+                    .map(|p| p.env_size()),
+            );
+            current = *parent;
+        }
+        let mut envs: HashMap<u32, EnvRegister> = HashMap::new();
+
+        for (ins, offset) in instructions.iter().zip(offsets.iter()) {
+            let offset = *offset;
+            let operands = ins.operands();
+            let operand = |name: &str| {
+                operands
+                    .iter()
+                    .find(|o| o.name == name)
+                    .map(|o| o.value.as_u32())
+            };
+
+            for op in operands.iter() {
+                let value = op.value.as_u32();
+                let kind = if op.kind.is_register() && value >= fh.frame_size() {
+                    Some(DiagnosticKind::RegisterOutOfRange {
+                        register: value,
+                        frame_size: fh.frame_size(),
+                    })
+                } else if op.kind.is_string_id() && value >= string_count {
+                    Some(DiagnosticKind::StringIdOutOfRange {
+                        id: value,
+                        count: string_count,
+                    })
+                } else if op.kind.is_big_int_id() && value >= bigint_count {
+                    Some(DiagnosticKind::BigIntIdOutOfRange {
+                        id: value,
+                        count: bigint_count,
+                    })
+                } else if op.kind.is_address() {
+                    check_target(offset as i64 + op.value.as_i64())
+                } else {
+                    None
+                };
+                if let Some(kind) = kind {
+                    report(Some(offset), Some(ins), kind);
+                }
+            }
+
+            if let Some(fid) = ins.function_id() {
+                if fid >= function_count {
+                    report(
+                        Some(offset),
+                        Some(ins),
+                        DiagnosticKind::FunctionIdOutOfRange {
+                            id: fid,
+                            count: function_count,
+                        },
+                    );
+                }
+            }
+
+            let name = ins.name();
+            match name {
+                "CreateRegExp" => {
+                    let id = operand("p2").unwrap_or(0);
+                    if id >= regexp_count {
+                        report(
+                            Some(offset),
+                            Some(ins),
+                            DiagnosticKind::RegExpIdOutOfRange {
+                                id,
+                                count: regexp_count,
+                            },
+                        );
+                    }
+                }
+                "GetByIdShort" | "GetById" | "GetByIdLong" | "TryGetById" | "TryGetByIdLong" => {
+                    let index = operand("p0").unwrap_or(0);
+                    if index > fh.highest_read_cache_index() {
+                        report(
+                            Some(offset),
+                            Some(ins),
+                            DiagnosticKind::ReadCacheIndexOutOfRange {
+                                index,
+                                highest: fh.highest_read_cache_index(),
+                            },
+                        );
+                    }
+                }
+                "PutById" | "PutByIdLong" | "TryPutById" | "TryPutByIdLong" => {
+                    let index = operand("p0").unwrap_or(0);
+                    if index > fh.highest_write_cache_index() {
+                        report(
+                            Some(offset),
+                            Some(ins),
+                            DiagnosticKind::WriteCacheIndexOutOfRange {
+                                index,
+                                highest: fh.highest_write_cache_index(),
+                            },
+                        );
+                    }
+                }
+                _ => {}
+            }
+
+            // Environment slots - the environment register and slot operand
+            let access = match name {
+                "LoadFromEnvironment" | "LoadFromEnvironmentL" => {
+                    Some((operand("r1"), operand("p0")))
+                }
+                "StoreToEnvironment"
+                | "StoreToEnvironmentL"
+                | "StoreNPToEnvironment"
+                | "StoreNPToEnvironmentL" => Some((operand("r0"), operand("p0"))),
+                _ => None,
+            };
+            if let Some((Some(env), Some(slot))) = access {
+                if let Some(EnvRegister::Sized(env_size)) = envs.get(&env) {
+                    if slot >= *env_size {
+                        report(
+                            Some(offset),
+                            Some(ins),
+                            DiagnosticKind::EnvironmentSlotOutOfRange {
+                                slot,
+                                env_size: *env_size,
+                            },
+                        );
+                    }
+                }
+            }
+
+            // Track which registers hold which environment
+            let dest = operands
+                .first()
+                .filter(|o| o.name == "r0")
+                .map(|o| o.value.as_u32());
+            match (name, dest) {
+                ("CreateEnvironment", Some(r0)) => {
+                    envs.insert(r0, EnvRegister::Sized(fh.env_size()));
+                }
+                ("CreateInnerEnvironment", Some(r0)) => {
+                    envs.insert(r0, EnvRegister::Sized(operand("p0").unwrap_or(0)));
+                }
+                ("GetEnvironment", Some(r0)) => {
+                    let level = operand("p0").unwrap_or(0) as usize;
+                    let env = match ancestors.get(level) {
+                        Some(Some(env_size)) => EnvRegister::Sized(*env_size),
+                        _ => EnvRegister::Unknown,
+                    };
+                    envs.insert(r0, env);
+                }
+                ("Mov" | "MovLong", Some(r0)) => {
+                    let env = operand("r1").and_then(|r1| envs.get(&r1).copied());
+                    match env {
+                        Some(env) => envs.insert(r0, env),
+                        None => envs.remove(&r0),
+                    };
+                }
+                // These take an environment or object in r0 and don't write it
+                (
+                    "StoreToEnvironment"
+                    | "StoreToEnvironmentL"
+                    | "StoreNPToEnvironment"
+                    | "StoreNPToEnvironmentL"
+                    | "PutById"
+                    | "PutByIdLong"
+                    | "TryPutById"
+                    | "TryPutByIdLong"
+                    | "PutByVal"
+                    | "PutOwnByVal"
+                    | "PutOwnByIndex"
+                    | "PutOwnByIndexL"
+                    | "PutNewOwnById"
+                    | "PutNewOwnByIdLong"
+                    | "PutNewOwnByIdShort"
+                    | "PutNewOwnNEById"
+                    | "PutNewOwnNEByIdLong",
+                    _,
+                ) => {}
+                (_, Some(r0)) => {
+                    envs.remove(&r0);
+                }
+                _ => {}
+            }
+        }
+
+        // Switch tables
+        for (i, table, min, max) in tables.iter() {
+            let switch_offset = offsets[*i];
+            if max < min {
+                continue;
+            }
+            for case in 0..=(max - min) as u32 {
+                let start = (*table + case * 4) as usize;
+                let Some(entry) = bytes.get(start..start + 4) else {
+                    report(
+                        Some(switch_offset),
+                        Some(&instructions[*i]),
+                        DiagnosticKind::TargetOutOfBounds {
+                            target: start as i64,
+                            code_size: code_end,
+                        },
+                    );
+                    break;
+                };
+                let rel = i32::from_le_bytes(entry.try_into().unwrap());
+                if let Some(kind) = check_target(switch_offset as i64 + rel as i64) {
+                    report(Some(switch_offset), Some(&instructions[*i]), kind);
+                }
+            }
+        }
+
+        // Exception handlers - `end` is exclusive, so it may also be the end of the code
+        if fh.flags().has_exception_handler {
+            for handler in fh.exception_handlers().iter() {
+                if handler.start >= handler.end {
+                    report(
+                        None,
+                        None,
+                        DiagnosticKind::InvalidHandlerRange {
+                            start: handler.start,
+                            end: handler.end,
+                        },
+                    );
+                }
+                for target in [handler.start, handler.target] {
+                    if let Some(kind) = check_target(target as i64) {
+                        report(Some(target), None, kind);
+                    }
+                }
+                if handler.end != code_end {
+                    if let Some(kind) = check_target(handler.end as i64) {
+                        report(Some(handler.end), None, kind);
+                    }
+                }
+            }
+        }
+
+        if let Some(last) = instructions.last() {
+            let name = last.name();
+            let terminates = matches!(
+                name,
+                "Ret" | "Throw" | "Unreachable" | "Jmp" | "JmpLong" | "SwitchImm"
+            ) || name.starts_with("CompleteGenerator");
+            if !terminates {
+                report(
+                    offsets.last().copied(),
+                    Some(last),
+                    DiagnosticKind::FallsOffEnd,
+                );
+            }
+        }
+
+        diagnostics
+    }
+}
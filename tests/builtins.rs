@@ -0,0 +1,117 @@
+/*
+ * Builtin names, spot-checked against Hermes' Builtins.def for each version.
+ * Builtins are added over time, so the same builtin has a different number in
+ * different versions - most visibly the `HermesBuiltin.*` ones.
+ */
+
+mod common;
+
+use hermes_rs::get_builtins;
+
+fn assert_builtins(version: u32, len: usize, names: &[(usize, &str)]) {
+    let builtins = get_builtins(version).unwrap();
+    for &(idx, name) in names {
+        assert_eq!(builtins[idx], name, "version {} builtin {}", version, idx);
+    }
+    assert_eq!(builtins.len(), len, "version {}", version);
+}
+
+// The versions without `ArrayBuffer.isView` and `Date.now`
+fn assert_builtins_before_96(version: u32, len: usize, last_private: &str) {
+    assert_builtins(
+        version,
+        len,
+        &[
+            (0, "Array.isArray"),
+            (3, "JSON.parse"),
+            (34, "Object.keys"),
+            (36, "String.fromCharCode"),
+            (37, "HermesBuiltin.silentSetPrototypeOf"),
+            (len - 2, last_private),
+            (len - 1, "spawnAsync"),
+        ],
+    );
+}
+
+#[cfg(feature = "v76")]
+#[test]
+fn v76_builtins_by_number() {
+    assert_builtins_before_96(76, 52, "HermesBuiltin.initRegexNamedGroups");
+}
+
+#[cfg(feature = "v84")]
+#[test]
+fn v84_builtins_by_number() {
+    assert_builtins_before_96(84, 53, "HermesBuiltin.getOriginalNativeErrorConstructor");
+}
+
+#[cfg(feature = "v89")]
+#[test]
+fn v89_builtins_by_number() {
+    assert_builtins_before_96(89, 53, "HermesBuiltin.getOriginalNativeErrorConstructor");
+}
+
+#[cfg(feature = "v90")]
+#[test]
+fn v90_builtins_by_number() {
+    assert_builtins_before_96(90, 53, "HermesBuiltin.getOriginalNativeErrorConstructor");
+}
+
+#[cfg(feature = "v93")]
+#[test]
+fn v93_builtins_by_number() {
+    assert_builtins_before_96(93, 53, "HermesBuiltin.getOriginalNativeErrorConstructor");
+}
+
+#[cfg(feature = "v94")]
+#[test]
+fn v94_builtins_by_number() {
+    assert_builtins_before_96(94, 53, "HermesBuiltin.getOriginalNativeErrorConstructor");
+}
+
+#[cfg(feature = "v95")]
+#[test]
+fn v95_builtins_by_number() {
+    assert_builtins_before_96(95, 53, "HermesBuiltin.getOriginalNativeErrorConstructor");
+}
+
+#[cfg(feature = "v96")]
+#[test]
+fn v96_builtins_by_number() {
+    assert_builtins(
+        96,
+        55,
+        &[
+            (0, "Array.isArray"),
+            (1, "ArrayBuffer.isView"),
+            (3, "Date.now"),
+            (5, "JSON.parse"),
+            (36, "Object.keys"),
+            (38, "String.fromCharCode"),
+            (39, "HermesBuiltin.silentSetPrototypeOf"),
+            (53, "HermesBuiltin.getOriginalNativeErrorConstructor"),
+            (54, "spawnAsync"),
+        ],
+    );
+}
+
+#[test]
+fn unknown_versions_have_no_table() {
+    assert!(get_builtins(0).is_none());
+}
+
+#[cfg(all(feature = "v76", feature = "v96"))]
+#[test]
+fn instructions_name_their_builtin() {
+    let snippet = "CallBuiltin r0, 36, 2\nRet r0";
+    let mut v96 = common::file_with(96, &[("global", 1, snippet)]);
+    let ins = v96.get_function_instructions(0);
+    assert_eq!(ins[0].builtin_name(), Some("Object.keys"));
+    assert_eq!(v96.get_builtin_name(36), Some("Object.keys"));
+
+    // The same number is another builtin in an older version
+    let mut v76 = common::file_with(76, &[("global", 1, snippet)]);
+    let ins = v76.get_function_instructions(0);
+    assert_eq!(ins[0].builtin_name(), Some("String.fromCharCode"));
+    assert_eq!(v76.get_builtin_name(36), Some("String.fromCharCode"));
+}
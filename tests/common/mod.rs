@@ -0,0 +1,487 @@
+/*
+ * Helpers shared by the tests that build small files from snippets of
+ * disassembly. Each test binary uses a different subset of them.
+ *
+ * A snippet is one instruction per line, written like the disassembly:
+ *
+ *   # comments start with # or //
+ *       GetGlobalObject r0
+ *       JmpUndefined done, r0
+ *       PutById r0, r0, 1, "lastArgument"
+ *   done:
+ *       Ret r0
+ *
+ * Operands are separated by commas. Registers are `r3`, strings are quoted,
+ * jump targets are labels and anything else is a number.
+ *
+ * The files are only built in memory: the code of every function is laid out
+ * in the reader, at the offsets in the function headers, and everything else
+ * is set directly - so the code is read back the way it is from a real file.
+ */
+#![allow(dead_code)]
+
+use hermes_rs::exception_handler::ExceptionHandlerInfo;
+use hermes_rs::function_header::{
+    FunctionHeader, FunctionHeaderFlag, FunctionHeaderFlagProhibitions, SmallFunctionHeader,
+};
+use hermes_rs::jenkins::hash_string;
+use hermes_rs::string_kind::StringKindEntryNew;
+use hermes_rs::types::{OperandKind, OperandValue};
+use hermes_rs::{
+    HermesFile, HermesHeader, HermesInstruction, OverflowStringTableEntry, SmallStringTableEntry,
+    StringKind, StringKindEntry,
+};
+use std::collections::HashMap;
+use std::io::{self, Cursor};
+
+pub type MemoryFile = HermesFile<io::BufReader<Cursor<Vec<u8>>>>;
+
+pub fn header(version: u32) -> HermesHeader {
+    HermesHeader {
+        magic: 0x1F1903C103BC1FC6,
+        version,
+        ..HermesHeader::new()
+    }
+}
+
+/// A file of the given version with nothing in it.
+pub fn empty(version: u32) -> MemoryFile {
+    let mut f = HermesFile::new(io::BufReader::new(Cursor::new(vec![])));
+    f.header = header(version);
+    f
+}
+
+/*
+ * A file of the given version with one function per (name, param_count,
+ * snippet), the first one being the global function. Functions are numbered in
+ * order, so snippets can refer to each other by ID.
+ */
+pub fn file_with(version: u32, functions: &[(&str, u32, &str)]) -> MemoryFile {
+    file_with_edits(version, functions, |_, _| {})
+}
+
+/*
+ * Same as `file_with`, with `edit` getting each function's body before it's
+ * laid out - for what a snippet can't say, like switch tables and exception
+ * handlers.
+ */
+pub fn file_with_edits<F>(version: u32, functions: &[(&str, u32, &str)], mut edit: F) -> MemoryFile
+where
+    F: FnMut(usize, &mut Body),
+{
+    let mut strings = Strings::default();
+    let mut bodies = vec![];
+    for (idx, (name, _, snippet)) in functions.iter().enumerate() {
+        let mut body = Body::parse(version, snippet, &mut strings)
+            .unwrap_or_else(|e| panic!("{}: {}", name, e));
+        edit(idx, &mut body);
+        bodies.push((strings.intern(name, StringKind::String), body));
+    }
+
+    let mut code = vec![];
+    let mut function_headers = vec![];
+    for ((name, param_count, _), (func_name, body)) in functions.iter().zip(bodies.iter()) {
+        let offset = code.len().div_ceil(4) * 4;
+        code.resize(offset, 0);
+        let (bytes, handlers) = body
+            .assemble(offset)
+            .unwrap_or_else(|e| panic!("{}: {}", name, e));
+        code.extend_from_slice(&bytes);
+
+        let (highest_read_cache_index, highest_write_cache_index) = body.cache_indices();
+        function_headers.push(FunctionHeader::Small(SmallFunctionHeader {
+            offset: offset as u32,
+            param_count: *param_count,
+            byte_size: bytes.len() as u32,
+            func_name: *func_name,
+            info_offset: 0,
+            frame_size: body.register_count(),
+            env_size: 0,
+            highest_read_cache_index,
+            highest_write_cache_index,
+            flags: FunctionHeaderFlag {
+                prohibit_invoke: FunctionHeaderFlagProhibitions::ProhibitNone,
+                strict_mode: true,
+                has_exception_handler: !handlers.is_empty(),
+                has_debug_info: false,
+                overflowed: false,
+            },
+            exception_handlers: handlers,
+            debug_info: None,
+        }));
+    }
+
+    let mut f = HermesFile::new(io::BufReader::new(Cursor::new(code)));
+    f.header = header(version);
+    f.header.function_count = function_headers.len() as u32;
+    f.function_headers = function_headers;
+    strings.set_on(&mut f);
+    f
+}
+
+/*
+ * The file as `to_bytes` writes it out. Unless some have been changed already,
+ * the instructions are parsed first: a file built here has no code of its own
+ * to copy, only what's in the reader.
+ */
+pub fn to_bytes(f: &mut MemoryFile) -> Vec<u8> {
+    if f.function_bytecode.is_empty() {
+        f.get_instructions();
+    }
+    f.to_bytes()
+}
+
+/// Reads a file from bytes.
+pub fn read(bytes: Vec<u8>) -> MemoryFile {
+    HermesFile::from_reader(io::BufReader::new(Cursor::new(bytes)))
+}
+
+/// Writes a file out and reads it back.
+pub fn reread(f: &mut MemoryFile) -> MemoryFile {
+    read(to_bytes(f))
+}
+
+/*
+ * One instruction written like a line of a snippet, for code added to a body
+ * after the file is read. Jumps are numbers of bytes, since there are no labels
+ * to go to, and there's no string table to take strings from.
+ */
+pub fn instruction(version: u32, line: &str) -> HermesInstruction {
+    let body = Body::parse(version, line, &mut Strings::default())
+        .unwrap_or_else(|e| panic!("{}: {}", line, e));
+    assert_eq!(body.len(), 1, "not one instruction: {}", line);
+    body.lines[0].ins
+}
+
+/// The string table of a file being built: strings get IDs in the order they're first used.
+#[derive(Default)]
+struct Strings {
+    strings: Vec<(String, StringKind)>,
+    ids: HashMap<String, u32>,
+}
+
+impl Strings {
+    fn intern(&mut self, string: &str, kind: StringKind) -> u32 {
+        if let Some(id) = self.ids.get(string) {
+            return *id;
+        }
+        self.strings.push((string.to_string(), kind));
+        self.ids
+            .insert(string.to_string(), self.strings.len() as u32 - 1);
+        self.strings.len() as u32 - 1
+    }
+
+    fn set_on<R>(&self, f: &mut HermesFile<R>)
+    where
+        R: io::Read + io::BufRead + io::Seek,
+    {
+        for (string, kind) in self.strings.iter() {
+            match f.string_kinds.last_mut() {
+                Some(StringKindEntry::New(run)) if run.kind == *kind => run.count += 1,
+                _ => f
+                    .string_kinds
+                    .push(StringKindEntry::New(StringKindEntryNew {
+                        count: 1,
+                        kind: *kind,
+                    })),
+            }
+            if *kind == StringKind::Identifier {
+                f.identifier_hashes.push(hash_string(string));
+            }
+
+            let is_utf_16 = string.chars().any(|c| c as u32 > 0xFF);
+            let offset = f.string_storage_bytes.len() as u32;
+            let length = if is_utf_16 {
+                let units: Vec<u16> = string.encode_utf16().collect();
+                for unit in units.iter() {
+                    f.string_storage_bytes
+                        .extend_from_slice(&unit.to_le_bytes());
+                }
+                units.len() as u32
+            } else {
+                f.string_storage_bytes
+                    .extend(string.chars().map(|c| c as u8));
+                string.chars().count() as u32
+            };
+            let entry = if length >= 255 {
+                f.overflow_string_storage
+                    .push(OverflowStringTableEntry { offset, length });
+                SmallStringTableEntry {
+                    is_utf_16,
+                    offset: f.overflow_string_storage.len() as u32 - 1,
+                    length: 255,
+                }
+            } else {
+                SmallStringTableEntry {
+                    is_utf_16,
+                    offset,
+                    length,
+                }
+            };
+            f.string_storage.push(entry);
+        }
+
+        f.header.string_count = f.string_storage.len() as u32;
+        f.header.identifier_count = f.identifier_hashes.len() as u32;
+        f.header.string_kind_count = f.string_kinds.len() as u32;
+        f.header.overflow_string_count = f.overflow_string_storage.len() as u32;
+        f.header.string_storage_size = f.string_storage_bytes.len() as u32;
+    }
+}
+
+/// An instruction of a snippet, with the instruction each of its jump operands goes to.
+struct Line {
+    ins: HermesInstruction,
+    jumps: Vec<(usize, usize)>,
+}
+
+/*
+ * A function's code before it's laid out. Instructions are referred to by
+ * their index in the snippet.
+ */
+pub struct Body {
+    lines: Vec<Line>,
+    switch_tables: HashMap<usize, Vec<usize>>,
+    handlers: Vec<(usize, usize, usize)>,
+}
+
+impl Body {
+    fn parse(version: u32, snippet: &str, strings: &mut Strings) -> Result<Body, String> {
+        let mut lines: Vec<Line> = vec![];
+        let mut labels: HashMap<&str, usize> = HashMap::new();
+        let mut jumps = vec![];
+
+        for line in snippet.lines() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(label) = line.strip_suffix(':') {
+                labels.insert(label, lines.len());
+                continue;
+            }
+
+            let (name, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let mut ins = HermesInstruction::named(version, name)
+                .ok_or_else(|| format!("unknown instruction `{}`", name))?;
+            let kinds: Vec<OperandKind> = ins.operands().iter().map(|op| op.kind).collect();
+            let operands = split_operands(rest);
+            if kinds.len() != operands.len() {
+                return Err(format!(
+                    "{} takes {} operands, not {}",
+                    name,
+                    kinds.len(),
+                    operands.len()
+                ));
+            }
+
+            for (i, (kind, operand)) in kinds.iter().zip(operands.iter()).enumerate() {
+                let value = if let Some(string) = operand.strip_prefix('"') {
+                    // Property names are identifiers, like the compiler makes them
+                    let string_kind = if name.contains("ById") {
+                        StringKind::Identifier
+                    } else {
+                        StringKind::String
+                    };
+                    let string = string.strip_suffix('"').ok_or("unterminated string")?;
+                    OperandValue::Int(strings.intern(string, string_kind) as i64)
+                } else if let Some(Ok(reg)) = operand.strip_prefix('r').map(|r| r.parse::<i64>()) {
+                    OperandValue::Int(reg)
+                } else if let Ok(n) = operand.parse::<f64>() {
+                    match kind {
+                        OperandKind::Double => OperandValue::Double(n),
+                        _ => OperandValue::Int(n as i64),
+                    }
+                } else if kind.is_address() {
+                    jumps.push((lines.len(), i, operand.clone()));
+                    continue;
+                } else {
+                    return Err(format!("can't parse operand `{}`", operand));
+                };
+                if !fits(*kind, value.as_i64()) {
+                    return Err(format!("{} doesn't fit operand {} of {}", value, i, name));
+                }
+                ins.set_operand(i, value);
+            }
+            lines.push(Line { ins, jumps: vec![] });
+        }
+
+        for (index, operand, label) in jumps {
+            let target = labels
+                .get(label.as_str())
+                .ok_or_else(|| format!("there's no label `{}`", label))?;
+            lines[index].jumps.push((operand, *target));
+        }
+        Ok(Body {
+            lines,
+            switch_tables: HashMap::new(),
+            handlers: vec![],
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Makes the SwitchImm at `index` jump to `targets`, one per case from its minimum.
+    pub fn set_switch_targets(&mut self, index: usize, targets: &[usize]) -> Result<(), String> {
+        match self.lines.get(index) {
+            Some(line) if line.ins.name() == "SwitchImm" => {}
+            _ => return Err(format!("instruction {} isn't a SwitchImm", index)),
+        }
+        self.switch_tables.insert(index, targets.to_vec());
+        Ok(())
+    }
+
+    /// Adds an exception handler covering instructions `start` to `end` (not included).
+    pub fn add_handler(&mut self, start: usize, end: usize, target: usize) -> Result<(), String> {
+        if start >= end {
+            return Err(format!("handler range {}..{} is empty", start, end));
+        }
+        self.handlers.insert(0, (start, end, target));
+        Ok(())
+    }
+
+    /// One past the highest register any instruction uses.
+    fn register_count(&self) -> u32 {
+        self.lines
+            .iter()
+            .flat_map(|line| line.ins.operands())
+            .filter(|op| op.kind.is_register())
+            .map(|op| op.value.as_u32() + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// The highest read and write property cache indices.
+    fn cache_indices(&self) -> (u32, u32) {
+        let mut read = 0;
+        let mut write = 0;
+        for line in self.lines.iter() {
+            let cache_index = line
+                .ins
+                .operand("p0")
+                .map(|op| op.value.as_u32())
+                .unwrap_or(0);
+            match line.ins.name() {
+                "GetByIdShort" | "GetById" | "GetByIdLong" | "TryGetById" | "TryGetByIdLong" => {
+                    read = read.max(cache_index);
+                }
+                "PutById" | "PutByIdLong" | "TryPutById" | "TryPutByIdLong" => {
+                    write = write.max(cache_index);
+                }
+                _ => {}
+            }
+        }
+        (read, write)
+    }
+
+    /*
+     * The code, with switch tables after it, and the exception handlers. `base`
+     * is where the code goes in the file: switch tables are aligned to 4 bytes
+     * in the file, not in the function.
+     */
+    fn assemble(&self, base: usize) -> Result<(Vec<u8>, Vec<ExceptionHandlerInfo>), String> {
+        let mut offsets = vec![0];
+        for line in self.lines.iter() {
+            offsets.push(offsets.last().unwrap() + line.ins.size() as i64);
+        }
+        let code_end = *offsets.last().unwrap();
+
+        let mut instructions: Vec<HermesInstruction> =
+            self.lines.iter().map(|line| line.ins).collect();
+        for (i, line) in self.lines.iter().enumerate() {
+            for &(operand, target) in line.jumps.iter() {
+                let relative = offsets[target] - offsets[i];
+                if !fits(instructions[i].operands()[operand].kind, relative) {
+                    return Err(format!("{} can't jump {} bytes", line.ins.name(), relative));
+                }
+                instructions[i].set_operand(operand, OperandValue::Int(relative));
+            }
+        }
+
+        let mut tables = vec![];
+        let mut table_offset = code_end;
+        for (i, ins) in instructions.iter_mut().enumerate() {
+            let Some(targets) = self.switch_tables.get(&i) else {
+                continue;
+            };
+            table_offset = (base as i64 + table_offset + 3) / 4 * 4 - base as i64;
+            let p0 = ins
+                .operands()
+                .iter()
+                .position(|op| op.name == "p0")
+                .unwrap();
+            ins.set_operand(p0, OperandValue::Int(table_offset - offsets[i]));
+            let table: Vec<u8> = targets
+                .iter()
+                .flat_map(|&target| ((offsets[target] - offsets[i]) as i32).to_le_bytes())
+                .collect();
+            tables.push((table_offset, table));
+            table_offset += targets.len() as i64 * 4;
+        }
+
+        let mut bytes = vec![];
+        for ins in instructions.iter() {
+            ins.serialize(&mut bytes);
+        }
+        for (offset, table) in tables {
+            bytes.resize(offset as usize, 0);
+            bytes.extend_from_slice(&table);
+        }
+
+        let handlers = self
+            .handlers
+            .iter()
+            .map(|&(start, end, target)| ExceptionHandlerInfo {
+                start: offsets[start] as u32,
+                end: offsets[end] as u32,
+                target: offsets[target] as u32,
+            })
+            .collect();
+        Ok((bytes, handlers))
+    }
+}
+
+fn fits(kind: OperandKind, value: i64) -> bool {
+    match kind {
+        OperandKind::Addr8 => i8::try_from(value).is_ok(),
+        OperandKind::Addr32 | OperandKind::Imm32 => i32::try_from(value).is_ok(),
+        OperandKind::Double => true,
+        _ => value >= 0 && value < 1 << (kind.size() * 8),
+    }
+}
+
+// `#` and `//` start a comment, unless inside a string
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            '/' if !in_string && line[i..].starts_with("//") => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn split_operands(text: &str) -> Vec<String> {
+    let mut operands = vec![];
+    let mut current = String::new();
+    let mut in_string = false;
+    for c in text.chars() {
+        match c {
+            '"' => {
+                in_string = !in_string;
+                current.push(c);
+            }
+            ',' if !in_string => operands.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    if !current.trim().is_empty() {
+        operands.push(current);
+    }
+    operands.iter().map(|o| o.trim().to_string()).collect()
+}
@@ -0,0 +1,256 @@
+/*
+ * Decompiler output for each construct the structurer recovers: if/else, loops,
+ * switch from SwitchImm, try/catch from exception handlers, short-circuit
+ * operators and closures printed where they're created.
+ */
+#![cfg(feature = "v96")]
+
+mod common;
+
+use common::{file_with, file_with_edits};
+
+// Functions 1 and 2, for the snippets that create closures
+const ON_EVENT: (&str, u32, &str) = ("onEvent", 2, "LoadParam r0, 1\nRet r0");
+const SETUP: (&str, u32, &str) = ("setup", 2, "LoadConstUndefined r0\nRet r0");
+
+fn decompile(snippet: &str) -> String {
+    let mut f = file_with(96, &[("global", 3, snippet), ON_EVENT, SETUP]);
+    f.decompile_function(0).to_string()
+}
+
+#[test]
+fn if_else() {
+    let js = decompile(
+        r#"
+            GetGlobalObject r2
+            LoadParam r0, 1
+            JmpFalse else, r0
+            GetById r1, r2, 0, "yes"
+            Call1 r1, r1, r2
+            Jmp end
+        else:
+            GetById r1, r2, 0, "no"
+            Call1 r1, r1, r2
+        end:
+            LoadConstUndefined r1
+            Ret r1
+        "#,
+    );
+    assert_eq!(
+        js,
+        "\
+function global(a0, a1) {
+    // #0
+    if (a0) {
+        yes();
+    } else {
+        no();
+    }
+    return;
+}
+"
+    );
+}
+
+#[test]
+fn while_and_for_loops() {
+    let js = decompile(
+        r#"
+            GetGlobalObject r0
+        loop:
+            GetById r1, r0, 0, "more"
+            Call1 r1, r1, r0
+            JmpFalse done, r1
+            GetById r1, r0, 0, "tick"
+            Call1 r1, r1, r0
+            Jmp loop
+        done:
+            LoadConstUndefined r1
+            Ret r1
+        "#,
+    );
+    assert_eq!(
+        js,
+        "\
+function global(a0, a1) {
+    // #0
+    while (more()) {
+        tick();
+    }
+    return;
+}
+"
+    );
+
+    // A counter updated at the end of the body makes it a for loop
+    let js = decompile(
+        r#"
+            GetGlobalObject r4
+            LoadParam r0, 1
+            LoadConstZero r1
+            LoadConstUInt8 r3, 1
+        loop:
+            JNotLess done, r1, r0
+            GetById r2, r4, 0, "tick"
+            Call2 r2, r2, r4, r1
+            Add r1, r1, r3
+            Jmp loop
+        done:
+            Ret r1
+        "#,
+    );
+    assert_eq!(
+        js,
+        "\
+function global(a0, a1) {
+    // #0
+    var v4;
+    for (v4 = 0; v4 < a0; v4 = v4 + 1) {
+        tick(v4);
+    }
+    return v4;
+}
+"
+    );
+}
+
+#[test]
+fn switch_from_switch_imm() {
+    let switch = r#"
+            LoadParam r0, 1
+            GetGlobalObject r1
+            SwitchImm r0, 0, other, 1, 3
+            GetById r2, r1, 0, "one"
+            Call1 r2, r2, r1
+            Jmp end
+            GetById r2, r1, 0, "two"
+            Call1 r2, r2, r1
+            Jmp end
+        other:
+            GetById r2, r1, 0, "other"
+            Call1 r2, r2, r1
+        end:
+            LoadConstUndefined r2
+            Ret r2
+    "#;
+    // Cases 1, 2 and 3 - the last two share a body
+    let mut f = file_with_edits(96, &[("global", 2, switch)], |_, body| {
+        body.set_switch_targets(2, &[3, 6, 6]).unwrap();
+    });
+    assert_eq!(
+        f.decompile_function(0).to_string(),
+        "\
+function global(a0) {
+    // #0
+    switch (a0) {
+        case 1:
+            one();
+            break;
+        case 2:
+        case 3:
+            two();
+            break;
+        default:
+            other();
+    }
+    return;
+}
+"
+    );
+}
+
+#[test]
+fn try_catch_from_handlers() {
+    let try_catch = r#"
+            GetGlobalObject r0
+            GetById r1, r0, 0, "risky"
+            Call1 r1, r1, r0
+            Jmp done
+            Catch r1
+            GetById r2, r0, 0, "log"
+            Call2 r2, r2, r0, r1
+        done:
+            LoadConstUndefined r1
+            Ret r1
+    "#;
+    let mut f = file_with_edits(96, &[("global", 1, try_catch)], |_, body| {
+        body.add_handler(1, 3, 4).unwrap();
+    });
+    assert_eq!(
+        f.decompile_function(0).to_string(),
+        "\
+function global() {
+    // #0
+    try {
+        risky();
+    } catch (v3) {
+        log(v3);
+    }
+    return;
+}
+"
+    );
+}
+
+#[test]
+fn short_circuit_operators() {
+    let and = decompile(
+        r#"
+            LoadParam r0, 1
+            JmpFalse done, r0
+            LoadParam r0, 2
+        done:
+            Ret r0
+        "#,
+    );
+    assert!(and.contains("v2 = a0 && a1;\n"), "{}", and);
+
+    let or = decompile(
+        r#"
+            LoadParam r0, 1
+            JmpTrue done, r0
+            LoadParam r0, 2
+        done:
+            Ret r0
+        "#,
+    );
+    assert!(or.contains("v2 = a0 || a1;\n"), "{}", or);
+}
+
+#[test]
+fn closures_are_inlined() {
+    // An immediately invoked function is parenthesized, or it would be read as a
+    // declaration
+    let js = decompile(
+        r#"
+            CreateEnvironment r0
+            GetGlobalObject r2
+            CreateClosure r1, r0, 1
+            PutById r2, r1, 1, "handler"
+            NewObject r2
+            CreateClosure r1, r0, 2
+            LoadConstUInt8 r3, 5
+            Call2 r1, r1, r2, r3
+            LoadConstUndefined r1
+            Ret r1
+        "#,
+    );
+    assert_eq!(
+        js,
+        "\
+function global(a0, a1) {
+    // #0
+    var v0 = $createEnvironment();
+    handler = function onEvent(a0) {
+        // #1
+        return a0;
+    };
+    (function setup(a0) {
+        // #2
+        return;
+    }.call({}, 5));
+    return;
+}
+"
+    );
+}
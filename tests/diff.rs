@@ -0,0 +1,108 @@
+/*
+ * Bundle diffs: functions that are only in one build are added or removed,
+ * matched functions whose code changed are modified, and moving a function
+ * around is not a change.
+ */
+#![cfg(feature = "v96")]
+
+mod common;
+
+use common::file_with;
+use hermes_rs::diff::DiffLine;
+
+const GLOBAL: (&str, u32, &str) = ("global", 1, "LoadConstUndefined r0\nRet r0");
+const KEEP: (&str, u32, &str) = ("keep", 1, "LoadParam r0, 1\nRet r0");
+
+#[test]
+fn functions_are_added_removed_and_modified() {
+    let mut old = file_with(
+        96,
+        &[
+            GLOBAL,
+            KEEP,
+            (
+                "change",
+                2,
+                r#"
+                LoadParam r1, 1
+                LoadConstUInt8 r2, 1
+                Add r0, r1, r2
+                Ret r0
+                "#,
+            ),
+            ("drop", 3, "LoadConstString r0, \"gone\"\nRet r0"),
+        ],
+    );
+    let mut new = file_with(
+        96,
+        &[
+            GLOBAL,
+            (
+                "fresh",
+                4,
+                r#"
+                LoadConstString r1, "hello"
+                GetByIdShort r0, r1, 0, "length"
+                JmpTrue done, r0
+                LoadConstZero r0
+                done:
+                Ret r0
+                "#,
+            ),
+            (
+                "change",
+                2,
+                r#"
+                LoadParam r1, 1
+                LoadConstUInt8 r2, 2
+                Mul r0, r1, r2
+                Ret r0
+                "#,
+            ),
+            KEEP,
+        ],
+    );
+
+    let diff = old.diff(&mut new);
+    assert_eq!(diff.removed, vec![3]);
+    assert_eq!(diff.added, vec![1]);
+    assert_eq!(diff.unchanged_count(), 2);
+
+    // keep moved from 1 to 3 without changing
+    assert!(diff.matches.iter().any(|m| m.left == 1 && m.right == 3));
+
+    assert_eq!(diff.modified.len(), 1);
+    let change = &diff.modified[0];
+    assert_eq!((change.left, change.right), (2, 2));
+    assert_eq!(change.left_name, "change");
+    assert_eq!(change.right_name, "change");
+
+    let changes: Vec<&DiffLine> = change.lines.iter().filter(|l| l.is_change()).collect();
+    let text = |line: &DiffLine| match line {
+        DiffLine::Same(text) | DiffLine::Removed(text) | DiffLine::Added(text) => text.clone(),
+    };
+    assert!(changes
+        .iter()
+        .any(|l| matches!(l, DiffLine::Removed(_)) && text(l).starts_with("Add")));
+    assert!(changes
+        .iter()
+        .any(|l| matches!(l, DiffLine::Added(_)) && text(l).starts_with("Mul")));
+    assert!(change
+        .lines
+        .iter()
+        .any(|l| matches!(l, DiffLine::Same(_)) && text(l).starts_with("LoadParam")));
+
+    assert_eq!(diff.strings_added, vec!["fresh", "hello", "length"]);
+    assert_eq!(diff.strings_removed, vec!["drop", "gone"]);
+}
+
+#[test]
+fn identical_builds_have_no_changes() {
+    let functions = [GLOBAL, KEEP];
+    let mut old = file_with(96, &functions);
+    let mut new = file_with(96, &functions);
+
+    let diff = old.diff(&mut new);
+    assert!(diff.added.is_empty() && diff.removed.is_empty() && diff.modified.is_empty());
+    assert_eq!(diff.unchanged_count(), 2);
+}
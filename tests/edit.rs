@@ -0,0 +1,779 @@
+/*
+ * Editing a file that was read back: replacing the string table moves strings
+ * to new IDs, and every reference to them has to follow - instructions, literal
+ * buffers, function names and the CJS module and function source tables.
+ * Instructions whose IDs or literal offsets outgrow their operands switch to
+ * their wide form, and the jumps and exception handlers around them move along -
+ * as they do for instructions inserted into or removed from a function body,
+ * along with its switch tables and debug locations. Functions can be added and
+ * called on entry, and traced through a global hook.
+ */
+#![cfg(feature = "v96")]
+
+mod common;
+
+use common::{file_with, file_with_edits, instruction, reread, MemoryFile};
+use hermes_rs::cjs_module::{CJSModule, CJSModuleEntry};
+use hermes_rs::debug_info::{
+    DebugFileRegion, DebugInfoHeader, DebugInfoOffsets, DebugInfoOffsetsNew, FunctionLocations,
+};
+use hermes_rs::function_header::FunctionHeader;
+use hermes_rs::function_sources::FunctionSourceEntry;
+use hermes_rs::hermes_file::builder::StringTypePair;
+use hermes_rs::string_table::StringTableBuilder;
+use hermes_rs::types::OperandValue;
+use hermes_rs::{define_instructions, HermesInstruction, StringKind};
+
+fn pair(string: &str, kind: StringKind) -> StringTypePair {
+    StringTypePair {
+        string: string.to_string(),
+        kind,
+    }
+}
+
+// Adds strings nothing refers to yet
+fn add_strings(f: &mut MemoryFile, strings: &[&str]) {
+    let mut table = f.string_table_builder().unwrap();
+    for string in strings {
+        table.push(string, StringKind::String);
+    }
+    f.set_string_table(&table);
+}
+
+/*
+ * global() { return "hello".length } - plus "hello" in the array buffer,
+ * "length" as an object key, "global" as a CJS module name and function
+ * source, and a string nothing uses.
+ */
+fn sample() -> MemoryFile {
+    let mut f = file_with(
+        96,
+        &[(
+            "global",
+            0,
+            "
+                LoadConstString r0, \"hello\"
+                GetByIdShort r0, r0, 1, \"length\"
+                Ret r0
+            ",
+        )],
+    );
+    add_strings(&mut f, &["unused"]);
+
+    f.array_buffer_storage = vec![0x61, 0x00];
+    f.object_key_buffer = vec![0x61, 0x01];
+    f.object_val_buffer = vec![0x11];
+
+    f.cjs_modules = vec![CJSModule::CJSModuleEntry(CJSModuleEntry {
+        symbol_id: 2,
+        offset: 0,
+    })];
+    f.function_source_entries = vec![FunctionSourceEntry {
+        function_id: 0,
+        string_id: 2,
+    }];
+
+    reread(&mut f)
+}
+
+fn string_operands(f: &mut MemoryFile) -> Vec<String> {
+    f.get_function_instructions(0)
+        .iter()
+        .flat_map(|ins| ins.operands())
+        .filter(|op| op.kind.is_string_id())
+        .map(|op| f.get_string_from_storage_by_index(op.value.as_i64() as usize))
+        .collect()
+}
+
+#[test]
+fn replacing_strings_remaps_references() {
+    let mut f = sample();
+    assert_eq!(string_operands(&mut f), vec!["hello", "length"]);
+
+    // The file's own strings keep their IDs, until one is removed
+    let mut strings = f.string_table_builder().unwrap();
+    assert_eq!(strings.get("length"), Some(1));
+    strings.retain(|string, _| string.to_string_lossy() != "hello");
+    assert_eq!(strings.get("length"), Some(0));
+
+    // Drop "unused", and move everything else around
+    let mut reordered = StringTableBuilder::new();
+    reordered.intern("added", StringKind::String);
+    reordered.intern("length", StringKind::Identifier);
+    reordered.intern("hello", StringKind::String);
+    reordered.intern("global", StringKind::String);
+
+    let map = f.replace_strings(&reordered).unwrap();
+    assert_eq!(map, vec![Some(2), Some(1), Some(3), None]);
+
+    let mut f = reread(&mut f);
+    assert!(f.validate().is_empty(), "{:?}", f.validate());
+    assert_eq!(f.get_strings(), vec!["added", "length", "hello", "global"]);
+    assert_eq!(string_operands(&mut f), vec!["hello", "length"]);
+    assert_eq!(f.get_function_name(0), "global");
+    assert_eq!(f.array_buffer_storage, vec![0x61, 0x02]);
+    assert_eq!(f.object_key_buffer, vec![0x61, 0x01]);
+    match &f.cjs_modules[0] {
+        CJSModule::CJSModuleEntry(entry) => assert_eq!(entry.symbol_id, 3),
+        _ => panic!("expected a CJS module entry"),
+    }
+    assert_eq!(f.function_source_entries[0].string_id, 3);
+}
+
+#[test]
+fn removing_a_used_string_fails() {
+    let mut f = sample();
+    let err = f
+        .replace_string_pairs(vec![
+            pair("global", StringKind::String),
+            pair("length", StringKind::Identifier),
+        ])
+        .unwrap_err();
+    assert!(err.contains("string 0 was removed"), "{}", err);
+
+    // Nothing changed
+    assert_eq!(f.get_strings(), vec!["hello", "length", "global", "unused"]);
+    assert_eq!(f.array_buffer_storage, vec![0x61, 0x00]);
+    assert!(f.function_bytecode.is_empty());
+}
+
+#[test]
+fn literal_strings_outgrow_their_tags() {
+    // Two array literals, ["hello"] at 0 and ["global"] at 2
+    let mut f = file_with(
+        96,
+        &[(
+            "global",
+            0,
+            "
+                NewArrayWithBuffer r0, 1, 1, 0
+                NewArrayWithBuffer r1, 1, 1, 2
+                Ret r0
+            ",
+        )],
+    );
+    add_strings(&mut f, &["hello"]);
+    f.array_buffer_storage = vec![0x61, 0x01, 0x61, 0x00];
+    let mut f = reread(&mut f);
+
+    // Enough strings in front that neither fits a byte anymore
+    let mut strings = StringTableBuilder::new();
+    for i in 0..300 {
+        strings.intern(&format!("pad{}", i), StringKind::String);
+    }
+    strings.intern("hello", StringKind::String);
+    strings.intern("global", StringKind::String);
+    f.replace_strings(&strings).unwrap();
+
+    let mut f = reread(&mut f);
+    assert!(f.validate().is_empty(), "{:?}", f.validate());
+    assert_eq!(
+        f.array_buffer_storage,
+        vec![0x51, 0x2c, 0x01, 0x51, 0x2d, 0x01]
+    );
+    let offsets: Vec<i64> = f
+        .get_function_instructions(0)
+        .iter()
+        .filter(|ins| ins.name() == "NewArrayWithBuffer")
+        .map(|ins| ins.operands()[3].value.as_i64())
+        .collect();
+    assert_eq!(offsets, vec![0, 3]);
+
+    // A buffer that ends in the middle of a sequence is an error
+    f.array_buffer_storage = vec![0x62, 0x2c];
+    let err = f.replace_strings(&strings).unwrap_err();
+    assert!(err.contains("array buffer: buffer ends"), "{}", err);
+    assert_eq!(f.array_buffer_storage, vec![0x62, 0x2c]);
+}
+
+#[test]
+fn with_operand_picks_the_narrowest_form() {
+    let get_by_id = HermesInstruction::V96(
+        define_instructions!(
+            hermes_rs::v96,
+            GetByIdShort {
+                r0: 0.into(),
+                r1: 1.into(),
+                p0: 2.into(),
+                p1: 3.into()
+            }
+        )
+        .unwrap()[0],
+    );
+
+    let set = |ins: &HermesInstruction, operand: usize, value: i64| {
+        ins.with_operand(operand, OperandValue::Int(value)).unwrap()
+    };
+    assert_eq!(set(&get_by_id, 3, 200).name(), "GetByIdShort");
+    assert_eq!(set(&get_by_id, 3, 300).name(), "GetById");
+    let long = set(&get_by_id, 3, 70000);
+    assert_eq!(long.name(), "GetByIdLong");
+    assert_eq!(long.operand("r1").unwrap().value.as_i64(), 1);
+    assert_eq!(long.operand("p1").unwrap().value.as_i64(), 70000);
+
+    // Kept long once it's long
+    assert_eq!(set(&long, 3, 1).name(), "GetByIdLong");
+    // Registers only come in one size for GetById
+    assert!(get_by_id.with_operand(0, OperandValue::Int(300)).is_none());
+
+    let create_closure = HermesInstruction::V96(
+        define_instructions!(
+            hermes_rs::v96,
+            CreateClosure {
+                r0: 0.into(),
+                r1: 1.into(),
+                p0: 2.into()
+            }
+        )
+        .unwrap()[0],
+    );
+    assert_eq!(
+        set(&create_closure, 2, 70000).name(),
+        "CreateClosureLongIndex"
+    );
+}
+
+#[test]
+fn ids_that_outgrow_their_operands_widen_instructions() {
+    // if (r0) jumps over 29 loads of "hello" and a "length" lookup - all of it
+    // inside a try
+    let snippet = format!(
+        "
+            JmpTrue end, r0
+            {}
+            GetByIdShort r0, r1, 1, \"length\"
+        end:
+            Ret r0
+        ",
+        "LoadConstString r1, \"hello\"\n".repeat(29)
+    );
+    let mut f = file_with_edits(96, &[("global", 0, &snippet)], |_, body| {
+        body.add_handler(1, 31, 31).unwrap();
+    });
+    let padding: Vec<String> = (0..70000).map(|i| format!("s{}", i)).collect();
+    let padding: Vec<&str> = padding.iter().map(|s| s.as_str()).collect();
+    add_strings(&mut f, &padding);
+    let mut f = reread(&mut f);
+
+    // "length" becomes 301 and "hello" 70002
+    let mut strings = StringTableBuilder::new();
+    strings.intern("global", StringKind::String);
+    for s in padding[..300].iter() {
+        strings.intern(s, StringKind::String);
+    }
+    strings.intern("length", StringKind::Identifier);
+    for s in padding[300..].iter() {
+        strings.intern(s, StringKind::String);
+    }
+    strings.intern("hello", StringKind::String);
+    f.replace_strings(&strings).unwrap();
+
+    let mut f = reread(&mut f);
+    assert!(f.validate().is_empty(), "{:?}", f.validate());
+
+    let instructions = f.get_function_instructions(0);
+    let names: Vec<&str> = instructions.iter().map(|ins| ins.name()).collect();
+    assert_eq!(names[0], "JmpTrueLong");
+    assert!(names[1..30]
+        .iter()
+        .all(|name| *name == "LoadConstStringLongIndex"));
+    assert_eq!(names[30..], ["GetById", "Ret"]);
+
+    // The jump still lands on the Ret, and the try still covers everything before it
+    let ret = 6 + 29 * 6 + 6;
+    assert_eq!(instructions[0].operand("p0").unwrap().value.as_i64(), ret);
+    let handlers = f.function_headers[0].exception_handlers();
+    assert_eq!(
+        (handlers[0].start, handlers[0].end, handlers[0].target),
+        (6, ret as u32, ret as u32)
+    );
+    assert_eq!(f.function_headers[0].byte_size(), ret as u32 + 2);
+
+    assert_eq!(string_operands(&mut f)[..2], ["hello", "hello"]);
+    assert_eq!(string_operands(&mut f)[29], "length");
+}
+
+#[test]
+fn literal_offsets_that_outgrow_their_operands_widen_instructions() {
+    // 15 sequences of 4095 "hello"s, then ["global"] just below 64K
+    let mut buffer = vec![];
+    for _ in 0..15 {
+        buffer.extend_from_slice(&[0xef, 0xff]);
+        buffer.extend(std::iter::repeat_n(0x01, 4095));
+    }
+    let global = buffer.len();
+    buffer.extend_from_slice(&[0x61, 0x00]);
+
+    let mut f = file_with(
+        96,
+        &[(
+            "global",
+            0,
+            &format!(
+                "
+                    NewArrayWithBuffer r0, 61425, 61425, 0
+                    NewArrayWithBuffer r1, 1, 1, {}
+                    Ret r0
+                ",
+                global
+            ),
+        )],
+    );
+    add_strings(&mut f, &["hello"]);
+    f.array_buffer_storage = buffer;
+    let mut f = reread(&mut f);
+
+    // "hello" no longer fits a byte, so every sequence before ["global"] doubles
+    let mut strings = StringTableBuilder::new();
+    for i in 0..300 {
+        strings.intern(&format!("pad{}", i), StringKind::String);
+    }
+    strings.intern("hello", StringKind::String);
+    strings.intern("global", StringKind::String);
+    f.replace_strings(&strings).unwrap();
+
+    let mut f = reread(&mut f);
+    assert!(f.validate().is_empty(), "{:?}", f.validate());
+    let global = 15 * (2 + 2 * 4095);
+    assert_eq!(f.array_buffer_storage[..4], [0xdf, 0xff, 0x2c, 0x01]);
+    assert_eq!(f.array_buffer_storage[global..], [0x51, 0x2d, 0x01]);
+
+    let instructions = f.get_function_instructions(0);
+    let literals: Vec<(&str, i64)> = instructions[..2]
+        .iter()
+        .map(|ins| (ins.name(), ins.operand("p2").unwrap().value.as_i64()))
+        .collect();
+    assert_eq!(
+        literals,
+        [
+            ("NewArrayWithBuffer", 0),
+            ("NewArrayWithBufferLong", global as i64)
+        ]
+    );
+}
+
+/*
+ * r0 = undefined; goto ret; try { r0 = 0 } ret: return r0 - laid out as
+ *   0: LoadConstUndefined, 2: Jmp, 4: LoadConstZero, 6: Ret
+ */
+const JUMPING: &str = "
+        LoadConstUndefined r0
+        Jmp ret
+        LoadConstZero r0
+    ret:
+        Ret r0
+";
+
+fn jumping_file(names: &[&str]) -> MemoryFile {
+    let functions: Vec<(&str, u32, &str)> = names.iter().map(|name| (*name, 0, JUMPING)).collect();
+    file_with_edits(96, &functions, |_, body| body.add_handler(2, 3, 3).unwrap())
+}
+
+#[test]
+fn inserting_instructions_moves_jumps_and_handlers() {
+    let mut f = reread(&mut jumping_file(&["global"]));
+
+    // 70 instructions at the start of the try push the Ret out of the Jmp's reach
+    let mut body = f.function_body(0);
+    for _ in 0..70 {
+        body.insert(2, instruction(96, "LoadConstZero r0")).unwrap();
+    }
+    assert_eq!(body.jump_target(1, 0), Some(73));
+    f.set_function_body(0, &body).unwrap();
+
+    let mut f = reread(&mut f);
+    assert!(f.validate().is_empty(), "{:?}", f.validate());
+
+    let instructions = f.get_function_instructions(0);
+    assert_eq!(instructions.len(), 74);
+    assert_eq!(instructions[1].name(), "JmpLong");
+    let ret = 2 + 5 + 70 * 2 + 2;
+    assert_eq!(
+        instructions[1].operand("p0").unwrap().value.as_i64(),
+        ret - 2
+    );
+    assert_eq!(instructions[73].name(), "Ret");
+
+    // The try now starts at the first inserted instruction
+    let handlers = f.function_headers[0].exception_handlers();
+    assert_eq!(
+        (handlers[0].start, handlers[0].end, handlers[0].target),
+        (7, ret as u32, ret as u32)
+    );
+    assert_eq!(f.function_headers[0].byte_size(), ret as u32 + 2);
+
+    // Removing them again brings the short jump back, and a replaced jump keeps
+    // its target
+    let mut body = f.function_body(0);
+    for _ in 0..70 {
+        body.remove(2).unwrap();
+    }
+    body.replace(1, instruction(96, "JmpTrue 0, r0")).unwrap();
+    f.set_function_body(0, &body).unwrap();
+
+    let instructions = f.get_function_instructions(0);
+    let names: Vec<&str> = instructions.iter().map(|ins| ins.name()).collect();
+    assert_eq!(
+        names,
+        ["LoadConstUndefined", "JmpTrue", "LoadConstZero", "Ret"]
+    );
+    // JmpTrue is a byte longer than Jmp
+    assert_eq!(instructions[1].operand("p0").unwrap().value.as_i64(), 5);
+    let handlers = f.function_headers[0].exception_handlers();
+    assert_eq!(
+        (handlers[0].start, handlers[0].end, handlers[0].target),
+        (5, 7, 7)
+    );
+}
+
+#[test]
+fn switch_tables_follow_their_targets() {
+    let mut f = reread(&mut file_with(
+        96,
+        &[("global", 0, "LoadConstZero r0\nRet r0")],
+    ));
+
+    // switch (r0) { case 0: return r0; case 1: r0 = undefined; return r0 }
+    let mut body = f.function_body(0);
+    body.insert(1, instruction(96, "SwitchImm r0, 0, 0, 0, 1"))
+        .unwrap();
+    body.insert(2, instruction(96, "LoadConstUndefined r0"))
+        .unwrap();
+    assert!(body.assemble().is_err());
+    body.set_jump_target(1, 2, 3).unwrap();
+    body.set_switch_targets(1, &[3, 2]).unwrap();
+    f.set_function_body(0, &body).unwrap();
+
+    let mut f = reread(&mut f);
+    assert!(f.validate().is_empty(), "{:?}", f.validate());
+
+    // Growing the code in front of it moves the table along
+    let mut body = f.function_body(0);
+    assert_eq!(body.len(), 4);
+    assert_eq!(body.switch_targets(1), Some(vec![3, 2]));
+    body.insert(0, instruction(96, "LoadConstZero r0")).unwrap();
+    f.set_function_body(0, &body).unwrap();
+
+    let body = f.function_body(0);
+    assert_eq!(body.get(2).unwrap().name(), "SwitchImm");
+    assert_eq!(body.jump_target(2, 2), Some(4));
+    assert_eq!(body.switch_targets(2), Some(vec![4, 3]));
+}
+
+#[test]
+fn editing_a_body_moves_its_debug_locations() {
+    // Each location holds address, line, column and two more deltas
+    let locations = |function_index, entries: Vec<u32>| FunctionLocations {
+        function_index,
+        line: 1,
+        column: 1,
+        entries: entries
+            .into_iter()
+            .map(|address| (address, vec![2, 0, 0, 0]))
+            .collect(),
+    };
+    let first = locations(0, vec![4, 6]);
+    let second = locations(1, vec![0]);
+    let mut sources = first.encode();
+    let second_src = sources.len() as u32;
+    sources.extend(second.encode());
+
+    let mut f = jumping_file(&["global", "second"]);
+    for (idx, src) in [0, second_src].into_iter().enumerate() {
+        let FunctionHeader::Small(fh) = &mut f.function_headers[idx] else {
+            unreachable!()
+        };
+        fh.flags.has_debug_info = true;
+        fh.debug_info = Some(DebugInfoOffsets::New(DebugInfoOffsetsNew {
+            src,
+            scope_desc: 0,
+            callee: 0,
+        }));
+        f.debug_info.file_regions.push(DebugFileRegion {
+            from_address: src,
+            filename_id: idx as u32,
+            source_mapping_url_id: 0,
+        });
+    }
+    let size = sources.len() as u32;
+    f.debug_info.sources_data_storage = sources;
+    f.debug_info.header = DebugInfoHeader {
+        filename_count: 0,
+        filename_storage_size: 0,
+        file_region_count: 2,
+        scope_desc_data_offset: size,
+        textified_callee_offset: Some(size),
+        string_table_offset: Some(size),
+        debug_data_size: size,
+    };
+
+    let mut f = reread(&mut f);
+    assert_eq!(f.debug_info.function_locations(0, second_src), Some(first));
+
+    let mut body = f.function_body(0);
+    for _ in 0..70 {
+        body.insert(2, instruction(96, "LoadConstZero r0")).unwrap();
+    }
+    f.set_function_body(0, &body).unwrap();
+
+    let f = reread(&mut f);
+
+    // The second location is now too far from the first for one byte, which
+    // moves everything after it
+    let moved_src = second_src + 1;
+    let src = |idx: usize| f.function_headers[idx].debug_info().unwrap().src();
+    assert_eq!((src(0), src(1)), (0, moved_src));
+    assert_eq!(f.debug_info.file_regions[1].from_address, moved_src);
+    assert_eq!(f.debug_info.header.scope_desc_data_offset, size + 1);
+    assert_eq!(f.debug_info.header.debug_data_size, size + 1);
+    assert_eq!(
+        f.debug_info.function_locations(0, moved_src),
+        Some(locations(0, vec![7, 2 + 5 + 70 * 2 + 2]))
+    );
+    assert_eq!(
+        f.debug_info.function_locations(moved_src, size + 1),
+        Some(second)
+    );
+}
+
+#[test]
+fn injected_functions_are_called_on_entry() {
+    let mut f = reread(&mut file_with(
+        96,
+        &[
+            ("global", 0, "LoadConstUndefined r0\nRet r0"),
+            // add(a, b) { return a + b }
+            (
+                "add",
+                3,
+                "
+                    LoadParam r0, 1
+                    LoadParam r1, 2
+                    Add r0, r0, r1
+                    Ret r0
+                ",
+            ),
+        ],
+    ));
+
+    let hook = f
+        .assemble_snippet(
+            r#"
+            # globalThis.lastArgument = a, unless it's undefined
+                GetGlobalObject r0
+                LoadParam r1, 1
+                JmpUndefined done, r1
+                PutById r0, r1, 1, "lastArgument"
+            done:
+                LoadConstUndefined r0   // the result is dropped anyway
+                Ret r0
+            "#,
+        )
+        .unwrap();
+    assert_eq!(hook.jump_target(2, 0), Some(4));
+    let hook = f.add_function_body("hook", 3, &hook).unwrap();
+    assert_eq!(hook, 2);
+    f.call_on_entry(1, hook).unwrap();
+
+    let mut f = reread(&mut f);
+    assert!(f.validate().is_empty(), "{:?}", f.validate());
+    assert!(f.verify().is_empty(), "{:?}", f.verify());
+
+    assert_eq!(f.header.function_count, 3);
+    assert_eq!(f.get_function_name(2), "hook");
+    let fh = &f.function_headers[2];
+    assert_eq!((fh.param_count(), fh.frame_size()), (3, 2));
+    assert_eq!(fh.highest_write_cache_index(), 1);
+    assert_eq!(
+        f.get_strings(),
+        vec!["global", "add", "lastArgument", "hook"]
+    );
+
+    // The closure goes in r0 and `this`, a and b in r1-r3. Calls pass them on at
+    // the end of the frame, so it has to grow past them; the function's own
+    // registers move up to r5 and r6.
+    let display: Vec<String> = f
+        .get_function_instructions(1)
+        .iter()
+        .map(|ins| {
+            ins.display(&f)
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect();
+    assert_eq!(
+        display,
+        [
+            "GetEnvironment r0, 0",
+            "CreateClosure r0, r0, Function<hook>",
+            "LoadParam r1, 0",
+            "LoadParam r2, 1",
+            "LoadParam r3, 2",
+            "Call3 r0, r0, r1, r2, r3",
+            "LoadParam r5, 1",
+            "LoadParam r6, 2",
+            "Add r5, r5, r6",
+            "Ret r5",
+        ]
+    );
+    assert_eq!(f.function_headers[1].frame_size(), 7);
+
+    let err = f.assemble_snippet("Ret r0, r1").unwrap_err();
+    assert_eq!(err, "line 1: Ret takes 1 operands, not 2");
+    let err = f.assemble_snippet("Jmp nowhere").unwrap_err();
+    assert_eq!(err, "line 1: there's no label `nowhere`");
+}
+
+#[test]
+fn generators_are_not_called_on_entry() {
+    let mut f = reread(&mut file_with(
+        96,
+        &[
+            ("global", 0, "LoadConstUndefined r0\nRet r0"),
+            // The inner function of `function* gen() {}`
+            (
+                "gen",
+                1,
+                "
+                    StartGenerator
+                    ResumeGenerator r0, r1
+                    CompleteGenerator
+                    Ret r0
+                ",
+            ),
+        ],
+    ));
+
+    let hook = f.assemble_snippet("LoadConstUndefined r0\nRet r0").unwrap();
+    let hook = f.add_function_body("hook", 1, &hook).unwrap();
+    let names = |f: &mut MemoryFile| {
+        f.get_function_instructions(1)
+            .iter()
+            .map(|ins| ins.name())
+            .collect::<Vec<_>>()
+    };
+    let before = names(&mut f);
+
+    let err = f.call_on_entry(1, hook).unwrap_err();
+    assert_eq!(err, "function 1 is a generator");
+    assert_eq!(names(&mut f), before);
+    assert_eq!(f.function_headers[1].frame_size(), 2);
+}
+
+#[test]
+fn traced_functions_call_the_hook_on_entry_and_exit() {
+    // check(x) { if (!x) { try { return 0 } catch {} } return x }
+    let check = "
+            LoadParam r0, 1
+            JmpTrue done, r0
+            LoadConstZero r0
+            Ret r0
+            Catch r0
+        done:
+            Ret r0
+    ";
+    let mut f = reread(&mut file_with_edits(
+        96,
+        &[
+            ("global", 0, "LoadConstUndefined r0\nRet r0"),
+            ("check", 2, check),
+        ],
+        |idx, body| {
+            if idx == 1 {
+                body.add_handler(2, 3, 4).unwrap();
+            }
+        },
+    ));
+    assert!(f.verify().is_empty(), "{:?}", f.verify());
+
+    let traced = f.trace_functions("__hbcTrace", |id, _| id != 0).unwrap();
+    assert_eq!(traced, vec![1]);
+
+    let mut f = reread(&mut f);
+    assert!(f.validate().is_empty(), "{:?}", f.validate());
+    assert!(f.verify().is_empty(), "{:?}", f.verify());
+    assert_eq!(f.get_strings(), vec!["global", "check", "__hbcTrace"]);
+    assert_eq!(f.get_function_instructions(0).len(), 2);
+
+    let instructions = f.get_function_instructions(1);
+    let display: Vec<String> = instructions
+        .iter()
+        .map(|ins| {
+            ins.display(&f)
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect();
+    assert_eq!(
+        display[..11],
+        [
+            "GetGlobalObject r0",
+            "GetById r0, r0, 0, \"__hbcTrace\"",
+            "LoadConstUndefined r1",
+            "LoadConstUInt8 r2, 1",
+            "LoadConstString r3, \"check\"",
+            "LoadConstUndefined r4",
+            "ReifyArguments r4",
+            "Call4 r0, r0, r1, r2, r3, r4",
+            "Jmp 4",
+            "Catch r0",
+            "LoadParam r7, 1",
+        ]
+    );
+
+    // `return 0`: the return value is kept in r1, and the call's `this` and
+    // arguments are the last registers of the frame
+    assert_eq!(
+        display[12..26],
+        [
+            "LoadConstZero r7",
+            "Mov r1, r7",
+            "GetGlobalObject r0",
+            "GetById r0, r0, 0, \"__hbcTrace\"",
+            "LoadConstUndefined r7",
+            "LoadConstUInt8 r6, 1",
+            "LoadConstString r5, \"check\"",
+            "LoadConstUndefined r4",
+            "ReifyArguments r4",
+            "Mov r3, r1",
+            "Call r0, r0, 5",
+            "Jmp 4",
+            "Catch r0",
+            "Ret r1",
+        ]
+    );
+    assert_eq!(display[26..28], ["Catch r7", "Mov r1, r7"]);
+    assert_eq!(display.len(), 40);
+    let fh = &f.function_headers[1];
+    assert_eq!(fh.frame_size(), 8);
+    assert!(fh.flags().has_exception_handler);
+
+    let mut offsets = vec![0u32];
+    for ins in instructions.iter() {
+        offsets.push(offsets.last().unwrap() + ins.size() as u32);
+    }
+    // Jumps to a Ret run its call too
+    assert_eq!(
+        offsets[11] as i64 + instructions[11].operand("p0").unwrap().value.as_i64(),
+        offsets[27] as i64
+    );
+
+    // Each call is caught by a handler of its own, ahead of the function's try -
+    // which still only covers `return 0`, not the call after it
+    let handlers: Vec<(u32, u32, u32)> = fh
+        .exception_handlers()
+        .iter()
+        .map(|h| (h.start, h.end, h.target))
+        .collect();
+    assert_eq!(
+        handlers,
+        vec![
+            (0, offsets[8], offsets[9]),
+            (offsets[13], offsets[23], offsets[24]),
+            (offsets[27], offsets[37], offsets[38]),
+            (offsets[12], offsets[13], offsets[26]),
+        ]
+    );
+}
@@ -0,0 +1,168 @@
+/*
+ * Function fingerprints: they describe what a function does, not where its
+ * strings happen to sit in the string table or which operand widths the
+ * compiler picked.
+ */
+#![cfg(feature = "v96")]
+
+mod common;
+
+use common::{file_with, reread, MemoryFile};
+use hermes_rs::fingerprint::{normalize_opcode_name, MatchKind};
+use hermes_rs::hermes_file::builder::StringTypePair;
+use hermes_rs::StringKind;
+
+const DESCRIBE: &str = r#"
+    LoadParam r1, 1
+    GetByIdShort r2, r1, 0, "length"
+    LoadConstString r3, "items: "
+    Add r0, r3, r2
+    JmpTrue end, r2
+    LoadConstString r0, "empty"
+    end:
+    Ret r0
+"#;
+
+// The same function with the wide forms of every instruction
+const DESCRIBE_LONG: &str = r#"
+    LoadParam r1, 1
+    GetById r2, r1, 0, "length"
+    LoadConstStringLongIndex r3, "items: "
+    Add r0, r3, r2
+    JmpTrueLong end, r2
+    LoadConstStringLongIndex r0, "empty"
+    end:
+    Ret r0
+"#;
+
+fn sample(global: &str, describe: &str) -> MemoryFile {
+    file_with(96, &[("global", 1, global), ("describe", 2, describe)])
+}
+
+#[test]
+fn fingerprints_ignore_string_ids_and_operand_widths() {
+    let mut f = sample(
+        r#"
+        CreateClosure r1, r0, 1
+        LoadConstString r2, "ready"
+        Call2 r0, r1, r0, r2
+        Ret r0
+        "#,
+        DESCRIBE,
+    );
+    // Strings loaded first, so every string of `describe` gets another ID
+    let mut other = sample(
+        r#"
+        LoadConstString r2, "padding 1"
+        LoadConstString r2, "padding 2"
+        LoadConstString r2, "padding 3"
+        CreateClosure r1, r0, 1
+        LoadConstString r2, "ready"
+        Call2 r0, r1, r0, r2
+        Ret r0
+        "#,
+        DESCRIBE_LONG,
+    );
+
+    let before = f.get_function_fingerprint(1);
+    let after = other.get_function_fingerprint(1);
+    assert_eq!(before.hash, after.hash);
+    assert_eq!(before.tokens, after.tokens);
+    assert_eq!(before.trigrams, after.trigrams);
+    assert_eq!(before.similarity(&after), 1.0);
+    assert_eq!(after.strings, vec!["empty", "items: ", "length"]);
+    assert_eq!(f.get_function_fingerprint(0).callees, vec!["describe"]);
+
+    let matches = f.match_functions(&mut other);
+    let describe = matches.iter().find(|m| m.left == 1).unwrap();
+    assert_eq!(describe.right, 1);
+    assert_eq!(describe.kind, MatchKind::Exact);
+}
+
+#[test]
+fn fingerprints_survive_a_string_table_remap() {
+    let mut f = reread(&mut sample(
+        r#"
+        CreateClosure r1, r0, 1
+        LoadConstString r2, "ready"
+        Call2 r0, r1, r0, r2
+        Ret r0
+        "#,
+        DESCRIBE,
+    ));
+    let before = f.get_fingerprints();
+
+    // Reverse the table and put enough strings in front of it that every ID
+    // changes and the short forms no longer fit
+    let mut pairs: Vec<StringTypePair> = (0..300)
+        .map(|i| StringTypePair {
+            string: format!("padding{}", i),
+            kind: StringKind::String,
+        })
+        .collect();
+    pairs.extend(f.get_strings_by_kind().into_iter().rev());
+    let map = f.replace_string_pairs(pairs).unwrap();
+    assert!(map
+        .iter()
+        .enumerate()
+        .all(|(old, new)| new.unwrap() != old as u32));
+
+    let mut f = reread(&mut f);
+    assert!(f.validate().is_empty(), "{:?}", f.validate());
+    assert_eq!(
+        f.get_function_instructions(1)[1].name(),
+        "GetById",
+        "the string operand should have outgrown GetByIdShort"
+    );
+
+    let after = f.get_fingerprints();
+    assert_eq!(before.len(), after.len());
+    for (before, after) in before.iter().zip(after.iter()) {
+        assert_eq!(before.name, after.name);
+        assert_eq!(before.hash, after.hash, "{}", before.name);
+        assert_eq!(before.strings, after.strings);
+        assert_eq!(before.callees, after.callees);
+        assert_eq!(before.tokens, after.tokens);
+        assert_eq!(before.trigrams, after.trigrams);
+        assert_eq!(before.similarity(after), 1.0);
+    }
+}
+
+#[test]
+fn wide_forms_normalize_to_the_short_name() {
+    for (name, normalized) in [
+        ("GetByIdShort", "GetById"),
+        ("GetByIdLong", "GetById"),
+        ("LoadConstStringLongIndex", "LoadConstString"),
+        ("PutOwnByIndexL", "PutOwnByIndex"),
+        ("LoadFromEnvironmentL", "LoadFromEnvironment"),
+        ("StoreToEnvironmentL", "StoreToEnvironment"),
+        ("StoreNPToEnvironmentL", "StoreNPToEnvironment"),
+        ("Call", "Call"),
+    ] {
+        assert_eq!(normalize_opcode_name(name), normalized);
+    }
+}
+
+#[test]
+fn string_ids_past_the_string_table_are_kept_as_numbers() {
+    let mut f = file_with(
+        96,
+        &[(
+            "global",
+            1,
+            "LoadConstString r0, 250\nGetById r1, r0, 0, 251\nRet r1",
+        )],
+    );
+
+    let fingerprint = f.get_function_fingerprint(0);
+    assert!(fingerprint.strings.is_empty());
+    assert_eq!(
+        f.get_normalized_instructions(0),
+        vec![
+            "LoadConstString r, 250",
+            "GetById r, r, $cache, 251",
+            "Ret r"
+        ]
+    );
+}
@@ -0,0 +1,101 @@
+/*
+ * Global inventory: declarations, and reads and writes through the global
+ * object - followed through Mov, and not confused with the same names on other
+ * objects.
+ */
+#![cfg(feature = "v96")]
+
+mod common;
+
+use common::file_with;
+use hermes_rs::globals::GlobalAccessKind;
+
+#[test]
+fn global_reads_and_writes_are_found() {
+    let mut f = file_with(
+        96,
+        &[
+            (
+                "global",
+                1,
+                r#"
+                DeclareGlobalVar "config"
+                GetGlobalObject r0
+                TryGetById r1, r0, 0, "__d"
+                PutById r0, r1, 0, "config"
+                LoadConstUndefined r1
+                Ret r1
+                "#,
+            ),
+            (
+                "polyfill",
+                2,
+                r#"
+                GetGlobalObject r1
+                Mov r2, r1
+                LoadParam r0, 1
+                TryPutById r2, r0, 0, "fetch"
+                GetById r3, r1, 0, "navigator"
+                # Not the global object any more
+                GetById r2, r3, 0, "userAgent"
+                PutById r3, r0, 0, "fetch"
+                DelById r0, r1, "oldFetch"
+                Ret r0
+                "#,
+            ),
+        ],
+    );
+
+    let kinds: Vec<(GlobalAccessKind, String)> = f
+        .get_function_global_accesses(1)
+        .into_iter()
+        .map(|access| (access.kind, access.name))
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            (GlobalAccessKind::TryPut, "fetch".to_string()),
+            (GlobalAccessKind::Get, "navigator".to_string()),
+            (GlobalAccessKind::Delete, "oldFetch".to_string()),
+        ]
+    );
+
+    let inventory = f.get_global_inventory();
+    let names = |globals: Vec<&hermes_rs::globals::GlobalVariable>| -> Vec<String> {
+        globals.iter().map(|g| g.name.clone()).collect()
+    };
+    assert_eq!(names(inventory.declared()), vec!["config"]);
+    assert_eq!(names(inventory.assigned()), vec!["fetch", "oldFetch"]);
+    assert_eq!(names(inventory.read_only()), vec!["__d", "navigator"]);
+
+    let config = &inventory.globals["config"];
+    assert_eq!(config.declared_in, vec![0]);
+    assert_eq!(config.written_in, vec![0]);
+    assert!(inventory.globals["__d"].is_metro_runtime());
+    assert!(!inventory.globals.contains_key("userAgent"));
+}
+
+#[test]
+fn string_ids_past_the_string_table_are_skipped() {
+    let mut f = file_with(
+        96,
+        &[(
+            "global",
+            1,
+            r#"
+            DeclareGlobalVar 200
+            GetGlobalObject r0
+            GetById r1, r0, 0, 201
+            GetById r1, r0, 0, "known"
+            Ret r1
+            "#,
+        )],
+    );
+
+    let names: Vec<String> = f
+        .get_function_global_accesses(0)
+        .into_iter()
+        .map(|access| access.name)
+        .collect();
+    assert_eq!(names, vec!["known"]);
+}
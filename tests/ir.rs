@@ -0,0 +1,158 @@
+/*
+ * Lifting to SSA: registers become values, values that merge at a join get a
+ * phi, constants are resolved and calls get their arguments in source order -
+ * the same IR whichever HBC version the function came from.
+ */
+#![cfg(all(feature = "v76", feature = "v96"))]
+
+mod common;
+
+use common::file_with;
+
+const LOOP: &str = r#"
+        LoadParam r0, 1
+        LoadConstZero r1
+        LoadConstZero r2
+        LoadConstUInt8 r3, 1
+    loop:
+        JNotLess done, r1, r0
+        Add r2, r2, r1
+        Add r1, r1, r3
+        Jmp loop
+    done:
+        Ret r2
+"#;
+
+const CONSTANTS: &str = r#"
+        LoadConstString r0, "hello"
+        LoadConstUInt8 r1, 42
+        LoadConstDouble r2, 1.5
+        LoadConstTrue r3
+        LoadConstNull r4
+        Mov r5, r0
+        Add r0, r5, r1
+        Ret r0
+"#;
+
+const ENVIRONMENT: &str = r#"
+        CreateEnvironment r0
+        LoadParam r1, 1
+        StoreToEnvironment r0, 2, r1
+        GetEnvironment r2, 0
+        LoadFromEnvironment r3, r2, 1
+        Ret r3
+"#;
+
+// f(a, b) with Call3, and g(a, b, c) with Call - `this` and the arguments at the
+// end of the frame, last argument first
+const CALLS: &str = r#"
+        GetGlobalObject r0
+        GetById r1, r0, 0, "f"
+        LoadConstUInt8 r2, 1
+        LoadConstUInt8 r3, 2
+        Call3 r4, r1, r0, r2, r3
+        GetById r1, r0, 0, "g"
+        LoadConstUInt8 r6, 3
+        LoadConstUInt8 r7, 2
+        LoadConstUInt8 r8, 1
+        Mov r9, r0
+        Call r4, r1, 4
+        Ret r4
+"#;
+
+/// Lifts the snippet on v96 and on v76, which should give the same IR.
+fn lift(snippet: &str) -> String {
+    let ir: Vec<String> = [96, 76]
+        .iter()
+        .map(|&version| {
+            let mut f = file_with(version, &[("global", 1, snippet)]);
+            f.lift_function(0).to_string()
+        })
+        .collect();
+    assert_eq!(ir[0], ir[1]);
+    ir[0].clone()
+}
+
+#[test]
+fn loop_headers_get_phis() {
+    assert_eq!(
+        lift(LOOP),
+        "\
+function global(#0, 1 params):
+bb0: # offset 0
+  %0 = param 1
+  %1 = const 0
+  %2 = const 0
+  %3 = const 1
+  jump bb1
+bb1: # offset 10, preds: bb0, bb2
+  %4 = phi [bb0: %1, bb2: %10] # r1
+  %7 = phi [bb0: %2, bb2: %8] # r2
+  %6 = Less %4, %0
+  branch %6, bb2, bb3
+bb2: # offset 14, preds: bb1
+  %8 = Add %7, %4
+  %10 = Add %4, %3
+  jump bb1
+bb3: # offset 24, preds: bb1
+  return %7
+"
+    );
+}
+
+#[test]
+fn constants_are_resolved_and_moves_disappear() {
+    assert_eq!(
+        lift(CONSTANTS),
+        "\
+function global(#0, 1 params):
+bb0: # offset 0
+  %0 = const \"hello\"
+  %1 = const 42
+  %2 = const 1.5
+  %3 = const true
+  %4 = const null
+  %5 = Add %0, %1
+  return %5
+"
+    );
+}
+
+#[test]
+fn environment_loads_and_stores() {
+    assert_eq!(
+        lift(ENVIRONMENT),
+        "\
+function global(#0, 1 params):
+bb0: # offset 0
+  %0 = create_environment
+  %1 = param 1
+  store_env %0[2] = %1
+  %2 = get_environment 0
+  %3 = load_env %2[1]
+  return %3
+"
+    );
+}
+
+#[test]
+fn call_arguments_are_in_source_order() {
+    assert_eq!(
+        lift(CALLS),
+        "\
+function global(#0, 1 params):
+bb0: # offset 0
+  %0 = global_object
+  %1 = get_property %0[\"f\"]
+  %2 = const 1
+  %3 = const 2
+  %4 = call %1, this=%0, (%2, %3)
+  %5 = get_property %0[\"g\"]
+  %6 = const 3
+  %7 = const 2
+  %8 = const 1
+  %9 = call %5, this=%0, (%8, %7, %6)
+  return %9
+"
+    );
+}
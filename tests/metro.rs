@@ -0,0 +1,171 @@
+/*
+ * Metro module recovery: `__d(factory, id, deps, name?)` calls in the global
+ * function are found by tracking what each register holds, whichever call form
+ * and register order the compiler picked.
+ */
+#![cfg(feature = "v96")]
+
+mod common;
+
+use common::file_with;
+use hermes_rs::metro::{MetroDependencyGraph, MetroModule};
+
+const GLOBAL: &str = r#"
+    GetGlobalObject r0
+    TryGetById r1, r0, 1, "__d"
+    LoadConstUndefined r2
+
+    # __d(factory1, 7, [8, 9])
+    CreateClosure r3, r2, 1
+    LoadConstUInt8 r4, 7
+    NewArray r5, 2
+    LoadConstUInt8 r6, 8
+    PutOwnByIndex r5, r6, 0
+    LoadConstUInt8 r6, 9
+    PutOwnByIndex r5, r6, 1
+    Call4 r6, r1, r2, r3, r4, r5
+
+    # __d(factory3, 8, [9], "lib/b.js") through Call, which takes `this` and the
+    # arguments from the end of the frame - and __d moved to another register
+    Mov r7, r1
+    LoadConstString r8, "lib/b.js"
+    NewArray r9, 1
+    LoadConstUInt8 r10, 9
+    PutOwnByIndex r9, r10, 0
+    LoadConstUInt8 r10, 8
+    CreateClosure r11, r2, 3
+    LoadConstUndefined r12
+    Call r6, r7, 5
+
+    # Not a module: the register holding __d was overwritten
+    LoadConstZero r1
+    Call4 r6, r1, r2, r3, r4, r5
+    Ret r2
+"#;
+
+#[test]
+fn modules_are_recovered_from_define_calls() {
+    let mut f = file_with(
+        96,
+        &[
+            ("global", 1, GLOBAL),
+            (
+                "factory1",
+                1,
+                r#"
+                LoadConstUndefined r0
+                CreateClosure r1, r0, 2
+                CreateClosure r1, r0, 2
+                Ret r0
+                "#,
+            ),
+            (
+                "helper",
+                1,
+                r#"
+                LoadConstUndefined r0
+                CreateClosure r1, r0, 1
+                Ret r0
+                "#,
+            ),
+            ("factory3", 1, "LoadConstUndefined r0\nRet r0"),
+        ],
+    );
+
+    let modules = f.get_metro_modules();
+    let summary: Vec<_> = modules
+        .iter()
+        .map(|m| {
+            (
+                m.id,
+                m.factory_function,
+                m.dependencies.clone(),
+                m.verbose_name.clone(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (7, 1, vec![8, 9], None),
+            (8, 3, vec![9], Some("lib/b.js".to_string())),
+        ]
+    );
+
+    let graph = MetroDependencyGraph::new(modules);
+    assert_eq!(graph.module_by_factory(3).map(|m| m.id), Some(8));
+    assert!(graph.module_by_factory(2).is_none());
+    assert_eq!(f.get_metro_module_by_factory(3).map(|m| m.id), Some(8));
+    assert!(f.get_metro_module_by_factory(2).is_none());
+    assert_eq!(graph.dependents[&9], vec![7, 8]);
+    assert_eq!(graph.dependents[&8], vec![7]);
+
+    // helper is listed once, though it's created twice, and the cycle back to
+    // the factory stops there
+    assert_eq!(f.get_nested_functions(1), vec![2]);
+}
+
+#[test]
+fn strings_past_the_table_are_not_names() {
+    let trivial = "LoadConstUndefined r0\nRet r0";
+    let mut f = file_with(
+        96,
+        &[
+            ("global", 1, GLOBAL),
+            ("factory1", 1, trivial),
+            ("helper", 1, trivial),
+            ("factory3", 1, trivial),
+        ],
+    );
+
+    // Only "__d" is left, so "lib/b.js" is past the end of the string table
+    f.string_storage.truncate(1);
+    let names: Vec<_> = f
+        .get_metro_modules()
+        .iter()
+        .map(|m| (m.id, m.verbose_name.clone()))
+        .collect();
+    assert_eq!(names, vec![(7, None), (8, None)]);
+}
+
+#[test]
+fn graph_exports() {
+    // Module 2 is only ever depended on, never defined
+    let graph = MetroDependencyGraph::new(vec![
+        MetroModule {
+            id: 0,
+            factory_function: 1,
+            dependencies: vec![1, 2],
+            verbose_name: Some("src/\"a\"\\b.js".to_string()),
+        },
+        MetroModule {
+            id: 1,
+            factory_function: 2,
+            dependencies: vec![2],
+            verbose_name: None,
+        },
+    ]);
+
+    assert_eq!(
+        graph.to_json(),
+        r#"{
+  "modules": [
+    {"id": 0, "factory_function": 1, "verbose_name": "src/\"a\"\\b.js", "dependencies": [1, 2], "dependents": []},
+    {"id": 1, "factory_function": 2, "verbose_name": null, "dependencies": [2], "dependents": [0]},
+    {"id": 2, "factory_function": null, "verbose_name": null, "dependencies": [], "dependents": [0, 1]}
+  ]
+}
+"#
+    );
+    assert_eq!(
+        graph.to_dot(),
+        r#"digraph metro {
+  0 [label="0: src/\"a\"\\b.js"];
+  1 [label="1"];
+  0 -> 1;
+  0 -> 2;
+  1 -> 2;
+}
+"#
+    );
+}
@@ -0,0 +1,326 @@
+/*
+ * Instruction patterns: the regex subset used for string operands, the rule
+ * parser, matching with variables and `...`, and the search binary.
+ */
+#![cfg(feature = "v96")]
+
+mod common;
+
+use common::{file_with, to_bytes, MemoryFile};
+use hermes_rs::pattern::{match_rule, parse_pattern, parse_rules, Regex, StepGap};
+use std::process::Command;
+
+fn re(source: &str) -> Regex {
+    Regex::new(source, false).unwrap()
+}
+
+#[test]
+fn regex_anchors() {
+    assert!(re("^https").is_match("https://x"));
+    assert!(!re("^https").is_match("see https://x"));
+    assert!(re("json$").is_match("data.json"));
+    assert!(!re("json$").is_match("data.json5"));
+    assert!(re("^$").is_match(""));
+    assert!(!re("^ab$").is_match("abc"));
+    // Unanchored matches anywhere, including nowhere
+    assert!(re("b").is_match("abc"));
+    assert!(re("").is_match("anything"));
+    // An escaped `$` is just a dollar sign
+    assert!(re("a\\$").is_match("a$b"));
+}
+
+#[test]
+fn regex_classes() {
+    assert!(re("^[a-c]+$").is_match("abcab"));
+    assert!(!re("^[a-c]+$").is_match("abcd"));
+    assert!(re("^[^\"]*$").is_match("no quotes"));
+    assert!(!re("^[^\"]*$").is_match("a \"quote\""));
+    assert!(re("^\\d\\d\\d$").is_match("404"));
+    assert!(!re("^\\d+$").is_match("4o4"));
+    assert!(re("^\\w+\\s\\W$").is_match("foo_1 !"));
+    assert!(re("^\\D\\S$").is_match("a-"));
+    assert!(re("^[\\d.]+$").is_match("1.2.3"));
+    // `]` first in a class is literal, `-` last is literal
+    assert!(re("^[]a-]+$").is_match("]-a"));
+    assert!(re("^a.c$").is_match("abc"));
+    assert!(!re("^a.c$").is_match("ac"));
+}
+
+#[test]
+fn regex_alternation_and_quantifiers() {
+    let scheme = re("^https?://|^wss?://");
+    assert!(scheme.is_match("http://x"));
+    assert!(scheme.is_match("https://x"));
+    assert!(scheme.is_match("ws://x"));
+    assert!(!scheme.is_match("ftp://x"));
+    // `|` inside a class isn't alternation
+    assert!(re("^[a|b]$").is_match("|"));
+
+    assert!(re("^ab*c$").is_match("ac"));
+    assert!(re("^ab*c$").is_match("abbbc"));
+    assert!(!re("^ab+c$").is_match("ac"));
+    assert!(re("^ab+c$").is_match("abc"));
+    assert!(re("^ab?c$").is_match("ac"));
+    assert!(!re("^ab?c$").is_match("abbc"));
+    // Greedy, but backs off for the rest of the pattern
+    assert!(re("^a.*b$").is_match("axxbxxb"));
+    assert!(re("^a+a$").is_match("aaa"));
+}
+
+#[test]
+fn regex_escapes_and_case() {
+    assert!(re("^a\\.b$").is_match("a.b"));
+    assert!(!re("^a\\.b$").is_match("axb"));
+    assert!(re("^\\*\\+\\?\\|\\[$").is_match("*+?|["));
+    assert!(re("^a\\tb$").is_match("a\tb"));
+    assert!(re("^https:\\/\\/").is_match("https://x"));
+
+    let insensitive = Regex::new("^fetch[A-Z]$", true).unwrap();
+    assert!(insensitive.is_match("FETCHx"));
+    assert!(!re("^fetch[A-Z]$").is_match("FETCHx"));
+}
+
+#[test]
+fn regex_rejects_what_it_cannot_match() {
+    for (source, error) in [
+        ("a{2,3}", "unsupported `{`"),
+        ("(ab)+", "unsupported `(`"),
+        ("(a|b)", "unsupported `(`"),
+        ("a+?", "nothing to repeat"),
+        ("*a", "nothing to repeat"),
+        ("\\bword", "unsupported escape `\\b`"),
+        ("(a)\\1", "unsupported `(`"),
+        ("a\\1", "unsupported escape `\\1`"),
+        ("[\\x41]", "unsupported escape `\\x`"),
+        ("[abc", "unterminated character class"),
+        ("abc\\", "trailing backslash"),
+    ] {
+        let err = Regex::new(source, false).unwrap_err();
+        assert!(err.contains(error), "/{}/: {}", source, err);
+    }
+}
+
+#[test]
+fn parser_reads_steps_and_gaps() {
+    let rules = parse_rules(
+        r#"
+        // two rules
+        rule a
+            GetById*|TryGetById* $obj _ _ "x"   # a comment
+            within 3 Mov _, $obj
+            then Ret
+        end
+
+        rule b
+            LoadConstString* _ /a#b\/c/i
+            after Call* p0=$f
+        end
+        "#,
+    )
+    .unwrap();
+    assert_eq!(rules.len(), 2);
+    assert_eq!(rules[0].name, "a");
+    let gaps: Vec<&StepGap> = rules[0].steps.iter().map(|s| &s.gap).collect();
+    assert_eq!(
+        gaps,
+        vec![&StepGap::Anywhere, &StepGap::Within(3), &StepGap::Next]
+    );
+    assert_eq!(
+        rules[0].steps[0].opcode.alternatives,
+        vec!["GetById*", "TryGetById*"]
+    );
+    assert_eq!(rules[0].steps[1].operands.len(), 2);
+    assert_eq!(rules[1].steps[1].gap, StepGap::Anywhere);
+
+    // `#` and `//` inside a regex don't start a comment, and `\/` is a slash
+    assert!(rules[0].steps[0].opcode.matches("GetByIdShort"));
+    let rule = parse_pattern("p", "LoadConstString* _ /a#b\\/c/i").unwrap();
+    assert_eq!(rule.steps, rules[1].steps[..1]);
+}
+
+#[test]
+fn parser_errors() {
+    for (text, error) in [
+        ("rule a\n  Mov\n", "rule a (line 1): missing `end`"),
+        (
+            "rule a\n  Mov\nrule b\nend\n",
+            "line 3: `rule` inside a rule",
+        ),
+        ("Mov\n", "line 1: expected `rule <name>`"),
+        ("rule\n", "line 1: rule without a name"),
+        (
+            "rule a\n  Mov\n  within Ret\nend\n",
+            "positive instruction count",
+        ),
+        (
+            "rule a\n  Mov\n  within 0 Ret\nend\n",
+            "positive instruction count",
+        ),
+        (
+            "rule a\n  Mov\n  within -2 Ret\nend\n",
+            "positive instruction count",
+        ),
+        (
+            "rule a\n  within 2 Mov\nend\n",
+            "the first step can't start with `within`",
+        ),
+        ("rule a\n  Mov /a{2}/\nend\n", "unsupported `{`"),
+        ("rule a\n  Mov %x\nend\n", "can't parse operand `%x`"),
+        ("rule a\n  Mov /a/g\nend\n", "unknown regex flags `g`"),
+        ("rule a\n  Mov \"open\nend\n", "unterminated string"),
+        ("rule a\n  Mov $\nend\n", "`$` without a variable name"),
+        ("rule a\nend\n", "empty pattern"),
+    ] {
+        let err = parse_rules(text).unwrap_err();
+        assert!(err.contains(error), "{:?}: {}", text, err);
+    }
+    assert!(parse_pattern("p", "Mov; ; then").is_err());
+}
+
+fn fetch_file() -> MemoryFile {
+    file_with(
+        96,
+        &[
+            ("global", 1, "LoadConstUndefined r0\nRet r0"),
+            (
+                "fetchIt",
+                1,
+                r#"
+                LoadConstUndefined r1
+                GetByIdShort r2, r1, 1, "fetch"
+                LoadConstString r0, "https://x"
+                Call2 r0, r2, r1, r0
+                Ret r0
+                "#,
+            ),
+            (
+                "fetchPlain",
+                1,
+                r#"
+                LoadConstUndefined r1
+                GetByIdShort r2, r1, 1, "fetch"
+                LoadConstString r0, "http://x"
+                Call2 r0, r2, r1, r0
+                Ret r0
+                "#,
+            ),
+            (
+                "fetchOther",
+                1,
+                r#"
+                LoadConstUndefined r1
+                GetByIdShort r2, r1, 1, "fetch"
+                GetByIdShort r3, r1, 2, "other"
+                LoadConstString r0, "https://x"
+                Call2 r0, r3, r1, r0
+                Ret r0
+                "#,
+            ),
+        ],
+    )
+}
+
+#[test]
+fn variables_bind_and_rest_skips_operands() {
+    let mut f = fetch_file();
+    let rule = parse_pattern(
+        "p",
+        "GetById* $fetch _ _ \"fetch\"; within 5 Call* _ $fetch ... $url ...",
+    )
+    .unwrap();
+
+    // $fetch is r2 here, so the call has to be through r2 - and `$url` then
+    // binds to whatever `...` leaves it
+    let instructions = f.get_pattern_instructions(1);
+    assert_eq!(match_rule(&rule, &instructions), vec![vec![1, 3]]);
+    // fetchOther calls r3, which isn't what "fetch" was loaded into
+    assert!(match_rule(&rule, &f.get_pattern_instructions(3)).is_empty());
+
+    // `then` is only the next instruction, `within 1` the same
+    let next = parse_pattern("p", "GetById* ...; then Call*").unwrap();
+    assert!(match_rule(&next, &instructions).is_empty());
+    let close = parse_pattern("p", "LoadConstString* ...; within 1 Call*").unwrap();
+    assert_eq!(match_rule(&close, &instructions), vec![vec![2, 3]]);
+
+    // Without `...`, every operand has to be accounted for
+    let exact = parse_pattern("p", "Call2 _ r2 r1").unwrap();
+    assert!(match_rule(&exact, &instructions).is_empty());
+    let exact = parse_pattern("p", "Call2 _ r2 r1 r0").unwrap();
+    assert_eq!(match_rule(&exact, &instructions), vec![vec![3]]);
+    // Named operands alone don't need to
+    let named = parse_pattern("p", "GetById* p1=\"fetch\" r0=r2").unwrap();
+    assert_eq!(match_rule(&named, &instructions), vec![vec![1]]);
+    let numbers = parse_pattern("p", "GetById* _ _ 1 _").unwrap();
+    assert_eq!(match_rule(&numbers, &instructions), vec![vec![1]]);
+}
+
+// The rule in the README's search section
+fn readme_rule() -> String {
+    let readme = include_str!("../README.md");
+    let start = readme.find("rule fetch_https").unwrap();
+    let end = start + readme[start..].find("\nend").unwrap();
+    format!("{}\nend\n", &readme[start..end])
+}
+
+#[test]
+fn readme_rule_finds_https_fetches() {
+    let rules = parse_rules(&readme_rule()).unwrap();
+    let mut f = fetch_file();
+    let matches = f.search(&rules);
+
+    assert_eq!(matches.len(), 1);
+    let m = &matches[0];
+    assert_eq!(
+        (m.rule.as_str(), m.function_id, m.function_name.as_str()),
+        ("fetch_https", 1, "fetchIt")
+    );
+    assert_eq!(m.offsets, vec![2, 7, 11]);
+    assert_eq!(m.offset(), 2);
+    assert_eq!(
+        m.instructions,
+        vec![
+            "GetByIdShort r2, r1, 1, \"fetch\"",
+            "LoadConstString r0, \"https://x\"",
+            "Call2 r0, r2, r1, r0",
+        ]
+    );
+}
+
+fn search(args: &[&str]) -> (bool, String) {
+    let path = std::env::temp_dir().join(format!("hermes_rs_pattern_{}.hbc", std::process::id()));
+    std::fs::write(&path, to_bytes(&mut fetch_file())).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_search"))
+        .arg(&path)
+        .args(args)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    (
+        output.status.success(),
+        String::from_utf8_lossy(&output.stdout).into_owned(),
+    )
+}
+
+#[test]
+fn search_binary_prints_matches() {
+    let (ok, stdout) = search(&["--pattern", "LoadConstString* _ /^https:\\/\\//"]);
+    assert!(ok, "{}", stdout);
+    assert!(stdout.contains("pattern: #1 fetchIt @ 7"), "{}", stdout);
+    assert!(stdout.contains("pattern: #3 fetchOther @ 12"), "{}", stdout);
+    assert!(stdout.ends_with("2 match(es)\n"), "{}", stdout);
+}
+
+#[test]
+fn search_binary_rejects_unsupported_regexes() {
+    for (pattern, error) in [
+        ("LoadConstString* _ /^h{2,3}/", "unsupported `{`"),
+        ("LoadConstString* _ /^(https|http):/", "unsupported `(`"),
+        ("LoadConstString* _ /\\bhttp/", "unsupported escape `\\b`"),
+    ] {
+        let (ok, stdout) = search(&["--pattern", pattern]);
+        assert!(!ok, "{}: {}", pattern, stdout);
+        assert!(stdout.starts_with("Invalid pattern: "), "{}", stdout);
+        assert!(stdout.contains(error), "{}: {}", pattern, stdout);
+        assert!(!stdout.contains("match(es)"), "{}", stdout);
+    }
+}
@@ -0,0 +1,110 @@
+/*
+ * Property inventory: reads, writes and deletes by name, and object literals
+ * grouped by key set whichever order the keys were defined in.
+ */
+#![cfg(feature = "v96")]
+
+mod common;
+
+use common::file_with;
+use hermes_rs::properties::PropertyAccessKind;
+
+#[test]
+fn literals_with_the_same_keys_share_a_shape() {
+    let mut f = file_with(
+        96,
+        &[
+            ("global", 1, "LoadConstUndefined r0\nRet r0"),
+            (
+                "request",
+                2,
+                r#"
+                LoadParam r1, 1
+                NewObject r0
+                PutNewOwnById r0, r1, "url"
+                PutNewOwnById r0, r1, "method"
+                Ret r0
+                "#,
+            ),
+            (
+                "retry",
+                2,
+                r#"
+                LoadParam r1, 1
+                NewObject r2
+                Mov r0, r2
+                PutNewOwnById r0, r1, "method"
+                PutNewOwnById r2, r1, "url"
+                GetById r3, r1, 0, "url"
+                PutById r1, r3, 0, "lastUrl"
+                DelById r3, r1, "url"
+                NewObject r2
+                PutNewOwnById r2, r1, "id"
+                Ret r0
+                "#,
+            ),
+        ],
+    );
+
+    let inventory = f.get_property_inventory();
+    let shapes: Vec<(Vec<String>, Vec<u32>)> = inventory
+        .shapes
+        .iter()
+        .map(|shape| (shape.keys.clone(), shape.functions()))
+        .collect();
+    assert_eq!(
+        shapes,
+        vec![
+            (vec!["method".to_string(), "url".to_string()], vec![1, 2]),
+            (vec!["id".to_string()], vec![2]),
+        ]
+    );
+    assert_eq!(inventory.shapes_with_keys(&["url"]).len(), 1);
+    assert!(inventory.shapes_with_keys(&["url", "id"]).is_empty());
+
+    let url = &inventory.properties["url"];
+    assert_eq!((url.reads, url.writes, url.deletes), (1, 2, 1));
+    assert_eq!(url.functions, vec![1, 2]);
+    assert_eq!(inventory.properties["lastUrl"].writes, 1);
+
+    let kinds: Vec<PropertyAccessKind> = f
+        .get_function_property_accesses(2)
+        .iter()
+        .map(|access| access.kind)
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            PropertyAccessKind::PutNewOwn,
+            PropertyAccessKind::PutNewOwn,
+            PropertyAccessKind::Get,
+            PropertyAccessKind::Put,
+            PropertyAccessKind::Delete,
+            PropertyAccessKind::PutNewOwn,
+        ]
+    );
+}
+
+#[test]
+fn string_ids_past_the_string_table_are_skipped() {
+    let mut f = file_with(
+        96,
+        &[(
+            "global",
+            1,
+            r#"
+            LoadParam r1, 1
+            NewObject r0
+            PutNewOwnById r0, r1, 200
+            GetById r2, r1, 0, 201
+            PutById r1, r2, 0, "known"
+            Ret r0
+            "#,
+        )],
+    );
+
+    let accesses = f.get_function_property_accesses(0);
+    let names: Vec<&str> = accesses.iter().map(|access| access.name.as_str()).collect();
+    assert_eq!(names, vec!["known"]);
+    assert!(f.get_function_object_literals(0)[0].keys.is_empty());
+}
@@ -0,0 +1,89 @@
+/*
+ * Dead code: a function is live if it's an entry point or something live
+ * creates a closure for it or calls it directly, however deep the nesting.
+ */
+#![cfg(feature = "v96")]
+
+mod common;
+
+use common::file_with;
+use hermes_rs::reachability::FunctionReferenceKind;
+
+#[test]
+fn closures_created_by_live_code_are_live() {
+    let mut f = file_with(
+        96,
+        &[
+            (
+                "global",
+                1,
+                "LoadConstUndefined r0\nCreateClosure r1, r0, 1\nRet r0",
+            ),
+            // Only ever created as a closure inside another closure
+            (
+                "outer",
+                1,
+                r#"
+                LoadConstUndefined r0
+                CreateClosure r1, r0, 2
+                CreateAsyncClosure r1, r0, 3
+                Ret r1
+                "#,
+            ),
+            (
+                "inner",
+                1,
+                "LoadConstUndefined r0\nCallDirect r0, 1, 6\nRet r0",
+            ),
+            ("later", 1, "LoadConstUndefined r0\nRet r0"),
+            // Dead, so what it creates is too
+            (
+                "unused",
+                1,
+                "LoadConstUndefined r0\nCreateClosure r1, r0, 5\nRet r0",
+            ),
+            // Dead: only dead code and itself create it
+            (
+                "unusedHelper",
+                1,
+                "LoadConstUndefined r0\nCreateClosure r1, r0, 5\nRet r0",
+            ),
+            ("direct", 1, "LoadConstUndefined r0\nRet r0"),
+        ],
+    );
+
+    let references = f.get_function_references(1);
+    let targets: Vec<(u32, FunctionReferenceKind)> =
+        references.iter().map(|r| (r.to, r.kind)).collect();
+    assert_eq!(
+        targets,
+        vec![
+            (2, FunctionReferenceKind::Closure),
+            (3, FunctionReferenceKind::Closure),
+        ]
+    );
+    assert_eq!(references[1].offset, references[0].offset + 5);
+    assert_eq!(
+        f.get_function_references(2)[0].kind,
+        FunctionReferenceKind::DirectCall
+    );
+
+    let report = f.get_reachability_report();
+    assert_eq!(report.entry_points, vec![0]);
+    assert_eq!(report.reachable_count, 5);
+
+    let dead: Vec<(u32, &str, Vec<u32>)> = report
+        .unreferenced
+        .iter()
+        .map(|u| (u.function_id, u.name.as_str(), u.referenced_by.clone()))
+        .collect();
+    assert_eq!(
+        dead,
+        vec![(4, "unused", vec![]), (5, "unusedHelper", vec![4])]
+    );
+    assert_eq!(
+        report.unreferenced_size(),
+        report.unreferenced.iter().map(|u| u.byte_size).sum::<u32>()
+    );
+    assert!(report.unreferenced_size() > 0);
+}
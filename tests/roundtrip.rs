@@ -0,0 +1,168 @@
+/*
+ * Round-trip tests: a small file is serialized, read back and serialized again,
+ * and both outputs have to be byte-for-byte identical - with and without the
+ * instructions having been parsed in between. The layout the writer plans has
+ * to match the layout of the file it read.
+ */
+
+mod common;
+
+use common::{file_with_edits, read, to_bytes, MemoryFile};
+use hermes_rs::cjs_module::{CJSModule, CJSModuleEntry};
+use hermes_rs::function_header::{FunctionHeader, LargeFunctionHeader};
+use hermes_rs::function_sources::FunctionSourceEntry;
+use hermes_rs::regexp_table::RegExpTableEntry;
+
+/*
+ * Three functions - one with an exception handler, one loading a string long
+ * enough to overflow and with a frame too big for a small header - plus literal
+ * buffers, BigInts, RegExps, CJS modules and function sources.
+ */
+fn sample(version: u32) -> MemoryFile {
+    let long = format!("LoadConstString r0, \"{}\"\nRet r0", "a".repeat(300));
+    let mut f = file_with_edits(
+        version,
+        &[
+            (
+                "global",
+                0,
+                "
+                    CreateClosure r0, r0, 1
+                    CreateClosure r0, r0, 2
+                    LoadConstZero r0
+                    Ret r0
+                ",
+            ),
+            (
+                "hello",
+                1,
+                "
+                    GetGlobalObject r1
+                    GetByIdShort r2, r1, 1, \"console\"
+                    GetByIdShort r2, r2, 2, \"log\"
+                    LoadConstString r0, \"hello\"
+                    Call2 r0, r2, r1, r0
+                    Catch r0
+                    Ret r0
+                ",
+            ),
+            ("long", 1, &long),
+        ],
+        |idx, body| {
+            if idx == 1 {
+                body.add_handler(0, 5, 5).unwrap();
+            }
+        },
+    );
+
+    let FunctionHeader::Small(small) = f.function_headers[2].clone() else {
+        unreachable!()
+    };
+    f.function_headers[2] = FunctionHeader::Large(LargeFunctionHeader {
+        offset: small.offset,
+        param_count: small.param_count,
+        byte_size: small.byte_size,
+        func_name: small.func_name,
+        info_offset: small.info_offset,
+        frame_size: 300,
+        env_size: small.env_size,
+        highest_read_cache_index: small.highest_read_cache_index,
+        highest_write_cache_index: small.highest_write_cache_index,
+        flags: small.flags,
+        exception_handlers: vec![],
+        debug_info: None,
+    });
+
+    f.array_buffer_storage = vec![0x13, 0x01, 0x02, 0x03];
+    f.object_key_buffer = vec![0x51, 0x00, 0x00];
+    f.object_val_buffer = vec![0x11, 0x00];
+
+    if version >= 87 {
+        f.set_big_ints(vec![12345678901234567890]);
+    }
+
+    f.reg_exp_table = vec![RegExpTableEntry {
+        offset: 0,
+        length: 3,
+    }];
+    f.reg_exp_storage = vec![1, 2, 3];
+
+    f.cjs_modules = vec![CJSModule::CJSModuleEntry(CJSModuleEntry {
+        symbol_id: 1,
+        offset: 1,
+    })];
+    f.cjs_module_offset = 4;
+
+    if version >= 84 {
+        f.function_source_entries = vec![FunctionSourceEntry {
+            function_id: 1,
+            string_id: 1,
+        }];
+    }
+
+    f
+}
+
+fn assert_roundtrip(version: u32) {
+    let first = to_bytes(&mut sample(version));
+
+    // Unmodified, instructions never parsed
+    let mut file = read(first.clone());
+    let findings = file.validate();
+    assert!(findings.is_empty(), "{:?}", findings);
+    let layout = file.section_layout();
+    assert_eq!(layout.end(), first.len() as u64);
+    assert_eq!(layout, file.plan_layout());
+    assert_eq!(first, file.to_bytes());
+
+    // Unmodified, instructions parsed
+    assert_eq!(first, to_bytes(&mut read(first.clone())));
+}
+
+#[cfg(feature = "v76")]
+#[test]
+fn roundtrip_v76() {
+    assert_roundtrip(76);
+}
+
+#[cfg(feature = "v84")]
+#[test]
+fn roundtrip_v84() {
+    assert_roundtrip(84);
+}
+
+#[cfg(feature = "v89")]
+#[test]
+fn roundtrip_v89() {
+    assert_roundtrip(89);
+}
+
+#[cfg(feature = "v90")]
+#[test]
+fn roundtrip_v90() {
+    assert_roundtrip(90);
+}
+
+#[cfg(feature = "v93")]
+#[test]
+fn roundtrip_v93() {
+    assert_roundtrip(93);
+}
+
+#[cfg(feature = "v94")]
+#[test]
+fn roundtrip_v94() {
+    assert_roundtrip(94);
+}
+
+#[cfg(feature = "v95")]
+#[test]
+fn roundtrip_v95() {
+    assert_roundtrip(95);
+}
+
+#[cfg(feature = "v96")]
+#[test]
+fn roundtrip_v96() {
+    assert_roundtrip(96);
+}
@@ -0,0 +1,142 @@
+/*
+ * Library signatures: generated from a dev bundle whose `__d` calls name each
+ * module, and matched against a release build where the functions, strings
+ * and module ids have all moved.
+ */
+#![cfg(feature = "v96")]
+
+mod common;
+
+use common::file_with;
+use hermes_rs::signatures::{library_name, LabelKind, SignatureDatabase};
+use std::collections::HashMap;
+
+const LODASH_FACTORY: &str = r#"
+    LoadConstUndefined r0
+    CreateClosure r1, r0, {chunk}
+    LoadParam r2, 1
+    PutById r2, r1, 0, "exports"
+    LoadConstUndefined r0
+    Ret r0
+"#;
+
+const CHUNK: &str = r#"
+    LoadParam r1, 1
+    LoadParam r2, 2
+    NewArray r0, 0
+    GetByIdShort r3, r1, 0, "length"
+    Div r4, r3, r2
+    Ret r0
+"#;
+
+const APP_FACTORY: &str = r#"
+    LoadConstString r0, "hello app"
+    LoadParam r1, 1
+    PutById r1, r0, 0, "greeting"
+    LoadConstUndefined r0
+    Ret r0
+"#;
+
+#[test]
+fn library_functions_are_found_in_another_build() {
+    // Dev bundle: lodash's module is 0, named, and the app (1) is skipped
+    let reference_global = r#"
+        GetGlobalObject r0
+        TryGetById r1, r0, 1, "__d"
+        LoadConstUndefined r2
+
+        LoadConstString r8, "node_modules/lodash/chunk.js"
+        NewArray r9, 0
+        LoadConstZero r10
+        CreateClosure r11, r2, 1
+        LoadConstUndefined r12
+        Call r6, r1, 5
+
+        LoadConstString r8, "src/App.js"
+        NewArray r9, 0
+        LoadConstUInt8 r10, 1
+        CreateClosure r11, r2, 3
+        LoadConstUndefined r12
+        Call r6, r1, 5
+        Ret r2
+    "#;
+    let lodash_factory = LODASH_FACTORY.replace("{chunk}", "2");
+    let mut reference = file_with(
+        96,
+        &[
+            ("global", 1, reference_global),
+            ("", 1, &lodash_factory),
+            ("chunk", 3, CHUNK),
+            ("", 1, APP_FACTORY),
+        ],
+    );
+    let db = reference.generate_signatures(&HashMap::new());
+    assert_eq!(db.functions.len(), 2);
+    assert_eq!(db.modules.len(), 1);
+    let module = db.modules.values().next().unwrap();
+    assert_eq!(module.library, "lodash");
+    assert_eq!(module.module, "node_modules/lodash/chunk.js");
+    assert_eq!(module.function_count, 2);
+
+    // The database survives being written out
+    let db = SignatureDatabase::from_text(&db.to_text()).unwrap();
+
+    // Release bundle: no names, new module ids, the app first and an extra string
+    let target_global = r#"
+        GetGlobalObject r0
+        TryGetById r1, r0, 1, "__d"
+        LoadConstUndefined r2
+        LoadConstString r7, "unrelated"
+        NewArray r5, 0
+        CreateClosure r3, r2, 1
+        LoadConstUInt8 r4, 40
+        Call4 r6, r1, r2, r3, r4, r5
+        CreateClosure r3, r2, 2
+        LoadConstUInt8 r4, 41
+        Call4 r6, r1, r2, r3, r4, r5
+        Ret r2
+    "#;
+    let lodash_factory = LODASH_FACTORY.replace("{chunk}", "3");
+    let mut target = file_with(
+        96,
+        &[
+            ("global", 1, target_global),
+            ("", 1, APP_FACTORY),
+            ("", 1, &lodash_factory),
+            ("chunk", 3, CHUNK),
+        ],
+    );
+    let labels = target.identify_libraries(&db);
+
+    let functions: Vec<(u32, &str, LabelKind)> = labels
+        .functions
+        .iter()
+        .map(|(fid, label)| (*fid, label.library.as_str(), label.kind))
+        .collect();
+    assert_eq!(
+        functions,
+        vec![
+            (2, "lodash", LabelKind::Function),
+            (3, "lodash", LabelKind::Function),
+        ]
+    );
+    assert_eq!(
+        labels.modules.keys().copied().collect::<Vec<u32>>(),
+        vec![41]
+    );
+    assert_eq!(labels.modules[&41].kind, LabelKind::Module);
+    assert_eq!(labels.modules[&41].module, "node_modules/lodash/chunk.js");
+    assert!(!labels.is_library(0) && !labels.is_library(1));
+    assert_eq!(labels.libraries().get("lodash"), Some(&2));
+}
+
+#[test]
+fn library_names_come_from_the_package() {
+    assert_eq!(library_name("node_modules/lodash/chunk.js"), "lodash");
+    assert_eq!(
+        library_name("a/node_modules/@babel/runtime/helpers/x.js"),
+        "@babel/runtime"
+    );
+    assert_eq!(library_name("node_modules/a/node_modules/b/index.js"), "b");
+    assert_eq!(library_name("src/App.js"), "src/App.js");
+}
@@ -0,0 +1,239 @@
+/*
+ * String storage packing: identical strings and strings that are a suffix of
+ * another one share storage, and every string still reads back the same. The
+ * StringTableBuilder produces a string table that passes validation, and keeps
+ * strings that aren't valid Unicode exactly as they were.
+ */
+
+mod common;
+
+use common::{empty, read};
+use hermes_rs::hermes_file::builder::StringTypePair;
+use hermes_rs::hermes_file::validator::ValidationKind;
+use hermes_rs::jenkins::hash_string;
+use hermes_rs::string_table::{build_string_storage, HermesString, StringTableBuilder};
+use hermes_rs::StringKind;
+
+fn latin1(s: &str) -> HermesString {
+    HermesString::Latin1(s.as_bytes().to_vec())
+}
+
+fn utf16(s: &str) -> HermesString {
+    HermesString::Utf16(s.encode_utf16().collect())
+}
+
+#[test]
+fn packs_duplicates_and_suffixes() {
+    let strings = vec![
+        latin1("car"),
+        latin1("racecar"),
+        latin1("car"),
+        latin1("ecar"),
+        latin1(""),
+        utf16("💙 car"),
+        utf16("car"),
+    ];
+    let (storage, offsets) = build_string_storage(&strings, true);
+
+    // "racecar", a byte of padding and the UTF-16 "💙 car"
+    assert_eq!(storage.len(), 8 + 2 * "💙 car".encode_utf16().count());
+    assert_eq!(&storage[offsets[1] as usize..][..7], b"racecar");
+    assert_eq!(offsets[0], offsets[1] + 4);
+    assert_eq!(offsets[0], offsets[2]);
+    assert_eq!(offsets[3], offsets[1] + 3);
+    assert_eq!(offsets[5] % 2, 0);
+    assert_eq!(offsets[6], offsets[5] + 2 * 3);
+
+    let (storage, offsets) = build_string_storage(&strings, false);
+    assert_eq!(offsets[..5], [0, 3, 10, 13, 17]);
+    assert_eq!(storage.len(), 17 + 2 * 6 + 2 * 3);
+}
+
+#[test]
+fn builder_packs_strings() {
+    let strings = ["racecar", "car", "hello", "car", "ello"];
+    let pairs: Vec<StringTypePair> = strings
+        .iter()
+        .map(|s| StringTypePair {
+            string: s.to_string(),
+            kind: StringKind::String,
+        })
+        .collect();
+
+    let mut packed = empty(96);
+    packed.set_string_pairs_unordered(pairs.clone());
+    assert_eq!(packed.string_storage_bytes.len(), 12);
+    assert_eq!(packed.get_strings(), strings);
+
+    let mut unpacked = empty(96);
+    unpacked.pack_strings = false;
+    unpacked.set_string_pairs_unordered(pairs);
+    assert_eq!(unpacked.string_storage_bytes.len(), 22);
+    assert_eq!(unpacked.get_strings(), strings);
+}
+
+#[test]
+fn string_pairs_make_a_valid_file() {
+    let pair = |string: &str, kind: StringKind| StringTypePair {
+        string: string.to_string(),
+        kind,
+    };
+    let mut f = empty(96);
+
+    // Runs of kinds, as bundles have them, and a duplicate that keeps its own ID.
+    // Predefined strings are identifiers in this version, so the last two are one run.
+    f.set_string_pairs_unordered(vec![
+        pair("global", StringKind::String),
+        pair("length", StringKind::Identifier),
+        pair("push", StringKind::Identifier),
+        pair("hello", StringKind::String),
+        pair("length", StringKind::Identifier),
+        pair("Array", StringKind::Predefined),
+    ]);
+    assert_eq!(
+        f.get_strings(),
+        vec!["global", "length", "push", "hello", "length", "Array"]
+    );
+    assert_eq!(
+        f.identifier_hashes,
+        ["length", "push", "length", "Array"].map(hash_string)
+    );
+    assert_eq!(f.header.string_kind_count, 4);
+    assert_eq!(f.header.identifier_count, 4);
+
+    let mut f = read(f.to_bytes());
+    let findings = f.validate();
+    assert!(findings.is_empty(), "{:?}", findings);
+    assert_eq!(f.get_strings()[5], "Array");
+
+    // Grouped by list, whatever kind the pairs say
+    let mut f = empty(96);
+    f.set_strings_ordered(
+        vec![pair("global", StringKind::Identifier)],
+        vec![
+            pair("length", StringKind::String),
+            pair("push", StringKind::String),
+        ],
+        vec![],
+    );
+    assert_eq!(f.identifier_hashes, ["length", "push"].map(hash_string));
+    assert_eq!(f.header.string_kind_count, 2);
+    assert_eq!(f.header.identifier_count, 2);
+
+    let findings = read(f.to_bytes()).validate();
+    assert!(findings.is_empty(), "{:?}", findings);
+}
+
+#[test]
+fn overflow_strings_are_appended() {
+    let long = "a".repeat(300);
+    let mut f = empty(96);
+    f.set_string_pairs_unordered(vec![StringTypePair {
+        string: long.clone(),
+        kind: StringKind::String,
+    }]);
+    assert_eq!(f.overflow_string_storage.len(), 1);
+
+    let longer = "b".repeat(400);
+    assert_eq!(f.set_overflow_string(vec![longer.clone()]), 1);
+    assert_eq!(f.header.overflow_string_count, 2);
+    assert_eq!(
+        f.header.string_storage_size as usize,
+        f.string_storage_bytes.len()
+    );
+
+    // The string table still points at the first one
+    assert_eq!(f.get_strings(), vec![long]);
+    let offset = f.overflow_string_storage[1].offset as usize;
+    assert_eq!(&f.string_storage_bytes[offset..], longer.as_bytes());
+}
+
+#[test]
+fn string_table_builder() {
+    let mut strings = StringTableBuilder::new();
+    assert_eq!(strings.intern("hello", StringKind::String), 0);
+    assert_eq!(strings.intern("log", StringKind::Identifier), 1);
+    assert_eq!(strings.intern("café", StringKind::String), 2);
+    assert_eq!(strings.intern("hello", StringKind::Identifier), 0);
+    assert_eq!(strings.intern("log", StringKind::String), 1);
+    assert_eq!(strings.intern(&"x".repeat(300), StringKind::String), 3);
+    assert_eq!(strings.get("café"), Some(2));
+    assert_eq!(strings.len(), 4);
+
+    let mut f = empty(96);
+    f.set_string_table(&strings);
+
+    assert_eq!(
+        f.get_strings(),
+        vec!["hello", "log", "café", &"x".repeat(300)]
+    );
+    assert!(f.string_storage[2].is_utf_16);
+    assert!(!f.string_storage[1].is_utf_16);
+    assert_eq!(f.string_storage[3].length, 255);
+    assert_eq!(f.overflow_string_storage.len(), 1);
+
+    // "hello" was promoted to an identifier, so the kinds are
+    // [Identifier x 2, String x 2]
+    assert_eq!(f.string_kinds.len(), 2);
+    assert_eq!(f.string_kinds[0].get_length(), 2);
+    assert_eq!(
+        f.identifier_hashes,
+        vec![hash_string("hello"), hash_string("log")]
+    );
+
+    assert_eq!(f.header.string_kind_count, 2);
+    assert_eq!(f.header.identifier_count, 2);
+    assert_eq!(f.header.string_count, 4);
+    assert_eq!(f.header.overflow_string_count, 1);
+    assert_eq!(
+        f.header.string_storage_size as usize,
+        f.string_storage_bytes.len()
+    );
+
+    let findings = f.validate();
+    assert!(findings.is_empty(), "{:?}", findings);
+}
+
+#[test]
+fn strings_are_kept_exactly() {
+    let latin1 = HermesString::Latin1(b"caf\xe9".to_vec());
+    let lone_surrogate = HermesString::Utf16(vec![0x61, 0xd800]);
+    assert_eq!(latin1.to_string_lossy(), "café");
+    assert_eq!(lone_surrogate.to_string_lossy(), "a\u{fffd}");
+
+    let mut strings = StringTableBuilder::new();
+    strings.intern_hermes_string(latin1.clone(), StringKind::String);
+    strings.intern_hermes_string(lone_surrogate.clone(), StringKind::Identifier);
+
+    let mut f = empty(96);
+    f.set_string_table(&strings);
+    assert_eq!(f.get_strings(), vec!["café", "a\u{fffd}"]);
+    assert_eq!(
+        f.get_hermes_strings(),
+        Ok(vec![latin1.clone(), lone_surrogate.clone()])
+    );
+    assert_eq!(f.identifier_hashes, vec![lone_surrogate.identifier_hash()]);
+
+    // Rebuilding from what was read gives the same storage
+    let mut rebuilt = StringTableBuilder::new();
+    for string in f.get_hermes_strings().unwrap() {
+        rebuilt.intern_hermes_string(string, StringKind::String);
+    }
+    let table = rebuilt.build(96);
+    assert_eq!(table.string_storage_bytes, f.string_storage_bytes);
+
+    // A string past the end of storage is an error rather than a panic
+    f.string_storage[1].length = 100;
+    assert!(matches!(
+        f.get_hermes_string(1),
+        Err(ValidationKind::StringOutOfBounds { string_id: 1, .. })
+    ));
+    assert_eq!(f.get_string_from_storage_by_index(1), "");
+    assert!(matches!(
+        f.get_hermes_string(2),
+        Err(ValidationKind::StringIdOutOfRange {
+            string_id: 2,
+            count: 2
+        })
+    ));
+}
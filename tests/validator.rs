@@ -0,0 +1,178 @@
+/*
+ * Container validation: a file written by the writer is clean, and each kind
+ * of corruption - in the identifier hashes, the header or the footer - is
+ * reported as what it is.
+ */
+#![cfg(feature = "v96")]
+
+mod common;
+
+use common::{file_with, read, reread, to_bytes, MemoryFile};
+use hermes_rs::hermes_file::layout::SectionKind;
+use hermes_rs::hermes_file::validator::ValidationKind;
+use hermes_rs::jenkins::hash_string;
+use hermes_rs::verifier::Severity;
+
+fn sample_bytes() -> Vec<u8> {
+    to_bytes(&mut file_with(
+        96,
+        &[(
+            "global",
+            1,
+            r#"
+            LoadParam r1, 1
+            GetByIdShort r0, r1, 0, "length"
+            TryGetById r0, r1, 1, "console"
+            Ret r0
+            "#,
+        )],
+    ))
+}
+
+fn kinds(f: &mut MemoryFile) -> Vec<ValidationKind> {
+    f.validate()
+        .into_iter()
+        .map(|finding| finding.kind)
+        .collect()
+}
+
+#[test]
+fn written_files_are_clean() {
+    let mut f = read(sample_bytes());
+    assert_eq!(f.identifier_hashes.len(), 2);
+    assert_eq!(f.header.identifier_count, 2);
+    assert!(f.validate().is_empty(), "{:?}", f.validate());
+}
+
+#[test]
+fn shared_bodies_are_clean() {
+    // Two functions with the same code, as deduplicated bundles have them
+    let body = "LoadConstUndefined r0\nRet r0";
+    let mut f = reread(&mut file_with(
+        96,
+        &[("global", 0, body), ("copy", 0, body)],
+    ));
+    let offset = f.function_headers[0].offset();
+    f.function_headers[1].set_offset(offset);
+    assert!(f.validate().is_empty(), "{:?}", f.validate());
+
+    // Overlapping without being the same body is still an error
+    f.function_headers[1].set_offset(offset + 1);
+    assert!(kinds(&mut f)
+        .iter()
+        .any(|kind| matches!(kind, ValidationKind::SectionsOverlap { .. })));
+}
+
+#[test]
+fn short_identifier_hash_table() {
+    let mut f = read(sample_bytes());
+    f.identifier_hashes.pop();
+    assert_eq!(
+        kinds(&mut f),
+        vec![ValidationKind::IdentifierCountMismatch {
+            header: 2,
+            identifiers: 2,
+            hashes: 1
+        }]
+    );
+
+    // An extra hash is no better
+    let mut f = read(sample_bytes());
+    f.identifier_hashes.push(0);
+    assert_eq!(
+        kinds(&mut f),
+        vec![ValidationKind::IdentifierCountMismatch {
+            header: 2,
+            identifiers: 2,
+            hashes: 3
+        }]
+    );
+
+    // And a header that disagrees with both
+    let mut f = read(sample_bytes());
+    f.header.identifier_count = 3;
+    assert!(
+        kinds(&mut f).contains(&ValidationKind::IdentifierCountMismatch {
+            header: 3,
+            identifiers: 2,
+            hashes: 2
+        })
+    );
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// The footer SHA-1 of a file that was changed after it was written
+fn is_stale_footer(kind: &ValidationKind, original: &[u8]) -> bool {
+    matches!(kind, ValidationKind::FooterHashMismatch { stored, computed }
+        if *stored == hex(&original[original.len() - 20..]) && *computed != *stored)
+}
+
+#[test]
+fn wrong_identifier_hash() {
+    let mut bytes = sample_bytes();
+    let original = bytes.clone();
+    let f = read(bytes.clone());
+    let stored = f.identifier_hashes[1];
+    let string_id = f
+        .get_strings()
+        .iter()
+        .position(|s| hash_string(s) == stored)
+        .unwrap() as u32;
+
+    let at = f
+        .section_layout()
+        .offset(SectionKind::IdentifierHashes)
+        .unwrap() as usize;
+    bytes[at + 4] ^= 1;
+
+    let found = kinds(&mut read(bytes));
+    assert_eq!(found.len(), 2, "{:?}", found);
+    assert_eq!(
+        found[0],
+        ValidationKind::IdentifierHashMismatch {
+            string_id,
+            stored: stored ^ 1,
+            computed: stored,
+        }
+    );
+    assert!(is_stale_footer(&found[1], &original), "{:?}", found);
+}
+
+#[test]
+fn bad_magic() {
+    let mut bytes = sample_bytes();
+    let original = bytes.clone();
+    bytes[0] ^= 0xff;
+
+    let found = kinds(&mut read(bytes));
+    assert_eq!(found.len(), 2, "{:?}", found);
+    assert_eq!(
+        found[0],
+        ValidationKind::BadMagic {
+            magic: 0x1F1903C103BC1F39
+        }
+    );
+    assert!(is_stale_footer(&found[1], &original), "{:?}", found);
+}
+
+#[test]
+fn bad_footer() {
+    let mut bytes = sample_bytes();
+    let last = bytes.len() - 1;
+    bytes[last] ^= 1;
+
+    let found = read(bytes.clone()).validate();
+    assert_eq!(found.len(), 1, "{:?}", found);
+    // Hermes doesn't check it, so it's only a warning
+    assert_eq!(found[0].severity, Severity::Warning);
+    assert_eq!(
+        found[0].kind,
+        ValidationKind::FooterHashMismatch {
+            stored: hex(&bytes[last - 19..]),
+            computed: hex(&sample_bytes()[last - 19..]),
+        }
+    );
+}
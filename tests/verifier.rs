@@ -0,0 +1,241 @@
+/*
+ * Verifier diagnostics: each check is tripped by a small function that's
+ * otherwise valid, so it's the only thing reported.
+ */
+#![cfg(feature = "v96")]
+
+mod common;
+
+use common::{file_with, MemoryFile};
+use hermes_rs::exception_handler::ExceptionHandlerInfo;
+use hermes_rs::function_header::{FunctionHeader, SmallFunctionHeader};
+use hermes_rs::verifier::{DiagnosticKind, Severity};
+
+const GLOBAL: (&str, u32, &str) = ("global", 1, "LoadConstUndefined r0\nRet r0");
+
+// Function 1 with the given code, after the global function
+fn file(snippet: &str) -> MemoryFile {
+    file_with(96, &[GLOBAL, ("f", 1, snippet)])
+}
+
+// Function 1's header, to break what the snippet got right
+fn header(f: &mut MemoryFile) -> &mut SmallFunctionHeader {
+    match &mut f.function_headers[1] {
+        FunctionHeader::Small(fh) => fh,
+        FunctionHeader::Large(_) => panic!("expected a small header"),
+    }
+}
+
+fn diagnostics(f: &mut MemoryFile) -> Vec<(u32, Option<u32>, DiagnosticKind)> {
+    f.verify()
+        .into_iter()
+        .map(|d| (d.function_id, d.offset, d.kind))
+        .collect()
+}
+
+#[test]
+fn valid_functions_have_no_diagnostics() {
+    let mut f = file(
+        r#"
+        LoadParam r1, 1
+        JmpTrue skip, r1
+        GetByIdShort r0, r1, 0, "x"
+        skip:
+        Ret r0
+        "#,
+    );
+    assert!(f.verify().is_empty(), "{:?}", f.verify());
+}
+
+#[test]
+fn registers_outside_the_frame() {
+    let mut f = file("LoadConstUndefined r0\nLoadConstZero r3\nRet r0");
+    header(&mut f).frame_size = 2;
+    assert_eq!(
+        diagnostics(&mut f),
+        vec![(
+            1,
+            Some(2),
+            DiagnosticKind::RegisterOutOfRange {
+                register: 3,
+                frame_size: 2
+            }
+        )]
+    );
+}
+
+#[test]
+fn ids_out_of_range() {
+    let mut f = file(
+        r#"
+        LoadConstString r0, 200
+        CreateClosure r1, r0, 9
+        LoadConstBigInt r2, 0
+        CreateRegExp r0, 0, 0, 4
+        Ret r0
+        "#,
+    );
+    let strings = f.string_storage.len() as u32;
+    assert_eq!(
+        diagnostics(&mut f),
+        vec![
+            (
+                1,
+                Some(0),
+                DiagnosticKind::StringIdOutOfRange {
+                    id: 200,
+                    count: strings
+                }
+            ),
+            (
+                1,
+                Some(4),
+                DiagnosticKind::FunctionIdOutOfRange { id: 9, count: 2 }
+            ),
+            (
+                1,
+                Some(9),
+                DiagnosticKind::BigIntIdOutOfRange { id: 0, count: 0 }
+            ),
+            (
+                1,
+                Some(13),
+                DiagnosticKind::RegExpIdOutOfRange { id: 4, count: 0 }
+            ),
+        ]
+    );
+}
+
+// A jump over a LoadConstString, which ends at 6, to `offset`
+fn jump_to(offset: u8) -> MemoryFile {
+    file(&format!(
+        "Jmp {}\nLoadConstString r0, \"x\"\nRet r0",
+        offset
+    ))
+}
+
+#[test]
+fn jumps_into_an_instruction() {
+    assert!(jump_to(6).verify().is_empty());
+    assert_eq!(
+        diagnostics(&mut jump_to(3)),
+        vec![(
+            1,
+            Some(0),
+            DiagnosticKind::TargetNotOnBoundary { target: 3 }
+        )]
+    );
+    assert_eq!(
+        diagnostics(&mut jump_to(40)),
+        vec![(
+            1,
+            Some(0),
+            DiagnosticKind::TargetOutOfBounds {
+                target: 40,
+                code_size: 8
+            }
+        )]
+    );
+}
+
+#[test]
+fn reversed_handler_ranges() {
+    let mut f = file("LoadConstZero r0\nLoadConstZero r0\nLoadConstZero r0\nRet r0");
+    let fh = header(&mut f);
+    fh.flags.has_exception_handler = true;
+    fh.exception_handlers = vec![ExceptionHandlerInfo {
+        start: 4,
+        end: 2,
+        target: 6,
+    }];
+    assert_eq!(
+        diagnostics(&mut f),
+        vec![(
+            1,
+            None,
+            DiagnosticKind::InvalidHandlerRange { start: 4, end: 2 }
+        )]
+    );
+}
+
+#[test]
+fn environment_slots_past_the_end() {
+    let mut f = file(
+        r#"
+        CreateEnvironment r0
+        CreateInnerEnvironment r1, r0, 2
+        LoadConstZero r2
+        StoreToEnvironment r1, 1, r2
+        StoreToEnvironment r1, 2, r2
+        LoadFromEnvironment r2, r1, 3
+        Mov r3, r1
+        StoreNPToEnvironment r3, 5, r2
+        LoadConstUndefined r0
+        Ret r0
+        "#,
+    );
+    let slot = |slot| DiagnosticKind::EnvironmentSlotOutOfRange { slot, env_size: 2 };
+    assert_eq!(
+        diagnostics(&mut f),
+        vec![
+            (1, Some(15), slot(2)),
+            (1, Some(19), slot(3)),
+            (1, Some(26), slot(5)),
+        ]
+    );
+}
+
+#[test]
+fn cache_indices_above_the_highest() {
+    let mut f = file(
+        r#"
+        LoadParam r1, 1
+        GetByIdShort r0, r1, 3, "x"
+        TryGetById r0, r1, 1, "y"
+        PutById r1, r0, 2, "z"
+        PutById r1, r0, 1, "z"
+        Ret r0
+        "#,
+    );
+    let fh = header(&mut f);
+    assert_eq!(
+        (fh.highest_read_cache_index, fh.highest_write_cache_index),
+        (3, 2)
+    );
+    fh.highest_read_cache_index = 1;
+    fh.highest_write_cache_index = 1;
+    assert_eq!(
+        diagnostics(&mut f),
+        vec![
+            (
+                1,
+                Some(3),
+                DiagnosticKind::ReadCacheIndexOutOfRange {
+                    index: 3,
+                    highest: 1
+                }
+            ),
+            (
+                1,
+                Some(14),
+                DiagnosticKind::WriteCacheIndexOutOfRange {
+                    index: 2,
+                    highest: 1
+                }
+            ),
+        ]
+    );
+}
+
+#[test]
+fn falling_off_the_end_is_a_warning() {
+    let mut f = file("LoadConstZero r0");
+    let found = f.verify();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].kind, DiagnosticKind::FallsOffEnd);
+    assert_eq!(found[0].severity, Severity::Warning);
+    assert_eq!(
+        found[0].to_string(),
+        "warning: function #1 @ 0 (LoadConstZero r0): the last instruction falls through past the end of the function"
+    );
+}